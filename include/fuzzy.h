@@ -0,0 +1,46 @@
+#ifndef FUZZY_H
+#define FUZZY_H
+
+/* Generated with cbindgen from src/ffi.rs, do not edit. */
+
+#include <stddef.h>
+#include <stdint.h>
+#include <stdio.h>
+
+#define SPAMSUM_LENGTH 64
+
+#define FUZZY_MAX_RESULT (2 * SPAMSUM_LENGTH + 20)
+
+#define FUZZY_FLAG_ELIMSEQ 1
+
+#define FUZZY_FLAG_NOTRUNC 2
+
+struct fuzzy_state;
+
+#ifdef __cplusplus
+extern "C" {
+#endif  // __cplusplus
+
+int fuzzy_hash_buf(const uint8_t *buf, uint32_t buf_len, char *result);
+
+int fuzzy_hash_filename(const char *filename, char *result);
+
+int fuzzy_hash_file(FILE *handle, char *result);
+
+int fuzzy_hash_stream(FILE *handle, char *result);
+
+int fuzzy_compare(const char *sig1, const char *sig2);
+
+struct fuzzy_state *fuzzy_new(void);
+
+int fuzzy_update(struct fuzzy_state *state, const uint8_t *buffer, size_t buffer_size);
+
+int fuzzy_digest(const struct fuzzy_state *state, char *result, unsigned int flags);
+
+void fuzzy_free(struct fuzzy_state *state);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* FUZZY_H */
@@ -0,0 +1,84 @@
+//! Swift and Kotlin functions for hashing and comparing, generated with
+//! UniFFI. The bindings are generated from the built library:
+//!
+//! ```text
+//! cd mobile
+//! cargo build --release
+//! cargo run --bin uniffi-bindgen generate --library target/release/libspamsum_mobile.so \
+//!     --language swift --out-dir out
+//! cargo run --bin uniffi-bindgen generate --library target/release/libspamsum_mobile.so \
+//!     --language kotlin --out-dir out
+//! ```
+//!
+//! For iOS and Android the library is built for their targets instead, e.g.
+//! `aarch64-apple-ios` (as a static library) or `aarch64-linux-android`.
+use std::fmt;
+
+use spamsum::{get_spamsum, Spamsum};
+
+uniffi::setup_scaffolding!();
+
+/// Errors thrown by `hash()` and `compare()`.
+#[derive(Debug, uniffi::Error)]
+pub enum SpamsumError {
+    /// The input cannot be hashed.
+    InvalidInput { message: String },
+    /// A string is not a valid spamsum.
+    InvalidSpamsum { message: String },
+}
+
+impl fmt::Display for SpamsumError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SpamsumError::InvalidInput { message } | SpamsumError::InvalidSpamsum { message } => {
+                f.write_str(message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SpamsumError {}
+
+/// Returns the spamsum of `input` as a string.
+#[uniffi::export]
+pub fn hash(input: Vec<u8>) -> Result<String, SpamsumError> {
+    let spamsum = get_spamsum(&input).map_err(|e| SpamsumError::InvalidInput {
+        message: e.to_string(),
+    })?;
+    Ok(spamsum.to_string())
+}
+
+/// Compares two spamsum strings and returns their similarity from 0 to 100.
+#[uniffi::export]
+pub fn compare(a: String, b: String) -> Result<u32, SpamsumError> {
+    Ok(spamsum::compare(&parse(&a)?, &parse(&b)?))
+}
+
+fn parse(s: &str) -> Result<Spamsum, SpamsumError> {
+    s.parse().map_err(
+        |e: spamsum::ParseSpamsumError| SpamsumError::InvalidSpamsum {
+            message: e.to_string(),
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash() {
+        let input = b"Please buy my stuff\nDear Sir or Madam\n".to_vec();
+        assert_eq!(hash(input).unwrap(), "3:clclDDvWIMF/hv:cGZ/EJv");
+    }
+
+    #[test]
+    fn test_compare() {
+        let a = "3:clclDDvWIMF/hv:cGZ/EJv".to_string();
+        assert_eq!(compare(a.clone(), a.clone()).unwrap(), 100);
+        assert!(matches!(
+            compare(a, "3:Hn".to_string()),
+            Err(SpamsumError::InvalidSpamsum { .. })
+        ));
+    }
+}
@@ -0,0 +1,66 @@
+//! Node.js functions for hashing and comparing, built with napi-rs:
+//!
+//! ```text
+//! cd node && npm install && npm run build && npm test
+//! ```
+//!
+//! ```text
+//! const { hash, hashFile, compare } = require("spamsum");
+//!
+//! const a = hash(Buffer.from(message));
+//! const b = await hashFile("mail.eml"); // hashed on the libuv thread pool
+//! compare(a, b); // 0 to 100
+//! ```
+use std::fs::File;
+
+use napi::bindgen_prelude::*;
+use napi_derive::napi;
+use spamsum::{get_spamsum, get_spamsum_from_reader, Spamsum};
+
+/// Returns the spamsum of a `Buffer` as a string.
+#[napi]
+pub fn hash(input: Buffer) -> Result<String> {
+    let spamsum = get_spamsum(&input).map_err(to_error)?;
+    Ok(spamsum.to_string())
+}
+
+/// Compares two spamsum strings and returns their similarity from 0 to 100.
+///
+/// Throws if one of them is not a valid spamsum.
+#[napi]
+pub fn compare(a: String, b: String) -> Result<u32> {
+    let a: Spamsum = a.parse().map_err(to_error)?;
+    let b: Spamsum = b.parse().map_err(to_error)?;
+    Ok(spamsum::compare(&a, &b))
+}
+
+/// Reads and hashes a file on the libuv thread pool, so that the event loop
+/// is not blocked. The returned promise resolves to the spamsum.
+#[napi]
+pub fn hash_file(path: String) -> AsyncTask<HashFile> {
+    AsyncTask::new(HashFile { path })
+}
+
+pub struct HashFile {
+    path: String,
+}
+
+#[napi]
+impl Task for HashFile {
+    type Output = String;
+    type JsValue = String;
+
+    fn compute(&mut self) -> Result<String> {
+        let file = File::open(&self.path).map_err(to_error)?;
+        let spamsum = get_spamsum_from_reader(file, Default::default()).map_err(to_error)?;
+        Ok(spamsum.to_string())
+    }
+
+    fn resolve(&mut self, _env: Env, spamsum: String) -> Result<String> {
+        Ok(spamsum)
+    }
+}
+
+fn to_error<E: ToString>(error: E) -> Error {
+    Error::from_reason(error.to_string())
+}
@@ -0,0 +1,33 @@
+// Tests of the built module: npm run build:debug && npm test
+const assert = require("node:assert");
+const fs = require("node:fs");
+const os = require("node:os");
+const path = require("node:path");
+const test = require("node:test");
+
+const { hash, hashFile, compare } = require("./index.js");
+
+const MESSAGE = "Please buy my stuff\nDear Sir or Madam\n";
+const SPAMSUM = "3:clclDDvWIMF/hv:cGZ/EJv";
+
+test("hash", () => {
+  assert.strictEqual(hash(Buffer.from(MESSAGE)), SPAMSUM);
+  assert.strictEqual(hash(Buffer.alloc(0)), "3::");
+});
+
+test("compare", () => {
+  assert.strictEqual(compare(SPAMSUM, SPAMSUM), 100);
+  assert.strictEqual(compare(SPAMSUM, "3:Hn:Hn"), 0);
+  assert.throws(() => compare(SPAMSUM, "3:Hn"));
+});
+
+test("hashFile", async () => {
+  const file = path.join(os.tmpdir(), `spamsum-node-${process.pid}`);
+  fs.writeFileSync(file, MESSAGE);
+  try {
+    assert.strictEqual(await hashFile(file), SPAMSUM);
+  } finally {
+    fs.unlinkSync(file);
+  }
+  await assert.rejects(hashFile(file));
+});
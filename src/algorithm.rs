@@ -0,0 +1,289 @@
+use alloc::vec::Vec;
+use core::fmt;
+use core::str::FromStr;
+
+#[cfg(feature = "minhash")]
+use crate::MinHash;
+#[cfg(feature = "nilsimsa")]
+use crate::Nilsimsa;
+#[cfg(feature = "sdhash")]
+use crate::Sdbf;
+#[cfg(feature = "simhash")]
+use crate::SimHash;
+#[cfg(feature = "tlsh")]
+use crate::Tlsh;
+use crate::{
+    compare, get_configured_spamsum, ParseSpamsumError, Spamsum, SpamsumError, SpamsumOptions,
+};
+
+/// The operations all fuzzy hashes of this crate have in common, so that
+/// code can be written once for any of them.
+///
+/// Every algorithm measures similarity differently, so `similarity()`
+/// maps its own measure to a score from 0 for unrelated inputs to 100 for
+/// identical ones. The scores are roughly comparable, but the thresholds of
+/// one algorithm do not carry over to another.
+///
+/// ```
+/// use spamsum::{FuzzyHash, Spamsum};
+///
+/// fn most_similar<H: FuzzyHash>(input: &[u8], known: &[&[u8]]) -> Option<usize> {
+///     let hash = H::hash(input, Default::default()).ok()?;
+///     let known: Vec<H> = known
+///         .iter()
+///         .map(|input| H::hash(input, Default::default()))
+///         .collect::<Result<_, _>>()
+///         .ok()?;
+///     (0..known.len()).max_by_key(|&i| hash.similarity(&known[i]))
+/// }
+///
+/// let known: [&[u8]; 2] = [b"Hello", b"Please buy my stuff\nDear Sir or Madam\n"];
+/// assert_eq!(most_similar::<Spamsum>(b"Please buy my stuff\nDear Sir\n", &known), Some(1));
+/// ```
+pub trait FuzzyHash: Clone + fmt::Display + FromStr<Err = ParseSpamsumError> {
+    /// The algorithm of the hash.
+    const ALGORITHM: Algorithm;
+
+    /// Hashes `input` after preprocessing it with `options`, like
+    /// `get_configured_spamsum()`; options that only make sense for
+    /// spamsums, like the blocksize, are ignored by other algorithms.
+    fn hash(input: &[u8], options: SpamsumOptions) -> Result<Self, SpamsumError>;
+
+    /// Returns the similarity of both hashes from 0 to 100.
+    fn similarity(&self, other: &Self) -> u32;
+}
+
+impl FuzzyHash for Spamsum {
+    const ALGORITHM: Algorithm = Algorithm::Spamsum;
+
+    fn hash(input: &[u8], options: SpamsumOptions) -> Result<Spamsum, SpamsumError> {
+        get_configured_spamsum(input, options)
+    }
+
+    fn similarity(&self, other: &Spamsum) -> u32 {
+        compare(self, other)
+    }
+}
+
+/// A fuzzy hashing algorithm, to choose one at runtime, e.g. from a command
+/// line option. Only the algorithms of enabled features are available.
+///
+/// ```
+/// use spamsum::Algorithm;
+///
+/// let algorithm = Algorithm::from_name("spamsum").unwrap();
+/// let a = algorithm.hash(b"Please buy my stuff\nDear Sir or Madam\n", Default::default()).unwrap();
+/// let b = algorithm.parse("3:clclDDvWIMF/hv:cGZ/EJv").unwrap();
+/// assert_eq!(a.similarity(&b), Some(100));
+/// ```
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Algorithm {
+    #[default]
+    Spamsum,
+    #[cfg(feature = "tlsh")]
+    Tlsh,
+    #[cfg(feature = "nilsimsa")]
+    Nilsimsa,
+    #[cfg(feature = "simhash")]
+    SimHash,
+    #[cfg(feature = "sdhash")]
+    Sdhash,
+    #[cfg(feature = "minhash")]
+    MinHash,
+}
+
+impl Algorithm {
+    /// Returns all available algorithms.
+    pub fn all() -> Vec<Algorithm> {
+        // no algorithm is added without optional features
+        #[allow(unused_mut)]
+        let mut algorithms = alloc::vec![Algorithm::Spamsum];
+        #[cfg(feature = "tlsh")]
+        algorithms.push(Algorithm::Tlsh);
+        #[cfg(feature = "nilsimsa")]
+        algorithms.push(Algorithm::Nilsimsa);
+        #[cfg(feature = "simhash")]
+        algorithms.push(Algorithm::SimHash);
+        #[cfg(feature = "sdhash")]
+        algorithms.push(Algorithm::Sdhash);
+        #[cfg(feature = "minhash")]
+        algorithms.push(Algorithm::MinHash);
+        algorithms
+    }
+
+    /// Returns the lowercase name of the algorithm, e.g. `"spamsum"`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Algorithm::Spamsum => "spamsum",
+            #[cfg(feature = "tlsh")]
+            Algorithm::Tlsh => "tlsh",
+            #[cfg(feature = "nilsimsa")]
+            Algorithm::Nilsimsa => "nilsimsa",
+            #[cfg(feature = "simhash")]
+            Algorithm::SimHash => "simhash",
+            #[cfg(feature = "sdhash")]
+            Algorithm::Sdhash => "sdhash",
+            #[cfg(feature = "minhash")]
+            Algorithm::MinHash => "minhash",
+        }
+    }
+
+    /// Returns the available algorithm with the given name, ignoring case.
+    pub fn from_name(name: &str) -> Option<Algorithm> {
+        Algorithm::all()
+            .into_iter()
+            .find(|algorithm| algorithm.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Hashes `input` with this algorithm, see `FuzzyHash::hash()`.
+    pub fn hash(self, input: &[u8], options: SpamsumOptions) -> Result<Digest, SpamsumError> {
+        Ok(match self {
+            Algorithm::Spamsum => Digest::Spamsum(FuzzyHash::hash(input, options)?),
+            #[cfg(feature = "tlsh")]
+            Algorithm::Tlsh => Digest::Tlsh(FuzzyHash::hash(input, options)?),
+            #[cfg(feature = "nilsimsa")]
+            Algorithm::Nilsimsa => Digest::Nilsimsa(FuzzyHash::hash(input, options)?),
+            #[cfg(feature = "simhash")]
+            Algorithm::SimHash => Digest::SimHash(FuzzyHash::hash(input, options)?),
+            #[cfg(feature = "sdhash")]
+            Algorithm::Sdhash => Digest::Sdhash(FuzzyHash::hash(input, options)?),
+            #[cfg(feature = "minhash")]
+            Algorithm::MinHash => Digest::MinHash(FuzzyHash::hash(input, options)?),
+        })
+    }
+
+    /// Parses a hash of this algorithm. The formats of different algorithms
+    /// can be ambiguous, so the algorithm has to be known.
+    pub fn parse(self, s: &str) -> Result<Digest, ParseSpamsumError> {
+        Ok(match self {
+            Algorithm::Spamsum => Digest::Spamsum(s.parse()?),
+            #[cfg(feature = "tlsh")]
+            Algorithm::Tlsh => Digest::Tlsh(s.parse()?),
+            #[cfg(feature = "nilsimsa")]
+            Algorithm::Nilsimsa => Digest::Nilsimsa(s.parse()?),
+            #[cfg(feature = "simhash")]
+            Algorithm::SimHash => Digest::SimHash(s.parse()?),
+            #[cfg(feature = "sdhash")]
+            Algorithm::Sdhash => Digest::Sdhash(s.parse()?),
+            #[cfg(feature = "minhash")]
+            Algorithm::MinHash => Digest::MinHash(s.parse()?),
+        })
+    }
+}
+
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A hash of any `Algorithm`.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Digest {
+    Spamsum(Spamsum),
+    #[cfg(feature = "tlsh")]
+    Tlsh(Tlsh),
+    #[cfg(feature = "nilsimsa")]
+    Nilsimsa(Nilsimsa),
+    #[cfg(feature = "simhash")]
+    SimHash(SimHash),
+    #[cfg(feature = "sdhash")]
+    Sdhash(Sdbf),
+    #[cfg(feature = "minhash")]
+    MinHash(MinHash),
+}
+
+impl Digest {
+    /// Returns the algorithm of the hash.
+    pub fn algorithm(&self) -> Algorithm {
+        match self {
+            Digest::Spamsum(_) => Algorithm::Spamsum,
+            #[cfg(feature = "tlsh")]
+            Digest::Tlsh(_) => Algorithm::Tlsh,
+            #[cfg(feature = "nilsimsa")]
+            Digest::Nilsimsa(_) => Algorithm::Nilsimsa,
+            #[cfg(feature = "simhash")]
+            Digest::SimHash(_) => Algorithm::SimHash,
+            #[cfg(feature = "sdhash")]
+            Digest::Sdhash(_) => Algorithm::Sdhash,
+            #[cfg(feature = "minhash")]
+            Digest::MinHash(_) => Algorithm::MinHash,
+        }
+    }
+
+    /// Returns the similarity of both hashes from 0 to 100, see
+    /// `FuzzyHash::similarity()`, or `None` if their algorithms differ.
+    pub fn similarity(&self, other: &Digest) -> Option<u32> {
+        match (self, other) {
+            (Digest::Spamsum(a), Digest::Spamsum(b)) => Some(a.similarity(b)),
+            #[cfg(feature = "tlsh")]
+            (Digest::Tlsh(a), Digest::Tlsh(b)) => Some(a.similarity(b)),
+            #[cfg(feature = "nilsimsa")]
+            (Digest::Nilsimsa(a), Digest::Nilsimsa(b)) => Some(a.similarity(b)),
+            #[cfg(feature = "simhash")]
+            (Digest::SimHash(a), Digest::SimHash(b)) => Some(a.similarity(b)),
+            #[cfg(feature = "sdhash")]
+            (Digest::Sdhash(a), Digest::Sdhash(b)) => Some(a.similarity(b)),
+            #[cfg(feature = "minhash")]
+            (Digest::MinHash(a), Digest::MinHash(b)) => Some(a.similarity(b)),
+            #[allow(unreachable_patterns)]
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Digest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Digest::Spamsum(spamsum) => spamsum.fmt(f),
+            #[cfg(feature = "tlsh")]
+            Digest::Tlsh(tlsh) => tlsh.fmt(f),
+            #[cfg(feature = "nilsimsa")]
+            Digest::Nilsimsa(nilsimsa) => nilsimsa.fmt(f),
+            #[cfg(feature = "simhash")]
+            Digest::SimHash(simhash) => simhash.fmt(f),
+            #[cfg(feature = "sdhash")]
+            Digest::Sdhash(sdbf) => sdbf.fmt(f),
+            #[cfg(feature = "minhash")]
+            Digest::MinHash(minhash) => minhash.fmt(f),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pseudo_random_bytes;
+    use alloc::string::ToString;
+
+    #[test]
+    fn test_algorithm_names() {
+        for algorithm in Algorithm::all() {
+            assert_eq!(Algorithm::from_name(algorithm.name()), Some(algorithm));
+            assert_eq!(algorithm.to_string(), algorithm.name());
+        }
+        assert_eq!(Algorithm::from_name("SpamSum"), Some(Algorithm::Spamsum));
+        assert_eq!(Algorithm::from_name("md5"), None);
+    }
+
+    #[test]
+    fn test_digests() {
+        // long and varied enough for every algorithm
+        let input = pseudo_random_bytes(20_000, 1);
+        let mut digests: Vec<Digest> = Vec::new();
+        for algorithm in Algorithm::all() {
+            let digest = algorithm.hash(&input, Default::default()).unwrap();
+            assert_eq!(digest.algorithm(), algorithm);
+            assert_eq!(algorithm.parse(&digest.to_string()), Ok(digest.clone()));
+            assert_eq!(digest.similarity(&digest), Some(100), "{}", algorithm);
+            digests.push(digest);
+        }
+        for (i, a) in digests.iter().enumerate() {
+            for b in &digests[i + 1..] {
+                assert_eq!(a.similarity(b), None);
+            }
+        }
+    }
+}
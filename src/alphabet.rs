@@ -0,0 +1,258 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::str;
+
+use crate::{HashBuffer, ParseSpamsumError, Spamsum, BASE64_CHARSET, MAX_HASH_LENGTH};
+
+// marks bytes that are not part of an alphabet
+const INVALID: u8 = 0xff;
+
+/// The 64 characters the symbols of a spamsum are written with.
+///
+/// Spamsums always keep their hashes in the standard base64 alphabet, so
+/// comparisons do not depend on the alphabet: other alphabets are only used
+/// for `to_string_with_alphabet()` and `parse_with_alphabet()`.
+///
+/// ```
+/// use spamsum::{Alphabet, Spamsum};
+///
+/// let spamsum: Spamsum = "3:clclDDvWIMF/hv:cGZ/EJv".parse().unwrap();
+/// let url_safe = spamsum.to_string_with_alphabet(&Alphabet::URL_SAFE);
+/// assert_eq!(url_safe, "3:clclDDvWIMF_hv:cGZ_EJv");
+/// assert_eq!(Spamsum::parse_with_alphabet(&url_safe, &Alphabet::URL_SAFE), Ok(spamsum));
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Alphabet {
+    chars: [u8; 64],
+    // the index of every byte in `chars`, or INVALID
+    indices: [u8; 256],
+}
+
+impl Alphabet {
+    /// The base64 alphabet spamsum and ssdeep use.
+    pub const STANDARD: Alphabet = Alphabet::from_bytes(BASE64_CHARSET.as_bytes());
+    /// The URL and filename safe base64 alphabet, with `-` and `_` instead
+    /// of `+` and `/`.
+    pub const URL_SAFE: Alphabet =
+        Alphabet::from_bytes(b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_");
+
+    /// Creates an alphabet from 64 different printable ASCII characters,
+    /// except for `:` and `,` which separate the parts of spamsums and hash
+    /// lists. Returns `None` for any other string.
+    ///
+    /// ```
+    /// use spamsum::Alphabet;
+    ///
+    /// assert!(Alphabet::new("0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz.~").is_some());
+    /// assert!(Alphabet::new("ABC").is_none());
+    /// ```
+    pub fn new(chars: &str) -> Option<Alphabet> {
+        let bytes = chars.as_bytes();
+        if bytes.len() != 64
+            || bytes
+                .iter()
+                .any(|&c| !c.is_ascii_graphic() || c == b':' || c == b',')
+        {
+            return None;
+        }
+        let alphabet = Alphabet::from_bytes(bytes);
+        // duplicates leave fewer than 64 characters with an index
+        if alphabet.indices.iter().filter(|&&i| i != INVALID).count() != 64 {
+            return None;
+        }
+        Some(alphabet)
+    }
+
+    const fn from_bytes(bytes: &[u8]) -> Alphabet {
+        let mut chars = [0; 64];
+        let mut indices = [INVALID; 256];
+        let mut i = 0;
+        while i < 64 {
+            chars[i] = bytes[i];
+            indices[bytes[i] as usize] = i as u8;
+            i += 1;
+        }
+        Alphabet { chars, indices }
+    }
+
+    /// Returns the character for a symbol from 0 to 63.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is 64 or larger.
+    pub fn encode(&self, index: u8) -> char {
+        char::from(self.chars[usize::from(index)])
+    }
+
+    /// Returns the symbol a character stands for, or `None` if it is not
+    /// part of the alphabet.
+    pub fn decode(&self, c: char) -> Option<u8> {
+        if !c.is_ascii() {
+            return None;
+        }
+        match self.indices[c as usize] {
+            INVALID => None,
+            index => Some(index),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        // only ASCII characters are accepted
+        str::from_utf8(&self.chars).unwrap()
+    }
+
+    // Rewrites a hash from this alphabet into `other`. The hash must only
+    // contain characters of this alphabet.
+    pub(crate) fn translate<'a>(
+        &'a self,
+        hash: &'a str,
+        other: &'a Alphabet,
+    ) -> impl Iterator<Item = char> + 'a {
+        hash.chars()
+            .map(move |c| other.encode(self.decode(c).unwrap()))
+    }
+}
+
+impl Default for Alphabet {
+    fn default() -> Alphabet {
+        Alphabet::STANDARD
+    }
+}
+
+impl fmt::Debug for Alphabet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Alphabet").field(&self.as_str()).finish()
+    }
+}
+
+impl Spamsum {
+    /// Returns the spamsum as a string like `to_string()`, but with the
+    /// hashes written in `alphabet` instead of standard base64.
+    pub fn to_string_with_alphabet(&self, alphabet: &Alphabet) -> String {
+        format!(
+            "{}:{}:{}",
+            self.left_hash_blocksize,
+            Alphabet::STANDARD
+                .translate(&self.left_hash, alphabet)
+                .collect::<String>(),
+            Alphabet::STANDARD
+                .translate(&self.right_hash, alphabet)
+                .collect::<String>()
+        )
+    }
+
+    /// Returns the symbols (0 to 63) of the left hash, i.e. the hash
+    /// without the base64 encoding.
+    ///
+    /// ```
+    /// use spamsum::Spamsum;
+    ///
+    /// let spamsum: Spamsum = "3:Hn/:Hn".parse().unwrap();
+    /// assert_eq!(spamsum.left_symbols(), vec![7, 39, 63]);
+    /// ```
+    pub fn left_symbols(&self) -> Vec<u8> {
+        symbols(&self.left_hash)
+    }
+
+    /// Returns the symbols (0 to 63) of the right hash.
+    pub fn right_symbols(&self) -> Vec<u8> {
+        symbols(&self.right_hash)
+    }
+
+    /// Creates a spamsum from the symbols of its hashes, e.g. from
+    /// `left_symbols()` and `right_symbols()` stored in a custom encoding.
+    ///
+    /// ```
+    /// use spamsum::Spamsum;
+    ///
+    /// let spamsum = Spamsum::from_symbols(3, &[7, 39, 63], &[7, 39]).unwrap();
+    /// assert_eq!(spamsum.to_string(), "3:Hn/:Hn");
+    /// ```
+    pub fn from_symbols(
+        blocksize: u32,
+        left_symbols: &[u8],
+        right_symbols: &[u8],
+    ) -> Result<Spamsum, ParseSpamsumError> {
+        if blocksize == 0 {
+            return Err(ParseSpamsumError::InvalidBlocksize);
+        }
+        Ok(Spamsum {
+            left_hash_blocksize: blocksize,
+            left_hash: encode_symbols(left_symbols, MAX_HASH_LENGTH)?,
+            right_hash: encode_symbols(right_symbols, MAX_HASH_LENGTH / 2)?,
+        })
+    }
+}
+
+fn symbols(hash: &str) -> Vec<u8> {
+    hash.chars()
+        .map(|c| Alphabet::STANDARD.decode(c).unwrap())
+        .collect()
+}
+
+fn encode_symbols(symbols: &[u8], hash_length: u32) -> Result<HashBuffer, ParseSpamsumError> {
+    if let Some(&symbol) = symbols.iter().find(|&&symbol| symbol >= 64) {
+        return Err(ParseSpamsumError::InvalidSymbol(symbol));
+    }
+    if symbols.len() > hash_length as usize {
+        return Err(ParseSpamsumError::HashTooLong);
+    }
+    Ok(symbols
+        .iter()
+        .map(|&symbol| Alphabet::STANDARD.encode(symbol))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_alphabet() {
+        assert_eq!(Alphabet::STANDARD.as_str(), BASE64_CHARSET);
+        assert_eq!(Alphabet::URL_SAFE.encode(63), '_');
+        assert_eq!(Alphabet::URL_SAFE.decode('-'), Some(62));
+        assert_eq!(Alphabet::URL_SAFE.decode('+'), None);
+        assert_eq!(Alphabet::STANDARD.decode('é'), None);
+        assert_eq!(
+            Alphabet::new(Alphabet::URL_SAFE.as_str()),
+            Some(Alphabet::URL_SAFE)
+        );
+    }
+
+    #[test]
+    fn test_invalid_alphabet() {
+        let duplicate = BASE64_CHARSET.replace('/', "A");
+        assert_eq!(Alphabet::new(&duplicate), None);
+        let separator = BASE64_CHARSET.replace('/', ":");
+        assert_eq!(Alphabet::new(&separator), None);
+        let space = BASE64_CHARSET.replace('/', " ");
+        assert_eq!(Alphabet::new(&space), None);
+        assert_eq!(Alphabet::new(&BASE64_CHARSET[1..]), None);
+    }
+
+    #[test]
+    fn test_symbols() {
+        let spamsum: Spamsum = "3:clclDDvWIMF/hv:cGZ/EJv".parse().unwrap();
+        let copy = Spamsum::from_symbols(
+            spamsum.blocksize(),
+            &spamsum.left_symbols(),
+            &spamsum.right_symbols(),
+        );
+        assert_eq!(copy, Ok(spamsum));
+        assert_eq!(
+            Spamsum::from_symbols(3, &[1, 64], &[]),
+            Err(ParseSpamsumError::InvalidSymbol(64))
+        );
+        assert_eq!(
+            Spamsum::from_symbols(3, &[], &[0; 129]),
+            Err(ParseSpamsumError::HashTooLong)
+        );
+        assert_eq!(
+            Spamsum::from_symbols(0, &[], &[]),
+            Err(ParseSpamsumError::InvalidBlocksize)
+        );
+    }
+}
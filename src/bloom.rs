@@ -0,0 +1,202 @@
+#[cfg(feature = "serde")]
+use std::convert::TryFrom;
+use std::f64::consts::LN_2;
+
+use crate::mix;
+use crate::ngram::hashed_keys;
+#[cfg(feature = "serde")]
+use crate::SpamsumError;
+use crate::{Spamsum, LEFT_HASH_LENGTH, RIGHT_HASH_LENGTH, ROLLING_WINDOW};
+
+// the most 7-character substrings a spamsum can contain
+const MAX_NGRAMS: u32 = LEFT_HASH_LENGTH + RIGHT_HASH_LENGTH - 2 * (ROLLING_WINDOW - 1);
+// more hash functions only make sense for very low false positive rates
+const MAX_HASH_COUNT: u32 = 32;
+
+/// A Bloom filter over the 7-character substrings of spamsums, to cheaply
+/// reject spamsums that cannot match any inserted one.
+///
+/// `compare()` only scores hashes that share such a substring (or are
+/// identical), so if `may_match()` returns `false`, the spamsum scores 0
+/// against every inserted spamsum. It may return `true` for spamsums that do
+/// not match though. The filter only takes a few bytes per spamsum, so it can
+/// be kept in memory in front of a database that is not.
+///
+/// ```
+/// use spamsum::{get_spamsum, SpamsumDatabase};
+///
+/// let mut database = SpamsumDatabase::new();
+/// database.insert(get_spamsum(b"Hello").unwrap(), ());
+/// let filter = database.bloom_filter(0.01);
+///
+/// let query = get_spamsum(b"Goodbye").unwrap();
+/// if filter.may_match(&query) {
+///     database.query(&query, 50);
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "BloomFilterFields")
+)]
+pub struct BloomFilter {
+    bits: Vec<u64>,
+    hash_count: u32,
+}
+
+// The serialized fields of a `BloomFilter`, which are checked before they
+// are used.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+#[serde(rename = "BloomFilter")]
+struct BloomFilterFields {
+    bits: Vec<u64>,
+    hash_count: u32,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<BloomFilterFields> for BloomFilter {
+    type Error = SpamsumError;
+
+    fn try_from(fields: BloomFilterFields) -> Result<BloomFilter, SpamsumError> {
+        if fields.bits.is_empty() {
+            return Err(SpamsumError::InvalidOptions("Bloom filter without bits"));
+        }
+        if !(1..=MAX_HASH_COUNT).contains(&fields.hash_count) {
+            return Err(SpamsumError::InvalidOptions(
+                "invalid Bloom filter hash count",
+            ));
+        }
+        Ok(BloomFilter {
+            bits: fields.bits,
+            hash_count: fields.hash_count,
+        })
+    }
+}
+
+impl BloomFilter {
+    /// Creates a filter for up to `expected_len` spamsums that wrongly
+    /// matches with at most about the given rate when that many are inserted.
+    pub fn new(expected_len: usize, false_positive_rate: f64) -> BloomFilter {
+        let false_positive_rate = false_positive_rate.clamp(f64::MIN_POSITIVE, 0.5);
+        // assume every spamsum is as long as possible, and that every
+        // substring of a query may be a false positive
+        let ngram_count = (expected_len as f64 * f64::from(MAX_NGRAMS)).max(1.0);
+        let ngram_false_positive_rate = false_positive_rate / f64::from(MAX_NGRAMS);
+        let bit_count = (-ngram_count * ngram_false_positive_rate.ln() / (LN_2 * LN_2)).ceil();
+        let word_count = (bit_count / 64.0).ceil().max(1.0) as usize;
+        let hash_count = ((word_count * 64) as f64 / ngram_count * LN_2).round();
+        BloomFilter {
+            bits: vec![0; word_count],
+            hash_count: hash_count.clamp(1.0, f64::from(MAX_HASH_COUNT)) as u32,
+        }
+    }
+
+    pub fn insert(&mut self, spamsum: &Spamsum) {
+        let spamsum = spamsum.normalized();
+        for key in hashed_keys(&spamsum) {
+            for bit in self.bit_positions(key) {
+                self.bits[bit / 64] |= 1 << (bit % 64);
+            }
+        }
+    }
+
+    /// Returns `false` if the spamsum scores 0 against all inserted
+    /// spamsums.
+    pub fn may_match(&self, spamsum: &Spamsum) -> bool {
+        let spamsum = spamsum.normalized();
+        let may_match = hashed_keys(&spamsum).any(|key| {
+            self.bit_positions(key)
+                .all(|bit| self.bits[bit / 64] & 1 << (bit % 64) != 0)
+        });
+        may_match
+    }
+
+    // double hashing, see Kirsch and Mitzenmacher: "Less Hashing, Same
+    // Performance: Building a Better Bloom Filter"
+    fn bit_positions(&self, key: u64) -> impl Iterator<Item = usize> {
+        let bit_count = self.bits.len() as u64 * 64;
+        let first = mix(key);
+        let second = mix(first) | 1;
+        (0..u64::from(self.hash_count))
+            .map(move |i| (first.wrapping_add(i.wrapping_mul(second)) % bit_count) as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compare, pseudo_random_bytes};
+
+    #[test]
+    fn test_may_match() {
+        let inserted: Vec<Spamsum> = ["48:ABCDEFGHIJKLMNOP:ABCD", "3:Hn:Hn"]
+            .iter()
+            .map(|s| s.parse().unwrap())
+            .collect();
+        let mut filter = BloomFilter::new(2, 0.001);
+        for spamsum in &inserted {
+            filter.insert(spamsum);
+        }
+        for (hash, matches) in &[
+            ("48:ABCDEFGHIJKLMNOP:ABCD", true),
+            ("48:ABCDEFGHIJKLMNQR:WXYZ", true),
+            ("24:QRSTUVWX:ABCDEFGHIJKLMNOP", true),
+            ("3:Hn:Hn", true),
+            ("3:Hnn:Hn", false),
+            ("12:ABCDEFGHIJKLMNOP:ABCD", false),
+            ("48:PONMLKJIHGFEDCBA:ABCD", false),
+        ] {
+            let spamsum: Spamsum = hash.parse().unwrap();
+            assert_eq!(filter.may_match(&spamsum), *matches, "{}", hash);
+            let score = inserted.iter().map(|other| compare(&spamsum, other)).max();
+            assert_eq!(score.unwrap() > 0, *matches, "{}", hash);
+        }
+    }
+
+    #[test]
+    fn test_false_positive_rate() {
+        let mut filter = BloomFilter::new(100, 0.01);
+        let mut bytes = pseudo_random_bytes(1100 * 96, 1).into_iter();
+        let mut random_hash = |length: usize| -> String {
+            bytes
+                .by_ref()
+                .take(length)
+                .map(|c| char::from(crate::BASE64_CHARSET.as_bytes()[usize::from(c % 64)]))
+                .collect()
+        };
+        let mut random_spamsum = || -> Spamsum {
+            format!("96:{}:{}", random_hash(64), random_hash(32))
+                .parse()
+                .unwrap()
+        };
+        for _ in 0..100 {
+            filter.insert(&random_spamsum());
+        }
+        let false_positives = (0..1000)
+            .filter(|_| filter.may_match(&random_spamsum()))
+            .count();
+        assert!(false_positives < 30, "{}", false_positives);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_deserialize() {
+        let mut filter = BloomFilter::new(10, 0.01);
+        filter.insert(&"3:Hn:Hn".parse().unwrap());
+        let json = serde_json::to_string(&filter).unwrap();
+        assert_eq!(serde_json::from_str::<BloomFilter>(&json).unwrap(), filter);
+        for json in &[
+            r#"{"bits":[],"hash_count":3}"#,
+            r#"{"bits":[1],"hash_count":0}"#,
+            r#"{"bits":[1],"hash_count":33}"#,
+        ] {
+            assert!(
+                serde_json::from_str::<BloomFilter>(json).is_err(),
+                "{}",
+                json
+            );
+        }
+    }
+}
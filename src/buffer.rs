@@ -0,0 +1,177 @@
+use alloc::string::String;
+use core::cmp::Ordering;
+use core::fmt;
+use core::hash::{Hash, Hasher};
+use core::iter::FromIterator;
+use core::ops::Deref;
+use core::str;
+
+use crate::LEFT_HASH_LENGTH;
+
+const INLINE_LENGTH: usize = LEFT_HASH_LENGTH as usize;
+
+// The characters of a hash. Hashes of the standard length are kept inline,
+// so that hashing and comparing do not allocate; only the longer hashes of a
+// larger `hash_length` are moved to the heap.
+#[derive(Clone)]
+pub(crate) enum HashBuffer {
+    Inline(u8, [u8; INLINE_LENGTH]),
+    Heap(String),
+}
+
+impl HashBuffer {
+    pub(crate) fn new() -> HashBuffer {
+        HashBuffer::Inline(0, [0; INLINE_LENGTH])
+    }
+
+    // Only ASCII characters can be pushed, as hashes are base64.
+    pub(crate) fn push(&mut self, c: char) {
+        debug_assert!(c.is_ascii());
+        match self {
+            HashBuffer::Inline(length, bytes) if usize::from(*length) < INLINE_LENGTH => {
+                bytes[usize::from(*length)] = c as u8;
+                *length += 1;
+            }
+            HashBuffer::Inline(..) => {
+                let mut hash = String::with_capacity(INLINE_LENGTH * 2);
+                hash.push_str(self.as_str());
+                hash.push(c);
+                *self = HashBuffer::Heap(hash);
+            }
+            HashBuffer::Heap(hash) => hash.push(c),
+        }
+    }
+
+    pub(crate) fn pop(&mut self) -> Option<char> {
+        match self {
+            HashBuffer::Inline(0, _) => None,
+            HashBuffer::Inline(length, bytes) => {
+                *length -= 1;
+                Some(char::from(bytes[usize::from(*length)]))
+            }
+            HashBuffer::Heap(hash) => hash.pop(),
+        }
+    }
+
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        match self {
+            HashBuffer::Inline(length, bytes) => &bytes[..usize::from(*length)],
+            HashBuffer::Heap(hash) => hash.as_bytes(),
+        }
+    }
+
+    pub(crate) fn as_str(&self) -> &str {
+        match self {
+            // only ASCII characters are stored inline
+            HashBuffer::Inline(length, bytes) => {
+                str::from_utf8(&bytes[..usize::from(*length)]).unwrap()
+            }
+            HashBuffer::Heap(hash) => hash,
+        }
+    }
+}
+
+impl Default for HashBuffer {
+    fn default() -> HashBuffer {
+        HashBuffer::new()
+    }
+}
+
+impl Deref for HashBuffer {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl From<&str> for HashBuffer {
+    fn from(hash: &str) -> HashBuffer {
+        hash.chars().collect()
+    }
+}
+
+impl FromIterator<char> for HashBuffer {
+    fn from_iter<I: IntoIterator<Item = char>>(iter: I) -> HashBuffer {
+        let mut hash = HashBuffer::new();
+        for c in iter {
+            hash.push(c);
+        }
+        hash
+    }
+}
+
+impl PartialEq for HashBuffer {
+    fn eq(&self, other: &HashBuffer) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for HashBuffer {}
+
+impl PartialEq<&str> for HashBuffer {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl Ord for HashBuffer {
+    fn cmp(&self, other: &HashBuffer) -> Ordering {
+        self.as_str().cmp(other.as_str())
+    }
+}
+
+impl PartialOrd for HashBuffer {
+    fn partial_cmp(&self, other: &HashBuffer) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Hash for HashBuffer {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state);
+    }
+}
+
+impl fmt::Debug for HashBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl fmt::Display for HashBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for HashBuffer {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_buffer() {
+        let mut hash = HashBuffer::new();
+        assert!(hash.is_empty());
+        for _ in 0..INLINE_LENGTH {
+            hash.push('A');
+        }
+        assert!(matches!(hash, HashBuffer::Inline(..)));
+        hash.push('B');
+        assert!(matches!(hash, HashBuffer::Heap(_)));
+        assert_eq!(hash.len(), INLINE_LENGTH + 1);
+        assert_eq!(hash.pop(), Some('B'));
+        assert_eq!(hash, HashBuffer::from("A".repeat(INLINE_LENGTH).as_str()));
+        let mut hash = HashBuffer::from("Hn");
+        assert_eq!(hash.pop(), Some('n'));
+        assert_eq!(hash.pop(), Some('H'));
+        assert_eq!(hash.pop(), None);
+    }
+}
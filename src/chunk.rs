@@ -0,0 +1,175 @@
+use core::iter::FusedIterator;
+
+use core::num::Wrapping;
+
+use crate::{RollingHash, HASH_INIT, HASH_PRIME};
+
+/// Returns the offsets where spamsum ends a chunk of `input` for the given
+/// blocksize, i.e. where it adds a character to the hash and starts hashing
+/// the next chunk.
+///
+/// Every offset is the end (exclusive) of a chunk. The end of the input is
+/// only included if a chunk happens to end there as well.
+///
+/// ```
+/// use spamsum::chunk_boundaries;
+///
+/// let input = b"Please buy my stuff\nDear Sir or Madam\n";
+/// let boundaries: Vec<usize> = chunk_boundaries(input, 3).collect();
+/// assert_eq!(boundaries, vec![3, 12, 13, 17, 18, 19, 20, 22, 29, 31, 32, 35, 37]);
+/// ```
+///
+/// # Panics
+///
+/// Panics if `blocksize` is 0.
+pub fn chunk_boundaries(input: &[u8], blocksize: u32) -> ChunkBoundaries<'_> {
+    assert!(blocksize > 0, "blocksize must not be 0");
+    ChunkBoundaries {
+        input,
+        blocksize,
+        offset: 0,
+        rolling: RollingHash::new(),
+    }
+}
+
+/// An iterator over chunk boundaries, see `chunk_boundaries()`.
+#[derive(Debug, Clone)]
+pub struct ChunkBoundaries<'a> {
+    input: &'a [u8],
+    blocksize: u32,
+    offset: usize,
+    rolling: RollingHash,
+}
+
+impl Iterator for ChunkBoundaries<'_> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        while self.offset < self.input.len() {
+            let hash = self.rolling.push(self.input[self.offset]);
+            self.offset += 1;
+            if hash.wrapping_add(1).is_multiple_of(self.blocksize) {
+                return Some(self.offset);
+            }
+        }
+        None
+    }
+}
+
+impl FusedIterator for ChunkBoundaries<'_> {}
+
+/// Returns the hash spamsum calculates for a chunk. The character it adds to
+/// the spamsum for the chunk is the base64 digit of the hash modulo 64 (except
+/// for the last character of a full spamsum, which covers all the remaining
+/// chunks).
+pub fn chunk_hash(chunk: &[u8]) -> u32 {
+    let mut hasher = FnvHasher::default();
+    for &c in chunk {
+        hasher.update(c);
+    }
+    hasher.value()
+}
+
+/// The hash of the chunks between the boundaries that the rolling hash finds.
+///
+/// A new hasher is created with `Default::default()` for every chunk, and the
+/// character added to the spamsum for the chunk is the base64 digit of
+/// `value() % 64`. Hashers are cloned while the blocksize is not known yet,
+/// so they should be cheap to clone.
+///
+/// ```
+/// use spamsum::{ChunkHasher, SpamsumHasher};
+///
+/// // sums up the bytes of a chunk
+/// #[derive(Clone, Default)]
+/// struct Sum(u32);
+///
+/// impl ChunkHasher for Sum {
+///     fn update(&mut self, c: u8) {
+///         self.0 = self.0.wrapping_add(u32::from(c));
+///     }
+///
+///     fn value(&self) -> u32 {
+///         self.0
+///     }
+/// }
+///
+/// let mut hasher = SpamsumHasher::<Sum>::with_chunk_hasher(Default::default()).unwrap();
+/// hasher.update(b"Please buy my stuff\nDear Sir or Madam\n");
+/// assert_eq!(hasher.finalize().unwrap().to_string(), "3:h258mmKpBhgSOK:hviaBgK");
+/// ```
+pub trait ChunkHasher: Clone + Default {
+    fn update(&mut self, c: u8);
+
+    /// Returns the hash of the bytes so far.
+    fn value(&self) -> u32;
+}
+
+/// The FNV-1 based hash spamsum uses for chunks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FnvHasher {
+    hash: Wrapping<u32>,
+}
+
+impl Default for FnvHasher {
+    fn default() -> FnvHasher {
+        FnvHasher { hash: HASH_INIT }
+    }
+}
+
+impl ChunkHasher for FnvHasher {
+    #[inline]
+    fn update(&mut self, c: u8) {
+        self.hash *= HASH_PRIME;
+        self.hash ^= Wrapping(u32::from(c));
+    }
+
+    #[inline]
+    fn value(&self) -> u32 {
+        self.hash.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{get_configured_spamsum, SpamsumOptions};
+
+    #[test]
+    fn test_chunk_boundaries_match_spamsum() {
+        let input: Vec<u8> = (0..5000u32).map(|i| (i * i % 251) as u8).collect();
+        for &blocksize in [3, 12, 48].iter() {
+            let boundaries: Vec<usize> = chunk_boundaries(&input, blocksize).collect();
+            assert!(boundaries.windows(2).all(|pair| pair[0] < pair[1]));
+            let options = SpamsumOptions::builder()
+                .blocksize(blocksize)
+                .build()
+                .unwrap();
+            let spamsum = get_configured_spamsum(&input, options).unwrap();
+            // one character per chunk, plus one for what is left at the end
+            let leftover = boundaries.last() != Some(&input.len());
+            assert_eq!(
+                spamsum.left_hash().len(),
+                (boundaries.len() + leftover as usize).min(64)
+            );
+        }
+    }
+
+    #[test]
+    fn test_chunk_hash_matches_spamsum() {
+        let input = b"Please buy my stuff\nDear Sir or Madam\n";
+        let alphabet = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+        let mut start = 0;
+        let mut hash = String::new();
+        for end in chunk_boundaries(input, 3).chain(Some(input.len())) {
+            hash.push(alphabet[(chunk_hash(&input[start..end]) % 64) as usize] as char);
+            start = end;
+        }
+        assert_eq!(hash, "clclDDvWIMF/hv");
+    }
+
+    #[test]
+    fn test_chunk_boundaries_empty_input() {
+        assert_eq!(chunk_boundaries(b"", 3).next(), None);
+    }
+}
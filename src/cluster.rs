@@ -0,0 +1,602 @@
+use std::cmp;
+use std::collections::HashMap;
+
+use crate::compare::compare_normalized;
+use crate::ngram::NgramIndex;
+#[cfg(feature = "minhash")]
+use crate::{mix, LshOptions, MinHash};
+use crate::{FuzzyHash, Spamsum};
+
+/// Groups the spamsums into connected components, where two spamsums are
+/// connected if they score at least `min_score` against each other.
+///
+/// Returns the indices of the spamsums in each group. Groups are ordered by
+/// their lowest index and contain their indices in ascending order, and every
+/// spamsum is part of exactly one group.
+///
+/// Instead of comparing every pair, only spamsums that share a substring (and
+/// can therefore score above 0) are compared, and none within the same group,
+/// so this scales to large corpora of mostly unrelated spamsums.
+///
+/// ```
+/// use spamsum::{connected_components, get_spamsum};
+///
+/// let spamsums = vec![
+///     get_spamsum(b"Hello").unwrap(),
+///     get_spamsum(b"Goodbye").unwrap(),
+///     get_spamsum(b"Hello").unwrap(),
+/// ];
+/// assert_eq!(
+///     connected_components(&spamsums, 50),
+///     vec![vec![0, 2], vec![1]]
+/// );
+/// ```
+pub fn connected_components(spamsums: &[Spamsum], min_score: u32) -> Vec<Vec<usize>> {
+    let min_score = cmp::max(min_score, 1);
+    let normalized: Vec<Spamsum> = spamsums.iter().map(Spamsum::normalized).collect();
+    let mut index = NgramIndex::default();
+    let mut components = DisjointSet::new(spamsums.len());
+    for (i, spamsum) in normalized.iter().enumerate() {
+        for j in index.candidates(spamsum) {
+            if components.find(i) == components.find(j) {
+                continue;
+            }
+            if compare_normalized(spamsum, &normalized[j], Default::default(), min_score)
+                >= min_score
+            {
+                components.union(i, j);
+            }
+        }
+        index.insert(i, spamsum);
+    }
+    components.groups()
+}
+
+/// How the similarity of two clusters is derived from the scores of their
+/// spamsums in `hierarchical_clustering()`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Linkage {
+    /// The best score of any pair, which chains similar spamsums together.
+    Single,
+    /// The worst score of any pair, so every spamsum in a cluster is similar
+    /// to every other.
+    Complete,
+    /// The average score of all pairs.
+    Average,
+}
+
+/// One step of `hierarchical_clustering()`, joining two clusters.
+///
+/// Clusters are identified like in SciPy: the spamsums are the clusters `0`
+/// to `n - 1`, and the cluster created by the `k`-th merge is `n + k`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Merge {
+    pub left: usize,
+    pub right: usize,
+    /// The similarity of both clusters as a score from 0 to 100, which is
+    /// fractional for average linkage.
+    pub similarity: f64,
+    /// The number of spamsums in the merged cluster.
+    pub size: usize,
+}
+
+/// The result of `hierarchical_clustering()`: all merges, from the most
+/// similar clusters to the least similar.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Dendrogram {
+    len: usize,
+    merges: Vec<Merge>,
+}
+
+impl Dendrogram {
+    /// Returns the number of clustered spamsums.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the `len() - 1` merges, ordered by descending similarity.
+    pub fn merges(&self) -> &[Merge] {
+        &self.merges
+    }
+
+    /// Returns the clusters formed by all merges with a similarity of at
+    /// least `min_similarity`, in the same format as `connected_components()`.
+    pub fn cut(&self, min_similarity: f64) -> Vec<Vec<usize>> {
+        let mut clusters = DisjointSet::new(self.len);
+        // any spamsum of each cluster, to merge clusters by their spamsums
+        let mut representatives: Vec<usize> = (0..self.len).collect();
+        for merge in &self.merges {
+            if merge.similarity < min_similarity {
+                break;
+            }
+            let (left, right) = (representatives[merge.left], representatives[merge.right]);
+            representatives.push(clusters.union(left, right));
+        }
+        clusters.groups()
+    }
+}
+
+/// Clusters the spamsums bottom-up, starting with each spamsum in its own
+/// cluster and repeatedly merging the two most similar clusters.
+///
+/// The resulting `Dendrogram` can be cut at different similarities to get
+/// coarser or finer clusters without clustering again. This needs memory
+/// quadratic in the number of spamsums, so use `connected_components()` for
+/// large corpora.
+///
+/// ```
+/// use spamsum::{get_spamsum, hierarchical_clustering, Linkage};
+///
+/// let spamsums = vec![
+///     get_spamsum(b"Hello").unwrap(),
+///     get_spamsum(b"Goodbye").unwrap(),
+///     get_spamsum(b"Hello").unwrap(),
+/// ];
+/// let dendrogram = hierarchical_clustering(&spamsums, Linkage::Average);
+/// assert_eq!(dendrogram.cut(50.0), vec![vec![0, 2], vec![1]]);
+/// assert_eq!(dendrogram.cut(0.0), vec![vec![0, 1, 2]]);
+/// ```
+pub fn hierarchical_clustering(spamsums: &[Spamsum], linkage: Linkage) -> Dendrogram {
+    let len = spamsums.len();
+    let mut similarities = vec![0.0; len * len];
+    let normalized: Vec<Spamsum> = spamsums.iter().map(Spamsum::normalized).collect();
+    let mut index = NgramIndex::default();
+    for (i, spamsum) in normalized.iter().enumerate() {
+        // all other pairs score 0
+        for j in index.candidates(spamsum) {
+            let score = f64::from(compare_normalized(
+                spamsum,
+                &normalized[j],
+                Default::default(),
+                1,
+            ));
+            similarities[i * len + j] = score;
+            similarities[j * len + i] = score;
+        }
+        index.insert(i, spamsum);
+    }
+    cluster(len, similarities, linkage)
+}
+
+/// Like `connected_components()`, but for hashes of any algorithm, which are
+/// connected if their `similarity()` is at least `min_similarity`.
+///
+/// Other hashes than spamsums are not indexed, so every pair of hashes in
+/// different groups is compared.
+///
+/// ```
+/// use spamsum::{fuzzy_connected_components, get_spamsum};
+///
+/// let spamsums = vec![
+///     get_spamsum(b"Hello").unwrap(),
+///     get_spamsum(b"Goodbye").unwrap(),
+///     get_spamsum(b"Hello").unwrap(),
+/// ];
+/// assert_eq!(
+///     fuzzy_connected_components(&spamsums, 50),
+///     vec![vec![0, 2], vec![1]]
+/// );
+/// ```
+pub fn fuzzy_connected_components<H: FuzzyHash>(
+    hashes: &[H],
+    min_similarity: u32,
+) -> Vec<Vec<usize>> {
+    let min_similarity = cmp::max(min_similarity, 1);
+    let mut components = DisjointSet::new(hashes.len());
+    for (i, hash) in hashes.iter().enumerate() {
+        for (j, other) in hashes[..i].iter().enumerate() {
+            if components.find(i) != components.find(j) && hash.similarity(other) >= min_similarity
+            {
+                components.union(i, j);
+            }
+        }
+    }
+    components.groups()
+}
+
+/// Like `hierarchical_clustering()`, but for hashes of any algorithm, with
+/// their `similarity()` as the similarity of two inputs.
+pub fn fuzzy_hierarchical_clustering<H: FuzzyHash>(hashes: &[H], linkage: Linkage) -> Dendrogram {
+    let len = hashes.len();
+    let mut similarities = vec![0.0; len * len];
+    for (i, hash) in hashes.iter().enumerate() {
+        for (j, other) in hashes[..i].iter().enumerate() {
+            let similarity = f64::from(hash.similarity(other));
+            similarities[i * len + j] = similarity;
+            similarities[j * len + i] = similarity;
+        }
+    }
+    cluster(len, similarities, linkage)
+}
+
+/// Groups inputs into connected components by their `MinHash` signatures,
+/// where two inputs are connected if their estimated Jaccard similarity is at
+/// least `min_jaccard`. Returns groups in the same format as
+/// `connected_components()`.
+///
+/// Signatures are split into bands, and only signatures with all hash values
+/// of a band in common are compared. The bands are chosen to find pairs with
+/// a similarity of `min_jaccard` with a probability of at least 99%, see
+/// `LshOptions`, so this scales to very large corpora.
+///
+/// ```
+/// use spamsum::{get_minhash, minhash_connected_components, MinHash};
+///
+/// let minhashes: Vec<MinHash> = [&b"Hello, World"[..], b"Goodbye", b"Hello, World!"]
+///     .iter()
+///     .map(|input| get_minhash(input, Default::default(), Default::default()).unwrap())
+///     .collect();
+/// assert_eq!(
+///     minhash_connected_components(&minhashes, 0.5),
+///     vec![vec![0, 2], vec![1]]
+/// );
+/// ```
+#[cfg(feature = "minhash")]
+pub fn minhash_connected_components(minhashes: &[MinHash], min_jaccard: f64) -> Vec<Vec<usize>> {
+    let len = minhashes
+        .iter()
+        .map(|minhash| minhash.signature().len())
+        .min()
+        .unwrap_or(0);
+    // with single rows, any pair with a common hash value shares a band
+    let mut lsh = LshOptions {
+        bands: len as u32,
+        rows: 1,
+    };
+    for rows in 2..=len as u32 {
+        let options = LshOptions {
+            bands: len as u32 / rows,
+            rows,
+        };
+        if options.recall(min_jaccard) >= 0.99 {
+            lsh = options;
+        }
+    }
+    let mut buckets: HashMap<(u32, u64), Vec<usize>> = HashMap::new();
+    let mut components = DisjointSet::new(minhashes.len());
+    for (i, minhash) in minhashes.iter().enumerate() {
+        let keys: Vec<(u32, u64)> = minhash.signature()[..(lsh.bands * lsh.rows) as usize]
+            .chunks(lsh.rows as usize)
+            .enumerate()
+            .map(|(band, rows)| {
+                (
+                    band as u32,
+                    rows.iter().fold(0, |hash, &row| mix(hash ^ row)),
+                )
+            })
+            .collect();
+        let mut candidates: Vec<usize> = keys
+            .iter()
+            .filter_map(|key| buckets.get(key))
+            .flatten()
+            .copied()
+            .collect();
+        candidates.sort_unstable();
+        candidates.dedup();
+        for j in candidates {
+            if components.find(i) != components.find(j)
+                && minhash.jaccard(&minhashes[j]) >= min_jaccard
+            {
+                components.union(i, j);
+            }
+        }
+        for key in keys {
+            buckets.entry(key).or_default().push(i);
+        }
+    }
+    components.groups()
+}
+
+/// Like `hierarchical_clustering()`, but for `MinHash` signatures, with the
+/// estimated Jaccard similarity in percent as the similarity of two inputs.
+///
+/// This compares every pair of signatures, so it is meant for corpora that
+/// have already been split up, e.g. by `minhash_connected_components()`.
+#[cfg(feature = "minhash")]
+pub fn minhash_hierarchical_clustering(minhashes: &[MinHash], linkage: Linkage) -> Dendrogram {
+    let len = minhashes.len();
+    let mut similarities = vec![0.0; len * len];
+    for i in 0..len {
+        for j in 0..i {
+            let similarity = 100.0 * minhashes[i].jaccard(&minhashes[j]);
+            similarities[i * len + j] = similarity;
+            similarities[j * len + i] = similarity;
+        }
+    }
+    cluster(len, similarities, linkage)
+}
+
+// Clusters `len` elements by their `similarities`, a `len * len` matrix of
+// scores from 0 to 100.
+fn cluster(len: usize, mut similarities: Vec<f64>, linkage: Linkage) -> Dendrogram {
+    // the nearest-neighbor chain algorithm: follow the most similar clusters
+    // until two clusters are each other's most similar one, then merge them
+    // into the slot of the second one
+    let mut active = vec![true; len];
+    let mut sizes = vec![1; len];
+    let mut chain: Vec<usize> = Vec::new();
+    // (slot, slot, similarity), in the order they were found
+    let mut slot_merges = Vec::with_capacity(len.saturating_sub(1));
+    while slot_merges.len() + 1 < len {
+        if chain.is_empty() {
+            chain.push(active.iter().position(|&active| active).unwrap());
+        }
+        let a = chain[chain.len() - 1];
+        // prefer the previous cluster of the chain on ties, so it terminates
+        let previous = if chain.len() >= 2 {
+            Some(chain[chain.len() - 2])
+        } else {
+            None
+        };
+        let mut best = previous;
+        for b in (0..len).filter(|&b| active[b] && b != a) {
+            let is_better = match best {
+                Some(best) => similarities[a * len + b] > similarities[a * len + best],
+                None => true,
+            };
+            if is_better {
+                best = Some(b);
+            }
+        }
+        let b = best.unwrap();
+        if Some(b) != previous {
+            chain.push(b);
+            continue;
+        }
+        chain.truncate(chain.len() - 2);
+        slot_merges.push((a, b, similarities[a * len + b]));
+        active[a] = false;
+        for k in (0..len).filter(|&k| active[k] && k != b) {
+            let (from_a, from_b) = (similarities[a * len + k], similarities[b * len + k]);
+            let similarity = match linkage {
+                Linkage::Single => from_a.max(from_b),
+                Linkage::Complete => from_a.min(from_b),
+                Linkage::Average => {
+                    (sizes[a] as f64 * from_a + sizes[b] as f64 * from_b)
+                        / (sizes[a] + sizes[b]) as f64
+                }
+            };
+            similarities[b * len + k] = similarity;
+            similarities[k * len + b] = similarity;
+        }
+        sizes[b] += sizes[a];
+    }
+
+    // the chain finds merges out of order, so sort and number them
+    slot_merges.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap());
+    let mut clusters = DisjointSet::new(len);
+    let mut cluster_ids: Vec<usize> = (0..len).collect();
+    let mut merges = Vec::with_capacity(slot_merges.len());
+    for &(a, b, similarity) in &slot_merges {
+        let (root_a, root_b) = (clusters.find(a), clusters.find(b));
+        let (id_a, id_b) = (cluster_ids[root_a], cluster_ids[root_b]);
+        let root = clusters.union(root_a, root_b);
+        cluster_ids[root] = len + merges.len();
+        merges.push(Merge {
+            left: cmp::min(id_a, id_b),
+            right: cmp::max(id_a, id_b),
+            similarity,
+            size: clusters.sizes[root],
+        });
+    }
+    Dendrogram { len, merges }
+}
+
+// A union-find structure with path halving and union by size.
+struct DisjointSet {
+    parents: Vec<usize>,
+    sizes: Vec<usize>,
+}
+
+impl DisjointSet {
+    fn new(len: usize) -> DisjointSet {
+        DisjointSet {
+            parents: (0..len).collect(),
+            sizes: vec![1; len],
+        }
+    }
+
+    fn find(&mut self, mut element: usize) -> usize {
+        while self.parents[element] != element {
+            self.parents[element] = self.parents[self.parents[element]];
+            element = self.parents[element];
+        }
+        element
+    }
+
+    // Returns the elements of each set, ordered by their lowest element.
+    fn groups(&mut self) -> Vec<Vec<usize>> {
+        let mut groups: Vec<Vec<usize>> = Vec::new();
+        let mut group_of_root = HashMap::new();
+        for element in 0..self.parents.len() {
+            let root = self.find(element);
+            let group = *group_of_root.entry(root).or_insert_with(|| {
+                groups.push(Vec::new());
+                groups.len() - 1
+            });
+            groups[group].push(element);
+        }
+        groups
+    }
+
+    fn union(&mut self, a: usize, b: usize) -> usize {
+        let (a, b) = (self.find(a), self.find(b));
+        if a == b {
+            return a;
+        }
+        let (larger, smaller) = if self.sizes[a] >= self.sizes[b] {
+            (a, b)
+        } else {
+            (b, a)
+        };
+        self.parents[smaller] = larger;
+        self.sizes[larger] += self.sizes[smaller];
+        larger
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compare;
+
+    fn spamsums() -> Vec<Spamsum> {
+        [
+            "48:ABCDEFGHIJKLMNOP:ABCD",
+            "3:Hn:Hn",
+            "12:ABCDEFGHIJKLMNOP:ABCD",
+            "48:ABCDEFGHIJKLMNQR:WXYZ",
+            "24:QRSTUVWX:ABCDEFGHIJKLMNOP",
+            "3:Hn:Hn",
+            "96:ABCDEFGHIJKLMNOPQRSTU:ABCD",
+        ]
+        .iter()
+        .map(|s| s.parse().unwrap())
+        .collect()
+    }
+
+    #[test]
+    fn test_connected_components() {
+        let spamsums = spamsums();
+        assert_eq!(
+            connected_components(&spamsums, 1),
+            vec![vec![0, 3, 4], vec![1, 5], vec![2], vec![6]]
+        );
+        // 3 only scores 88 against 0, but 4 is connected to both
+        assert_eq!(compare(&spamsums[0], &spamsums[3]), 88);
+        assert_eq!(
+            connected_components(&spamsums, 90),
+            vec![vec![0, 4], vec![1, 5], vec![2], vec![3], vec![6]]
+        );
+        assert!(connected_components(&[], 1).is_empty());
+    }
+
+    #[test]
+    fn test_hierarchical_clustering() {
+        let spamsums: Vec<Spamsum> = [
+            "48:ABCDEFGHIJKLMNOP:A",
+            "48:ABCDEFGHIJKLMNQR:A",
+            "48:ABCDEFGHIJKLSTUV:A",
+            "48:XBCDEFGHIJKLSTUV:A",
+        ]
+        .iter()
+        .map(|s| s.parse().unwrap())
+        .collect();
+        let expected = [
+            (Linkage::Single, 75.0),
+            (Linkage::Complete, 69.0),
+            (Linkage::Average, 72.0),
+        ];
+        for &(linkage, similarity) in &expected {
+            let dendrogram = hierarchical_clustering(&spamsums, linkage);
+            assert_eq!(dendrogram.len(), 4);
+            assert_eq!(
+                dendrogram.merges(),
+                &[
+                    Merge {
+                        left: 2,
+                        right: 3,
+                        similarity: 94.0,
+                        size: 2
+                    },
+                    Merge {
+                        left: 0,
+                        right: 1,
+                        similarity: 88.0,
+                        size: 2
+                    },
+                    Merge {
+                        left: 4,
+                        right: 5,
+                        similarity,
+                        size: 4
+                    },
+                ]
+            );
+            assert_eq!(dendrogram.cut(90.0), vec![vec![0], vec![1], vec![2, 3]]);
+            assert_eq!(dendrogram.cut(80.0), vec![vec![0, 1], vec![2, 3]]);
+            assert_eq!(dendrogram.cut(similarity), vec![vec![0, 1, 2, 3]]);
+        }
+        assert!(hierarchical_clustering(&[], Linkage::Single).is_empty());
+    }
+
+    #[cfg(feature = "minhash")]
+    #[test]
+    fn test_minhash_clustering() {
+        use crate::get_minhash;
+
+        let texts = [
+            "Dear Sir or Madam, please buy my stuff at a very special price",
+            "Minutes of the meeting of the board of directors",
+            "Dear Sir or Madam, please buy my stuff at a very special price!",
+            "Minutes of the meeting of the board of directors, held on Monday",
+            "Dear Sir or Madam, please buy our stuff at a very special price",
+        ];
+        let minhashes: Vec<MinHash> = texts
+            .iter()
+            .map(|text| get_minhash(text.as_bytes(), Default::default(), Default::default()))
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let groups = minhash_connected_components(&minhashes, 0.5);
+        assert_eq!(groups, vec![vec![0, 2, 4], vec![1, 3]]);
+        for group in &groups {
+            for &i in group {
+                for other in groups.iter().filter(|&other| other != group) {
+                    for &j in other {
+                        assert!(minhashes[i].jaccard(&minhashes[j]) < 0.5);
+                    }
+                }
+            }
+        }
+        let dendrogram = minhash_hierarchical_clustering(&minhashes, Linkage::Average);
+        assert_eq!(dendrogram.len(), 5);
+        assert_eq!(
+            dendrogram.merges()[0].similarity,
+            100.0 * minhashes[0].jaccard(&minhashes[2])
+        );
+        assert_eq!(dendrogram.cut(50.0), groups);
+        assert!(minhash_connected_components(&[], 0.5).is_empty());
+    }
+
+    #[test]
+    fn test_fuzzy_clustering() {
+        let spamsums = spamsums();
+        for &min_score in &[1, 50, 88, 89, 100] {
+            assert_eq!(
+                fuzzy_connected_components(&spamsums, min_score),
+                connected_components(&spamsums, min_score)
+            );
+        }
+        for &linkage in &[Linkage::Single, Linkage::Complete, Linkage::Average] {
+            assert_eq!(
+                fuzzy_hierarchical_clustering(&spamsums, linkage),
+                hierarchical_clustering(&spamsums, linkage)
+            );
+        }
+        assert!(fuzzy_connected_components::<Spamsum>(&[], 1).is_empty());
+    }
+
+    #[test]
+    fn test_connected_components_matches_pairwise() {
+        let spamsums = spamsums();
+        for &min_score in &[1, 50, 88, 89, 100] {
+            let groups = connected_components(&spamsums, min_score);
+            for group in &groups {
+                for &i in group {
+                    for other in groups.iter().filter(|&other| other != group) {
+                        for &j in other {
+                            assert!(compare(&spamsums[i], &spamsums[j]) < min_score);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,527 @@
+use core::cmp;
+
+use crate::{
+    HashBuffer, Spamsum, LEFT_HASH_LENGTH, MAX_HASH_LENGTH, MIN_BLOCKSIZE, ROLLING_WINDOW,
+};
+
+// longer runs of identical characters are shortened to this length
+const MAX_SEQUENCE_LENGTH: usize = 3;
+// normalized hashes are written into stack buffers of this size when
+// comparing; longer hashes score 0 anyway, so the extra byte only marks
+// them as too long
+const NORMALIZED_BUFFER_LENGTH: usize = MAX_HASH_LENGTH as usize + 1;
+
+/// Options for `compare_with_options()`.
+#[derive(Copy, Clone, Default, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CompareOptions {
+    /// Score hashes even if they do not share a substring of
+    /// `ROLLING_WINDOW` (7) characters. ssdeep always requires one, as
+    /// unrelated short hashes otherwise get spurious scores.
+    pub skip_common_substring_check: bool,
+}
+
+impl Spamsum {
+    /// Returns the spamsum with every run of more than three identical
+    /// characters shortened to three characters.
+    ///
+    /// Long runs carry little information but would dominate the edit
+    /// distance, so `compare()` normalizes both spamsums first, like ssdeep.
+    pub fn normalized(&self) -> Spamsum {
+        Spamsum {
+            left_hash_blocksize: self.left_hash_blocksize,
+            left_hash: eliminate_sequences(&self.left_hash),
+            right_hash: eliminate_sequences(&self.right_hash),
+        }
+    }
+
+    /// Returns whether the blocksizes of both spamsums are equal or differ by
+    /// a factor of two.
+    ///
+    /// All other pairs always score 0, so this is a cheap way to filter
+    /// candidates before comparing them.
+    pub fn is_comparable_with(&self, other: &Spamsum) -> bool {
+        let blocksize = u64::from(self.left_hash_blocksize);
+        let other_blocksize = u64::from(other.left_hash_blocksize);
+        blocksize == other_blocksize
+            || blocksize == other.right_hash_blocksize()
+            || other_blocksize == self.right_hash_blocksize()
+    }
+}
+
+/// Compares two spamsums and returns a similarity score from 0 (no
+/// similarity) to 100 (identical).
+///
+/// The scoring follows spamsum/ssdeep, so scores are interoperable with
+/// existing tooling: only hashes whose blocksizes are equal or differ by a
+/// factor of two can be compared, everything else scores 0.
+pub fn compare(a: &Spamsum, b: &Spamsum) -> u32 {
+    compare_with_options(a, b, Default::default())
+}
+
+/// Compares two spamsums like `compare()`, but returns the similarity as a
+/// value from 0.0 to 1.0.
+///
+/// This is exactly the integer score divided by 100, so it only takes the
+/// values 0.00, 0.01, ..., 1.00 and carries the truncation of the integer
+/// scoring, e.g. a score of 94 is always 0.94.
+pub fn similarity_f64(a: &Spamsum, b: &Spamsum) -> f64 {
+    f64::from(compare(a, b)) / 100.0
+}
+
+/// Compares two spamsums like `compare()`, but with configurable scoring.
+pub fn compare_with_options(a: &Spamsum, b: &Spamsum, options: CompareOptions) -> u32 {
+    compare_with_min_score(a, b, options, 1)
+}
+
+/// Compares two spamsums like `compare()`, but only returns scores of at
+/// least `min_score`.
+///
+/// This is faster for "find anything that scores at least X" workloads, as
+/// the comparison stops as soon as `min_score` is out of reach.
+pub fn compare_with_cutoff(a: &Spamsum, b: &Spamsum, min_score: u32) -> Option<u32> {
+    let score = compare_with_min_score(a, b, Default::default(), cmp::max(min_score, 1));
+    if score >= min_score {
+        Some(score)
+    } else {
+        None
+    }
+}
+
+// Returns 0 as soon as it is clear that the score would be below `min_score`.
+fn compare_with_min_score(
+    a: &Spamsum,
+    b: &Spamsum,
+    options: CompareOptions,
+    min_score: u32,
+) -> u32 {
+    if !a.is_comparable_with(b) {
+        return 0;
+    }
+    // normalize into buffers on the stack instead of calling `normalized()`,
+    // so that comparing never allocates
+    let mut buffers = [[0; NORMALIZED_BUFFER_LENGTH]; 4];
+    let [left_a, right_a, left_b, right_b] = &mut buffers;
+    compare_hashes(
+        Hashes {
+            blocksize: u64::from(a.left_hash_blocksize),
+            left: eliminate_sequences_into(a.left_hash.as_bytes(), left_a),
+            right: eliminate_sequences_into(a.right_hash.as_bytes(), right_a),
+        },
+        Hashes {
+            blocksize: u64::from(b.left_hash_blocksize),
+            left: eliminate_sequences_into(b.left_hash.as_bytes(), left_b),
+            right: eliminate_sequences_into(b.right_hash.as_bytes(), right_b),
+        },
+        options,
+        min_score,
+    )
+}
+
+// The parts of a normalized spamsum that are compared.
+#[derive(Clone, Copy)]
+struct Hashes<'a> {
+    blocksize: u64,
+    left: &'a [u8],
+    right: &'a [u8],
+}
+
+// Like `compare_with_min_score()`, but for spamsums that are already
+// normalized, so batch comparisons only need to normalize each spamsum once.
+pub(crate) fn compare_normalized(
+    a: &Spamsum,
+    b: &Spamsum,
+    options: CompareOptions,
+    min_score: u32,
+) -> u32 {
+    compare_hashes(
+        Hashes {
+            blocksize: u64::from(a.left_hash_blocksize),
+            left: a.left_hash.as_bytes(),
+            right: a.right_hash.as_bytes(),
+        },
+        Hashes {
+            blocksize: u64::from(b.left_hash_blocksize),
+            left: b.left_hash.as_bytes(),
+            right: b.right_hash.as_bytes(),
+        },
+        options,
+        min_score,
+    )
+}
+
+fn compare_hashes(a: Hashes, b: Hashes, options: CompareOptions, min_score: u32) -> u32 {
+    if a.blocksize == b.blocksize {
+        if a.left == b.left && a.right == b.right {
+            return 100;
+        }
+        let left_score = score_hashes(a.left, b.left, a.blocksize, options, min_score);
+        // the right hashes only matter if they score even better
+        let right_score = score_hashes(
+            a.right,
+            b.right,
+            a.blocksize * 2,
+            options,
+            cmp::max(min_score, left_score + 1),
+        );
+        cmp::max(left_score, right_score)
+    } else if a.blocksize == b.blocksize * 2 {
+        score_hashes(a.left, b.right, a.blocksize, options, min_score)
+    } else if b.blocksize == a.blocksize * 2 {
+        score_hashes(a.right, b.left, b.blocksize, options, min_score)
+    } else {
+        0
+    }
+}
+
+fn eliminate_sequences(hash: &str) -> HashBuffer {
+    without_long_runs(hash.as_bytes()).map(char::from).collect()
+}
+
+// Writes the normalized hash into `buffer`, but at most
+// NORMALIZED_BUFFER_LENGTH characters of it.
+fn eliminate_sequences_into<'a>(
+    hash: &[u8],
+    buffer: &'a mut [u8; NORMALIZED_BUFFER_LENGTH],
+) -> &'a [u8] {
+    let mut length = 0;
+    for (slot, c) in buffer.iter_mut().zip(without_long_runs(hash)) {
+        *slot = c;
+        length += 1;
+    }
+    &buffer[..length]
+}
+
+fn without_long_runs(hash: &[u8]) -> impl Iterator<Item = u8> + '_ {
+    let mut run_length = 0;
+    let mut previous = None;
+    hash.iter().copied().filter(move |&c| {
+        run_length = if previous == Some(c) {
+            run_length + 1
+        } else {
+            1
+        };
+        previous = Some(c);
+        run_length <= MAX_SEQUENCE_LENGTH
+    })
+}
+
+fn has_common_substring(hash_a: &[u8], hash_b: &[u8]) -> bool {
+    let length = ROLLING_WINDOW as usize;
+    hash_a
+        .windows(length)
+        .any(|substring| hash_b.windows(length).any(|other| substring == other))
+}
+
+// Returns 0 as soon as it is clear that the score would be below `min_score`.
+fn score_hashes(
+    hash_a: &[u8],
+    hash_b: &[u8],
+    blocksize: u64,
+    options: CompareOptions,
+    min_score: u32,
+) -> u32 {
+    let (len_a, len_b) = (hash_a.len() as u32, hash_b.len() as u32);
+    if len_a == 0 || len_b == 0 || len_a > MAX_HASH_LENGTH || len_b > MAX_HASH_LENGTH {
+        return 0;
+    }
+    // small blocksizes produce many matches by chance, so don't let the
+    // score exceed what the hash lengths can justify
+    let limit = if blocksize >= u64::from((99 + ROLLING_WINDOW) / ROLLING_WINDOW * MIN_BLOCKSIZE) {
+        100
+    } else {
+        blocksize as u32 / MIN_BLOCKSIZE * cmp::min(len_a, len_b)
+    };
+    if limit < min_score {
+        return 0;
+    }
+    if !options.skip_common_substring_check && !has_common_substring(hash_a, hash_b) {
+        return 0;
+    }
+    let max_distance = match max_distance(min_score, len_a + len_b) {
+        Some(max_distance) => max_distance,
+        None => return 0,
+    };
+    match edit_distance_bounded(hash_a, hash_b, max_distance) {
+        Some(distance) => cmp::min(distance_score(distance, len_a + len_b), limit),
+        None => 0,
+    }
+}
+
+// scales the edit distance to a value between 0 and 100
+fn distance_score(distance: u32, total_length: u32) -> u32 {
+    let score = distance * LEFT_HASH_LENGTH / total_length;
+    let score = 100 * score / LEFT_HASH_LENGTH;
+    100u32.saturating_sub(score)
+}
+
+// The largest edit distance that still results in at least `min_score`.
+fn max_distance(min_score: u32, total_length: u32) -> Option<u32> {
+    if min_score > 100 {
+        return None;
+    }
+    // the score decreases with the distance, which is at most total_length
+    let (mut low, mut high) = (0, total_length);
+    while low < high {
+        let middle = (low + high).div_ceil(2);
+        if distance_score(middle, total_length) >= min_score {
+            low = middle;
+        } else {
+            high = middle - 1;
+        }
+    }
+    Some(low)
+}
+
+// Calculates the edit distance if it is at most `limit`.
+//
+// With a replacement costing as much as a removal plus an insertion, the
+// edit distance is len(a) + len(b) - 2 * LCS(a, b). The length of the longest
+// common subsequence is calculated bit-parallel in the style of Myers and
+// Hyyrö, using one bit of a u64 per character of `a`. Hashes longer than 64
+// characters, which only come from a larger `hash_length`, fall back to the
+// plain calculation.
+fn edit_distance_bounded(a: &[u8], b: &[u8], limit: u32) -> Option<u32> {
+    let total_length = (a.len() + b.len()) as u32;
+    if (a.len() as i64 - b.len() as i64).unsigned_abs() > u64::from(limit) {
+        return None;
+    }
+    if a.len() > 64 {
+        return Some(edit_distance(a, b)).filter(|&distance| distance <= limit);
+    }
+    let mut matches = [0u64; 256];
+    for (i, &c) in a.iter().enumerate() {
+        matches[c as usize] |= 1 << i;
+    }
+    // every zero bit of `v` within the length of `a` is part of the LCS
+    let mask = if a.len() == 64 {
+        !0
+    } else {
+        (1u64 << a.len()) - 1
+    };
+    let mut v: u64 = !0;
+    for (i, &c) in b.iter().enumerate() {
+        let u = v & matches[c as usize];
+        v = v.wrapping_add(u) | (v - u);
+        // each remaining character can extend the LCS by at most one, so
+        // stop as soon as the limit is out of reach
+        let remaining = (b.len() - i - 1) as u32;
+        let best_lcs = (!v & mask).count_ones() + remaining;
+        if total_length - 2 * best_lcs.min(a.len() as u32) > limit {
+            return None;
+        }
+    }
+    let distance = total_length - 2 * (!v & mask).count_ones();
+    Some(distance).filter(|&distance| distance <= limit)
+}
+
+// The plain edit distance calculation with the costs used by ssdeep, for
+// hashes of at most MAX_HASH_LENGTH characters.
+fn edit_distance(a: &[u8], b: &[u8]) -> u32 {
+    const INSERT_COST: u32 = 1;
+    const REMOVE_COST: u32 = 1;
+    const REPLACE_COST: u32 = 2;
+    let mut rows = [[0; MAX_HASH_LENGTH as usize + 1]; 2];
+    let [previous, current] = &mut rows;
+    let (mut previous, mut current) = (&mut previous[..=b.len()], &mut current[..=b.len()]);
+    for (j, cost) in previous.iter_mut().enumerate() {
+        *cost = j as u32 * INSERT_COST;
+    }
+    for (i, &char_a) in a.iter().enumerate() {
+        current[0] = (i as u32 + 1) * REMOVE_COST;
+        for (j, &char_b) in b.iter().enumerate() {
+            let replace_cost = if char_a == char_b { 0 } else { REPLACE_COST };
+            current[j + 1] = cmp::min(
+                cmp::min(previous[j + 1] + REMOVE_COST, current[j] + INSERT_COST),
+                previous[j] + replace_cost,
+            );
+        }
+        core::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pseudo_random_bytes;
+
+    fn spamsum(blocksize: u32, left_hash: &str, right_hash: &str) -> Spamsum {
+        Spamsum {
+            left_hash_blocksize: blocksize,
+            left_hash: HashBuffer::from(left_hash),
+            right_hash: HashBuffer::from(right_hash),
+        }
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance(b"", b""), 0);
+        assert_eq!(edit_distance(b"abc", b""), 3);
+        assert_eq!(edit_distance(b"", b"abc"), 3);
+        assert_eq!(edit_distance(b"abc", b"abd"), 2);
+        assert_eq!(edit_distance(b"abc", b"abxc"), 1);
+        assert_eq!(edit_distance(b"kitten", b"sitting"), 5);
+    }
+
+    #[test]
+    fn test_edit_distance_bounded() {
+        assert_eq!(edit_distance_bounded(b"abc", b"abd", 2), Some(2));
+        assert_eq!(edit_distance_bounded(b"abc", b"abd", 1), None);
+        assert_eq!(edit_distance_bounded(b"abc", b"", 3), Some(3));
+        assert_eq!(edit_distance_bounded(b"abcdef", b"ab", 3), None);
+        assert_eq!(edit_distance_bounded(b"", b"", 0), Some(0));
+    }
+
+    #[test]
+    fn test_edit_distance_bounded_matches_edit_distance() {
+        let mut bytes = pseudo_random_bytes(65 * 128, 1).into_iter();
+        let mut random_hash = |length: usize| -> Vec<u8> {
+            bytes
+                .by_ref()
+                .take(length)
+                .map(|c| b"ABCD"[usize::from(c % 4)])
+                .collect()
+        };
+        for length in 0..=64 {
+            let a = random_hash(length);
+            let b = random_hash(64 - length / 2);
+            let distance = edit_distance(&a, &b);
+            for limit in 0..(a.len() + b.len()) as u32 {
+                let expected = if distance <= limit {
+                    Some(distance)
+                } else {
+                    None
+                };
+                assert_eq!(edit_distance_bounded(&a, &b, limit), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_max_distance() {
+        for total_length in 2..=128 {
+            for min_score in 1..=100 {
+                let max = max_distance(min_score, total_length).unwrap();
+                assert!(distance_score(max, total_length) >= min_score);
+                if max < total_length {
+                    assert!(distance_score(max + 1, total_length) < min_score);
+                }
+            }
+        }
+        assert_eq!(max_distance(101, 10), None);
+    }
+
+    #[test]
+    fn test_normalized() {
+        let a = spamsum(3, "AAAAAABCCCDDDD", "EEEEEEEEF");
+        assert_eq!(a.normalized().to_string(), "3:AAABCCCDDD:EEEF");
+        let b = spamsum(3, "ABC", "");
+        assert_eq!(b.normalized(), b);
+    }
+
+    #[test]
+    fn test_compare_ignores_long_sequences() {
+        let a = spamsum(3, "clclDDDDDDvWIMF/hv", "cGZ/EJv");
+        let b = spamsum(3, "clclDDDvWIMF/hv", "cGZ/EJv");
+        assert_eq!(compare(&a, &b), 100);
+    }
+
+    #[test]
+    fn test_has_common_substring() {
+        assert!(has_common_substring(b"xxABCDEFGyy", b"zABCDEFGz"));
+        assert!(!has_common_substring(b"xxABCDEFyy", b"zABCDEFz"));
+        assert!(!has_common_substring(b"ABC", b"ABC"));
+    }
+
+    #[test]
+    fn test_compare_requires_common_substring() {
+        let a = spamsum(48, "ABCDEFxGHIJKLxMNOPQRx", "ABCD");
+        let b = spamsum(48, "ABCDEFyGHIJKLyMNOPQRy", "WXYZ");
+        assert_eq!(compare(&a, &b), 0);
+        let options = CompareOptions {
+            skip_common_substring_check: true,
+        };
+        assert_eq!(compare_with_options(&a, &b, options), 86);
+    }
+
+    #[test]
+    fn test_is_comparable_with() {
+        let a = spamsum(12, "ABCD", "EFGH");
+        assert!(a.is_comparable_with(&spamsum(12, "IJKL", "MNOP")));
+        assert!(a.is_comparable_with(&spamsum(6, "IJKL", "MNOP")));
+        assert!(a.is_comparable_with(&spamsum(24, "IJKL", "MNOP")));
+        assert!(!a.is_comparable_with(&spamsum(48, "ABCD", "EFGH")));
+        assert!(!a.is_comparable_with(&spamsum(3, "ABCD", "EFGH")));
+        let large = spamsum(3 << 30, "ABCD", "EFGH");
+        assert!(!large.is_comparable_with(&spamsum(3 << 29 | 1, "ABCD", "EFGH")));
+        assert!(large.is_comparable_with(&spamsum(3 << 29, "ABCD", "EFGH")));
+    }
+
+    #[test]
+    fn test_similarity_f64() {
+        let a = spamsum(48, "ABCDEFGHIJKLMNOP", "ABCD");
+        let b = spamsum(48, "ABCDEFGHIJKLMNOQ", "WXYZ");
+        assert_eq!(similarity_f64(&a, &b), 0.94);
+        assert_eq!(similarity_f64(&a, &a), 1.0);
+        assert_eq!(similarity_f64(&a, &spamsum(12, "ABCD", "EFGH")), 0.0);
+    }
+
+    #[test]
+    fn test_compare_with_cutoff() {
+        let a = spamsum(48, "ABCDEFGHIJKLMNOP", "ABCD");
+        let b = spamsum(48, "ABCDEFGHIJKLMNOQ", "WXYZ");
+        assert_eq!(compare_with_cutoff(&a, &b, 94), Some(94));
+        assert_eq!(compare_with_cutoff(&a, &b, 95), None);
+        assert_eq!(compare_with_cutoff(&a, &a, 100), Some(100));
+        let c = spamsum(12, "ABCDEFGHIJKLMNOP", "ABCD");
+        assert_eq!(compare_with_cutoff(&a, &c, 0), Some(0));
+        assert_eq!(compare_with_cutoff(&a, &c, 1), None);
+    }
+
+    #[test]
+    fn test_compare_identical() {
+        let a = spamsum(3, "clclDDvWIMF/hv", "cGZ/EJv");
+        assert_eq!(compare(&a, &a), 100);
+    }
+
+    #[test]
+    fn test_compare_incompatible_blocksizes() {
+        let a = spamsum(3, "clclDDvWIMF/hv", "cGZ/EJv");
+        let b = spamsum(12, "clclDDvWIMF/hv", "cGZ/EJv");
+        assert_eq!(compare(&a, &b), 0);
+    }
+
+    #[test]
+    fn test_compare_similar() {
+        let a = spamsum(48, "ABCDEFGHIJKLMNOP", "ABCD");
+        let b = spamsum(48, "ABCDEFGHIJKLMNOQ", "WXYZ");
+        assert_eq!(compare(&a, &b), 94);
+        assert_eq!(compare(&b, &a), 94);
+    }
+
+    #[test]
+    fn test_compare_small_blocksize_is_capped() {
+        let a = spamsum(3, "ABCDEFGHIJKLMNOP", "ABCD");
+        let b = spamsum(3, "ABCDEFGHIJKLMNOQ", "WXYZ");
+        assert_eq!(compare(&a, &b), 16);
+    }
+
+    #[test]
+    fn test_compare_double_blocksize() {
+        let a = spamsum(96, "ABCDEFGHIJKLMNOP", "ABCD");
+        let b = spamsum(48, "ZZZZ", "ABCDEFGHIJKLMNOQ");
+        assert_eq!(compare(&a, &b), 94);
+        assert_eq!(compare(&b, &a), 94);
+    }
+
+    #[test]
+    fn test_compare_too_long_hashes() {
+        let long_hash =
+            "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/".repeat(5);
+        let a = spamsum(96, &long_hash, "ABCD");
+        let b = spamsum(96, &long_hash.replace('Q', "q"), "WXYZ");
+        assert_eq!(compare(&a, &b), 0);
+        assert_eq!(compare(&a, &a), 100);
+    }
+}
@@ -0,0 +1,137 @@
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use crate::explain::chunks;
+use crate::{get_spamsum, SpamsumError};
+
+/// The result of `containment()`: how much of a fragment was found in an
+/// input, and where.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Containment {
+    /// The percentage of the inner chunks of the fragment, by their length,
+    /// that were found in the input in the same order, from 0 to 100.
+    pub score: u32,
+    /// The byte range of the input from the first to the last chunk that
+    /// was found, if any.
+    pub range: Option<Range<usize>>,
+}
+
+/// Checks whether `fragment` is embedded in the (usually much larger)
+/// `input`, e.g. a macro or a payload in different documents.
+///
+/// Comparing spamsums fails for this, as the spamsum of the input has a
+/// much larger blocksize than the one of the fragment and hardly any of its
+/// characters stem from the fragment. Instead, both are split into chunks at
+/// the blocksize of the spamsum of the fragment, and the chunks of the
+/// fragment are looked up in those of the input, in order. Chunk boundaries
+/// only depend on the few bytes before them, so they are the same in the
+/// fragment and the input, except for the first and the last chunk of the
+/// fragment, which continue in the input. Those are left out, so fragments
+/// with less than three chunks always score 0. Preprocessing options are not
+/// applied, so that the range refers to the input as it is.
+///
+/// ```
+/// use spamsum::containment;
+///
+/// let fragment: Vec<u8> = (0..2_000u32).map(|i| (i * i % 251) as u8).collect();
+/// let mut input: Vec<u8> = (0..50_000u32).map(|i| (i * 7 % 253) as u8).collect();
+/// input.splice(20_000..20_000, fragment.iter().copied());
+///
+/// let contained = containment(&fragment, &input).unwrap();
+/// assert_eq!(contained.score, 100);
+/// assert!(contained.range.unwrap().start >= 20_000);
+/// ```
+pub fn containment(fragment: &[u8], input: &[u8]) -> Result<Containment, SpamsumError> {
+    let blocksize = get_spamsum(fragment)?.blocksize();
+    let fragment_chunks = chunks(fragment, blocksize);
+    if fragment_chunks.len() < 3 {
+        return Ok(Containment {
+            score: 0,
+            range: None,
+        });
+    }
+    let inner_chunks = &fragment_chunks[1..fragment_chunks.len() - 1];
+    let input_chunks = chunks(input, blocksize);
+    // the indices of the chunks of the input by their hash
+    let mut positions: BTreeMap<u32, Vec<usize>> = BTreeMap::new();
+    for (i, (_, hash)) in input_chunks.iter().enumerate() {
+        positions.entry(*hash).or_default().push(i);
+    }
+
+    // every chunk of the fragment is matched with the first chunk with the
+    // same hash after the previous match
+    let (mut found, mut total) = (0, 0);
+    let (mut first, mut last) = (None, None);
+    for (range, hash) in inner_chunks {
+        total += range.len();
+        let next = last.map_or(0, |last| last + 1);
+        let position = positions
+            .get(hash)
+            .and_then(|indices| indices.get(indices.partition_point(|&i| i < next)).copied());
+        if let Some(position) = position {
+            found += range.len();
+            first.get_or_insert(position);
+            last = Some(position);
+        }
+    }
+    Ok(Containment {
+        score: (found * 100 / total) as u32,
+        range: first
+            .zip(last)
+            .map(|(first, last)| input_chunks[first].0.start..input_chunks[last].0.end),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compare, pseudo_random_bytes};
+
+    #[test]
+    fn test_containment_embedded() {
+        let fragment = pseudo_random_bytes(3_000, 1);
+        for &(before, after) in &[(0, 100_000), (40_000, 60_000), (100_000, 0)] {
+            let mut container = pseudo_random_bytes(before, 2);
+            container.extend_from_slice(&fragment);
+            container.extend(pseudo_random_bytes(after, 3));
+            let contained = containment(&fragment, &container).unwrap();
+            assert_eq!(contained.score, 100);
+            let range = contained.range.unwrap();
+            assert!(range.start >= before && range.end <= before + fragment.len());
+            assert!(range.len() > fragment.len() / 2);
+            // whereas the spamsums have nothing in common
+            let spamsums = (get_spamsum(&fragment), get_spamsum(&container));
+            assert_eq!(compare(&spamsums.0.unwrap(), &spamsums.1.unwrap()), 0);
+        }
+    }
+
+    #[test]
+    fn test_containment_modified() {
+        let fragment = pseudo_random_bytes(3_000, 1);
+        let mut modified = fragment.clone();
+        modified.splice(1_500..1_600, pseudo_random_bytes(100, 4));
+        let mut container = pseudo_random_bytes(50_000, 2);
+        container.splice(20_000..20_000, modified);
+        let contained = containment(&fragment, &container).unwrap();
+        assert!(contained.score > 60 && contained.score < 100);
+    }
+
+    #[test]
+    fn test_containment_unrelated() {
+        let contained = containment(
+            &pseudo_random_bytes(3_000, 1),
+            &pseudo_random_bytes(100_000, 2),
+        )
+        .unwrap();
+        assert_eq!(
+            contained,
+            Containment {
+                score: 0,
+                range: None
+            }
+        );
+        assert_eq!(containment(b"", b"").unwrap().score, 0);
+    }
+}
@@ -0,0 +1,638 @@
+use std::cmp::{self, Reverse};
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+use std::io::{Read, Write};
+use std::str;
+
+use crate::compare::compare_normalized;
+use crate::lsh::LshIndex;
+use crate::ngram::NgramIndex;
+use crate::parse::parse_hash;
+use crate::{
+    check_blocksize, BloomFilter, HashBuffer, LshOptions, Spamsum, SpamsumError, MAX_HASH_LENGTH,
+};
+
+// The saved database format, all numbers little-endian:
+//
+// - the magic bytes, the format version (u32) and the number of ids (u64)
+// - for each entry: its id (u64), blocksize (u32), the lengths (u16) and bytes
+//   of the left and right hash and the length (u32) and bytes of the metadata
+// - a CRC-32 of everything before it (u32)
+//
+// Version 1 stored the hash lengths as u8 and is still read.
+const MAGIC: &[u8; 8] = b"SPAMSUM\0";
+const FORMAT_VERSION: u32 = 2;
+const HEADER_LENGTH: usize = 8 + 4 + 8;
+const CHECKSUM_LENGTH: usize = 4;
+
+/// An in-memory collection of spamsums, each with user metadata, that can be
+/// queried for similar spamsums.
+///
+/// Every inserted spamsum gets a new id, starting at 0. Ids are not reused
+/// after removal.
+///
+/// Like ssdeep, the database indexes every 7-character substring of the
+/// (normalized) hashes by blocksize. As `compare()` only scores hashes that
+/// share such a substring, queries only need to compare the usually few
+/// spamsums sharing one with the query instead of the whole database. For
+/// very large databases, `with_lsh()` trades some matches for even fewer
+/// comparisons.
+///
+/// ```
+/// use spamsum::{get_spamsum, SpamsumDatabase};
+///
+/// let mut database = SpamsumDatabase::new();
+/// let hello = database.insert(get_spamsum(b"Hello").unwrap(), "hello.txt");
+/// database.insert(get_spamsum(b"Goodbye").unwrap(), "goodbye.txt");
+///
+/// let matches = database.query(&get_spamsum(b"Hello").unwrap(), 50);
+/// assert_eq!(matches, vec![(hello, 100)]);
+/// assert_eq!(database.get(hello).unwrap().1, &"hello.txt");
+/// ```
+#[derive(Debug, Clone)]
+pub struct SpamsumDatabase<T = ()> {
+    // by id, without the removed ones
+    entries: BTreeMap<usize, Entry<T>>,
+    next_id: usize,
+    index: CandidateIndex,
+}
+
+// finds the spamsums a query needs to be compared with
+#[derive(Debug, Clone)]
+enum CandidateIndex {
+    Exact(NgramIndex),
+    Lsh(LshIndex),
+}
+
+impl CandidateIndex {
+    fn insert(&mut self, id: usize, spamsum: &Spamsum) {
+        match self {
+            CandidateIndex::Exact(index) => index.insert(id, spamsum),
+            CandidateIndex::Lsh(index) => index.insert(id, spamsum),
+        }
+    }
+
+    fn remove(&mut self, id: usize, spamsum: &Spamsum) {
+        match self {
+            CandidateIndex::Exact(index) => index.remove(id, spamsum),
+            CandidateIndex::Lsh(index) => index.remove(id, spamsum),
+        }
+    }
+
+    fn candidates(&self, spamsum: &Spamsum) -> Vec<usize> {
+        match self {
+            CandidateIndex::Exact(index) => index.candidates(spamsum),
+            CandidateIndex::Lsh(index) => index.candidates(spamsum),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Entry<T> {
+    spamsum: Spamsum,
+    // compared against queries, so it is only normalized once
+    normalized: Spamsum,
+    metadata: T,
+}
+
+impl<T> SpamsumDatabase<T> {
+    pub fn new() -> SpamsumDatabase<T> {
+        SpamsumDatabase {
+            entries: BTreeMap::new(),
+            next_id: 0,
+            index: CandidateIndex::Exact(NgramIndex::default()),
+        }
+    }
+
+    /// Creates a database whose queries only compare spamsums that are
+    /// likely to be similar, as configured by `options`, and may therefore
+    /// miss some matches.
+    ///
+    /// This index is not saved by `save()`, a loaded database always finds
+    /// all matches. Fails for options with no or too many MinHash values.
+    pub fn with_lsh(options: LshOptions) -> Result<SpamsumDatabase<T>, SpamsumError> {
+        Ok(SpamsumDatabase {
+            entries: BTreeMap::new(),
+            next_id: 0,
+            index: CandidateIndex::Lsh(LshIndex::new(options)?),
+        })
+    }
+
+    /// Adds the spamsum with its metadata and returns its id.
+    pub fn insert(&mut self, spamsum: Spamsum, metadata: T) -> usize {
+        let id = self.next_id;
+        let normalized = spamsum.normalized();
+        self.index.insert(id, &normalized);
+        self.entries.insert(
+            id,
+            Entry {
+                spamsum,
+                normalized,
+                metadata,
+            },
+        );
+        self.next_id += 1;
+        id
+    }
+
+    /// Returns the spamsum with the given id and its metadata, or `None` if
+    /// there is none (anymore).
+    pub fn get(&self, id: usize) -> Option<(&Spamsum, &T)> {
+        self.entries
+            .get(&id)
+            .map(|entry| (&entry.spamsum, &entry.metadata))
+    }
+
+    /// Removes the spamsum with the given id and returns it with its
+    /// metadata, or `None` if there is none (anymore).
+    pub fn remove(&mut self, id: usize) -> Option<(Spamsum, T)> {
+        let entry = self.entries.remove(&id)?;
+        self.index.remove(id, &entry.normalized);
+        Some((entry.spamsum, entry.metadata))
+    }
+
+    /// Returns the ids of all spamsums with a score of at least `min_score`
+    /// against `spamsum` as `(id, score)`, best first and ties by id.
+    pub fn query(&self, spamsum: &Spamsum, min_score: u32) -> Vec<(usize, u32)> {
+        let min_score = cmp::max(min_score, 1);
+        let spamsum = spamsum.normalized();
+        let mut matches = Vec::new();
+        for id in self.index.candidates(&spamsum) {
+            let normalized = &self.entries[&id].normalized;
+            let score = compare_normalized(&spamsum, normalized, Default::default(), min_score);
+            if score >= min_score {
+                matches.push((id, score));
+            }
+        }
+        matches.sort_by_key(|&(id, score)| (Reverse(score), id));
+        matches
+    }
+
+    /// Returns a `BloomFilter` of all spamsums in the database with the given
+    /// false positive rate.
+    pub fn bloom_filter(&self, false_positive_rate: f64) -> BloomFilter {
+        let mut filter = BloomFilter::new(self.len(), false_positive_rate);
+        for (_, entry) in self.iter_entries() {
+            filter.insert(&entry.normalized);
+        }
+        filter
+    }
+
+    /// Returns all spamsums with their ids and metadata, ordered by id.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &Spamsum, &T)> {
+        self.iter_entries()
+            .map(|(id, entry)| (id, &entry.spamsum, &entry.metadata))
+    }
+
+    /// Returns the number of spamsums in the database.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn iter_entries(&self) -> impl Iterator<Item = (usize, &Entry<T>)> {
+        self.entries.iter().map(|(&id, entry)| (id, entry))
+    }
+}
+
+impl<T: DatabaseMetadata> SpamsumDatabase<T> {
+    /// Writes the database in a compact binary format that can be read back
+    /// with `load()`, keeping all ids.
+    ///
+    /// Fails without writing anything for spamsums whose blocksize could not
+    /// have been calculated, e.g. parsed from "1:Hn:Hn".
+    ///
+    /// ```
+    /// use spamsum::{get_spamsum, SpamsumDatabase};
+    ///
+    /// let mut database = SpamsumDatabase::new();
+    /// database.insert(get_spamsum(b"Hello").unwrap(), String::from("hello.txt"));
+    /// let mut saved = Vec::new();
+    /// database.save(&mut saved).unwrap();
+    ///
+    /// let loaded = SpamsumDatabase::<String>::load(&saved[..]).unwrap();
+    /// assert_eq!(loaded.get(0), database.get(0));
+    /// ```
+    pub fn save<W: Write>(&self, mut writer: W) -> Result<(), SpamsumError> {
+        let mut data = Vec::new();
+        data.extend_from_slice(MAGIC);
+        data.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        data.extend_from_slice(&(self.next_id as u64).to_le_bytes());
+        for (id, entry) in self.iter_entries() {
+            let spamsum = &entry.spamsum;
+            check_saved_blocksize(spamsum.left_hash_blocksize)?;
+            data.extend_from_slice(&(id as u64).to_le_bytes());
+            data.extend_from_slice(&spamsum.left_hash_blocksize.to_le_bytes());
+            for hash in &[&spamsum.left_hash, &spamsum.right_hash] {
+                data.extend_from_slice(&(hash.len() as u16).to_le_bytes());
+                data.extend_from_slice(hash.as_bytes());
+            }
+            let metadata = entry.metadata.to_bytes();
+            data.extend_from_slice(&(metadata.len() as u32).to_le_bytes());
+            data.extend_from_slice(&metadata);
+        }
+        let checksum = crc32(&data);
+        data.extend_from_slice(&checksum.to_le_bytes());
+        writer.write_all(&data)?;
+        Ok(())
+    }
+
+    /// Reads a database written by `save()`.
+    pub fn load<R: Read>(mut reader: R) -> Result<SpamsumDatabase<T>, SpamsumError> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        if data.len() < HEADER_LENGTH + CHECKSUM_LENGTH || &data[..MAGIC.len()] != MAGIC {
+            return Err(SpamsumError::InvalidDatabase("not a spamsum database"));
+        }
+        let (data, checksum) = data.split_at(data.len() - CHECKSUM_LENGTH);
+        if crc32(data).to_le_bytes() != checksum {
+            return Err(SpamsumError::InvalidDatabase("checksum mismatch"));
+        }
+        let mut data = Cursor(&data[MAGIC.len()..]);
+        let version = data.read_u32()?;
+        if version != 1 && version != FORMAT_VERSION {
+            return Err(SpamsumError::InvalidDatabase("unsupported format version"));
+        }
+        let id_count: usize = data
+            .read_u64()?
+            .try_into()
+            .ok()
+            // the next id has to fit as well
+            .filter(|&id_count| id_count < usize::MAX)
+            .ok_or(SpamsumError::InvalidDatabase("invalid id"))?;
+        let mut database = SpamsumDatabase::new();
+        while !data.0.is_empty() {
+            let id = data.read_u64()?;
+            if id >= id_count as u64 || id < database.next_id as u64 {
+                return Err(SpamsumError::InvalidDatabase("invalid id"));
+            }
+            let blocksize = data.read_u32()?;
+            check_saved_blocksize(blocksize)?;
+            let left_hash = data.read_hash(version, MAX_HASH_LENGTH)?;
+            let right_hash = data.read_hash(version, MAX_HASH_LENGTH / 2)?;
+            let metadata_length = data.read_u32()? as usize;
+            let metadata = T::from_bytes(data.read_bytes(metadata_length)?)
+                .ok_or(SpamsumError::InvalidDatabase("invalid metadata"))?;
+            database.next_id = id as usize;
+            database.insert(
+                Spamsum {
+                    left_hash_blocksize: blocksize,
+                    left_hash,
+                    right_hash,
+                },
+                metadata,
+            );
+        }
+        // removed ids stay unused
+        database.next_id = id_count;
+        Ok(database)
+    }
+}
+
+/// Metadata that can be stored in a saved `SpamsumDatabase`.
+pub trait DatabaseMetadata: Sized {
+    fn to_bytes(&self) -> Vec<u8>;
+
+    /// Returns `None` if the bytes are not valid metadata.
+    fn from_bytes(bytes: &[u8]) -> Option<Self>;
+}
+
+impl DatabaseMetadata for () {
+    fn to_bytes(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.is_empty() {
+            Some(())
+        } else {
+            None
+        }
+    }
+}
+
+impl DatabaseMetadata for String {
+    fn to_bytes(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        String::from_utf8(bytes.to_vec()).ok()
+    }
+}
+
+impl DatabaseMetadata for Vec<u8> {
+    fn to_bytes(&self) -> Vec<u8> {
+        self.clone()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        Some(bytes.to_vec())
+    }
+}
+
+// Reads the numbers and strings of a saved database.
+struct Cursor<'a>(&'a [u8]);
+
+impl<'a> Cursor<'a> {
+    fn read_bytes(&mut self, length: usize) -> Result<&'a [u8], SpamsumError> {
+        if self.0.len() < length {
+            return Err(SpamsumError::InvalidDatabase("unexpected end of data"));
+        }
+        let (bytes, rest) = self.0.split_at(length);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    fn read_u32(&mut self) -> Result<u32, SpamsumError> {
+        Ok(u32::from_le_bytes(self.read_bytes(4)?.try_into().unwrap()))
+    }
+
+    fn read_u64(&mut self) -> Result<u64, SpamsumError> {
+        Ok(u64::from_le_bytes(self.read_bytes(8)?.try_into().unwrap()))
+    }
+
+    fn read_u16(&mut self) -> Result<u16, SpamsumError> {
+        Ok(u16::from_le_bytes(self.read_bytes(2)?.try_into().unwrap()))
+    }
+
+    fn read_hash(&mut self, version: u32, hash_length: u32) -> Result<HashBuffer, SpamsumError> {
+        let length = match version {
+            1 => self.read_bytes(1)?[0] as usize,
+            _ => self.read_u16()? as usize,
+        };
+        let hash = str::from_utf8(self.read_bytes(length)?)
+            .map_err(|_| SpamsumError::InvalidDatabase("invalid hash"))?;
+        parse_hash(hash, hash_length).map_err(|_| SpamsumError::InvalidDatabase("invalid hash"))
+    }
+}
+
+fn check_saved_blocksize(blocksize: u32) -> Result<(), SpamsumError> {
+    if blocksize == 0 || check_blocksize(blocksize).is_err() {
+        return Err(SpamsumError::InvalidDatabase("invalid blocksize"));
+    }
+    Ok(())
+}
+
+// CRC-32 as used by zlib and PNG.
+fn crc32(data: &[u8]) -> u32 {
+    static TABLE: [u32; 256] = crc32_table();
+    !data.iter().fold(!0, |crc, &byte| {
+        TABLE[((crc ^ u32::from(byte)) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                0xedb8_8320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+impl<T> Default for SpamsumDatabase<T> {
+    fn default() -> Self {
+        SpamsumDatabase::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        compare, get_configured_spamsum, get_spamsum, pseudo_random_bytes, SpamsumOptions,
+    };
+
+    fn database_entries() -> Vec<(&'static str, &'static str)> {
+        vec![
+            ("48:ABCDEFGHIJKLMNQR:WXYZ", "a"),
+            ("12:ABCDEFGHIJKLMNOP:ABCD", "b"),
+            ("48:ABCDEFGHIJKLMNOP:ABCD", "c"),
+            ("24:QRSTUVWX:ABCDEFGHIJKLMNOP", "d"),
+        ]
+    }
+
+    fn database() -> SpamsumDatabase<&'static str> {
+        let mut database = SpamsumDatabase::new();
+        for (hash, name) in database_entries() {
+            database.insert(hash.parse().unwrap(), name);
+        }
+        database
+    }
+
+    #[test]
+    fn test_query() {
+        let database = database();
+        let query = "48:ABCDEFGHIJKLMNOP:ABCD".parse().unwrap();
+        assert_eq!(database.query(&query, 1), vec![(2, 100), (3, 100), (0, 88)]);
+        assert_eq!(database.query(&query, 90), vec![(2, 100), (3, 100)]);
+        assert!(SpamsumDatabase::<()>::new().query(&query, 1).is_empty());
+    }
+
+    #[test]
+    fn test_query_matches_compare() {
+        let base: Vec<u8> = pseudo_random_bytes(20000, 7)
+            .into_iter()
+            .map(|c| b'a' + c % 16)
+            .collect();
+        let spamsums: Vec<Spamsum> = (0..24)
+            .map(|i| {
+                let mut input = base.clone();
+                input.truncate(base.len() - i * 300);
+                input[i * 500..i * 500 + i * 40].reverse();
+                get_spamsum(&input).unwrap()
+            })
+            .collect();
+        let mut database = SpamsumDatabase::new();
+        for spamsum in &spamsums {
+            database.insert(spamsum.clone(), ());
+        }
+        for id in (0..spamsums.len()).step_by(5) {
+            database.remove(id);
+        }
+        let mut matched = 0;
+        for query in &spamsums {
+            let mut expected: Vec<(usize, u32)> = database
+                .iter()
+                .map(|(id, spamsum, _)| (id, compare(query, spamsum)))
+                .filter(|&(_, score)| score >= 30)
+                .collect();
+            expected.sort_by_key(|&(id, score)| (Reverse(score), id));
+            matched += expected.len();
+            assert_eq!(database.query(query, 30), expected);
+        }
+        assert!(matched > spamsums.len());
+    }
+
+    #[test]
+    fn test_query_with_lsh() {
+        let query = "48:ABCDEFGHIJKLMNOP:ABCD".parse().unwrap();
+        let exact = database().query(&query, 1);
+        let mut database = SpamsumDatabase::with_lsh(LshOptions::default()).unwrap();
+        for (hash, name) in database_entries() {
+            database.insert(hash.parse().unwrap(), name);
+        }
+        let matches = database.query(&query, 1);
+        assert!(matches.contains(&(2, 100)));
+        assert!(matches.iter().all(|found| exact.contains(found)));
+        database.remove(2);
+        assert!(!database.query(&query, 1).contains(&(2, 100)));
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let mut database: SpamsumDatabase<String> = SpamsumDatabase::new();
+        for (hash, name) in database_entries() {
+            database.insert(hash.parse().unwrap(), String::from(name));
+        }
+        database.remove(0);
+        database.remove(3);
+        let mut saved = Vec::new();
+        database.save(&mut saved).unwrap();
+        let loaded = SpamsumDatabase::<String>::load(&saved[..]).unwrap();
+        let entries: Vec<_> = loaded.iter().collect();
+        assert_eq!(entries, database.iter().collect::<Vec<_>>());
+        assert_eq!(loaded.len(), 2);
+        let query = "48:ABCDEFGHIJKLMNOP:ABCD".parse().unwrap();
+        assert_eq!(loaded.query(&query, 1), database.query(&query, 1));
+        // removed ids stay unused
+        let mut loaded = loaded;
+        assert_eq!(loaded.insert(query, String::new()), 4);
+    }
+
+    #[test]
+    fn test_load_invalid() {
+        let mut database = SpamsumDatabase::new();
+        database.insert("3:Hn:Hn".parse().unwrap(), ());
+        let mut saved = Vec::new();
+        database.save(&mut saved).unwrap();
+        let load = |data: &[u8]| match SpamsumDatabase::<()>::load(data) {
+            Err(SpamsumError::InvalidDatabase(reason)) => reason,
+            _ => panic!("loaded an invalid database"),
+        };
+        assert_eq!(load(&saved[..10]), "not a spamsum database");
+        assert_eq!(load(&saved[1..]), "not a spamsum database");
+        let mut corrupted = saved.clone();
+        corrupted[HEADER_LENGTH + 3] ^= 1;
+        assert_eq!(load(&corrupted), "checksum mismatch");
+        let mut truncated = saved[..saved.len() - CHECKSUM_LENGTH - 1].to_vec();
+        truncated.extend_from_slice(&crc32(&truncated).to_le_bytes());
+        assert_eq!(load(&truncated), "unexpected end of data");
+        assert!(SpamsumDatabase::<Vec<u8>>::load(&saved[..]).is_ok());
+
+        // a huge number of (removed) ids is stored without any memory
+        let mut huge = MAGIC.to_vec();
+        huge.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        huge.extend_from_slice(&0x1300_0000_0000u64.to_le_bytes());
+        huge.extend_from_slice(&crc32(&huge).to_le_bytes());
+        assert_eq!(huge.len(), 24);
+        let mut loaded = SpamsumDatabase::<()>::load(&huge[..]).unwrap();
+        assert!(loaded.is_empty());
+        assert_eq!(
+            loaded.insert("3:Hn:Hn".parse().unwrap(), ()),
+            0x1300_0000_0000
+        );
+        assert!(SpamsumDatabase::<String>::load(&saved[..]).is_ok());
+
+        // the next insert would need an id after the last one
+        let mut huge = MAGIC.to_vec();
+        huge.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        huge.extend_from_slice(&u64::MAX.to_le_bytes());
+        huge.extend_from_slice(&crc32(&huge).to_le_bytes());
+        assert_eq!(load(&huge), "invalid id");
+
+        for &blocksize in &[0, 1, u32::MAX / 2 + 1] {
+            let mut invalid = saved[..HEADER_LENGTH + 8].to_vec();
+            invalid.extend_from_slice(&blocksize.to_le_bytes());
+            invalid.extend_from_slice(&saved[HEADER_LENGTH + 12..saved.len() - CHECKSUM_LENGTH]);
+            invalid.extend_from_slice(&crc32(&invalid).to_le_bytes());
+            assert_eq!(load(&invalid), "invalid blocksize");
+        }
+    }
+
+    #[test]
+    fn test_save_invalid_blocksize() {
+        let mut database = SpamsumDatabase::new();
+        database.insert("3:Hn:Hn".parse().unwrap(), ());
+        database.insert("1:Hn:Hn".parse().unwrap(), ());
+        let mut saved = Vec::new();
+        assert!(matches!(
+            database.save(&mut saved),
+            Err(SpamsumError::InvalidDatabase("invalid blocksize"))
+        ));
+        assert!(saved.is_empty());
+    }
+
+    #[test]
+    fn test_save_hash_length() {
+        let input = pseudo_random_bytes(50_000, 1);
+        for &hash_length in &[16, 64, 128, 256] {
+            let options = SpamsumOptions::builder()
+                .hash_length(hash_length)
+                .build()
+                .unwrap();
+            let spamsum = get_configured_spamsum(&input, options).unwrap();
+            let mut database = SpamsumDatabase::new();
+            database.insert(spamsum.clone(), ());
+            assert!(spamsum.left_hash().len() > hash_length as usize / 2);
+            let mut saved = Vec::new();
+            database.save(&mut saved).unwrap();
+            let loaded = SpamsumDatabase::<()>::load(&saved[..]).unwrap();
+            assert_eq!(loaded.get(0), Some((&spamsum, &())));
+        }
+    }
+
+    #[test]
+    fn test_load_version_1() {
+        let mut saved = MAGIC.to_vec();
+        saved.extend_from_slice(&1u32.to_le_bytes());
+        saved.extend_from_slice(&1u64.to_le_bytes());
+        saved.extend_from_slice(&0u64.to_le_bytes());
+        saved.extend_from_slice(&3u32.to_le_bytes());
+        saved.extend_from_slice(b"\x02Hn\x01H");
+        saved.extend_from_slice(&0u32.to_le_bytes());
+        saved.extend_from_slice(&crc32(&saved).to_le_bytes());
+        let loaded = SpamsumDatabase::<()>::load(&saved[..]).unwrap();
+        assert_eq!(loaded.get(0), Some((&"3:Hn:H".parse().unwrap(), &())));
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut database = database();
+        assert_eq!(database.len(), 4);
+        let (spamsum, name) = database.remove(2).unwrap();
+        assert_eq!(
+            (spamsum.to_string().as_str(), name),
+            ("48:ABCDEFGHIJKLMNOP:ABCD", "c")
+        );
+        assert_eq!(database.remove(2), None);
+        assert_eq!(database.remove(4), None);
+        assert_eq!(database.get(2), None);
+        assert_eq!(database.len(), 3);
+        assert_eq!(database.query(&spamsum, 1), vec![(3, 100), (0, 88)]);
+        assert_eq!(database.insert(spamsum, "e"), 4);
+        let names: Vec<(usize, &str)> = database.iter().map(|(id, _, &name)| (id, name)).collect();
+        assert_eq!(names, vec![(0, "a"), (1, "b"), (3, "d"), (4, "e")]);
+    }
+}
@@ -0,0 +1,99 @@
+use crate::{ChunkHasher, Spamsum, SpamsumError, SpamsumHasher};
+
+/// Incremental hashing in the shape of the `Digest` trait of the RustCrypto
+/// hashes, so that generic code can drive a fuzzy hash like it drives
+/// SHA-256.
+///
+/// Unlike cryptographic hashes, a fuzzy hash has no fixed output size and
+/// finalizing can fail, e.g. if the input is too large, so the output is a
+/// `Result` of the algorithm's own digest type.
+///
+/// ```
+/// use spamsum::{FuzzyDigest, SpamsumHasher};
+///
+/// fn hash_lines<D: FuzzyDigest>(mut digest: D, lines: &[&str]) -> D::Output {
+///     for line in lines {
+///         digest.update(line);
+///         digest.update("\n");
+///     }
+///     digest.finalize().unwrap()
+/// }
+///
+/// let lines = ["Please buy my stuff", "Dear Sir or Madam"];
+/// let spamsum = hash_lines(SpamsumHasher::new(), &lines);
+/// assert_eq!(spamsum.to_string(), "3:clclDDvWIMF/hv:cGZ/EJv");
+/// ```
+pub trait FuzzyDigest: Sized {
+    /// The digest the input is hashed to.
+    type Output;
+
+    /// Hashes the next chunk of input.
+    fn update(&mut self, data: impl AsRef<[u8]>);
+
+    /// Hashes the next chunk of input and returns the digest, for chaining.
+    fn chain_update(mut self, data: impl AsRef<[u8]>) -> Self {
+        self.update(data);
+        self
+    }
+
+    /// Returns the digest of all input so far.
+    fn finalize(self) -> Result<Self::Output, SpamsumError>;
+
+    /// Returns the digest of all input so far and resets the hasher for the
+    /// next input.
+    fn finalize_reset(&mut self) -> Result<Self::Output, SpamsumError>;
+
+    /// Starts over with a new input.
+    fn reset(&mut self);
+
+    /// Returns the digest of `data` with a default hasher.
+    fn digest(data: impl AsRef<[u8]>) -> Result<Self::Output, SpamsumError>
+    where
+        Self: Default,
+    {
+        Self::default().chain_update(data).finalize()
+    }
+}
+
+impl<H: ChunkHasher> FuzzyDigest for SpamsumHasher<H> {
+    type Output = Spamsum;
+
+    fn update(&mut self, data: impl AsRef<[u8]>) {
+        SpamsumHasher::update(self, data.as_ref());
+    }
+
+    fn finalize(self) -> Result<Spamsum, SpamsumError> {
+        SpamsumHasher::finalize(self)
+    }
+
+    fn finalize_reset(&mut self) -> Result<Spamsum, SpamsumError> {
+        SpamsumHasher::finalize_reset(self)
+    }
+
+    fn reset(&mut self) {
+        SpamsumHasher::reset(self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::get_spamsum;
+
+    #[test]
+    fn test_fuzzy_digest() {
+        let input = b"Please buy my stuff\nDear Sir or Madam\n";
+        let spamsum = get_spamsum(input).unwrap();
+        assert_eq!(
+            <SpamsumHasher as FuzzyDigest>::digest(input).unwrap(),
+            spamsum
+        );
+        let mut hasher = SpamsumHasher::new()
+            .chain_update(&input[..10])
+            .chain_update(&input[10..]);
+        assert_eq!(FuzzyDigest::finalize_reset(&mut hasher).unwrap(), spamsum);
+        FuzzyDigest::update(&mut hasher, b"Hello");
+        FuzzyDigest::reset(&mut hasher);
+        assert_eq!(FuzzyDigest::finalize(hasher).unwrap().to_string(), "3::");
+    }
+}
@@ -0,0 +1,99 @@
+use core::error::Error;
+use core::fmt;
+#[cfg(feature = "std")]
+use std::io;
+
+use crate::ParseSpamsumError;
+
+/// Errors that can occur while calculating or parsing spamsums.
+#[derive(Debug)]
+pub enum SpamsumError {
+    /// The configured blocksize cannot be used for hashing.
+    InvalidBlocksize(u32),
+    /// The configured hash length cannot be used for hashing.
+    InvalidHashLength(u32),
+    /// The input is longer than the largest blocksize can cover.
+    InputTooLarge(u64),
+    /// The input is longer than the configured `max_input_len`.
+    InputLimitExceeded(u64),
+    /// The input is too short or too uniform for a digest, e.g. for a TLSH
+    /// digest of less than 50 bytes.
+    InsufficientComplexity,
+    /// A spamsum string could not be parsed.
+    Parse(ParseSpamsumError),
+    /// Reading the input failed.
+    #[cfg(feature = "std")]
+    Io(io::Error),
+    /// A saved `SpamsumDatabase` is corrupt or in an unsupported format.
+    InvalidDatabase(&'static str),
+    /// Hashing or comparing was stopped because the caller cancelled it.
+    Cancelled,
+    /// Options other than the hashing options cannot be used, e.g.
+    /// `LshOptions` with too many MinHash values.
+    InvalidOptions(&'static str),
+}
+
+impl fmt::Display for SpamsumError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SpamsumError::InvalidBlocksize(blocksize) => {
+                write!(
+                    f,
+                    "invalid blocksize {}, expected 0 (automatic) or 3 to {}",
+                    blocksize,
+                    u32::MAX / 2
+                )
+            }
+            SpamsumError::InvalidHashLength(hash_length) => {
+                write!(
+                    f,
+                    "invalid hash length {}, expected 0 (standard) or 2 to 256",
+                    hash_length
+                )
+            }
+            SpamsumError::InputTooLarge(length) => {
+                write!(f, "input of {} bytes is too large", length)
+            }
+            SpamsumError::InputLimitExceeded(max_input_len) => {
+                write!(
+                    f,
+                    "input is longer than the limit of {} bytes",
+                    max_input_len
+                )
+            }
+            SpamsumError::InsufficientComplexity => {
+                write!(f, "input is too short or too uniform for a digest")
+            }
+            SpamsumError::Parse(e) => write!(f, "could not parse spamsum: {}", e),
+            #[cfg(feature = "std")]
+            SpamsumError::Io(e) => write!(f, "could not read input: {}", e),
+            SpamsumError::InvalidDatabase(reason) => write!(f, "invalid database: {}", reason),
+            SpamsumError::Cancelled => write!(f, "cancelled"),
+            SpamsumError::InvalidOptions(reason) => write!(f, "invalid options: {}", reason),
+        }
+    }
+}
+
+impl Error for SpamsumError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SpamsumError::Parse(e) => Some(e),
+            #[cfg(feature = "std")]
+            SpamsumError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<ParseSpamsumError> for SpamsumError {
+    fn from(e: ParseSpamsumError) -> SpamsumError {
+        SpamsumError::Parse(e)
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<io::Error> for SpamsumError {
+    fn from(e: io::Error) -> SpamsumError {
+        SpamsumError::Io(e)
+    }
+}
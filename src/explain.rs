@@ -0,0 +1,478 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp;
+use core::fmt;
+use core::ops::Range;
+
+use crate::{chunk_boundaries, chunk_hash, compare, get_spamsum, SpamsumError};
+
+/// A chunk of the inputs of `explain()`, by its byte range in the inputs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ChunkDiff {
+    /// A chunk with the same hash in both inputs.
+    Same {
+        first: Range<usize>,
+        second: Range<usize>,
+        hash: u32,
+    },
+    /// A chunk of the first input that is not in the second one.
+    Removed { first: Range<usize>, hash: u32 },
+    /// A chunk of the second input that is not in the first one.
+    Added { second: Range<usize>, hash: u32 },
+}
+
+/// The result of `explain()`: the chunks of both inputs, aligned like the
+/// lines of a diff.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Explanation {
+    /// The blocksize both inputs were split into chunks with.
+    pub blocksize: u32,
+    /// The score of the spamsums of both inputs.
+    pub score: u32,
+    /// All chunks of both inputs in the order of the inputs, with the
+    /// removed chunks before the added ones where both differ.
+    pub chunks: Vec<ChunkDiff>,
+}
+
+impl Explanation {
+    /// Returns the number of bytes of the first and of the second input
+    /// that are in chunks of both.
+    pub fn same_bytes(&self) -> (usize, usize) {
+        self.chunks
+            .iter()
+            .fold((0, 0), |(a, b), chunk| match chunk {
+                ChunkDiff::Same { first, second, .. } => (a + first.len(), b + second.len()),
+                _ => (a, b),
+            })
+    }
+}
+
+/// Writes one line per chunk: `=`, `-` or `+` for same, removed and added
+/// chunks, their byte ranges in the first and second input and their hash.
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "blocksize {}, score {}", self.blocksize, self.score)?;
+        let range = |range: &Range<usize>| alloc::format!("{}..{}", range.start, range.end);
+        for chunk in &self.chunks {
+            match chunk {
+                ChunkDiff::Same {
+                    first,
+                    second,
+                    hash,
+                } => writeln!(f, "=\t{}\t{}\t{:08x}", range(first), range(second), hash)?,
+                ChunkDiff::Removed { first, hash } => {
+                    writeln!(f, "-\t{}\t\t{:08x}", range(first), hash)?
+                }
+                ChunkDiff::Added { second, hash } => {
+                    writeln!(f, "+\t\t{}\t{:08x}", range(second), hash)?
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Explains the score of two inputs by the chunks they have in common.
+///
+/// Both inputs are split into chunks at the blocksize their spamsums are
+/// compared at, i.e. the larger of both blocksizes, and the chunks are
+/// aligned by their full 32 bit hashes, so the result shows which byte
+/// ranges of both inputs match, were removed or were added. Preprocessing
+/// options are not applied, so that the ranges refer to the inputs as they
+/// are.
+///
+/// ```
+/// use spamsum::{explain, ChunkDiff};
+///
+/// let explanation = explain(
+///     b"Please buy my stuff\nDear Sir or Madam\n",
+///     b"Please buy my things\nDear Sir or Madam\n",
+/// )
+/// .unwrap();
+/// assert_eq!(explanation.blocksize, 3);
+/// assert_eq!(
+///     explanation.chunks[0],
+///     ChunkDiff::Same { first: 0..3, second: 0..3, hash: 0xe9b0d61c }
+/// );
+/// assert!(explanation.chunks.iter().any(|chunk| matches!(chunk, ChunkDiff::Added { .. })));
+/// ```
+pub fn explain(first: &[u8], second: &[u8]) -> Result<Explanation, SpamsumError> {
+    let (first_spamsum, second_spamsum) = (get_spamsum(first)?, get_spamsum(second)?);
+    let blocksize = cmp::max(first_spamsum.blocksize(), second_spamsum.blocksize());
+    let first_chunks = chunks(first, blocksize);
+    let second_chunks = chunks(second, blocksize);
+
+    let first_hashes: Vec<u32> = first_chunks.iter().map(|&(_, hash)| hash).collect();
+    let second_hashes: Vec<u32> = second_chunks.iter().map(|&(_, hash)| hash).collect();
+    let mut diff = Diff::new(&first_hashes, &second_hashes);
+    diff.align(0..first_hashes.len(), 0..second_hashes.len());
+
+    let mut chunks = Vec::with_capacity(diff.edits.len());
+    let mut changed = 0;
+    for edit in diff.edits {
+        let chunk = match edit {
+            Edit::Same(i, j) => ChunkDiff::Same {
+                first: first_chunks[i].0.clone(),
+                second: second_chunks[j].0.clone(),
+                hash: first_chunks[i].1,
+            },
+            Edit::Removed(i) => ChunkDiff::Removed {
+                first: first_chunks[i].0.clone(),
+                hash: first_chunks[i].1,
+            },
+            Edit::Added(j) => ChunkDiff::Added {
+                second: second_chunks[j].0.clone(),
+                hash: second_chunks[j].1,
+            },
+        };
+        if let ChunkDiff::Same { .. } = chunk {
+            // removed chunks go first in every run of changed chunks
+            chunks[changed..].sort_by_key(|chunk| matches!(chunk, ChunkDiff::Added { .. }));
+            chunks.push(chunk);
+            changed = chunks.len();
+        } else {
+            chunks.push(chunk);
+        }
+    }
+    chunks[changed..].sort_by_key(|chunk| matches!(chunk, ChunkDiff::Added { .. }));
+    Ok(Explanation {
+        blocksize,
+        score: compare(&first_spamsum, &second_spamsum),
+        chunks,
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Edit {
+    Same(usize, usize),
+    Removed(usize),
+    Added(usize),
+}
+
+// Aligns two lists of hashes with the linear space variant of Myers' diff
+// algorithm, which takes O((n + m) d) time for d differences but only
+// O(n + m) memory, so that long inputs of (almost) identical chunks can be
+// explained as well.
+struct Diff<'a> {
+    first: &'a [u32],
+    second: &'a [u32],
+    // the furthest reaching paths forwards and backwards by diagonal, offset
+    // by `offset`
+    forward: Vec<usize>,
+    backward: Vec<usize>,
+    offset: isize,
+    edits: Vec<Edit>,
+}
+
+impl<'a> Diff<'a> {
+    fn new(first: &'a [u32], second: &'a [u32]) -> Diff<'a> {
+        let max_d = (first.len() + second.len()).div_ceil(2) + 1;
+        Diff {
+            first,
+            second,
+            forward: vec![0; 2 * max_d + 1],
+            backward: vec![0; 2 * max_d + 1],
+            offset: max_d as isize,
+            edits: Vec::with_capacity(cmp::max(first.len(), second.len())),
+        }
+    }
+
+    fn align(&mut self, mut first: Range<usize>, mut second: Range<usize>) {
+        let prefix = common_prefix(&self.first[first.clone()], &self.second[second.clone()]);
+        for k in 0..prefix {
+            self.edits
+                .push(Edit::Same(first.start + k, second.start + k));
+        }
+        first.start += prefix;
+        second.start += prefix;
+        let suffix = common_suffix(&self.first[first.clone()], &self.second[second.clone()]);
+        first.end -= suffix;
+        second.end -= suffix;
+
+        if first.is_empty() {
+            self.edits.extend(second.clone().map(Edit::Added));
+        } else if second.is_empty() {
+            self.edits.extend(first.clone().map(Edit::Removed));
+        } else {
+            let (x, y) = self.middle_snake(first.clone(), second.clone());
+            self.align(first.start..x, second.start..y);
+            self.align(x..first.end, y..second.end);
+        }
+        for k in 0..suffix {
+            self.edits.push(Edit::Same(first.end + k, second.end + k));
+        }
+    }
+
+    // Returns a point on an optimal path through the middle of the edit
+    // graph of both ranges, which both start and end with a difference.
+    fn middle_snake(&mut self, first: Range<usize>, second: Range<usize>) -> (usize, usize) {
+        let (n, m) = (first.len(), second.len());
+        let delta = n as isize - m as isize;
+        let odd = delta & 1 == 1;
+        let offset = self.offset;
+        let at = |k: isize| (k + offset) as usize;
+        self.forward[at(1)] = 0;
+        self.backward[at(1)] = 0;
+        let max_d = ((n + m).div_ceil(2) + 1) as isize;
+        for d in 0..max_d {
+            for k in (-d..=d).rev().step_by(2) {
+                let mut x =
+                    if k == -d || (k != d && self.forward[at(k - 1)] < self.forward[at(k + 1)]) {
+                        self.forward[at(k + 1)]
+                    } else {
+                        self.forward[at(k - 1)] + 1
+                    };
+                let y = (x as isize - k) as usize;
+                let (x0, y0) = (x, y);
+                if x < n && y < m {
+                    x += common_prefix(
+                        &self.first[first.start + x..first.end],
+                        &self.second[second.start + y..second.end],
+                    );
+                }
+                self.forward[at(k)] = x;
+                if odd
+                    && (k - delta).abs() < d
+                    && self.forward[at(k)] + self.backward[at(delta - k)] >= n
+                {
+                    return (first.start + x0, second.start + y0);
+                }
+            }
+            for k in (-d..=d).rev().step_by(2) {
+                let mut x =
+                    if k == -d || (k != d && self.backward[at(k - 1)] < self.backward[at(k + 1)]) {
+                        self.backward[at(k + 1)]
+                    } else {
+                        self.backward[at(k - 1)] + 1
+                    };
+                let mut y = (x as isize - k) as usize;
+                if x < n && y < m {
+                    let common = common_suffix(
+                        &self.first[first.start..first.end - x],
+                        &self.second[second.start..second.end - y],
+                    );
+                    x += common;
+                    y += common;
+                }
+                self.backward[at(k)] = x;
+                if !odd
+                    && (k - delta).abs() <= d
+                    && self.backward[at(k)] + self.forward[at(delta - k)] >= n
+                {
+                    return (first.end - x, second.end - y);
+                }
+            }
+        }
+        unreachable!("the paths always meet")
+    }
+}
+
+fn common_prefix(a: &[u32], b: &[u32]) -> usize {
+    a.iter().zip(b).take_while(|(a, b)| a == b).count()
+}
+
+fn common_suffix(a: &[u32], b: &[u32]) -> usize {
+    a.iter()
+        .rev()
+        .zip(b.iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count()
+}
+
+// Returns the chunks of the input and their hashes, including whatever
+// follows the last boundary, which spamsum hashes as well.
+pub(crate) fn chunks(input: &[u8], blocksize: u32) -> Vec<(Range<usize>, u32)> {
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    let end = if input.is_empty() {
+        None
+    } else {
+        Some(input.len())
+    };
+    for end in chunk_boundaries(input, blocksize).chain(end) {
+        if end > start {
+            chunks.push((start..end, chunk_hash(&input[start..end])));
+        }
+        start = end;
+    }
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pseudo_random_bytes;
+    use alloc::string::ToString;
+    use alloc::vec;
+
+    // Checks that the chunks of each input are contiguous and cover it.
+    fn assert_covers(explanation: &Explanation, first: &[u8], second: &[u8]) {
+        let (mut a, mut b) = (0, 0);
+        for chunk in &explanation.chunks {
+            match chunk {
+                ChunkDiff::Same { first, second, .. } => {
+                    assert_eq!((first.start, second.start), (a, b));
+                    a = first.end;
+                    b = second.end;
+                }
+                ChunkDiff::Removed { first, .. } => {
+                    assert_eq!(first.start, a);
+                    a = first.end;
+                }
+                ChunkDiff::Added { second, .. } => {
+                    assert_eq!(second.start, b);
+                    b = second.end;
+                }
+            }
+        }
+        assert_eq!((a, b), (first.len(), second.len()));
+    }
+
+    #[test]
+    fn test_explain_identical() {
+        let input = pseudo_random_bytes(10_000, 1);
+        let explanation = explain(&input, &input).unwrap();
+        assert_eq!(explanation.score, 100);
+        assert!(explanation
+            .chunks
+            .iter()
+            .all(|chunk| matches!(chunk, ChunkDiff::Same { .. })));
+        assert_eq!(explanation.same_bytes(), (input.len(), input.len()));
+        assert_covers(&explanation, &input, &input);
+    }
+
+    #[test]
+    fn test_explain_modified() {
+        let first = pseudo_random_bytes(10_000, 1);
+        let mut second = first.clone();
+        second.splice(5_000..5_100, pseudo_random_bytes(300, 2));
+        let explanation = explain(&first, &second).unwrap();
+        assert_covers(&explanation, &first, &second);
+        let (same, _) = explanation.same_bytes();
+        assert!(same > 8_000 && same < 10_000, "{}", same);
+        // the change is in the middle
+        let changed: Vec<&ChunkDiff> = explanation
+            .chunks
+            .iter()
+            .filter(|chunk| !matches!(chunk, ChunkDiff::Same { .. }))
+            .collect();
+        assert!(matches!(changed[0], ChunkDiff::Removed { first, .. } if first.start <= 5_000));
+        assert!(changed
+            .iter()
+            .any(|chunk| matches!(chunk, ChunkDiff::Added { second, .. } if second.end >= 5_300)));
+    }
+
+    #[test]
+    fn test_explain_unrelated() {
+        let (first, second) = (pseudo_random_bytes(5_000, 1), pseudo_random_bytes(7_000, 2));
+        let explanation = explain(&first, &second).unwrap();
+        assert_eq!(explanation.score, 0);
+        assert_eq!(explanation.same_bytes(), (0, 0));
+        assert_covers(&explanation, &first, &second);
+        assert!(explain(b"", b"").unwrap().chunks.is_empty());
+    }
+
+    #[test]
+    fn test_explain_long_repetitive_input() {
+        // almost 100,000 chunks of the same hash at blocksize 3, which
+        // are too many to align in quadratic memory
+        let first = vec![4u8; 100_000];
+        let explanation = explain(&first, &first).unwrap();
+        assert_eq!(explanation.blocksize, 3);
+        assert!(explanation.chunks.len() > 99_000);
+        assert_eq!(explanation.same_bytes(), (first.len(), first.len()));
+        let mut second = first.clone();
+        second[50_000] = 5;
+        let explanation = explain(&first, &second).unwrap();
+        assert_eq!(explanation.blocksize, 3);
+        assert_covers(&explanation, &first, &second);
+        let (same, _) = explanation.same_bytes();
+        assert!(same > 99_000, "{}", same);
+    }
+
+    // The length of the longest common subsequence, the slow way.
+    fn lcs_len(a: &[u32], b: &[u32]) -> usize {
+        let mut lengths = vec![vec![0; b.len() + 1]; a.len() + 1];
+        for i in (0..a.len()).rev() {
+            for j in (0..b.len()).rev() {
+                lengths[i][j] = if a[i] == b[j] {
+                    lengths[i + 1][j + 1] + 1
+                } else {
+                    cmp::max(lengths[i + 1][j], lengths[i][j + 1])
+                };
+            }
+        }
+        lengths[0][0]
+    }
+
+    #[test]
+    fn test_diff_is_optimal() {
+        let hashes = |length: usize, seed: u32| -> Vec<u32> {
+            pseudo_random_bytes(length, seed)
+                .iter()
+                .map(|c| u32::from(c % 4))
+                .collect()
+        };
+        for seed in 1..200 {
+            let a = hashes(seed as usize % 23, seed);
+            let b = hashes(seed as usize % 17, seed + 1000);
+            let mut diff = Diff::new(&a, &b);
+            diff.align(0..a.len(), 0..b.len());
+            // every index shows up once and in order
+            let (mut i, mut j, mut same) = (0, 0, 0);
+            for edit in diff.edits {
+                match edit {
+                    Edit::Same(x, y) => {
+                        assert_eq!((x, y), (i, j));
+                        assert_eq!(a[x], b[y]);
+                        i += 1;
+                        j += 1;
+                        same += 1;
+                    }
+                    Edit::Removed(x) => {
+                        assert_eq!(x, i);
+                        i += 1;
+                    }
+                    Edit::Added(y) => {
+                        assert_eq!(y, j);
+                        j += 1;
+                    }
+                }
+            }
+            assert_eq!((i, j), (a.len(), b.len()));
+            assert_eq!(same, lcs_len(&a, &b), "{:?} {:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn test_explanation_display() {
+        let explanation = Explanation {
+            blocksize: 3,
+            score: 50,
+            chunks: vec![
+                ChunkDiff::Same {
+                    first: 0..4,
+                    second: 0..4,
+                    hash: 1,
+                },
+                ChunkDiff::Removed {
+                    first: 4..6,
+                    hash: 0xabc,
+                },
+                ChunkDiff::Added {
+                    second: 4..9,
+                    hash: 0xdef,
+                },
+            ],
+        };
+        assert_eq!(
+            explanation.to_string(),
+            "blocksize 3, score 50\n\
+             =\t0..4\t0..4\t00000001\n\
+             -\t4..6\t\t00000abc\n\
+             +\t\t4..9\t00000def\n"
+        );
+    }
+}
@@ -0,0 +1,451 @@
+//! C functions with the signatures and semantics of libfuzzy, the ssdeep
+//! library, so that C and C++ programs can use this crate as a drop-in
+//! replacement.
+//!
+//! The functions are only exported by a C library, which is built with
+//!
+//! ```text
+//! cargo rustc --release --features ffi --crate-type cdylib
+//! ```
+//!
+//! or `--crate-type staticlib` for a static library. Programs written
+//! against `fuzzy.h` then link with `-lspamsum` instead of `-lfuzzy`. The
+//! header in `include/fuzzy.h` is generated with
+//! `cbindgen --config cbindgen.toml --output include/fuzzy.h`, and declares
+//! the same functions as the one of libfuzzy.
+use core::ffi::{c_char, c_int, c_uint};
+use std::ffi::CStr;
+use std::fs::File;
+use std::io::{self, Read};
+use std::ptr;
+use std::slice;
+
+use crate::hasher::DigestState;
+use crate::{compare, get_spamsum, get_spamsum_from_reader, Spamsum, SpamsumError};
+
+/// The length of the longest spamsum `fuzzy_hash_buf()` and the other
+/// hashing functions write, including the terminating NUL. Like in
+/// libfuzzy, result buffers must have at least this length.
+pub const FUZZY_MAX_RESULT: usize = 2 * SPAMSUM_LENGTH + 20;
+
+/// The length of the left hash, as in libfuzzy.
+pub const SPAMSUM_LENGTH: usize = crate::LEFT_HASH_LENGTH as usize;
+
+/// Flag for `fuzzy_digest()` to eliminate sequences of more than three
+/// identical characters from the hashes, like `Spamsum::normalized()`.
+pub const FUZZY_FLAG_ELIMSEQ: c_uint = 0x1;
+
+/// Flag for `fuzzy_digest()` not to truncate the right hash. It is not
+/// supported, as only the truncated right hash is kept, so `fuzzy_digest()`
+/// fails with it.
+pub const FUZZY_FLAG_NOTRUNC: c_uint = 0x2;
+
+/// The state of an incremental hash, created with `fuzzy_new()`. C only sees
+/// it as the opaque `struct fuzzy_state`.
+pub struct FuzzyState {
+    state: DigestState,
+}
+
+/// Calculates the spamsum of the `buf_len` bytes at `buf` and writes it to
+/// `result` as a NUL-terminated string.
+///
+/// Returns 0 on success and -1 on failure.
+///
+/// # Safety
+///
+/// `buf` must point to `buf_len` readable bytes and `result` to at least
+/// `FUZZY_MAX_RESULT` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn fuzzy_hash_buf(
+    buf: *const u8,
+    buf_len: u32,
+    result: *mut c_char,
+) -> c_int {
+    if result.is_null() || (buf.is_null() && buf_len > 0) {
+        return -1;
+    }
+    let input = if buf_len == 0 {
+        &[][..]
+    } else {
+        slice::from_raw_parts(buf, buf_len as usize)
+    };
+    write_result(get_spamsum(input), result)
+}
+
+/// Calculates the spamsum of the file named by the NUL-terminated string
+/// `filename` and writes it to `result`.
+///
+/// Returns 0 on success and -1 on failure, e.g. if the file cannot be read.
+///
+/// # Safety
+///
+/// `filename` must be a valid NUL-terminated string and `result` must point
+/// to at least `FUZZY_MAX_RESULT` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn fuzzy_hash_filename(
+    filename: *const c_char,
+    result: *mut c_char,
+) -> c_int {
+    if filename.is_null() || result.is_null() {
+        return -1;
+    }
+    let file = match open(CStr::from_ptr(filename)) {
+        Ok(file) => file,
+        Err(_) => return -1,
+    };
+    write_result(get_spamsum_from_reader(file, Default::default()), result)
+}
+
+/// Calculates the spamsum of the whole file `handle` refers to and writes it
+/// to `result`. The position of `handle` is restored afterwards.
+///
+/// Returns 0 on success and -1 on failure.
+///
+/// # Safety
+///
+/// `handle` must be an open `FILE` and `result` must point to at least
+/// `FUZZY_MAX_RESULT` writable bytes.
+#[cfg(unix)]
+#[no_mangle]
+pub unsafe extern "C" fn fuzzy_hash_file(handle: *mut libc::FILE, result: *mut c_char) -> c_int {
+    if handle.is_null() || result.is_null() {
+        return -1;
+    }
+    let position = libc::ftello(handle);
+    if position < 0 || libc::fseeko(handle, 0, libc::SEEK_SET) != 0 {
+        return -1;
+    }
+    let status = fuzzy_hash_stream(handle, result);
+    if libc::fseeko(handle, position, libc::SEEK_SET) != 0 {
+        return -1;
+    }
+    status
+}
+
+/// Calculates the spamsum of everything read from `handle` until the end of
+/// the file and writes it to `result`.
+///
+/// Returns 0 on success and -1 on failure.
+///
+/// # Safety
+///
+/// `handle` must be an open `FILE` and `result` must point to at least
+/// `FUZZY_MAX_RESULT` writable bytes.
+#[cfg(unix)]
+#[no_mangle]
+pub unsafe extern "C" fn fuzzy_hash_stream(handle: *mut libc::FILE, result: *mut c_char) -> c_int {
+    if handle.is_null() || result.is_null() {
+        return -1;
+    }
+    write_result(
+        get_spamsum_from_reader(Stream(handle), Default::default()),
+        result,
+    )
+}
+
+/// Compares two NUL-terminated spamsums and returns their similarity from 0
+/// to 100, or -1 if one of them cannot be parsed.
+///
+/// Like in libfuzzy, anything after a comma is ignored, so lines of ssdeep
+/// hash lists can be compared directly.
+///
+/// # Safety
+///
+/// `sig1` and `sig2` must be valid NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn fuzzy_compare(sig1: *const c_char, sig2: *const c_char) -> c_int {
+    if sig1.is_null() || sig2.is_null() {
+        return -1;
+    }
+    match (parse(CStr::from_ptr(sig1)), parse(CStr::from_ptr(sig2))) {
+        (Some(a), Some(b)) => compare(&a, &b) as c_int,
+        _ => -1,
+    }
+}
+
+/// Creates the state for hashing input incrementally with `fuzzy_update()`.
+///
+/// The state must be released with `fuzzy_free()`.
+#[no_mangle]
+pub extern "C" fn fuzzy_new() -> *mut FuzzyState {
+    Box::into_raw(Box::new(FuzzyState {
+        state: DigestState::new(Default::default()),
+    }))
+}
+
+/// Hashes the next `buffer_size` bytes at `buffer`.
+///
+/// Returns 0 on success and -1 on failure.
+///
+/// # Safety
+///
+/// `state` must have been created by `fuzzy_new()` and `buffer` must point
+/// to `buffer_size` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn fuzzy_update(
+    state: *mut FuzzyState,
+    buffer: *const u8,
+    buffer_size: usize,
+) -> c_int {
+    if state.is_null() || (buffer.is_null() && buffer_size > 0) {
+        return -1;
+    }
+    if buffer_size > 0 {
+        (*state)
+            .state
+            .update_slice(slice::from_raw_parts(buffer, buffer_size));
+    }
+    0
+}
+
+/// Writes the spamsum of the input so far to `result`. The state is not
+/// changed, so hashing can go on afterwards.
+///
+/// Returns 0 on success and -1 on failure, e.g. for unsupported `flags`.
+///
+/// # Safety
+///
+/// `state` must have been created by `fuzzy_new()` and `result` must point
+/// to at least `FUZZY_MAX_RESULT` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn fuzzy_digest(
+    state: *const FuzzyState,
+    result: *mut c_char,
+    flags: c_uint,
+) -> c_int {
+    if state.is_null() || result.is_null() || flags & !FUZZY_FLAG_ELIMSEQ != 0 {
+        return -1;
+    }
+    let spamsum = (*state).state.digest();
+    if flags & FUZZY_FLAG_ELIMSEQ != 0 {
+        write_result(spamsum.map(|spamsum| spamsum.normalized()), result)
+    } else {
+        write_result(spamsum, result)
+    }
+}
+
+/// Releases a state created by `fuzzy_new()`. Null is ignored.
+///
+/// # Safety
+///
+/// `state` must have been created by `fuzzy_new()` and must not be used
+/// afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn fuzzy_free(state: *mut FuzzyState) {
+    if !state.is_null() {
+        drop(Box::from_raw(state));
+    }
+}
+
+fn parse(signature: &CStr) -> Option<Spamsum> {
+    let signature = signature.to_str().ok()?;
+    let end = signature.find(',').unwrap_or(signature.len());
+    signature[..end].parse().ok()
+}
+
+#[cfg(unix)]
+fn open(filename: &CStr) -> io::Result<File> {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    File::open(OsStr::from_bytes(filename.to_bytes()))
+}
+
+#[cfg(not(unix))]
+fn open(filename: &CStr) -> io::Result<File> {
+    let filename = filename
+        .to_str()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    File::open(filename)
+}
+
+// Writes the spamsum and a NUL to `result` and returns the libfuzzy status.
+unsafe fn write_result(spamsum: Result<Spamsum, SpamsumError>, result: *mut c_char) -> c_int {
+    let spamsum = match spamsum {
+        Ok(spamsum) => spamsum.to_string(),
+        Err(_) => return -1,
+    };
+    if spamsum.len() >= FUZZY_MAX_RESULT {
+        return -1;
+    }
+    ptr::copy_nonoverlapping(spamsum.as_ptr(), result as *mut u8, spamsum.len());
+    *result.add(spamsum.len()) = 0;
+    0
+}
+
+// Reads from a C `FILE`.
+#[cfg(unix)]
+struct Stream(*mut libc::FILE);
+
+#[cfg(unix)]
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let length =
+            unsafe { libc::fread(buf.as_mut_ptr() as *mut libc::c_void, 1, buf.len(), self.0) };
+        if length == 0 && unsafe { libc::ferror(self.0) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(length)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CString;
+    use std::io::Write;
+
+    fn hash_buf(input: &[u8]) -> String {
+        let mut result = [0 as c_char; FUZZY_MAX_RESULT];
+        let status =
+            unsafe { fuzzy_hash_buf(input.as_ptr(), input.len() as u32, result.as_mut_ptr()) };
+        assert_eq!(status, 0);
+        unsafe { CStr::from_ptr(result.as_ptr()) }
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    fn fuzzy_compare_str(a: &str, b: &str) -> c_int {
+        let a = CString::new(a).unwrap();
+        let b = CString::new(b).unwrap();
+        unsafe { fuzzy_compare(a.as_ptr(), b.as_ptr()) }
+    }
+
+    #[test]
+    fn test_fuzzy_hash_buf() {
+        let input = b"Please buy my stuff\nDear Sir or Madam\n";
+        assert_eq!(hash_buf(input), "3:clclDDvWIMF/hv:cGZ/EJv");
+        assert_eq!(hash_buf(b""), "3::");
+        let status = unsafe { fuzzy_hash_buf(ptr::null(), 0, ptr::null_mut()) };
+        assert_eq!(status, -1);
+    }
+
+    #[test]
+    fn test_fuzzy_hash_filename() {
+        let path = std::env::temp_dir().join(format!("spamsum-ffi-{}", std::process::id()));
+        File::create(&path)
+            .unwrap()
+            .write_all(b"Please buy my stuff\nDear Sir or Madam\n")
+            .unwrap();
+        let filename = CString::new(path.to_str().unwrap()).unwrap();
+        let mut result = [0 as c_char; FUZZY_MAX_RESULT];
+        let status = unsafe { fuzzy_hash_filename(filename.as_ptr(), result.as_mut_ptr()) };
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(status, 0);
+        let spamsum = unsafe { CStr::from_ptr(result.as_ptr()) };
+        assert_eq!(spamsum.to_str(), Ok("3:clclDDvWIMF/hv:cGZ/EJv"));
+        let status = unsafe { fuzzy_hash_filename(filename.as_ptr(), result.as_mut_ptr()) };
+        assert_eq!(status, -1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_fuzzy_hash_file() {
+        let mut result = [0 as c_char; FUZZY_MAX_RESULT];
+        unsafe {
+            let handle = libc::tmpfile();
+            assert!(!handle.is_null());
+            let input = b"Please buy my stuff\nDear Sir or Madam\n";
+            let written = libc::fwrite(
+                input.as_ptr() as *const libc::c_void,
+                1,
+                input.len(),
+                handle,
+            );
+            assert_eq!(written, input.len());
+            libc::fseeko(handle, 7, libc::SEEK_SET);
+            assert_eq!(fuzzy_hash_file(handle, result.as_mut_ptr()), 0);
+            assert_eq!(
+                CStr::from_ptr(result.as_ptr()).to_str(),
+                Ok("3:clclDDvWIMF/hv:cGZ/EJv")
+            );
+            assert_eq!(libc::ftello(handle), 7);
+            // the stream is only hashed from the current position
+            assert_eq!(fuzzy_hash_stream(handle, result.as_mut_ptr()), 0);
+            assert_eq!(
+                CStr::from_ptr(result.as_ptr()).to_str().unwrap(),
+                hash_buf(&input[7..])
+            );
+            libc::fclose(handle);
+        }
+    }
+
+    #[test]
+    fn test_fuzzy_update() {
+        let mut result = [0 as c_char; FUZZY_MAX_RESULT];
+        unsafe {
+            let state = fuzzy_new();
+            let input = b"Please buy my stuff\nDear Sir or Madam\n";
+            for chunk in input.chunks(5) {
+                assert_eq!(fuzzy_update(state, chunk.as_ptr(), chunk.len()), 0);
+            }
+            assert_eq!(fuzzy_digest(state, result.as_mut_ptr(), 0), 0);
+            let spamsum = CStr::from_ptr(result.as_ptr());
+            assert_eq!(spamsum.to_str(), Ok("3:clclDDvWIMF/hv:cGZ/EJv"));
+            // the digest leaves the state as it is
+            assert_eq!(fuzzy_update(state, ptr::null(), 0), 0);
+            assert_eq!(fuzzy_digest(state, result.as_mut_ptr(), 0), 0);
+            assert_eq!(CStr::from_ptr(result.as_ptr()), spamsum);
+            assert_eq!(
+                fuzzy_digest(state, result.as_mut_ptr(), FUZZY_FLAG_NOTRUNC),
+                -1
+            );
+            fuzzy_free(state);
+            fuzzy_free(ptr::null_mut());
+        }
+    }
+
+    #[test]
+    fn test_fuzzy_digest_eliminating_sequences() {
+        let mut result = [0 as c_char; FUZZY_MAX_RESULT];
+        let input = b"Hello ".repeat(500);
+        let spamsum = get_spamsum(&input).unwrap();
+        assert_ne!(spamsum.normalized(), spamsum);
+        unsafe {
+            let state = fuzzy_new();
+            fuzzy_update(state, input.as_ptr(), input.len());
+            assert_eq!(
+                fuzzy_digest(state, result.as_mut_ptr(), FUZZY_FLAG_ELIMSEQ),
+                0
+            );
+            fuzzy_free(state);
+        }
+        let digest = unsafe { CStr::from_ptr(result.as_ptr()) };
+        assert_eq!(
+            digest.to_str(),
+            Ok(spamsum.normalized().to_string().as_str())
+        );
+    }
+
+    #[test]
+    fn test_header() {
+        let header = include_str!("../include/fuzzy.h");
+        for function in &[
+            "fuzzy_hash_buf(",
+            "fuzzy_hash_filename(",
+            "fuzzy_hash_file(",
+            "fuzzy_hash_stream(",
+            "fuzzy_compare(",
+            "fuzzy_new(",
+            "fuzzy_update(",
+            "fuzzy_digest(",
+            "fuzzy_free(",
+        ] {
+            assert!(header.contains(function), "{} is not declared", function);
+        }
+    }
+
+    #[test]
+    fn test_fuzzy_compare() {
+        let a = "3:clclDDvWIMF/hv:cGZ/EJv";
+        assert_eq!(fuzzy_compare_str(a, a), 100);
+        assert_eq!(
+            fuzzy_compare_str(a, "3:clclDDvWIMF/hv:cGZ/EJv,\"mail.txt\""),
+            100
+        );
+        assert_eq!(fuzzy_compare_str(a, "3:Hn:Hn"), 0);
+        assert_eq!(fuzzy_compare_str(a, "not a spamsum"), -1);
+        assert_eq!(unsafe { fuzzy_compare(ptr::null(), ptr::null()) }, -1);
+    }
+}
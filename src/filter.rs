@@ -0,0 +1,285 @@
+use alloc::borrow::Cow;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::cmp;
+use core::fmt;
+use core::num::Wrapping;
+
+use crate::html::HtmlFilter;
+use crate::url::UrlFilter;
+use crate::{is_whitespace, SpamsumOptions, HASH_INIT, HASH_PRIME};
+
+// Applies the filters that work byte by byte, after the headers are skipped.
+// Only allocates if the input actually has to be modified.
+pub(crate) fn filter_bytes(input: &[u8], options: SpamsumOptions) -> Cow<'_, [u8]> {
+    let mut filter = ByteFilter::new(options);
+    if filter.is_empty() {
+        return Cow::Borrowed(input);
+    }
+    if filter.filters.len() == 1
+        && options.ignore_whitespace
+        && !input.iter().any(|&c| is_whitespace(c))
+    {
+        return Cow::Borrowed(input);
+    }
+    let mut output = Vec::with_capacity(input.len());
+    let mut emit = |c| output.push(c);
+    for &c in input {
+        filter.update(c, &mut emit);
+    }
+    filter.finish(&mut emit);
+    Cow::Owned(output)
+}
+
+// A preprocessing step for input that is fed byte by byte.
+pub(crate) trait Filter: fmt::Debug {
+    // Passes `c` on to `emit` unless it is filtered out. Filters that need to
+    // look ahead may hold bytes back and pass them on later.
+    fn update(&mut self, c: u8, emit: &mut dyn FnMut(u8));
+
+    // Emits whatever is still pending at the end of the input.
+    fn finish(&mut self, _emit: &mut dyn FnMut(u8)) {}
+}
+
+// The byte filters enabled by the options, in the order they are applied.
+#[derive(Debug)]
+pub(crate) struct ByteFilter {
+    filters: Vec<Box<dyn Filter>>,
+}
+
+impl ByteFilter {
+    pub(crate) fn new(options: SpamsumOptions) -> ByteFilter {
+        let mut filters: Vec<Box<dyn Filter>> = Vec::new();
+        if options.ignore_html {
+            filters.push(Box::new(HtmlFilter::new(options.decode_html_entities)));
+        }
+        if options.strip_quoted_lines {
+            filters.push(Box::new(QuotedLineFilter::default()));
+        }
+        if options.normalize_urls {
+            filters.push(Box::new(UrlFilter::default()));
+        }
+        if options.strip_digits {
+            filters.push(Box::new(DigitFilter));
+        }
+        if let Some(max_run_len) = options.max_run_len {
+            filters.push(Box::new(RunFilter {
+                max_run_len: cmp::max(max_run_len, 1),
+                last: None,
+                run_len: 0,
+            }));
+        }
+        if options.tokenize {
+            filters.push(Box::new(Tokenizer::default()));
+        } else if options.ignore_whitespace {
+            filters.push(Box::new(WhitespaceFilter));
+        }
+        ByteFilter { filters }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.filters.is_empty()
+    }
+
+    pub(crate) fn update(&mut self, c: u8, emit: &mut dyn FnMut(u8)) {
+        apply(&mut self.filters, c, emit);
+    }
+
+    pub(crate) fn finish(&mut self, emit: &mut dyn FnMut(u8)) {
+        for i in 0..self.filters.len() {
+            let (filter, rest) = self.filters[i..].split_first_mut().unwrap();
+            filter.finish(&mut |c| apply(rest, c, emit));
+        }
+    }
+}
+
+fn apply(filters: &mut [Box<dyn Filter>], c: u8, emit: &mut dyn FnMut(u8)) {
+    match filters.split_first_mut() {
+        Some((filter, rest)) => filter.update(c, &mut |c| apply(rest, c, emit)),
+        None => emit(c),
+    }
+}
+
+#[derive(Debug)]
+struct WhitespaceFilter;
+
+impl Filter for WhitespaceFilter {
+    fn update(&mut self, c: u8, emit: &mut dyn FnMut(u8)) {
+        if !is_whitespace(c) {
+            emit(c);
+        }
+    }
+}
+
+#[derive(Debug)]
+struct DigitFilter;
+
+impl Filter for DigitFilter {
+    fn update(&mut self, c: u8, emit: &mut dyn FnMut(u8)) {
+        if !c.is_ascii_digit() {
+            emit(c);
+        }
+    }
+}
+
+// Cuts runs of the same byte off after `max_run_len` bytes.
+#[derive(Debug)]
+struct RunFilter {
+    max_run_len: u32,
+    last: Option<u8>,
+    run_len: u32,
+}
+
+impl Filter for RunFilter {
+    fn update(&mut self, c: u8, emit: &mut dyn FnMut(u8)) {
+        if self.last == Some(c) {
+            if self.run_len >= self.max_run_len {
+                return;
+            }
+            self.run_len += 1;
+        } else {
+            self.last = Some(c);
+            self.run_len = 1;
+        }
+        emit(c);
+    }
+}
+
+// Drops lines starting with ">", i.e. quoted replies.
+#[derive(Debug)]
+struct QuotedLineFilter {
+    at_line_start: bool,
+    in_quote: bool,
+}
+
+impl Default for QuotedLineFilter {
+    fn default() -> QuotedLineFilter {
+        QuotedLineFilter {
+            at_line_start: true,
+            in_quote: false,
+        }
+    }
+}
+
+impl Filter for QuotedLineFilter {
+    fn update(&mut self, c: u8, emit: &mut dyn FnMut(u8)) {
+        if self.in_quote || (self.at_line_start && c == b'>') {
+            self.in_quote = c != b'\n';
+            self.at_line_start = c == b'\n';
+            return;
+        }
+        self.at_line_start = c == b'\n';
+        emit(c);
+    }
+}
+
+// Turns every whitespace-delimited token into a single byte, ignoring case
+// and ASCII punctuation. Tokens of only punctuation are skipped.
+#[derive(Clone, Debug)]
+struct Tokenizer {
+    hash: Wrapping<u32>,
+    in_token: bool,
+}
+
+impl Default for Tokenizer {
+    fn default() -> Tokenizer {
+        Tokenizer {
+            hash: HASH_INIT,
+            in_token: false,
+        }
+    }
+}
+
+impl Filter for Tokenizer {
+    fn update(&mut self, c: u8, emit: &mut dyn FnMut(u8)) {
+        if is_whitespace(c) {
+            self.finish(emit);
+        } else if !c.is_ascii_punctuation() {
+            self.hash = (self.hash ^ Wrapping(u32::from(c.to_ascii_lowercase()))) * HASH_PRIME;
+            self.in_token = true;
+        }
+    }
+
+    fn finish(&mut self, emit: &mut dyn FnMut(u8)) {
+        if self.in_token {
+            let hash = self.hash.0;
+            *self = Tokenizer::default();
+            emit((hash ^ hash >> 8 ^ hash >> 16 ^ hash >> 24) as u8);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokenize(input: &[u8]) -> Vec<u8> {
+        let options = SpamsumOptions::builder().tokenize(true).build().unwrap();
+        filter_bytes(input, options).into_owned()
+    }
+
+    #[test]
+    fn test_tokenize() {
+        let tokens = tokenize(b"Please buy\tmy stuff\n");
+        assert_eq!(tokens.len(), 4);
+        assert_eq!(tokenize(b"  PLEASE  b.u.y my stuff!!! - "), tokens);
+        assert_ne!(tokenize(b"Please buy my stuff now"), tokens);
+        assert_eq!(tokenize(b" ... "), b"");
+    }
+
+    #[test]
+    fn test_tokenize_html() {
+        let options = SpamsumOptions::builder()
+            .tokenize(true)
+            .ignore_html(true)
+            .build()
+            .unwrap();
+        assert_eq!(
+            filter_bytes(b"<p>Please</p> b<i>u</i>y <br>my stuff", options).into_owned(),
+            tokenize(b"Please buy my stuff")
+        );
+    }
+
+    #[test]
+    fn test_strip_digits() {
+        let options = SpamsumOptions::builder()
+            .strip_digits(true)
+            .ignore_whitespace(true)
+            .build()
+            .unwrap();
+        assert_eq!(
+            filter_bytes(b"Invoice 2024-0815, due 1.1.", options).into_owned(),
+            b"Invoice-,due..".to_vec()
+        );
+    }
+
+    #[test]
+    fn test_collapse_runs() {
+        let options = SpamsumOptions::builder()
+            .max_run_len(Some(2))
+            .build()
+            .unwrap();
+        assert_eq!(
+            filter_bytes(b"Buy!!!!!!! nooow   aa", options).into_owned(),
+            b"Buy!! noow  aa".to_vec()
+        );
+        let options = SpamsumOptions::builder()
+            .max_run_len(Some(0))
+            .build()
+            .unwrap();
+        assert_eq!(filter_bytes(b"aaab", options).into_owned(), b"ab".to_vec());
+    }
+
+    #[test]
+    fn test_strip_quoted_lines() {
+        let options = SpamsumOptions::builder()
+            .strip_quoted_lines(true)
+            .build()
+            .unwrap();
+        let input = b"> Dear Sir\r\n>> buy\nThanks, but no\n> > > again\n\nBye >\n>";
+        assert_eq!(
+            filter_bytes(input, options).into_owned(),
+            b"Thanks, but no\n\nBye >\n".to_vec()
+        );
+    }
+}
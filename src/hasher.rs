@@ -0,0 +1,1094 @@
+use alloc::vec::Vec;
+use core::mem;
+#[cfg(feature = "std")]
+use std::io;
+
+use crate::{
+    check_options, chunk_hash, guess_initial_blocksize, spamsum_with_chunk_hasher,
+    update_hash_output, ByteFilter, ChunkHasher, FnvHasher, HashBuffer, HeaderEnd, RollingHash,
+    Spamsum, SpamsumError, SpamsumOptions, MAX_BLOCKSIZE, MIN_BLOCKSIZE,
+};
+
+// blocksizes MIN_BLOCKSIZE * 2^0 up to MIN_BLOCKSIZE * 2^30 still fit into a u32
+const MAX_BLOCKSIZE_STATES: usize = 31;
+// the number of bytes `update_slice()` checks for triggers at once, one per
+// bit of a u64
+const UPDATE_BLOCK_LENGTH: usize = 64;
+
+/// Incrementally calculates the spamsum of data that arrives in chunks.
+///
+/// Feed the data with repeated calls to `update()` and get the spamsum with
+/// `finalize()`. The result is the same as calling `get_configured_spamsum()`
+/// on the concatenated data, but the input never has to be buffered: the
+/// hasher keeps the state of every candidate blocksize and picks the right
+/// one at the end. Only with `ignore_mime_headers`,
+/// `decode_transfer_encoding` or a custom `filter` is the input buffered,
+/// since these need all of it at once.
+///
+/// ```
+/// use spamsum::SpamsumHasher;
+///
+/// let mut hasher = SpamsumHasher::new();
+/// hasher.update(b"Please buy my stuff\n");
+/// hasher.update(b"Dear Sir or Madam\n");
+/// assert_eq!(hasher.finalize().unwrap().to_string(), "3:clclDDvWIMF/hv:cGZ/EJv");
+/// ```
+///
+/// The chunks are hashed with `FnvHasher` like ssdeep does, unless another
+/// `ChunkHasher` is chosen with `with_chunk_hasher()`.
+///
+/// # Memory
+///
+/// The length of the input does not have to be known in advance, which makes
+/// the hasher suitable for pipes and network streams. It starts with the
+/// smallest blocksize and adds the doubled blocksize whenever the largest one
+/// so far is triggered for the first time. It drops the smallest one again
+/// once the input is too long for it and the next one has a long enough
+/// hash. Every blocksize keeps two hashes of bounded length, and there are
+/// at most 31 of them, so memory usage does not grow with the input (unless
+/// one of the options above buffers it).
+pub struct SpamsumHasher<H: ChunkHasher = FnvHasher> {
+    options: SpamsumOptions,
+    state: DigestState<H>,
+    searching_headers: bool,
+    header_end: HeaderEnd,
+    filter: ByteFilter,
+    // the buffered input if the options need all of it at once
+    message: Option<Vec<u8>>,
+    input_len: u64,
+}
+
+impl SpamsumHasher {
+    pub fn new() -> SpamsumHasher {
+        SpamsumHasher::with_valid_options(Default::default())
+    }
+
+    pub fn with_options(options: SpamsumOptions) -> Result<SpamsumHasher, SpamsumError> {
+        SpamsumHasher::with_chunk_hasher(options)
+    }
+}
+
+impl<H: ChunkHasher> SpamsumHasher<H> {
+    /// Creates a hasher that hashes the chunks with `H` instead of
+    /// `FnvHasher`. The spamsums can only be compared with spamsums
+    /// calculated with the same chunk hasher.
+    pub fn with_chunk_hasher(options: SpamsumOptions) -> Result<SpamsumHasher<H>, SpamsumError> {
+        check_options(&options)?;
+        Ok(SpamsumHasher::with_valid_options(options))
+    }
+
+    fn with_valid_options(options: SpamsumOptions) -> SpamsumHasher<H> {
+        SpamsumHasher {
+            options,
+            state: DigestState::new(options),
+            searching_headers: options.ignore_headers,
+            header_end: HeaderEnd::default(),
+            filter: ByteFilter::new(options),
+            message: if options.is_mime() || options.filter.is_some() {
+                Some(Vec::new())
+            } else {
+                None
+            },
+            input_len: 0,
+        }
+    }
+
+    /// Hashes the next chunk of input. Once the input is longer than
+    /// `max_input_len`, nothing is hashed anymore and `finalize()` fails.
+    pub fn update(&mut self, input: &[u8]) {
+        self.input_len = self.input_len.saturating_add(input.len() as u64);
+        if self.exceeds_max_input_len() {
+            return;
+        }
+        if let Some(message) = self.message.as_mut() {
+            message.extend_from_slice(input);
+            return;
+        }
+        if !self.searching_headers && self.filter.is_empty() {
+            self.state.update_slice(input);
+            return;
+        }
+        for &c in input {
+            if self.searching_headers {
+                // until the end of the headers shows up, the input is hashed
+                // as a whole in case there are no headers at all
+                if self.header_end.update(c) {
+                    self.state.reset(self.options);
+                    self.filter = ByteFilter::new(self.options);
+                    self.searching_headers = false;
+                    continue;
+                }
+            }
+            if self.filter.is_empty() {
+                self.state.update(c);
+            } else {
+                let state = &mut self.state;
+                self.filter.update(c, &mut |c| state.update(c));
+            }
+        }
+    }
+
+    pub fn finalize(mut self) -> Result<Spamsum, SpamsumError> {
+        self.digest()
+    }
+
+    /// Returns the spamsum like `finalize()`, but resets the hasher instead
+    /// of consuming it, so that it can hash the next input.
+    pub fn finalize_reset(&mut self) -> Result<Spamsum, SpamsumError> {
+        let spamsum = self.digest();
+        self.reset();
+        spamsum
+    }
+
+    /// Starts over with a new input, as if the hasher had just been created
+    /// with the same options. Its buffers are kept, so a hasher that is
+    /// reused for many small inputs does not allocate for every one of them.
+    ///
+    /// ```
+    /// use spamsum::SpamsumHasher;
+    ///
+    /// let mut hasher = SpamsumHasher::new();
+    /// hasher.update(b"Hello");
+    /// hasher.reset();
+    /// hasher.update(b"Please buy my stuff\nDear Sir or Madam\n");
+    /// assert_eq!(hasher.finalize_reset().unwrap().to_string(), "3:clclDDvWIMF/hv:cGZ/EJv");
+    /// assert_eq!(hasher.finalize_reset().unwrap().to_string(), "3::");
+    /// ```
+    pub fn reset(&mut self) {
+        self.state.reset(self.options);
+        self.searching_headers = self.options.ignore_headers;
+        self.header_end = HeaderEnd::default();
+        if !self.filter.is_empty() {
+            self.filter = ByteFilter::new(self.options);
+        }
+        if let Some(message) = self.message.as_mut() {
+            message.clear();
+        }
+        self.input_len = 0;
+    }
+
+    fn digest(&mut self) -> Result<Spamsum, SpamsumError> {
+        if let Some(max_input_len) = self.options.max_input_len {
+            if self.exceeds_max_input_len() {
+                return Err(SpamsumError::InputLimitExceeded(max_input_len));
+            }
+        }
+        if let Some(message) = self.message.as_ref() {
+            return spamsum_with_chunk_hasher::<H>(message, self.options);
+        }
+        let state = &mut self.state;
+        self.filter.finish(&mut |c| state.update(c));
+        self.state.digest()
+    }
+
+    /// Returns whether the input so far is longer than `max_input_len`, in
+    /// which case `finalize()` fails and the rest of the input can be
+    /// skipped.
+    pub fn exceeds_max_input_len(&self) -> bool {
+        self.options
+            .max_input_len
+            .is_some_and(|max_input_len| self.input_len > max_input_len)
+    }
+}
+
+impl Default for SpamsumHasher {
+    fn default() -> SpamsumHasher {
+        SpamsumHasher::new()
+    }
+}
+
+/// Hashes everything written to it, so that streams can be hashed with
+/// `io::copy()`.
+///
+/// ```
+/// use std::io;
+/// use spamsum::SpamsumHasher;
+///
+/// let mut hasher = SpamsumHasher::new();
+/// io::copy(&mut &b"Please buy my stuff\nDear Sir or Madam\n"[..], &mut hasher).unwrap();
+/// assert_eq!(hasher.finalize().unwrap().to_string(), "3:clclDDvWIMF/hv:cGZ/EJv");
+/// ```
+#[cfg(feature = "std")]
+impl<H: ChunkHasher> io::Write for SpamsumHasher<H> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Checks whether numbers are multiples of a divisor without dividing, as
+// divisions by the blocksize would dominate the time spent on every byte.
+// With divisor = odd * 2^shift, x is a multiple iff x * odd⁻¹ (mod 2^32)
+// rotated right by shift is at most u32::MAX / divisor (Granlund and
+// Montgomery).
+#[derive(Clone, Copy, Debug)]
+struct Divisor {
+    inverse: u32,
+    shift: u32,
+    limit: u32,
+}
+
+impl Divisor {
+    fn new(divisor: u32) -> Divisor {
+        let shift = divisor.trailing_zeros();
+        let odd = divisor >> shift;
+        // Newton's method, every step doubles the number of correct bits
+        // starting with 3, as odd * odd = 1 (mod 8)
+        let mut inverse = odd;
+        for _ in 0..4 {
+            inverse = inverse.wrapping_mul(2u32.wrapping_sub(odd.wrapping_mul(inverse)));
+        }
+        Divisor {
+            inverse,
+            shift,
+            limit: u32::MAX / divisor,
+        }
+    }
+
+    #[inline]
+    fn divides(self, x: u32) -> bool {
+        x.wrapping_mul(self.inverse).rotate_right(self.shift) <= self.limit
+    }
+}
+
+// The hashes for one blocksize: the left hash is used when this blocksize
+// ends up being the spamsum's blocksize, the right hash when half of it does.
+#[derive(Clone)]
+struct BlocksizeState<H> {
+    blocksize: u32,
+    divisor: Divisor,
+    // the hash value every chunk starts with
+    init: H,
+    left_hash_length: u32,
+    left_hash_value: H,
+    left_hash: HashBuffer,
+    right_hash_value: H,
+    right_hash: HashBuffer,
+    // both hash values are reset on every trigger until the right hash is
+    // almost full, so until then only the left one is updated
+    right_follows_left: bool,
+}
+
+impl<H: ChunkHasher> BlocksizeState<H> {
+    fn new(blocksize: u32, init: &H, left_hash_length: u32) -> BlocksizeState<H> {
+        BlocksizeState {
+            blocksize,
+            divisor: Divisor::new(blocksize),
+            init: init.clone(),
+            left_hash_length,
+            left_hash_value: init.clone(),
+            left_hash: HashBuffer::new(),
+            right_hash_value: init.clone(),
+            right_hash: HashBuffer::new(),
+            right_follows_left: true,
+        }
+    }
+
+    // Only valid as long as this blocksize has not been triggered yet, as
+    // the hash values then still cover the whole input.
+    fn fork(&self) -> BlocksizeState<H> {
+        BlocksizeState {
+            left_hash_value: self.left_hash_value.clone(),
+            right_hash_value: self.right_hash_value.clone(),
+            ..BlocksizeState::new(self.blocksize * 2, &self.init, self.left_hash_length)
+        }
+    }
+
+    #[inline]
+    fn update(&mut self, c: u8) {
+        self.left_hash_value.update(c);
+        if !self.right_follows_left {
+            self.right_hash_value.update(c);
+        }
+    }
+
+    fn trigger(&mut self) {
+        if self.right_follows_left {
+            self.right_hash_value = self.left_hash_value.clone();
+            self.right_follows_left =
+                self.right_hash.len() < (self.left_hash_length / 2 - 1) as usize;
+        }
+        update_hash_output(
+            &mut self.left_hash_value,
+            &self.init,
+            &mut self.left_hash,
+            self.left_hash_length,
+        );
+        update_hash_output(
+            &mut self.right_hash_value,
+            &self.init,
+            &mut self.right_hash,
+            self.left_hash_length / 2,
+        );
+    }
+
+    fn left_digest(&self, with_leftovers: bool) -> HashBuffer {
+        let mut hash = self.left_hash.clone();
+        if with_leftovers {
+            update_hash_output(
+                &mut self.left_hash_value.clone(),
+                &self.init,
+                &mut hash,
+                self.left_hash_length,
+            );
+        }
+        hash
+    }
+
+    // The length of `left_digest()`, without building it, which would allocate
+    // for hashes longer than the standard length.
+    fn left_digest_len(&self, with_leftovers: bool) -> usize {
+        let length = self.left_hash.len();
+        if with_leftovers && length < self.left_hash_length as usize {
+            length + 1
+        } else {
+            length
+        }
+    }
+
+    fn right_digest(&self, with_leftovers: bool) -> HashBuffer {
+        let mut hash = self.right_hash.clone();
+        if with_leftovers {
+            let right_hash_value = if self.right_follows_left {
+                &self.left_hash_value
+            } else {
+                &self.right_hash_value
+            };
+            update_hash_output(
+                &mut right_hash_value.clone(),
+                &self.init,
+                &mut hash,
+                self.left_hash_length / 2,
+            );
+        }
+        hash
+    }
+}
+
+// Hashes the input for all candidate blocksizes at once. With a fixed
+// blocksize only that one and its double are tracked, otherwise the
+// blocksizes MIN_BLOCKSIZE * 2^n are added as soon as the largest one so far
+// gets triggered for the first time, and dropped again (like ssdeep does)
+// once they can no longer be chosen.
+pub(crate) struct DigestState<H = FnvHasher> {
+    fixed_blocksize: bool,
+    // the n of the smallest tracked blocksize MIN_BLOCKSIZE * 2^n
+    first: usize,
+    rolling: RollingHash,
+    rolling_hash: u32,
+    trigger_mask: u32,
+    left_hash_length: u32,
+    length: u64,
+    blocksizes: Vec<BlocksizeState<H>>,
+}
+
+impl<H: ChunkHasher> DigestState<H> {
+    pub(crate) fn new(options: SpamsumOptions) -> DigestState<H> {
+        DigestState::reusing(options, Vec::new())
+    }
+
+    // Starts over with the next input, keeping the allocation of the
+    // blocksize states.
+    pub(crate) fn reset(&mut self, options: SpamsumOptions) {
+        let blocksizes = mem::take(&mut self.blocksizes);
+        *self = DigestState::reusing(options, blocksizes);
+    }
+
+    fn reusing(options: SpamsumOptions, mut blocksizes: Vec<BlocksizeState<H>>) -> DigestState<H> {
+        let blocksize = options.blocksize;
+        let left_hash_length = options.left_hash_length();
+        // with a key, the chunk hashes start with the hash of the key, and the
+        // rolling hash is masked so that the chunk boundaries depend on the
+        // key as well
+        let mut init = H::default();
+        let mut trigger_mask = 0;
+        if let Some(key) = options.key {
+            key.to_le_bytes().iter().for_each(|&c| init.update(c));
+            trigger_mask = chunk_hash(&key.to_be_bytes());
+        }
+        blocksizes.clear();
+        if blocksize > 0 {
+            blocksizes.push(BlocksizeState::new(blocksize, &init, left_hash_length));
+            blocksizes.push(BlocksizeState::new(blocksize * 2, &init, left_hash_length));
+        } else {
+            blocksizes.push(BlocksizeState::new(MIN_BLOCKSIZE, &init, left_hash_length));
+        }
+        DigestState {
+            fixed_blocksize: blocksize > 0,
+            first: 0,
+            rolling: RollingHash::new(),
+            rolling_hash: 0,
+            trigger_mask,
+            left_hash_length,
+            length: 0,
+            blocksizes,
+        }
+    }
+
+    #[inline]
+    pub(crate) fn update(&mut self, c: u8) {
+        self.length += 1;
+        self.rolling_hash = self.rolling.push(c);
+        for state in self.blocksizes.iter_mut() {
+            state.update(c);
+        }
+        // most bytes do not even trigger the smallest blocksize
+        let trigger_value = (self.rolling_hash ^ self.trigger_mask).wrapping_add(1);
+        if self.blocksizes[0].divisor.divides(trigger_value) {
+            self.trigger(trigger_value);
+        }
+    }
+
+    // Hashes the input like calling `update()` for every byte, but block by
+    // block: the rolling hashes of a block are calculated at once, and the
+    // chunk hashes are updated run by run up to the next trigger, which
+    // keeps them in registers.
+    pub(crate) fn update_slice(&mut self, input: &[u8]) {
+        let mut hashes = [0; UPDATE_BLOCK_LENGTH];
+        for block in input.chunks(UPDATE_BLOCK_LENGTH) {
+            let hashes = &mut hashes[..block.len()];
+            self.rolling.push_slice(block, hashes);
+            let mut triggers = 0u64;
+            for (i, &hash) in hashes.iter().enumerate() {
+                let trigger_value = (hash ^ self.trigger_mask).wrapping_add(1);
+                triggers |= u64::from(self.blocksizes[0].divisor.divides(trigger_value)) << i;
+            }
+            let mut start = 0;
+            while triggers != 0 {
+                let i = triggers.trailing_zeros() as usize;
+                triggers &= triggers - 1;
+                // the smallest blocksize may have been dropped since
+                let trigger_value = (hashes[i] ^ self.trigger_mask).wrapping_add(1);
+                if self.blocksizes[0].divisor.divides(trigger_value) {
+                    self.update_run(&block[start..=i]);
+                    self.trigger(trigger_value);
+                    start = i + 1;
+                }
+            }
+            self.update_run(&block[start..]);
+        }
+        self.rolling_hash = self.rolling.value();
+    }
+
+    // Updates the chunk hashes with bytes that trigger no blocksize, except
+    // maybe the last one. The blocksizes are updated up to four at a time,
+    // so that their chunk hashes are calculated in parallel.
+    fn update_run(&mut self, run: &[u8]) {
+        self.length += run.len() as u64;
+        for states in self.blocksizes.chunks_mut(4) {
+            match states {
+                [a, b, c, d] => run.iter().for_each(|&x| {
+                    a.update(x);
+                    b.update(x);
+                    c.update(x);
+                    d.update(x);
+                }),
+                [a, b, c] => run.iter().for_each(|&x| {
+                    a.update(x);
+                    b.update(x);
+                    c.update(x);
+                }),
+                [a, b] => run.iter().for_each(|&x| {
+                    a.update(x);
+                    b.update(x);
+                }),
+                [a] => run.iter().for_each(|&x| a.update(x)),
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    fn trigger(&mut self, trigger_value: u32) {
+        // every blocksize is a multiple of the previous one, so the first
+        // one not being triggered ends the search
+        let mut i = 0;
+        while i < self.blocksizes.len() && self.blocksizes[i].divisor.divides(trigger_value) {
+            if !self.fixed_blocksize
+                && i == self.blocksizes.len() - 1
+                && self.first + i < MAX_BLOCKSIZE_STATES - 1
+                && self.blocksizes[i].left_hash.is_empty()
+            {
+                let forked = self.blocksizes[i].fork();
+                self.blocksizes.push(forked);
+            }
+            self.blocksizes[i].trigger();
+            i += 1;
+        }
+        // `digest()` only goes below the guessed blocksize while the left
+        // hashes are too short, so once the input is too long for the
+        // smallest blocksize and the next one has a long enough left hash,
+        // the smallest one is never chosen
+        if !self.fixed_blocksize
+            && self.blocksizes.len() > 2
+            && self.length
+                > u64::from(self.blocksizes[1].blocksize) * u64::from(self.left_hash_length)
+            && self.blocksizes[1].left_hash.len() > (self.left_hash_length / 2) as usize
+        {
+            self.blocksizes.remove(0);
+            self.first += 1;
+        }
+    }
+
+    pub(crate) fn digest(&self) -> Result<Spamsum, SpamsumError> {
+        if !self.fixed_blocksize
+            && self.length > u64::from(MAX_BLOCKSIZE) * u64::from(self.left_hash_length)
+        {
+            return Err(SpamsumError::InputTooLarge(self.length));
+        }
+        // collect any leftovers so that we have always the last part of the message
+        let with_leftovers = self.rolling_hash != 0;
+        let mut index = 0;
+        if !self.fixed_blocksize {
+            let initial_blocksize = guess_initial_blocksize(self.length, self.left_hash_length);
+            while MIN_BLOCKSIZE << index < initial_blocksize {
+                index += 1;
+            }
+            while index > self.first
+                && self.state(index).left_digest_len(with_leftovers)
+                    <= (self.left_hash_length / 2) as usize
+            {
+                index -= 1;
+            }
+        }
+        let state = self.state(index);
+        Ok(Spamsum {
+            left_hash_blocksize: state.blocksize,
+            left_hash: state.left_digest(with_leftovers),
+            right_hash: self.state(index + 1).right_digest(with_leftovers),
+        })
+    }
+
+    // Blocksizes beyond the tracked ones have never been triggered, so they
+    // share the hash values of the largest tracked blocksize.
+    fn state(&self, index: usize) -> BlocksizeState<H> {
+        match self.blocksizes.get(index - self.first) {
+            Some(state) => state.clone(),
+            None => {
+                let largest = self.blocksizes.last().unwrap();
+                BlocksizeState {
+                    left_hash_value: largest.left_hash_value.clone(),
+                    right_hash_value: largest.right_hash_value.clone(),
+                    ..BlocksizeState::new(
+                        MIN_BLOCKSIZE << index,
+                        &largest.init,
+                        largest.left_hash_length,
+                    )
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        filter_input, get_configured_spamsum, get_spamsum, is_whitespace, pseudo_random_bytes,
+        LEFT_HASH_LENGTH,
+    };
+
+    fn assert_matches_spamsum(input: &[u8], options: SpamsumOptions) {
+        let expected = get_configured_spamsum(input, options).unwrap();
+        // the filters are applied while hashing, as if on a filtered copy
+        let mut state: DigestState = DigestState::new(options);
+        for &c in filter_input(input, options).iter() {
+            state.update(c);
+        }
+        assert_eq!(state.digest().unwrap(), expected);
+        for chunk_size in [1, 7, 4096].iter() {
+            let mut hasher = SpamsumHasher::with_options(options).unwrap();
+            for chunk in input.chunks(*chunk_size) {
+                hasher.update(chunk);
+            }
+            assert_eq!(hasher.finalize().unwrap().to_string(), expected.to_string());
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_hasher_as_writer() {
+        let input = pseudo_random_bytes(10000, 9);
+        let mut hasher = SpamsumHasher::new();
+        io::copy(&mut io::Cursor::new(&input), &mut hasher).unwrap();
+        assert_eq!(
+            hasher.finalize().unwrap().to_string(),
+            get_configured_spamsum(&input, Default::default())
+                .unwrap()
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn test_digest_beyond_4_gib() {
+        let input = pseudo_random_bytes(1000, 12);
+        let mut state: DigestState = DigestState::new(Default::default());
+        state.length = u64::from(u32::MAX);
+        for &c in input.iter() {
+            state.update(c);
+        }
+        assert!(state.length > u64::from(u32::MAX));
+        // the blocksize guess is far too large and gets reduced to the one
+        // the input would get on its own
+        assert_eq!(
+            state.digest().unwrap().to_string(),
+            get_configured_spamsum(&input, Default::default())
+                .unwrap()
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn test_small_blocksizes_are_dropped() {
+        let input = pseudo_random_bytes(200000, 14);
+        let mut state: DigestState = DigestState::new(Default::default());
+        for &c in input.iter() {
+            state.update(c);
+        }
+        assert!(state.first > 0);
+        let spamsum = state.digest().unwrap();
+        // a fixed blocksize tracks only that blocksize and its double
+        let options = SpamsumOptions::builder()
+            .blocksize(spamsum.blocksize())
+            .build()
+            .unwrap();
+        assert_eq!(get_configured_spamsum(&input, options).unwrap(), spamsum);
+    }
+
+    #[test]
+    fn test_memory_of_unknown_length_stream() {
+        let input = pseudo_random_bytes(2_000_000, 19);
+        let mut hasher = SpamsumHasher::new();
+        for chunk in input.chunks(4096) {
+            hasher.update(chunk);
+            // only the blocksizes around the one that will be chosen are kept
+            assert!(hasher.state.blocksizes.len() <= 16);
+        }
+        assert!(hasher.state.first > 10);
+        assert_eq!(hasher.finalize().unwrap(), get_spamsum(&input).unwrap());
+    }
+
+    #[test]
+    fn test_update_slice() {
+        let input = pseudo_random_bytes(200000, 15);
+        let keyed = SpamsumOptions::builder().key(Some(7)).build().unwrap();
+        for &options in [Default::default(), keyed].iter() {
+            let mut bytewise: DigestState = DigestState::new(options);
+            input.iter().for_each(|&c| bytewise.update(c));
+            let mut blockwise: DigestState = DigestState::new(options);
+            for chunk in input.chunks(1000) {
+                blockwise.update_slice(chunk);
+            }
+            assert!(blockwise.first > 0);
+            assert_eq!(blockwise.digest().unwrap(), bytewise.digest().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_divisor() {
+        let divisors = [1, 2, 3, 6, 7, 11, 96, 3 << 20, 3 << 30, u32::MAX];
+        let numbers = [0, 1, 2, 3, 95, 96, 97, 3 << 20, 3 << 30, u32::MAX - 1];
+        for &d in divisors.iter() {
+            let divisor = Divisor::new(d);
+            for &x in numbers
+                .iter()
+                .chain([u32::MAX, d, d.wrapping_mul(5)].iter())
+            {
+                assert_eq!(divisor.divides(x), x % d == 0, "{} % {}", x, d);
+            }
+        }
+    }
+
+    #[test]
+    fn test_digest_of_too_large_input() {
+        let mut state: DigestState = DigestState::new(Default::default());
+        state.length = u64::from(MAX_BLOCKSIZE) * u64::from(LEFT_HASH_LENGTH);
+        assert!(state.digest().is_ok());
+        state.update(0);
+        assert!(matches!(
+            state.digest(),
+            Err(SpamsumError::InputTooLarge(_))
+        ));
+    }
+
+    #[test]
+    fn test_hasher_with_invalid_blocksize() {
+        let options = SpamsumOptions {
+            blocksize: u32::MAX / 2 + 1,
+            ignore_headers: false,
+            ignore_whitespace: false,
+            ..Default::default()
+        };
+        assert!(SpamsumHasher::with_options(options).is_err());
+    }
+
+    #[test]
+    fn test_hasher_empty_input() {
+        assert_eq!(SpamsumHasher::new().finalize().unwrap().to_string(), "3::");
+    }
+
+    #[test]
+    fn test_hasher_matches_spamsum() {
+        for (length, seed) in [(4, 1), (100, 2), (5000, 3), (70000, 4)].iter() {
+            let input = pseudo_random_bytes(*length, *seed);
+            assert_matches_spamsum(&input, Default::default());
+        }
+    }
+
+    #[test]
+    fn test_hasher_reduces_blocksize() {
+        // low-entropy input rarely triggers, forcing smaller blocksizes
+        let input: Vec<u8> = pseudo_random_bytes(20000, 5)
+            .iter()
+            .map(|c| c % 3)
+            .collect();
+        assert_matches_spamsum(&input, Default::default());
+    }
+
+    #[test]
+    fn test_hasher_with_set_blocksize() {
+        let options = SpamsumOptions {
+            blocksize: 11,
+            ignore_headers: false,
+            ignore_whitespace: false,
+            ..Default::default()
+        };
+        assert_matches_spamsum(&pseudo_random_bytes(3000, 6), options);
+    }
+
+    #[test]
+    fn test_hasher_with_filters() {
+        let mut input: Vec<u8> = b"X-Spam: YES\nX-Score: 1337\n\nDear Sir\n\n".to_vec();
+        input.extend(pseudo_random_bytes(2000, 7));
+        let options = SpamsumOptions {
+            blocksize: 0,
+            ignore_headers: true,
+            ignore_whitespace: true,
+            ..Default::default()
+        };
+        assert_matches_spamsum(&input, options);
+        let mut input: Vec<u8> = b"X-Spam: YES\r\nX-Score: 1337\r\n\r\nDear Sir".to_vec();
+        input.extend(pseudo_random_bytes(2000, 7));
+        assert_matches_spamsum(&input, options);
+        assert_matches_spamsum(&pseudo_random_bytes(2000, 8), options);
+    }
+
+    #[test]
+    fn test_hasher_with_mime_headers() {
+        let mut input: Vec<u8> =
+            b"Content-Type: multipart/mixed;\r\n boundary=\"b\"\r\n\r\n--b\r\nX: 1\r\n\r\n"
+                .to_vec();
+        input.extend(pseudo_random_bytes(2000, 9));
+        input.extend_from_slice(b"\r\n--b--\r\n");
+        let options = SpamsumOptions::builder()
+            .ignore_mime_headers(true)
+            .ignore_whitespace(true)
+            .build()
+            .unwrap();
+        assert_matches_spamsum(&input, options);
+        let body: Vec<u8> = pseudo_random_bytes(2000, 9)
+            .into_iter()
+            .filter(|&c| !is_whitespace(c))
+            .collect();
+        assert_eq!(
+            get_configured_spamsum(&input, options).unwrap(),
+            get_spamsum(&body).unwrap()
+        );
+        let options = SpamsumOptions::builder()
+            .ignore_headers(true)
+            .decode_transfer_encoding(true)
+            .build()
+            .unwrap();
+        let input = b"Content-Transfer-Encoding: base64\r\n\r\nRGVhciBTaXI=\r\n";
+        assert_matches_spamsum(input, options);
+        assert_eq!(
+            get_configured_spamsum(input, options).unwrap(),
+            get_spamsum(b"Dear Sir").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_hasher_with_html() {
+        let mut input: Vec<u8> =
+            b"X-Spam: YES\n\n<p class=\"x\">Dear&nbsp;Sir</p><!-- -->".to_vec();
+        input.extend(pseudo_random_bytes(3000, 10));
+        input.extend_from_slice(b"&amp");
+        let options = SpamsumOptions::builder()
+            .ignore_headers(true)
+            .ignore_whitespace(true)
+            .strip_digits(true)
+            .max_run_len(Some(3))
+            .ignore_html(true)
+            .decode_html_entities(true)
+            .build()
+            .unwrap();
+        assert_matches_spamsum(&input, options);
+        let options = SpamsumOptions::builder().ignore_html(true).build().unwrap();
+        assert_matches_spamsum(&input, options);
+        assert_eq!(
+            get_configured_spamsum(b"<b>Dear</b> <i>Sir</i>", options).unwrap(),
+            get_spamsum(b"Dear Sir").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_hasher_with_tokens() {
+        let options = SpamsumOptions::builder()
+            .ignore_headers(true)
+            .ignore_html(true)
+            .normalize_urls(true)
+            .tokenize(true)
+            .build()
+            .unwrap();
+        assert_matches_spamsum(&pseudo_random_bytes(20000, 12), options);
+        let mut input: Vec<u8> = b"X-Spam: YES\n\n<p>Dear Sir</p>\n".to_vec();
+        input.extend(pseudo_random_bytes(5000, 13));
+        assert_matches_spamsum(&input, options);
+    }
+
+    #[test]
+    fn test_hasher_with_urls() {
+        let options = SpamsumOptions::builder()
+            .ignore_html(true)
+            .strip_quoted_lines(true)
+            .normalize_urls(true)
+            .build()
+            .unwrap();
+        let mut input: Vec<u8> = b"<a href=\"x\">http://u1.example.com/?id=1</a> ".to_vec();
+        input.extend(pseudo_random_bytes(3000, 14));
+        input.extend_from_slice(b"\n> https://example.com/a1\n https://example.com/a1");
+        assert_matches_spamsum(&input, options);
+        assert_eq!(
+            get_configured_spamsum(b"Buy at http://u12.example.com/1?x", options).unwrap(),
+            get_spamsum(b"Buy at http://*.example.com/*").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_hasher_with_max_input_len() {
+        let options = SpamsumOptions::builder()
+            .max_input_len(Some(10))
+            .build()
+            .unwrap();
+        let mut hasher = SpamsumHasher::with_options(options).unwrap();
+        hasher.update(b"Dear Sir");
+        hasher.update(b"\n\n");
+        assert_eq!(
+            hasher.finalize().unwrap(),
+            get_spamsum(b"Dear Sir\n\n").unwrap()
+        );
+        let mut hasher = SpamsumHasher::with_options(options).unwrap();
+        hasher.update(b"Dear Sir");
+        hasher.update(b"\n\nPlease buy");
+        assert!(hasher.exceeds_max_input_len());
+        assert!(matches!(
+            hasher.finalize(),
+            Err(SpamsumError::InputLimitExceeded(10))
+        ));
+    }
+
+    #[test]
+    fn test_hasher_reset() {
+        let mut headers: Vec<u8> = b"X-Spam: YES\n\nDear Sir\n".to_vec();
+        headers.extend(pseudo_random_bytes(2000, 16));
+        let inputs = [
+            pseudo_random_bytes(70000, 17),
+            headers,
+            pseudo_random_bytes(300, 18),
+            Vec::new(),
+        ];
+        let all_options = [
+            Default::default(),
+            SpamsumOptions::builder()
+                .ignore_headers(true)
+                .build()
+                .unwrap(),
+            SpamsumOptions::builder().ignore_html(true).build().unwrap(),
+            SpamsumOptions::builder()
+                .ignore_mime_headers(true)
+                .build()
+                .unwrap(),
+            SpamsumOptions::builder()
+                .max_input_len(Some(1000))
+                .build()
+                .unwrap(),
+        ];
+        for &options in all_options.iter() {
+            let mut hasher = SpamsumHasher::with_options(options).unwrap();
+            for input in inputs.iter() {
+                hasher.update(input);
+                let expected = get_configured_spamsum(input, options);
+                assert_eq!(
+                    format!("{:?}", hasher.finalize_reset()),
+                    format!("{:?}", expected)
+                );
+            }
+            hasher.update(b"Hello");
+            hasher.reset();
+            assert_eq!(hasher.finalize().unwrap().to_string(), "3::");
+        }
+    }
+
+    #[test]
+    fn test_hasher_with_custom_filter() {
+        fn keep_alphanumeric(input: &mut Vec<u8>) {
+            input.retain(|c| c.is_ascii_alphanumeric());
+        }
+        let options = SpamsumOptions::builder()
+            .ignore_headers(true)
+            .filter(keep_alphanumeric)
+            .build()
+            .unwrap();
+        let mut input: Vec<u8> = b"X-Spam: YES\n\n".to_vec();
+        input.extend(pseudo_random_bytes(3000, 11));
+        assert_matches_spamsum(&input, options);
+        let alphanumeric: Vec<u8> = input[13..]
+            .iter()
+            .cloned()
+            .filter(|c| c.is_ascii_alphanumeric())
+            .collect();
+        assert_eq!(
+            get_configured_spamsum(&input, options).unwrap(),
+            get_spamsum(&alphanumeric).unwrap()
+        );
+    }
+
+    // FNV-1a, i.e. xor before multiplying
+    #[derive(Clone)]
+    struct Fnv1a(u32);
+
+    impl Default for Fnv1a {
+        fn default() -> Fnv1a {
+            Fnv1a(0x811c9dc5)
+        }
+    }
+
+    impl ChunkHasher for Fnv1a {
+        fn update(&mut self, c: u8) {
+            self.0 = (self.0 ^ u32::from(c)).wrapping_mul(0x01000193);
+        }
+
+        fn value(&self) -> u32 {
+            self.0
+        }
+    }
+
+    #[test]
+    fn test_custom_chunk_hasher() {
+        let input = pseudo_random_bytes(20000, 7);
+        let expected = spamsum_with_chunk_hasher::<Fnv1a>(&input, Default::default()).unwrap();
+        assert_ne!(expected, get_spamsum(&input).unwrap());
+        let mut hasher = SpamsumHasher::<Fnv1a>::with_chunk_hasher(Default::default()).unwrap();
+        for chunk in input.chunks(777) {
+            hasher.update(chunk);
+        }
+        let actual = hasher.finalize().unwrap();
+        assert_eq!(actual, expected);
+        assert_eq!(actual.blocksize(), get_spamsum(&input).unwrap().blocksize());
+    }
+
+    #[test]
+    fn test_keyed_hasher() {
+        let input = pseudo_random_bytes(20000, 11);
+        let options = SpamsumOptions::builder().key(Some(7)).build().unwrap();
+        assert_matches_spamsum(&input, options);
+        let keyed = get_configured_spamsum(&input, options).unwrap();
+        assert_ne!(keyed, get_spamsum(&input).unwrap());
+        // keyed spamsums of similar inputs still match
+        let mut changed = input.clone();
+        changed[10000..10010].copy_from_slice(b"Buy now!!!");
+        let changed = get_configured_spamsum(&changed, options).unwrap();
+        assert!(crate::compare(&keyed, &changed) > 80);
+    }
+
+    #[test]
+    fn test_left_digest_len() {
+        for &hash_length in [0, 2, 128].iter() {
+            let options = SpamsumOptions::builder()
+                .hash_length(hash_length)
+                .build()
+                .unwrap();
+            let mut state: DigestState = DigestState::new(options);
+            state.update_slice(&pseudo_random_bytes(30000, 20));
+            for blocksize in state.blocksizes.iter() {
+                for &with_leftovers in [false, true].iter() {
+                    assert_eq!(
+                        blocksize.left_digest_len(with_leftovers),
+                        blocksize.left_digest(with_leftovers).len()
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_hash_length() {
+        let input = pseudo_random_bytes(20000, 13);
+        let mut blocksizes = Vec::new();
+        for &hash_length in [2, 32, 128].iter() {
+            let options = SpamsumOptions::builder()
+                .hash_length(hash_length)
+                .build()
+                .unwrap();
+            assert_matches_spamsum(&input, options);
+            let spamsum = get_configured_spamsum(&input, options).unwrap();
+            assert!(spamsum.left_hash().len() <= hash_length as usize);
+            assert!(spamsum.left_hash().len() > (hash_length / 2) as usize);
+            assert!(spamsum.right_hash().len() <= (hash_length / 2) as usize);
+            blocksizes.push(spamsum.blocksize());
+            if hash_length >= 32 {
+                let mut changed = input.clone();
+                changed[10000..10010].copy_from_slice(b"Buy now!!!");
+                let changed = get_configured_spamsum(&changed, options).unwrap();
+                assert!(crate::compare(&spamsum, &changed) > 80);
+            }
+        }
+        // longer hashes can cover the input with smaller blocksizes
+        assert!(blocksizes[0] > blocksizes[1] && blocksizes[1] > blocksizes[2]);
+        let standard = SpamsumOptions::builder().hash_length(64).build().unwrap();
+        assert_eq!(
+            get_configured_spamsum(&input, standard).unwrap(),
+            get_spamsum(&input).unwrap()
+        );
+        for &hash_length in [1, 257].iter() {
+            assert!(matches!(
+                SpamsumHasher::with_options(SpamsumOptions {
+                    hash_length,
+                    ..Default::default()
+                }),
+                Err(SpamsumError::InvalidHashLength(_))
+            ));
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_deserialize_options() {
+        let options = SpamsumOptions::builder()
+            .blocksize(48)
+            .strip_digits(true)
+            .hash_length(128)
+            .build()
+            .unwrap();
+        let json = serde_json::to_string(&options).unwrap();
+        let copy: SpamsumOptions = serde_json::from_str(&json).unwrap();
+        assert_eq!(format!("{:?}", copy), format!("{:?}", options));
+        let options: SpamsumOptions = serde_json::from_str(r#"{"tokenize":true}"#).unwrap();
+        assert!(options.tokenize && options.blocksize == 0);
+        for json in &[
+            r#"{"blocksize":1}"#,
+            r#"{"blocksize":1,"hash_length":9999}"#,
+            r#"{"hash_length":9999}"#,
+            r#"{"hash_length":1}"#,
+        ] {
+            assert!(
+                serde_json::from_str::<SpamsumOptions>(json).is_err(),
+                "{}",
+                json
+            );
+        }
+    }
+}
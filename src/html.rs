@@ -0,0 +1,192 @@
+use alloc::vec::Vec;
+
+use crate::filter::Filter;
+
+// longest entity that is decoded, e.g. "&#x0010FFFF;"
+const MAX_ENTITY_LENGTH: usize = 12;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum State {
+    Text,
+    // after "<", which only starts a tag if a name (or "/", "!", "?") follows
+    TagStart,
+    Tag,
+    Quoted(u8),
+    // after "<!" and "<!-"
+    Declaration,
+    DeclarationDash,
+    // the number of dashes just seen, up to 2
+    Comment(u8),
+}
+
+// Removes HTML tags and comments (and optionally decodes entities) from input
+// fed byte by byte. Text that only looks like markup, like "a < b", is kept.
+#[derive(Clone, Debug)]
+pub(crate) struct HtmlFilter {
+    decode_entities: bool,
+    state: State,
+    // the entity read so far, starting with "&"
+    entity: Vec<u8>,
+}
+
+impl HtmlFilter {
+    pub(crate) fn new(decode_entities: bool) -> HtmlFilter {
+        HtmlFilter {
+            decode_entities,
+            state: State::Text,
+            entity: Vec::with_capacity(MAX_ENTITY_LENGTH),
+        }
+    }
+
+    // Emits the decoded entity, or the entity as it is if it is unknown or
+    // incomplete.
+    fn flush_entity(&mut self, emit: &mut dyn FnMut(u8)) {
+        match decode_entity(&self.entity) {
+            Some(decoded) => {
+                let mut buffer = [0; 4];
+                for &c in decoded.encode_utf8(&mut buffer).as_bytes() {
+                    emit(c);
+                }
+            }
+            None => self.entity.iter().for_each(|&c| emit(c)),
+        }
+        self.entity.clear();
+    }
+}
+
+impl Filter for HtmlFilter {
+    fn update(&mut self, c: u8, emit: &mut dyn FnMut(u8)) {
+        if !self.entity.is_empty() {
+            if c == b';' {
+                self.entity.push(c);
+                self.flush_entity(emit);
+                return;
+            }
+            if c.is_ascii_alphanumeric() || (c == b'#' && self.entity.len() == 1) {
+                self.entity.push(c);
+                if self.entity.len() >= MAX_ENTITY_LENGTH {
+                    self.flush_entity(emit);
+                }
+                return;
+            }
+            self.flush_entity(emit);
+        }
+        self.state = match self.state {
+            State::Text => match c {
+                b'<' => State::TagStart,
+                b'&' if self.decode_entities => {
+                    self.entity.push(c);
+                    State::Text
+                }
+                _ => {
+                    emit(c);
+                    State::Text
+                }
+            },
+            State::TagStart => match c {
+                b'!' => State::Declaration,
+                b'/' | b'?' => State::Tag,
+                _ if c.is_ascii_alphabetic() => State::Tag,
+                _ => {
+                    emit(b'<');
+                    self.state = State::Text;
+                    return self.update(c, emit);
+                }
+            },
+            State::Tag => match c {
+                b'>' => State::Text,
+                b'"' | b'\'' => State::Quoted(c),
+                _ => State::Tag,
+            },
+            State::Quoted(quote) if c == quote => State::Tag,
+            State::Quoted(quote) => State::Quoted(quote),
+            State::Declaration => match c {
+                b'-' => State::DeclarationDash,
+                b'>' => State::Text,
+                _ => State::Tag,
+            },
+            State::DeclarationDash => match c {
+                b'-' => State::Comment(0),
+                b'>' => State::Text,
+                _ => State::Tag,
+            },
+            State::Comment(dashes) => match c {
+                b'-' => State::Comment(dashes.saturating_add(1).min(2)),
+                b'>' if dashes == 2 => State::Text,
+                _ => State::Comment(0),
+            },
+        };
+    }
+
+    // Emits whatever is still pending at the end of the input.
+    fn finish(&mut self, emit: &mut dyn FnMut(u8)) {
+        if !self.entity.is_empty() {
+            self.flush_entity(emit);
+        }
+        if self.state == State::TagStart {
+            emit(b'<');
+        }
+        self.state = State::Text;
+    }
+}
+
+// Decodes numeric entities and the most common named ones.
+fn decode_entity(entity: &[u8]) -> Option<char> {
+    let name = entity.strip_prefix(b"&")?.strip_suffix(b";")?;
+    if let Some(number) = name.strip_prefix(b"#") {
+        let (digits, radix) = match number
+            .strip_prefix(b"x")
+            .or_else(|| number.strip_prefix(b"X"))
+        {
+            Some(digits) => (digits, 16),
+            None => (number, 10),
+        };
+        let digits = core::str::from_utf8(digits).ok()?;
+        return core::char::from_u32(u32::from_str_radix(digits, radix).ok()?);
+    }
+    match name {
+        b"amp" => Some('&'),
+        b"lt" => Some('<'),
+        b"gt" => Some('>'),
+        b"quot" => Some('"'),
+        b"apos" => Some('\''),
+        b"nbsp" => Some('\u{a0}'),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strip_html(input: &[u8], decode_entities: bool) -> String {
+        let mut output = Vec::new();
+        let mut filter = HtmlFilter::new(decode_entities);
+        let mut emit = |c| output.push(c);
+        for &c in input {
+            filter.update(c, &mut emit);
+        }
+        filter.finish(&mut emit);
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_strip_html() {
+        let input = b"<!DOCTYPE html><html><body class=\"a>b\">\
+            <!-- <p>hidden</p> -->\
+            <p>Dear&nbsp;Sir</p><br/>1 < 2 &amp;&#62;&#x3C; &unknown; &amp<";
+        assert_eq!(
+            strip_html(input, false),
+            "Dear&nbsp;Sir1 < 2 &amp;&#62;&#x3C; &unknown; &amp<"
+        );
+        assert_eq!(
+            strip_html(input, true),
+            "Dear\u{a0}Sir1 < 2 &>< &unknown; &amp<"
+        );
+    }
+
+    #[test]
+    fn test_strip_html_comments() {
+        assert_eq!(strip_html(b"a<!-- b -- > c --->d<!->e<!>f", false), "adef");
+    }
+}
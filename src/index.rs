@@ -0,0 +1,395 @@
+use std::cmp::{self, Reverse};
+use std::convert::TryInto;
+use std::io::Write;
+use std::str;
+
+#[cfg(feature = "mmap")]
+use std::{fs, path::Path};
+
+#[cfg(feature = "mmap")]
+use memmap2::Mmap;
+
+use crate::compare::compare_normalized;
+use crate::ngram::hashed_keys;
+use crate::parse::parse_hash;
+use crate::{HashBuffer, Spamsum, SpamsumError, LEFT_HASH_LENGTH, RIGHT_HASH_LENGTH};
+
+// The index format, all numbers little-endian:
+//
+// - the magic bytes, the format version (u32), 4 reserved bytes and the
+//   number of spamsums, keys and postings (u64 each)
+// - for each spamsum: its blocksize (u32), the lengths (u8) of the left and
+//   right hash and both hashes padded to their maximum length
+// - the keys of all substrings (see `hashed_keys()`) in ascending order, each
+//   with the end of its ids in the postings (u64 each)
+// - the postings: the ids (u32) of the spamsums containing each key
+//
+// Everything has a fixed size, so it can be looked up without parsing.
+const MAGIC: &[u8; 8] = b"SPAMIDX\0";
+const FORMAT_VERSION: u32 = 1;
+const HEADER_LENGTH: usize = 40;
+const ENTRY_LENGTH: usize = 4 + 1 + 1 + (LEFT_HASH_LENGTH + RIGHT_HASH_LENGTH) as usize;
+const KEY_LENGTH: usize = 16;
+const POSTING_LENGTH: usize = 4;
+
+// Only spamsums with hashes of at most the default length fit into an entry.
+fn check_hash_lengths(spamsum: &Spamsum) -> Result<(), SpamsumError> {
+    if spamsum.left_hash.len() > LEFT_HASH_LENGTH as usize
+        || spamsum.right_hash.len() > RIGHT_HASH_LENGTH as usize
+    {
+        return Err(SpamsumError::InvalidDatabase("hash too long"));
+    }
+    Ok(())
+}
+
+/// A read-only index of spamsums that is queried directly from its binary
+/// format, e.g. from a memory-mapped file.
+///
+/// Like `SpamsumDatabase`, only spamsums sharing a 7-character substring
+/// with the query are compared, but only the parts of the index needed for a
+/// query are read. With the `mmap` feature, `open()` maps an index file into
+/// memory, so even huge indexes are queried with little resident memory.
+///
+/// The index is created with `write_index()` and its spamsums are identified
+/// by their position in the list it was written from. It is not checksummed;
+/// corruption is only detected when it leads to invalid spamsums.
+///
+/// ```
+/// use spamsum::{get_spamsum, write_index, SpamsumIndex};
+///
+/// let spamsums = vec![
+///     get_spamsum(b"Hello").unwrap(),
+///     get_spamsum(b"Goodbye").unwrap(),
+/// ];
+/// let mut data = Vec::new();
+/// write_index(&spamsums, &mut data).unwrap();
+///
+/// let index = SpamsumIndex::from_data(data).unwrap();
+/// let query = get_spamsum(b"Goodbye").unwrap();
+/// assert_eq!(index.query(&query, 50).unwrap(), vec![(1, 100)]);
+/// ```
+#[derive(Debug)]
+pub struct SpamsumIndex<D = Vec<u8>> {
+    data: D,
+    len: usize,
+    key_count: usize,
+}
+
+/// Writes the spamsums in the format read by `SpamsumIndex`.
+///
+/// This holds all substrings of the spamsums in memory while writing, about
+/// one kilobyte per spamsum. Like `SpamsumDatabase::save()`, it fails
+/// without writing anything for hashes longer than the default length.
+pub fn write_index<'a, I, W>(spamsums: I, mut writer: W) -> Result<(), SpamsumError>
+where
+    I: IntoIterator<Item = &'a Spamsum>,
+    W: Write,
+{
+    let mut entries = Vec::new();
+    let mut postings: Vec<(u64, u32)> = Vec::new();
+    let mut len: usize = 0;
+    for spamsum in spamsums {
+        let id: u32 = len
+            .try_into()
+            .map_err(|_| SpamsumError::InvalidDatabase("too many spamsums"))?;
+        check_hash_lengths(spamsum)?;
+        entries.extend_from_slice(&spamsum.left_hash_blocksize.to_le_bytes());
+        entries.push(spamsum.left_hash.len() as u8);
+        entries.push(spamsum.right_hash.len() as u8);
+        for (hash, hash_length) in &[
+            (&spamsum.left_hash, LEFT_HASH_LENGTH),
+            (&spamsum.right_hash, RIGHT_HASH_LENGTH),
+        ] {
+            entries.extend_from_slice(hash.as_bytes());
+            entries.resize(entries.len() + *hash_length as usize - hash.len(), 0);
+        }
+        let mut keys: Vec<u64> = hashed_keys(&spamsum.normalized()).collect();
+        // a hash may contain the same substring more than once
+        keys.sort_unstable();
+        keys.dedup();
+        postings.extend(keys.into_iter().map(|key| (key, id)));
+        len += 1;
+    }
+    postings.sort_unstable();
+
+    let mut keys = Vec::new();
+    for (i, &(key, _)) in postings.iter().enumerate() {
+        if postings.get(i + 1).map(|&(next, _)| next) != Some(key) {
+            keys.extend_from_slice(&key.to_le_bytes());
+            keys.extend_from_slice(&(i as u64 + 1).to_le_bytes());
+        }
+    }
+    let mut header = Vec::with_capacity(HEADER_LENGTH);
+    header.extend_from_slice(MAGIC);
+    header.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    header.extend_from_slice(&[0; 4]);
+    header.extend_from_slice(&(len as u64).to_le_bytes());
+    header.extend_from_slice(&((keys.len() / KEY_LENGTH) as u64).to_le_bytes());
+    header.extend_from_slice(&(postings.len() as u64).to_le_bytes());
+    writer.write_all(&header)?;
+    writer.write_all(&entries)?;
+    writer.write_all(&keys)?;
+    let mut ids = Vec::with_capacity(postings.len() * POSTING_LENGTH);
+    for &(_, id) in &postings {
+        ids.extend_from_slice(&id.to_le_bytes());
+    }
+    writer.write_all(&ids)?;
+    Ok(())
+}
+
+#[cfg(feature = "mmap")]
+impl SpamsumIndex<Mmap> {
+    /// Memory-maps an index file written by `write_index()`.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<SpamsumIndex<Mmap>, SpamsumError> {
+        let file = fs::File::open(path)?;
+        // Safety: the mapping is only read, but modifying the file while it
+        // is mapped may lead to wrong results (or a crash if truncated), just
+        // like with any other index using mmap.
+        let mmap = unsafe { Mmap::map(&file) }?;
+        SpamsumIndex::from_data(mmap)
+    }
+}
+
+impl<D: AsRef<[u8]>> SpamsumIndex<D> {
+    /// Uses data written by `write_index()` as the index.
+    pub fn from_data(data: D) -> Result<SpamsumIndex<D>, SpamsumError> {
+        let bytes = data.as_ref();
+        if bytes.len() < HEADER_LENGTH || &bytes[..MAGIC.len()] != MAGIC {
+            return Err(SpamsumError::InvalidDatabase("not a spamsum index"));
+        }
+        if read_u32(bytes, MAGIC.len()) != FORMAT_VERSION {
+            return Err(SpamsumError::InvalidDatabase("unsupported format version"));
+        }
+        let counts = [
+            read_u64(bytes, 16),
+            read_u64(bytes, 24),
+            read_u64(bytes, 32),
+        ];
+        let lengths = [ENTRY_LENGTH, KEY_LENGTH, POSTING_LENGTH];
+        let expected_length = counts
+            .iter()
+            .zip(&lengths)
+            .try_fold(HEADER_LENGTH as u64, |total, (&count, &length)| {
+                total.checked_add(count.checked_mul(length as u64)?)
+            });
+        if expected_length != Some(bytes.len() as u64) {
+            return Err(SpamsumError::InvalidDatabase("unexpected length"));
+        }
+        Ok(SpamsumIndex {
+            len: counts[0] as usize,
+            key_count: counts[1] as usize,
+            data,
+        })
+    }
+
+    /// Returns the number of spamsums in the index.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the spamsum with the given id, or `None` if there is none.
+    pub fn get(&self, id: usize) -> Result<Option<Spamsum>, SpamsumError> {
+        if id >= self.len {
+            return Ok(None);
+        }
+        let entry = &self.data.as_ref()[HEADER_LENGTH + id * ENTRY_LENGTH..][..ENTRY_LENGTH];
+        let blocksize = read_u32(entry, 0);
+        if blocksize == 0 {
+            return Err(SpamsumError::InvalidDatabase("invalid blocksize"));
+        }
+        let left = &entry[6..][..LEFT_HASH_LENGTH as usize];
+        let right = &entry[6 + LEFT_HASH_LENGTH as usize..];
+        Ok(Some(Spamsum {
+            left_hash_blocksize: blocksize,
+            left_hash: read_hash(left, entry[4], LEFT_HASH_LENGTH)?,
+            right_hash: read_hash(right, entry[5], RIGHT_HASH_LENGTH)?,
+        }))
+    }
+
+    /// Returns the ids of all spamsums with a score of at least `min_score`
+    /// against `spamsum` as `(id, score)`, best first and ties by id.
+    pub fn query(
+        &self,
+        spamsum: &Spamsum,
+        min_score: u32,
+    ) -> Result<Vec<(usize, u32)>, SpamsumError> {
+        let min_score = cmp::max(min_score, 1);
+        let spamsum = spamsum.normalized();
+        let mut candidates = Vec::new();
+        for key in hashed_keys(&spamsum) {
+            candidates.extend(self.postings(key)?);
+        }
+        candidates.sort_unstable();
+        candidates.dedup();
+        let mut matches = Vec::new();
+        for id in candidates {
+            let candidate = self
+                .get(id)?
+                .ok_or(SpamsumError::InvalidDatabase("invalid id"))?;
+            let score = compare_normalized(
+                &spamsum,
+                &candidate.normalized(),
+                Default::default(),
+                min_score,
+            );
+            if score >= min_score {
+                matches.push((id, score));
+            }
+        }
+        matches.sort_by_key(|&(id, score)| (Reverse(score), id));
+        Ok(matches)
+    }
+
+    // Returns the ids of the spamsums containing the key.
+    fn postings(&self, key: u64) -> Result<impl Iterator<Item = usize> + '_, SpamsumError> {
+        let bytes = self.data.as_ref();
+        let keys_start = HEADER_LENGTH + self.len * ENTRY_LENGTH;
+        let postings_start = keys_start + self.key_count * KEY_LENGTH;
+        let key_at = |i: usize| read_u64(bytes, keys_start + i * KEY_LENGTH);
+        let end_at = |i: usize| read_u64(bytes, keys_start + i * KEY_LENGTH + 8) as usize;
+        // binary search for the key
+        let (mut low, mut high) = (0, self.key_count);
+        while low < high {
+            let middle = low + (high - low) / 2;
+            if key_at(middle) < key {
+                low = middle + 1;
+            } else {
+                high = middle;
+            }
+        }
+        let range = if low < self.key_count && key_at(low) == key {
+            let start = if low == 0 { 0 } else { end_at(low - 1) };
+            let end = end_at(low);
+            if start > end || end > (bytes.len() - postings_start) / POSTING_LENGTH {
+                return Err(SpamsumError::InvalidDatabase("invalid postings"));
+            }
+            start..end
+        } else {
+            0..0
+        };
+        Ok(range.map(move |i| read_u32(bytes, postings_start + i * POSTING_LENGTH) as usize))
+    }
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+fn read_hash(padded: &[u8], length: u8, hash_length: u32) -> Result<HashBuffer, SpamsumError> {
+    let invalid = SpamsumError::InvalidDatabase("invalid hash");
+    let hash = padded.get(..length as usize).ok_or(invalid)?;
+    let hash = str::from_utf8(hash).map_err(|_| SpamsumError::InvalidDatabase("invalid hash"))?;
+    parse_hash(hash, hash_length).map_err(|_| SpamsumError::InvalidDatabase("invalid hash"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{get_configured_spamsum, pseudo_random_bytes, SpamsumDatabase, SpamsumOptions};
+
+    fn spamsums() -> Vec<Spamsum> {
+        [
+            "48:ABCDEFGHIJKLMNQR:WXYZ",
+            "12:ABCDEFGHIJKLMNOP:ABCD",
+            "48:ABCDEFGHIJKLMNOP:ABCD",
+            "3:Hn:Hn",
+            "24:QRSTUVWX:ABCDEFGHIJKLMNOP",
+            "48:ABCDEFGHIJKLMNOPPPPPPPPPPPPPPPPPPPPPPPPPPPPPPPPPPPPPPPPPPPPPPPPP:ABCDABCDABCDABCDABCDABCDABCDABCD",
+        ]
+        .iter()
+        .map(|s| s.parse().unwrap())
+        .collect()
+    }
+
+    #[test]
+    fn test_query_matches_database() {
+        let spamsums = spamsums();
+        let mut data = Vec::new();
+        write_index(&spamsums, &mut data).unwrap();
+        let index = SpamsumIndex::from_data(&data[..]).unwrap();
+        let mut database = SpamsumDatabase::new();
+        for spamsum in &spamsums {
+            database.insert(spamsum.clone(), ());
+        }
+        assert_eq!(index.len(), spamsums.len());
+        for (id, spamsum) in spamsums.iter().enumerate() {
+            assert_eq!(index.get(id).unwrap().as_ref(), Some(spamsum));
+            for &min_score in &[1, 90] {
+                assert_eq!(
+                    index.query(spamsum, min_score).unwrap(),
+                    database.query(spamsum, min_score)
+                );
+            }
+        }
+        assert_eq!(index.get(spamsums.len()).unwrap(), None);
+    }
+
+    #[test]
+    fn test_invalid_index() {
+        let mut data = Vec::new();
+        write_index(&spamsums(), &mut data).unwrap();
+        let error = |data: &[u8]| match SpamsumIndex::from_data(data) {
+            Err(SpamsumError::InvalidDatabase(reason)) => reason,
+            _ => panic!("opened an invalid index"),
+        };
+        assert_eq!(error(&data[..8]), "not a spamsum index");
+        assert_eq!(error(&data[..data.len() - 1]), "unexpected length");
+        let mut corrupted = data.clone();
+        corrupted[HEADER_LENGTH + 6] = b'-';
+        let index = SpamsumIndex::from_data(&corrupted).unwrap();
+        assert!(index.get(0).is_err());
+
+        let mut empty = Vec::new();
+        write_index(&[], &mut empty).unwrap();
+        let index = SpamsumIndex::from_data(empty).unwrap();
+        assert!(index.is_empty());
+        assert!(index.query(&spamsums()[0], 1).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_write_index_hash_length() {
+        let input = pseudo_random_bytes(50_000, 1);
+        for &hash_length in &[16, 64, 128, 256] {
+            let options = SpamsumOptions::builder()
+                .hash_length(hash_length)
+                .build()
+                .unwrap();
+            let spamsum = get_configured_spamsum(&input, options).unwrap();
+            let mut data = Vec::new();
+            if hash_length <= LEFT_HASH_LENGTH {
+                write_index(std::slice::from_ref(&spamsum), &mut data).unwrap();
+                let index = SpamsumIndex::from_data(data).unwrap();
+                assert_eq!(index.get(0).unwrap(), Some(spamsum));
+            } else {
+                assert!(spamsum.left_hash().len() > LEFT_HASH_LENGTH as usize);
+                assert!(matches!(
+                    write_index(&[spamsum], &mut data),
+                    Err(SpamsumError::InvalidDatabase("hash too long"))
+                ));
+                assert!(data.is_empty());
+            }
+        }
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn test_open() {
+        let path = std::env::temp_dir().join(format!("spamsum-index-{}", std::process::id()));
+        write_index(&spamsums(), fs::File::create(&path).unwrap()).unwrap();
+        let index = SpamsumIndex::open(&path).unwrap();
+        assert_eq!(
+            index.query(&spamsums()[2], 90).unwrap(),
+            vec![(2, 100), (4, 100), (5, 96)]
+        );
+        drop(index);
+        fs::remove_file(path).unwrap();
+    }
+}
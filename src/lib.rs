@@ -1,28 +1,199 @@
-use std::fmt;
-use std::num::Wrapping;
-use std::result::Result;
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+use alloc::borrow::Cow;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use core::fmt;
+use core::num::Wrapping;
+use core::result::Result;
+#[cfg(feature = "std")]
+use std::io::{self, Read};
+
+mod algorithm;
+mod alphabet;
+#[cfg(feature = "std")]
+mod bloom;
+mod buffer;
+mod chunk;
+#[cfg(feature = "std")]
+mod cluster;
+mod compare;
+mod containment;
+#[cfg(feature = "std")]
+mod database;
+mod digest;
+mod error;
+mod explain;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+mod filter;
+mod hasher;
+mod html;
+#[cfg(feature = "std")]
+mod index;
+#[cfg(feature = "std")]
+mod lsh;
+#[cfg(feature = "std")]
+mod matcher;
+mod matrix;
+mod mime;
+#[cfg(feature = "minhash")]
+mod minhash;
+mod named;
+#[cfg(feature = "std")]
+mod ngram;
+#[cfg(feature = "nilsimsa")]
+mod nilsimsa;
+#[cfg(feature = "rayon")]
+mod parallel;
+mod parse;
+mod rolling;
+#[cfg(feature = "sdhash")]
+mod sdhash;
+#[cfg(feature = "std")]
+mod search;
+mod segment;
+#[cfg(feature = "serde")]
+pub mod serde_str;
+#[cfg(feature = "simhash")]
+mod simhash;
+#[cfg(feature = "tlsh")]
+mod tlsh;
+mod url;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+mod window;
+#[cfg(any(feature = "minhash", feature = "simhash"))]
+mod word;
+mod yara;
+
+pub use algorithm::{Algorithm, Digest, FuzzyHash};
+pub use alphabet::Alphabet;
+#[cfg(feature = "std")]
+pub use bloom::BloomFilter;
+use buffer::HashBuffer;
+pub use chunk::{chunk_boundaries, chunk_hash, ChunkBoundaries, ChunkHasher, FnvHasher};
+#[cfg(feature = "std")]
+pub use cluster::{
+    connected_components, fuzzy_connected_components, fuzzy_hierarchical_clustering,
+    hierarchical_clustering, Dendrogram, Linkage, Merge,
+};
+#[cfg(feature = "minhash")]
+pub use cluster::{minhash_connected_components, minhash_hierarchical_clustering};
+pub use compare::{
+    compare, compare_with_cutoff, compare_with_options, similarity_f64, CompareOptions,
+};
+pub use containment::{containment, Containment};
+#[cfg(feature = "std")]
+pub use database::{DatabaseMetadata, SpamsumDatabase};
+pub use digest::FuzzyDigest;
+pub use error::SpamsumError;
+pub use explain::{explain, ChunkDiff, Explanation};
+use filter::{filter_bytes, ByteFilter};
+use hasher::DigestState;
+pub use hasher::SpamsumHasher;
+#[cfg(feature = "std")]
+pub use index::{write_index, SpamsumIndex};
+#[cfg(feature = "std")]
+pub use lsh::LshOptions;
+#[cfg(feature = "std")]
+pub use matcher::StreamingMatcher;
+pub use matrix::{
+    compare_all, compare_all_with_cancel, compare_all_with_threshold, SimilarityMatrix,
+};
+use mime::filter_mime;
+#[cfg(feature = "minhash")]
+pub use minhash::{get_minhash, MinHash, MinHashOptions, MinHasher, Shingles};
+#[cfg(feature = "std")]
+pub use named::parse_hash_list;
+pub use named::NamedSpamsum;
+#[cfg(feature = "nilsimsa")]
+pub use nilsimsa::{get_nilsimsa, Nilsimsa, NilsimsaHasher};
+#[cfg(feature = "rayon")]
+pub use parallel::{
+    compare_all_parallel, compare_all_parallel_with_cancel, compare_many, hash_many,
+};
+pub use parse::ParseSpamsumError;
+pub use rolling::RollingHash;
+#[cfg(feature = "sdhash")]
+pub use sdhash::{get_sdbf, Sdbf, SdbfHasher};
+#[cfg(feature = "std")]
+pub use search::{find_top_k, similarity_join};
+#[cfg(feature = "std")]
+pub use segment::get_segmented_spamsum_from_reader;
+pub use segment::{get_segmented_spamsum, SegmentedHasher, SegmentedSpamsum};
+#[cfg(feature = "simhash")]
+pub use simhash::{get_simhash, SimHash, SimHashHasher};
+#[cfg(feature = "tlsh")]
+pub use tlsh::{get_spamsum_and_tlsh, get_tlsh, Tlsh, TlshHasher};
+pub use window::{get_window_spamsums, WindowHasher};
+pub use yara::yara_rule;
 
 const LEFT_HASH_LENGTH: u32 = 64;
+#[cfg(feature = "std")]
 const RIGHT_HASH_LENGTH: u32 = LEFT_HASH_LENGTH / 2;
+// the longest left hash that can be configured
+const MAX_HASH_LENGTH: u32 = 256;
 const MIN_BLOCKSIZE: u32 = 3;
+const MAX_BLOCKSIZE: u32 = MIN_BLOCKSIZE << 30;
 const ROLLING_WINDOW: u32 = 7;
 // FNV hash parameters
 const HASH_PRIME: Wrapping<u32> = Wrapping(0x01000193);
 const HASH_INIT: Wrapping<u32> = Wrapping(0x28021967);
 
-static BASE64_CHARSET: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+// chunk size used when reading input from a reader
+#[cfg(feature = "std")]
+const READ_BUFFER_SIZE: usize = 64 * 1024;
 
-#[derive(Debug)]
+const BASE64_CHARSET: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "parse::SpamsumFields")
+)]
 pub struct Spamsum {
     left_hash_blocksize: u32,
-    left_hash: String,
-    right_hash: String,
+    left_hash: HashBuffer,
+    right_hash: HashBuffer,
 }
 
 impl Spamsum {
+    /// The blocksize of the left hash, the right hash uses twice this size.
+    pub fn blocksize(&self) -> u32 {
+        self.left_hash_blocksize
+    }
+
+    pub fn left_hash(&self) -> &str {
+        &self.left_hash
+    }
+
+    pub fn right_hash(&self) -> &str {
+        &self.right_hash
+    }
+
     #[inline]
-    fn right_hash_blocksize(&self) -> u32 {
-        self.left_hash_blocksize * 2
+    fn right_hash_blocksize(&self) -> u64 {
+        u64::from(self.left_hash_blocksize) * 2
+    }
+}
+
+/// Spamsums are ordered by blocksize, then left hash, then right hash.
+impl Ord for Spamsum {
+    fn cmp(&self, other: &Spamsum) -> Ordering {
+        self.blocksize()
+            .cmp(&other.blocksize())
+            .then_with(|| self.left_hash().cmp(other.left_hash()))
+            .then_with(|| self.right_hash().cmp(other.right_hash()))
+    }
+}
+
+impl PartialOrd for Spamsum {
+    fn partial_cmp(&self, other: &Spamsum) -> Option<Ordering> {
+        Some(self.cmp(other))
     }
 }
 
@@ -36,165 +207,534 @@ impl fmt::Display for Spamsum {
     }
 }
 
-struct HashState {
-    window: [u8; ROLLING_WINDOW as usize],
-    window_sum: Wrapping<u32>,  // h1
-    window_sum2: Wrapping<u32>, // h2
-    shift_hash: Wrapping<u32>,  // h3
-    position: u32,
-    left_hash_value: Wrapping<u32>,
-    right_hash_value: Wrapping<u32>,
-}
-
-#[derive(Copy, Clone, Default)]
+/// Options for `get_configured_spamsum()` and `SpamsumHasher`.
+///
+/// New options may be added in future versions, so use `builder()` (or
+/// `Default::default()`) to create them.
+#[derive(Copy, Clone, Default, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "SpamsumOptionsFields")
+)]
+#[non_exhaustive]
 pub struct SpamsumOptions {
+    /// A fixed blocksize, or 0 to choose one based on the input length.
+    /// Fixed blocksizes range from 3 to `u32::MAX / 2`. Only spamsums with
+    /// the same or doubled blocksizes can be compared, so the blocksizes
+    /// chosen automatically (3·2ⁿ) are the best choice.
     pub blocksize: u32,
+    /// Skip whitespace characters in the input.
     pub ignore_whitespace: bool,
+    /// Skip ASCII digits in the input, so that numbers like invoice numbers,
+    /// timestamps or per-recipient ids do not change the spamsum.
+    pub strip_digits: bool,
+    /// Cut runs of the same byte off after this many bytes (at least 1), so
+    /// that padding does not skew the spamsum.
+    pub max_run_len: Option<u32>,
+    /// Skip everything up to the first empty line, i.e. e-mail headers.
     pub ignore_headers: bool,
+    /// Skip the headers of MIME messages and of all parts of multipart
+    /// messages, as well as the boundaries between the parts, and hash only
+    /// the bodies. Folded header lines are understood. `SpamsumHasher` has
+    /// to buffer the whole message in this mode.
+    pub ignore_mime_headers: bool,
+    /// Decode quoted-printable and base64 bodies of MIME messages and their
+    /// parts, so that the encoding does not change the hash.
+    /// `SpamsumHasher` has to buffer the whole message in this mode.
+    pub decode_transfer_encoding: bool,
+    /// Skip HTML tags and comments, so that messages that only differ in
+    /// their markup get the same hash.
+    pub ignore_html: bool,
+    /// Decode HTML entities like `&amp;` and `&#62;` in the text, with
+    /// `ignore_html`.
+    pub decode_html_entities: bool,
+    /// Skip lines starting with ">", i.e. the quoted text in e-mail replies,
+    /// so that only the original content is hashed.
+    pub strip_quoted_lines: bool,
+    /// Normalize http(s) URLs, so that per-recipient tracking URLs do not
+    /// change the spamsum: the query and fragment are dropped, and host
+    /// labels and path segments containing digits are replaced by "*".
+    pub normalize_urls: bool,
+    /// Hash words instead of bytes: every whitespace-delimited token is
+    /// hashed into a single byte, ignoring case and ASCII punctuation, so
+    /// that obfuscation like "V.i.a.g.r.a" does not change the spamsum. Only
+    /// spamsums calculated in this mode can be compared with each other.
+    pub tokenize: bool,
+    /// A custom preprocessing step, e.g. to mask IDs, that runs on the input
+    /// after all other filters. `SpamsumHasher` has to buffer the whole input
+    /// when it is set.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub filter: Option<fn(&mut Vec<u8>)>,
+    /// Fail with `SpamsumError::InputLimitExceeded` instead of hashing
+    /// inputs longer than this many bytes (before any filtering).
+    pub max_input_len: Option<u64>,
+    /// A secret key that the hashes of the chunks and the positions where
+    /// the input is split into chunks depend on, so that attackers cannot
+    /// craft inputs that collide with or avoid matching known spamsums.
+    /// Spamsums can only be compared if they were calculated with the same
+    /// key.
+    pub key: Option<u64>,
+    /// The maximum length of the left hash from 2 to 256, or 0 for the
+    /// standard 64. The right hash is half as long. Only spamsums with the
+    /// same hash length can be compared, and only those with the standard
+    /// length can be stored in a `SpamsumIndex`.
+    pub hash_length: u32,
+}
+
+impl SpamsumOptions {
+    /// Returns a builder starting with the default options.
+    ///
+    /// ```
+    /// use spamsum::SpamsumOptions;
+    ///
+    /// let options = SpamsumOptions::builder()
+    ///     .ignore_whitespace(true)
+    ///     .blocksize(48)
+    ///     .build()
+    ///     .unwrap();
+    /// assert_eq!(options.blocksize, 48);
+    /// ```
+    pub fn builder() -> SpamsumOptionsBuilder {
+        SpamsumOptionsBuilder::default()
+    }
+
+    // Whether the input has to be parsed as a MIME message.
+    fn is_mime(&self) -> bool {
+        self.ignore_mime_headers || self.decode_transfer_encoding
+    }
+
+    fn left_hash_length(&self) -> u32 {
+        if self.hash_length == 0 {
+            LEFT_HASH_LENGTH
+        } else {
+            self.hash_length
+        }
+    }
+}
+
+// The fields of deserialized `SpamsumOptions`, which are checked like those
+// of built options.
+#[cfg(feature = "serde")]
+#[derive(Default, serde::Deserialize)]
+#[serde(rename = "SpamsumOptions", default)]
+struct SpamsumOptionsFields {
+    blocksize: u32,
+    ignore_whitespace: bool,
+    strip_digits: bool,
+    max_run_len: Option<u32>,
+    ignore_headers: bool,
+    ignore_mime_headers: bool,
+    decode_transfer_encoding: bool,
+    ignore_html: bool,
+    decode_html_entities: bool,
+    strip_quoted_lines: bool,
+    normalize_urls: bool,
+    tokenize: bool,
+    max_input_len: Option<u64>,
+    key: Option<u64>,
+    hash_length: u32,
 }
 
-pub fn get_spamsum(input: &Vec<u8>) -> Result<Spamsum, &'static str> {
+#[cfg(feature = "serde")]
+impl core::convert::TryFrom<SpamsumOptionsFields> for SpamsumOptions {
+    type Error = SpamsumError;
+
+    fn try_from(fields: SpamsumOptionsFields) -> Result<SpamsumOptions, SpamsumError> {
+        SpamsumOptionsBuilder {
+            options: SpamsumOptions {
+                blocksize: fields.blocksize,
+                ignore_whitespace: fields.ignore_whitespace,
+                strip_digits: fields.strip_digits,
+                max_run_len: fields.max_run_len,
+                ignore_headers: fields.ignore_headers,
+                ignore_mime_headers: fields.ignore_mime_headers,
+                decode_transfer_encoding: fields.decode_transfer_encoding,
+                ignore_html: fields.ignore_html,
+                decode_html_entities: fields.decode_html_entities,
+                strip_quoted_lines: fields.strip_quoted_lines,
+                normalize_urls: fields.normalize_urls,
+                tokenize: fields.tokenize,
+                filter: None,
+                max_input_len: fields.max_input_len,
+                key: fields.key,
+                hash_length: fields.hash_length,
+            },
+        }
+        .build()
+    }
+}
+
+/// A builder for `SpamsumOptions`, see `SpamsumOptions::builder()`.
+#[derive(Copy, Clone, Default, Debug)]
+pub struct SpamsumOptionsBuilder {
+    options: SpamsumOptions,
+}
+
+impl SpamsumOptionsBuilder {
+    pub fn blocksize(mut self, blocksize: u32) -> SpamsumOptionsBuilder {
+        self.options.blocksize = blocksize;
+        self
+    }
+
+    pub fn ignore_whitespace(mut self, ignore_whitespace: bool) -> SpamsumOptionsBuilder {
+        self.options.ignore_whitespace = ignore_whitespace;
+        self
+    }
+
+    pub fn strip_digits(mut self, strip_digits: bool) -> SpamsumOptionsBuilder {
+        self.options.strip_digits = strip_digits;
+        self
+    }
+
+    pub fn max_run_len(mut self, max_run_len: Option<u32>) -> SpamsumOptionsBuilder {
+        self.options.max_run_len = max_run_len;
+        self
+    }
+
+    pub fn ignore_headers(mut self, ignore_headers: bool) -> SpamsumOptionsBuilder {
+        self.options.ignore_headers = ignore_headers;
+        self
+    }
+
+    pub fn ignore_mime_headers(mut self, ignore_mime_headers: bool) -> SpamsumOptionsBuilder {
+        self.options.ignore_mime_headers = ignore_mime_headers;
+        self
+    }
+
+    pub fn decode_transfer_encoding(
+        mut self,
+        decode_transfer_encoding: bool,
+    ) -> SpamsumOptionsBuilder {
+        self.options.decode_transfer_encoding = decode_transfer_encoding;
+        self
+    }
+
+    pub fn ignore_html(mut self, ignore_html: bool) -> SpamsumOptionsBuilder {
+        self.options.ignore_html = ignore_html;
+        self
+    }
+
+    pub fn decode_html_entities(mut self, decode_html_entities: bool) -> SpamsumOptionsBuilder {
+        self.options.decode_html_entities = decode_html_entities;
+        self
+    }
+
+    pub fn strip_quoted_lines(mut self, strip_quoted_lines: bool) -> SpamsumOptionsBuilder {
+        self.options.strip_quoted_lines = strip_quoted_lines;
+        self
+    }
+
+    pub fn normalize_urls(mut self, normalize_urls: bool) -> SpamsumOptionsBuilder {
+        self.options.normalize_urls = normalize_urls;
+        self
+    }
+
+    pub fn tokenize(mut self, tokenize: bool) -> SpamsumOptionsBuilder {
+        self.options.tokenize = tokenize;
+        self
+    }
+
+    pub fn max_input_len(mut self, max_input_len: Option<u64>) -> SpamsumOptionsBuilder {
+        self.options.max_input_len = max_input_len;
+        self
+    }
+
+    /// Sets a secret key.
+    ///
+    /// ```
+    /// use spamsum::{get_configured_spamsum, SpamsumOptions};
+    ///
+    /// let input = b"Please buy my stuff\nDear Sir or Madam\n";
+    /// let options = SpamsumOptions::builder().key(Some(42)).build().unwrap();
+    /// let keyed = get_configured_spamsum(input, options).unwrap();
+    /// assert_eq!(keyed, get_configured_spamsum(input, options).unwrap());
+    ///
+    /// let other = SpamsumOptions::builder().key(Some(43)).build().unwrap();
+    /// assert_ne!(keyed, get_configured_spamsum(input, other).unwrap());
+    /// ```
+    pub fn key(mut self, key: Option<u64>) -> SpamsumOptionsBuilder {
+        self.options.key = key;
+        self
+    }
+
+    /// Sets a custom preprocessing step.
+    ///
+    /// ```
+    /// use spamsum::{get_configured_spamsum, get_spamsum, SpamsumOptions};
+    ///
+    /// fn mask_digits(input: &mut Vec<u8>) {
+    ///     for c in input.iter_mut().filter(|c| c.is_ascii_digit()) {
+    ///         *c = b'0';
+    ///     }
+    /// }
+    ///
+    /// let options = SpamsumOptions::builder().filter(mask_digits).build().unwrap();
+    /// assert_eq!(
+    ///     get_configured_spamsum(b"Order 1337", options).unwrap(),
+    ///     get_spamsum(b"Order 0000").unwrap()
+    /// );
+    /// ```
+    pub fn filter(mut self, filter: fn(&mut Vec<u8>)) -> SpamsumOptionsBuilder {
+        self.options.filter = Some(filter);
+        self
+    }
+
+    /// Sets the maximum length of the left hash.
+    ///
+    /// ```
+    /// use spamsum::{get_configured_spamsum, SpamsumOptions};
+    ///
+    /// let options = SpamsumOptions::builder().hash_length(16).build().unwrap();
+    /// let input: Vec<u8> = (0..10000u32).map(|i| (i * i % 251) as u8).collect();
+    /// let spamsum = get_configured_spamsum(&input, options).unwrap();
+    /// assert!(spamsum.left_hash().len() <= 16);
+    /// assert!(spamsum.right_hash().len() <= 8);
+    /// ```
+    pub fn hash_length(mut self, hash_length: u32) -> SpamsumOptionsBuilder {
+        self.options.hash_length = hash_length;
+        self
+    }
+
+    /// Returns the options, or an error if they cannot be used for hashing.
+    pub fn build(self) -> Result<SpamsumOptions, SpamsumError> {
+        check_options(&self.options)?;
+        Ok(self.options)
+    }
+}
+
+pub fn get_spamsum(input: &[u8]) -> Result<Spamsum, SpamsumError> {
     let options: SpamsumOptions = Default::default();
-    get_configured_spamsum(&input, options)
+    get_configured_spamsum(input, options)
 }
 
 pub fn get_configured_spamsum(
-    input: &Vec<u8>,
+    input: &[u8],
     options: SpamsumOptions,
-) -> Result<Spamsum, &'static str> {
-    let mut valid_input: Vec<u8> = input.clone();
-    filter_input(&mut valid_input, options);
-    let blocksize = if options.blocksize > 0 {
-        options.blocksize
+) -> Result<Spamsum, SpamsumError> {
+    spamsum_with_chunk_hasher::<FnvHasher>(input, options)
+}
+
+/// Like `get_configured_spamsum()`, but hashes the chunks with `H` instead of
+/// `FnvHasher`.
+pub fn spamsum_with_chunk_hasher<H: ChunkHasher>(
+    input: &[u8],
+    options: SpamsumOptions,
+) -> Result<Spamsum, SpamsumError> {
+    check_options(&options)?;
+    if let Some(max_input_len) = options.max_input_len {
+        if input.len() as u64 > max_input_len {
+            return Err(SpamsumError::InputLimitExceeded(max_input_len));
+        }
+    }
+    // all candidate blocksizes are hashed at once, so there is no need to
+    // hash the input again when the blocksize turns out to be too large
+    let mut state = DigestState::<H>::new(options);
+    if options.is_mime() || options.filter.is_some() {
+        // these options need all of the input at once
+        for &c in filter_input(input, options).iter() {
+            state.update(c);
+        }
+        return state.digest();
+    }
+    // the other filters are applied while hashing, without copying the input
+    let input = skip_headers(input, options);
+    let mut filter = ByteFilter::new(options);
+    if filter.is_empty() {
+        state.update_slice(input);
     } else {
-        guess_initial_blocksize(valid_input.len() as u32)
-    };
-    let mut result = get_spamsum_with_set_blocksize(&valid_input, blocksize, options).unwrap();
-    if options.blocksize == 0 {
-        while result.left_hash_blocksize > MIN_BLOCKSIZE
-            && result.left_hash.len() - 1 < RIGHT_HASH_LENGTH as usize
-        {
-            result = get_spamsum_with_set_blocksize(
-                &valid_input,
-                result.left_hash_blocksize / 2,
-                options,
-            )
-            .unwrap();
+        let mut update = |c| state.update(c);
+        for &c in input {
+            filter.update(c, &mut update);
         }
+        filter.finish(&mut update);
     }
-    Ok(result)
+    state.digest()
 }
 
-fn filter_input(input: &mut Vec<u8>, options: SpamsumOptions) {
-    if options.ignore_headers {
-        // find two consecutive newlines indicating the end of email headers
-        let two_newlines = input.windows(2).position(|window| window == [0xA, 0xA]);
-        let new_start = match two_newlines {
-            Some(position) => position + 2,
-            _ => 0,
+/// Calculates the spamsum of everything read from `reader`.
+///
+/// The input is read in fixed-size chunks, so memory usage does not depend
+/// on the size of the input.
+#[cfg(feature = "std")]
+pub fn get_spamsum_from_reader<R: Read>(
+    reader: R,
+    options: SpamsumOptions,
+) -> Result<Spamsum, SpamsumError> {
+    get_spamsum_from_reader_with_cancel(reader, options, &|| false)
+}
+
+/// Like `get_spamsum_from_reader()`, but calls `should_cancel` before every
+/// read and fails with `SpamsumError::Cancelled` as soon as it returns true,
+/// so that e.g. a GUI can abort hashing a large file.
+///
+/// ```
+/// use std::sync::atomic::{AtomicBool, Ordering};
+/// use spamsum::{get_spamsum_from_reader_with_cancel, SpamsumError};
+///
+/// let cancelled = AtomicBool::new(false);
+/// let should_cancel = || cancelled.load(Ordering::Relaxed);
+/// let input = &b"Please buy my stuff\nDear Sir or Madam\n"[..];
+/// let spamsum = get_spamsum_from_reader_with_cancel(input, Default::default(), &should_cancel);
+/// assert_eq!(spamsum.unwrap().to_string(), "3:clclDDvWIMF/hv:cGZ/EJv");
+/// cancelled.store(true, Ordering::Relaxed);
+/// let spamsum = get_spamsum_from_reader_with_cancel(input, Default::default(), &should_cancel);
+/// assert!(matches!(spamsum, Err(SpamsumError::Cancelled)));
+/// ```
+#[cfg(feature = "std")]
+pub fn get_spamsum_from_reader_with_cancel<R: Read>(
+    mut reader: R,
+    options: SpamsumOptions,
+    should_cancel: &dyn Fn() -> bool,
+) -> Result<Spamsum, SpamsumError> {
+    let mut hasher = SpamsumHasher::with_options(options)?;
+    let mut buffer = vec![0; READ_BUFFER_SIZE];
+    loop {
+        if should_cancel() {
+            return Err(SpamsumError::Cancelled);
+        }
+        match reader.read(&mut buffer) {
+            Ok(0) => return hasher.finalize(),
+            Ok(length) => {
+                hasher.update(&buffer[..length]);
+                if hasher.exceeds_max_input_len() {
+                    // stop reading early
+                    return hasher.finalize();
+                }
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(SpamsumError::Io(e)),
+        }
+    }
+}
+
+fn check_options(options: &SpamsumOptions) -> Result<(), SpamsumError> {
+    check_blocksize(options.blocksize)?;
+    // the right hash needs at least one character
+    if options.hash_length != 0 && !(2..=MAX_HASH_LENGTH).contains(&options.hash_length) {
+        return Err(SpamsumError::InvalidHashLength(options.hash_length));
+    }
+    Ok(())
+}
+
+// Smaller blocksizes trigger on (almost) every byte. The right hash uses twice
+// the blocksize, which has to fit as well.
+fn check_blocksize(blocksize: u32) -> Result<(), SpamsumError> {
+    if blocksize != 0 && !(MIN_BLOCKSIZE..=u32::MAX / 2).contains(&blocksize) {
+        return Err(SpamsumError::InvalidBlocksize(blocksize));
+    }
+    Ok(())
+}
+
+// the splitmix64 finalizer
+#[cfg(any(feature = "std", feature = "simhash"))]
+pub(crate) fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+// FNV-1a, for words and other short strings
+#[cfg(any(feature = "minhash", feature = "sdhash", feature = "simhash"))]
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+#[cfg(any(feature = "minhash", feature = "sdhash", feature = "simhash"))]
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+// A small xorshift generator, so that the tests need no extra dependencies.
+#[cfg(test)]
+pub(crate) fn pseudo_random_bytes(length: usize, mut seed: u32) -> Vec<u8> {
+    (0..length)
+        .map(|_| {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            seed as u8
+        })
+        .collect()
+}
+
+// Only allocates if the input actually has to be modified.
+fn filter_input(input: &[u8], options: SpamsumOptions) -> Cow<'_, [u8]> {
+    if let Some(filter) = options.filter {
+        let options = SpamsumOptions {
+            filter: None,
+            ..options
         };
-        input.drain(0..new_start);
+        let mut output = filter_input(input, options).into_owned();
+        filter(&mut output);
+        return Cow::Owned(output);
     }
-    if options.ignore_whitespace {
-        // imitating C's isspace(c) (POSIX locale), removing ASCII
-        // spaces, tabs, newlines, feeds, carriage returns, _and_ vertical tabs
-        let whitespaces = [0x20, 0x9, 0xA, 0xB, 0xC, 0xD];
-        input.retain(|&c| !whitespaces.contains(&c));
+    if options.is_mime() {
+        let body = filter_mime(input, options);
+        let options = SpamsumOptions {
+            ignore_headers: options.ignore_headers && !options.ignore_mime_headers,
+            ignore_mime_headers: false,
+            decode_transfer_encoding: false,
+            ..options
+        };
+        return Cow::Owned(filter_input(&body, options).into_owned());
     }
-    input.shrink_to_fit();
+    filter_bytes(skip_headers(input, options), options)
 }
 
-fn get_spamsum_with_set_blocksize(
-    input: &Vec<u8>,
-    blocksize: u32,
-    _options: SpamsumOptions,
-) -> Result<Spamsum, &'static str> {
-    let mut result = Spamsum {
-        left_hash_blocksize: blocksize,
-        left_hash: String::with_capacity(LEFT_HASH_LENGTH as usize),
-        right_hash: String::with_capacity(RIGHT_HASH_LENGTH as usize),
-    };
-    let mut rolling_hash: Wrapping<u32> = Wrapping(0);
-    let mut hash_state = HashState {
-        window: [0; ROLLING_WINDOW as usize],
-        window_sum: Wrapping(0),
-        window_sum2: Wrapping(0),
-        shift_hash: Wrapping(0),
-        position: 0,
-        left_hash_value: HASH_INIT,
-        right_hash_value: HASH_INIT,
-    };
-    for element in input {
-        let c: u32 = *element as u32;
-        let rolling_pos = (hash_state.position % ROLLING_WINDOW) as usize;
-
-        hash_state.window_sum2 -= hash_state.window_sum;
-        hash_state.window_sum2 += Wrapping(ROLLING_WINDOW * c);
-
-        hash_state.window_sum -= Wrapping(hash_state.window[rolling_pos] as u32);
-        hash_state.window_sum += Wrapping(c);
-
-        hash_state.shift_hash <<= 5;
-        hash_state.shift_hash ^= Wrapping(c);
-
-        hash_state.window[rolling_pos] = *element;
-        hash_state.position += 1;
-
-        hash_state.left_hash_value *= HASH_PRIME;
-        hash_state.left_hash_value ^= Wrapping(c);
-
-        hash_state.right_hash_value *= HASH_PRIME;
-        hash_state.right_hash_value ^= Wrapping(c);
-
-        rolling_hash = hash_state.window_sum + hash_state.window_sum2 + hash_state.shift_hash;
-
-        // check for reset point of left hash
-        if (rolling_hash + Wrapping(1)).0 % result.left_hash_blocksize == 0 {
-            update_hash_output(
-                &mut hash_state.left_hash_value,
-                &mut result.left_hash,
-                LEFT_HASH_LENGTH,
-            );
-        }
-        // check for reset point of right hash
-        if (rolling_hash + Wrapping(1)).0 % result.right_hash_blocksize() == 0 {
-            update_hash_output(
-                &mut hash_state.right_hash_value,
-                &mut result.right_hash,
-                RIGHT_HASH_LENGTH,
-            );
+// Skips everything up to the first empty line with `ignore_headers`, unless
+// there is none.
+fn skip_headers(input: &[u8], options: SpamsumOptions) -> &[u8] {
+    if options.ignore_headers {
+        let mut header_end = HeaderEnd::default();
+        if let Some(position) = input.iter().position(|&c| header_end.update(c)) {
+            return &input[position + 1..];
         }
     }
+    input
+}
 
-    // collect any leftovers so that we have always the last part of the message
-    if rolling_hash != Wrapping(0) {
-        update_hash_output(
-            &mut hash_state.left_hash_value,
-            &mut result.left_hash,
-            LEFT_HASH_LENGTH,
-        );
-        update_hash_output(
-            &mut hash_state.right_hash_value,
-            &mut result.right_hash,
-            RIGHT_HASH_LENGTH,
-        );
+// Finds the empty line that ends e-mail headers, i.e. "\n\n", "\r\n\r\n" or
+// (with old Mac line endings) "\r\r", in input fed byte by byte.
+#[derive(Copy, Clone, Default)]
+struct HeaderEnd {
+    last_bytes: u16,
+}
+
+impl HeaderEnd {
+    // Returns whether `c` is the last byte of the empty line.
+    fn update(&mut self, c: u8) -> bool {
+        let previous = self.last_bytes;
+        self.last_bytes = previous << 8 | u16::from(c);
+        match c {
+            b'\n' => previous & 0xFF == 0xA || previous == 0x0A0D,
+            b'\r' => previous & 0xFF == 0xD,
+            _ => false,
+        }
     }
-    Ok(result)
 }
 
-fn update_hash_output(hash_value: &mut Wrapping<u32>, hash_output: &mut String, hash_length: u32) {
-    let output_index: usize = (hash_value.0 % 64) as usize;
+#[inline]
+fn is_whitespace(c: u8) -> bool {
+    // imitating C's isspace(c) (POSIX locale), matching ASCII
+    // spaces, tabs, newlines, feeds, carriage returns, _and_ vertical tabs
+    matches!(c, 0x20 | 0x9 | 0xA | 0xB | 0xC | 0xD)
+}
+
+fn update_hash_output<H: ChunkHasher>(
+    hash_value: &mut H,
+    init: &H,
+    hash_output: &mut HashBuffer,
+    hash_length: u32,
+) {
+    let output_index = (hash_value.value() % 64) as u8;
     if hash_output.len() == (hash_length as usize) {
         hash_output.pop();
     } else if hash_output.len() < (hash_length - 1) as usize {
-        *hash_value = HASH_INIT;
+        *hash_value = init.clone();
     }
-    hash_output.push(BASE64_CHARSET.chars().nth(output_index).unwrap());
+    hash_output.push(Alphabet::STANDARD.encode(output_index));
 }
 
-fn guess_initial_blocksize(input_length: u32) -> u32 {
+fn guess_initial_blocksize(input_length: u64, hash_length: u32) -> u32 {
     let mut blocksize: u32 = MIN_BLOCKSIZE;
-    while blocksize * LEFT_HASH_LENGTH < input_length {
+    while blocksize < MAX_BLOCKSIZE && u64::from(blocksize) * u64::from(hash_length) < input_length
+    {
         blocksize *= 2;
     }
     blocksize
@@ -204,78 +744,178 @@ fn guess_initial_blocksize(input_length: u32) -> u32 {
 mod main_tests {
     use super::*;
 
+    #[test]
+    fn test_options_builder() {
+        let options = SpamsumOptions::builder()
+            .blocksize(12)
+            .ignore_whitespace(true)
+            .ignore_headers(true)
+            .build()
+            .unwrap();
+        assert_eq!(options.blocksize, 12);
+        assert!(options.ignore_whitespace && options.ignore_headers);
+        let options = SpamsumOptions::builder().build().unwrap();
+        assert_eq!(options.blocksize, 0);
+        assert!(!options.ignore_whitespace && !options.ignore_headers);
+        match SpamsumOptions::builder().blocksize(u32::MAX).build() {
+            Err(SpamsumError::InvalidBlocksize(u32::MAX)) => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+        for &blocksize in [1, 2, u32::MAX / 2 + 1].iter() {
+            assert!(SpamsumOptions::builder()
+                .blocksize(blocksize)
+                .build()
+                .is_err());
+        }
+        for &blocksize in [3, 11, u32::MAX / 2].iter() {
+            assert!(SpamsumOptions::builder()
+                .blocksize(blocksize)
+                .build()
+                .is_ok());
+        }
+    }
+
     #[test]
     fn test_get_left_blocksize() {
-        assert_eq!(guess_initial_blocksize(1), 3);
-        assert_eq!(guess_initial_blocksize(3 * 64), 3);
-        assert_eq!(guess_initial_blocksize(3 * 64 + 1), 6);
-        assert_eq!(guess_initial_blocksize(6 * 64 + 1), 12);
+        assert_eq!(guess_initial_blocksize(1, 64), 3);
+        assert_eq!(guess_initial_blocksize(3 * 64, 64), 3);
+        assert_eq!(guess_initial_blocksize(3 * 64 + 1, 64), 6);
+        assert_eq!(guess_initial_blocksize(6 * 64 + 1, 64), 12);
+        assert_eq!(guess_initial_blocksize(1 << 32, 64), 100663296);
+        assert_eq!(guess_initial_blocksize((1 << 32) + 1, 64), 100663296);
+        assert_eq!(guess_initial_blocksize(100663296 * 64 + 1, 64), 201326592);
+        assert_eq!(guess_initial_blocksize(u64::MAX, 64), MAX_BLOCKSIZE);
     }
 
     #[test]
     fn test_get_right_blocksize() {
         let spamsum = Spamsum {
             left_hash_blocksize: 6,
-            left_hash: String::new(),
-            right_hash: String::new(),
+            left_hash: HashBuffer::new(),
+            right_hash: HashBuffer::new(),
         };
         assert_eq!(spamsum.right_hash_blocksize(), 12);
     }
 
+    #[test]
+    fn test_accessors() {
+        let spamsum = get_spamsum(b"Please buy my stuff\nDear Sir or Madam\n").unwrap();
+        assert_eq!(spamsum.blocksize(), 3);
+        assert_eq!(spamsum.left_hash(), "clclDDvWIMF/hv");
+        assert_eq!(spamsum.right_hash(), "cGZ/EJv");
+        assert_eq!(spamsum.clone(), spamsum);
+    }
+
+    #[test]
+    fn test_ordering() {
+        let mut spamsums: Vec<Spamsum> = ["6:A:B", "3:B:A", "3:A:C", "12:A:A", "3:A:B"]
+            .iter()
+            .map(|s| s.parse().unwrap())
+            .collect();
+        spamsums.sort();
+        let sorted: Vec<String> = spamsums.iter().map(|s| s.to_string()).collect();
+        assert_eq!(sorted, ["3:A:B", "3:A:C", "3:B:A", "6:A:B", "12:A:A"]);
+    }
+
     #[test]
     fn test_filter_whitespaces() {
-        let mut input: Vec<u8> = b"H\tE\rLL\n\nO O\n".to_vec();
+        let input: &[u8] = b"H\tE\rLL\n\nO O\n";
         let options = SpamsumOptions {
             blocksize: 0,
             ignore_headers: false,
             ignore_whitespace: true,
+            ..Default::default()
         };
-        filter_input(&mut input, options);
-        assert_eq!(input, b"HELLOO".to_vec());
+        assert_eq!(filter_input(input, options).as_ref(), b"HELLOO");
     }
 
     #[test]
     fn test_filter_email_headers() {
-        let mut input: Vec<u8> = b"X-Spam: YES\nX-Score: 1337\n\nDear Sir\n\nPlease buy\n".to_vec();
+        let input: &[u8] = b"X-Spam: YES\nX-Score: 1337\n\nDear Sir\n\nPlease buy\n";
         let options = SpamsumOptions {
             blocksize: 0,
             ignore_headers: true,
             ignore_whitespace: false,
+            ..Default::default()
         };
-        filter_input(&mut input, options);
-        assert_eq!(input, b"Dear Sir\n\nPlease buy\n".to_vec());
+        assert_eq!(
+            filter_input(input, options).as_ref(),
+            b"Dear Sir\n\nPlease buy\n"
+        );
+    }
+
+    #[test]
+    fn test_filter_email_headers_crlf() {
+        let options = SpamsumOptions {
+            blocksize: 0,
+            ignore_headers: true,
+            ignore_whitespace: false,
+            ..Default::default()
+        };
+        let input: &[u8] = b"X-Spam: YES\r\nX-Score: 1337\r\n\r\nDear Sir\r\n\r\nPlease buy\r\n";
+        assert_eq!(
+            filter_input(input, options).as_ref(),
+            b"Dear Sir\r\n\r\nPlease buy\r\n"
+        );
+        let input: &[u8] = b"X-Spam: YES\rX-Score: 1337\r\rDear Sir\r";
+        assert_eq!(filter_input(input, options).as_ref(), b"Dear Sir\r");
+        // mixed line endings still end with an empty line
+        let input: &[u8] = b"X-Spam: YES\r\n\nDear Sir\n";
+        assert_eq!(filter_input(input, options).as_ref(), b"Dear Sir\n");
+        let input: &[u8] = b"X-Spam: YES\n\r\nDear Sir\n";
+        assert_eq!(filter_input(input, options).as_ref(), b"Dear Sir\n");
+        let input: &[u8] = b"X-Spam: YES\r\nX-Score: 1337\r\n";
+        assert_eq!(filter_input(input, options).as_ref(), input);
     }
 
     #[test]
     fn test_filter_email_headers_no_headers() {
-        let mut input: Vec<u8> = b"NO HEADER\nTO BE FOUND!\n".to_vec();
+        let input: &[u8] = b"NO HEADER\nTO BE FOUND!\n";
         let options = SpamsumOptions {
             blocksize: 0,
             ignore_headers: true,
             ignore_whitespace: false,
+            ..Default::default()
         };
-        filter_input(&mut input, options);
-        assert_eq!(input, b"NO HEADER\nTO BE FOUND!\n".to_vec());
+        assert_eq!(
+            filter_input(input, options).as_ref(),
+            b"NO HEADER\nTO BE FOUND!\n"
+        );
+    }
+
+    #[test]
+    fn test_filter_without_changes_borrows() {
+        let input: &[u8] = b"X-Spam: YES\n\nDear_Sir";
+        let options = SpamsumOptions {
+            blocksize: 0,
+            ignore_headers: true,
+            ignore_whitespace: true,
+            ..Default::default()
+        };
+        assert!(matches!(
+            filter_input(input, options),
+            Cow::Borrowed(b"Dear_Sir")
+        ));
     }
 
     #[test]
     fn test_filter_both() {
-        let mut input: Vec<u8> = b"X-Spam: YES\nX-Score: 1337\n\nDear Sir\n\nPlease buy\n".to_vec();
+        let input: &[u8] = b"X-Spam: YES\nX-Score: 1337\n\nDear Sir\n\nPlease buy\n";
         let options = SpamsumOptions {
             blocksize: 0,
             ignore_headers: true,
             ignore_whitespace: true,
+            ..Default::default()
         };
-        filter_input(&mut input, options);
-        assert_eq!(input, b"DearSirPleasebuy".to_vec());
+        assert_eq!(filter_input(input, options).as_ref(), b"DearSirPleasebuy");
     }
 
     #[test]
     fn test_calculate_spamsum() {
         let expected_spamsum = Spamsum {
             left_hash_blocksize: 3,
-            left_hash: String::from("Hn"),
-            right_hash: String::from("Hn"),
+            left_hash: HashBuffer::from("Hn"),
+            right_hash: HashBuffer::from("Hn"),
         };
         let input: Vec<u8> = b"test".to_vec();
         let spamsum = get_spamsum(&input).unwrap();
@@ -286,25 +926,91 @@ mod main_tests {
     fn test_calculate_another_spamsum() {
         let expected_spamsum = Spamsum {
             left_hash_blocksize: 3,
-            left_hash: String::from("clclDDvWIMF/hv"),
-            right_hash: String::from("cGZ/EJv"),
+            left_hash: HashBuffer::from("clclDDvWIMF/hv"),
+            right_hash: HashBuffer::from("cGZ/EJv"),
         };
         let input: Vec<u8> = b"Please buy my stuff\nDear Sir or Madam\n".to_vec();
         let spamsum = get_spamsum(&input).unwrap();
         assert_eq!(spamsum.to_string(), expected_spamsum.to_string());
     }
 
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_calculate_spamsum_from_reader() {
+        let input: &[u8] = b"Please buy my stuff\nDear Sir or Madam\n";
+        let spamsum = get_spamsum_from_reader(input, Default::default()).unwrap();
+        assert_eq!(spamsum.to_string(), "3:clclDDvWIMF/hv:cGZ/EJv");
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_halved_blocksize_reads_input_once() {
+        // rarely triggering input ends up several halvings below the guessed
+        // blocksize, which must not need another pass over the input
+        struct CountingReader<'a>(&'a [u8], usize);
+
+        impl Read for CountingReader<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                let length = self.0.read(buf)?;
+                self.1 += length;
+                Ok(length)
+            }
+        }
+
+        let input: Vec<u8> = (0..100000u32).map(|i| (i / 997 % 2) as u8).collect();
+        let mut reader = CountingReader(&input, 0);
+        let spamsum = get_spamsum_from_reader(&mut reader, Default::default()).unwrap();
+        assert_eq!(reader.1, input.len());
+        assert!(spamsum.blocksize() * 4 <= guess_initial_blocksize(100000, LEFT_HASH_LENGTH));
+        assert_eq!(spamsum, get_spamsum(&input).unwrap());
+    }
+
+    #[test]
+    fn test_calculate_spamsum_with_max_input_len() {
+        let options = SpamsumOptions::builder()
+            .max_input_len(Some(4))
+            .build()
+            .unwrap();
+        assert!(get_configured_spamsum(b"test", options).is_ok());
+        assert!(matches!(
+            get_configured_spamsum(b"tests", options),
+            Err(SpamsumError::InputLimitExceeded(4))
+        ));
+        // an endless reader is not read to the end
+        #[cfg(feature = "std")]
+        assert!(matches!(
+            get_spamsum_from_reader(io::repeat(b'A'), options),
+            Err(SpamsumError::InputLimitExceeded(4))
+        ));
+    }
+
+    #[test]
+    fn test_calculate_spamsum_with_invalid_blocksize() {
+        let options = SpamsumOptions {
+            blocksize: u32::MAX,
+            ignore_headers: false,
+            ignore_whitespace: false,
+            ..Default::default()
+        };
+        let input: &[u8] = b"test";
+        assert!(matches!(
+            get_configured_spamsum(input, options),
+            Err(SpamsumError::InvalidBlocksize(u32::MAX))
+        ));
+    }
+
     #[test]
     fn test_calculate_spamsum_with_set_blocksize() {
         let expected_spamsum = Spamsum {
             left_hash_blocksize: 11,
-            left_hash: String::from("ccsv"),
-            right_hash: String::from("Iv"),
+            left_hash: HashBuffer::from("ccsv"),
+            right_hash: HashBuffer::from("Iv"),
         };
         let options = SpamsumOptions {
             blocksize: 11,
             ignore_headers: false,
             ignore_whitespace: false,
+            ..Default::default()
         };
         let input: Vec<u8> = b"Please buy my stuff\nDear Sir or Madam\n".to_vec();
         let spamsum = get_configured_spamsum(&input, options).unwrap();
@@ -0,0 +1,262 @@
+use std::collections::HashMap;
+#[cfg(feature = "serde")]
+use std::convert::TryFrom;
+
+use crate::mix;
+use crate::ngram::hashed_keys;
+use crate::{Spamsum, SpamsumError};
+
+// the most MinHash values of `LshOptions`, as chosen by `for_recall()`
+pub(crate) const MAX_SIGNATURE_LENGTH: u32 = 256;
+
+/// Options for approximate queries with locality-sensitive hashing, see
+/// `SpamsumDatabase::with_lsh()`.
+///
+/// The 7-character substrings of each spamsum are summarized by
+/// `bands * rows` MinHash values, and spamsums are only compared if all
+/// `rows` values of at least one band are equal. Two spamsums sharing the
+/// fraction `j` of their substrings are compared with a probability of
+/// `1 - (1 - j^rows)^bands`, so more bands increase the recall and more rows
+/// reduce the number of comparisons.
+///
+/// Both must be at least 1, and there are at most 256 MinHash values.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "LshOptionsFields")
+)]
+pub struct LshOptions {
+    pub bands: u32,
+    pub rows: u32,
+}
+
+// The serialized fields of `LshOptions`, which are checked before they are
+// used.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+#[serde(rename = "LshOptions")]
+struct LshOptionsFields {
+    bands: u32,
+    rows: u32,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<LshOptionsFields> for LshOptions {
+    type Error = SpamsumError;
+
+    fn try_from(fields: LshOptionsFields) -> Result<LshOptions, SpamsumError> {
+        let options = LshOptions {
+            bands: fields.bands,
+            rows: fields.rows,
+        };
+        options.check()?;
+        Ok(options)
+    }
+}
+
+impl LshOptions {
+    /// Returns the options with the fewest comparisons that find spamsums
+    /// sharing the fraction `similarity` of their substrings with at least
+    /// the probability `recall`, or as close to it as possible with at most
+    /// 256 MinHash values.
+    ///
+    /// ```
+    /// use spamsum::LshOptions;
+    ///
+    /// let options = LshOptions::for_recall(0.5, 0.95);
+    /// assert!(options.recall(0.5) >= 0.95);
+    /// ```
+    pub fn for_recall(similarity: f64, recall: f64) -> LshOptions {
+        let similarity = similarity.clamp(0.01, 1.0);
+        let recall = recall.clamp(0.0, 0.9999);
+        let bands_for = |rows: u32| {
+            let band_probability = similarity.powi(rows as i32);
+            ((1.0 - recall).ln() / (1.0 - band_probability).ln())
+                .ceil()
+                .max(1.0)
+        };
+        let mut options = LshOptions {
+            bands: bands_for(1).min(f64::from(MAX_SIGNATURE_LENGTH)) as u32,
+            rows: 1,
+        };
+        for rows in 2..=MAX_SIGNATURE_LENGTH {
+            let bands = bands_for(rows);
+            if bands * f64::from(rows) > f64::from(MAX_SIGNATURE_LENGTH) {
+                break;
+            }
+            options = LshOptions {
+                bands: bands as u32,
+                rows,
+            };
+        }
+        options
+    }
+
+    // Fails for options that cannot be used for an index.
+    fn check(&self) -> Result<(), SpamsumError> {
+        if self.bands == 0 || self.rows == 0 {
+            return Err(SpamsumError::InvalidOptions("no LSH bands or rows"));
+        }
+        match self.bands.checked_mul(self.rows) {
+            Some(length) if length <= MAX_SIGNATURE_LENGTH => Ok(()),
+            _ => Err(SpamsumError::InvalidOptions("too many LSH bands and rows")),
+        }
+    }
+
+    /// Returns the probability of comparing two spamsums that share the
+    /// fraction `similarity` of their substrings.
+    pub fn recall(&self, similarity: f64) -> f64 {
+        1.0 - (1.0 - similarity.powi(self.rows as i32)).powi(self.bands as i32)
+    }
+}
+
+impl Default for LshOptions {
+    /// 36 bands of 3 rows, finding 99% of the spamsums sharing half of their
+    /// substrings.
+    fn default() -> LshOptions {
+        LshOptions { bands: 36, rows: 3 }
+    }
+}
+
+// Buckets of spamsums with equal bands of MinHash values.
+#[derive(Debug, Clone)]
+pub(crate) struct LshIndex {
+    options: LshOptions,
+    // (band, hash of its rows) -> ids
+    buckets: HashMap<(u32, u64), Vec<usize>>,
+}
+
+impl LshIndex {
+    pub(crate) fn new(options: LshOptions) -> Result<LshIndex, SpamsumError> {
+        options.check()?;
+        Ok(LshIndex {
+            options,
+            buckets: HashMap::new(),
+        })
+    }
+
+    // Adds a normalized spamsum under the given id.
+    pub(crate) fn insert(&mut self, id: usize, spamsum: &Spamsum) {
+        for key in self.band_keys(spamsum) {
+            self.buckets.entry(key).or_default().push(id);
+        }
+    }
+
+    // Removes a spamsum that was inserted under the given id. Ids must be
+    // inserted in ascending order for this to work.
+    pub(crate) fn remove(&mut self, id: usize, spamsum: &Spamsum) {
+        for key in self.band_keys(spamsum) {
+            if let Some(ids) = self.buckets.get_mut(&key) {
+                if let Ok(position) = ids.binary_search(&id) {
+                    ids.remove(position);
+                }
+                if ids.is_empty() {
+                    self.buckets.remove(&key);
+                }
+            }
+        }
+    }
+
+    // Returns the sorted ids of the indexed spamsums sharing a bucket with
+    // the normalized `spamsum`.
+    pub(crate) fn candidates(&self, spamsum: &Spamsum) -> Vec<usize> {
+        let mut candidates = Vec::new();
+        for key in self.band_keys(spamsum) {
+            if let Some(ids) = self.buckets.get(&key) {
+                candidates.extend_from_slice(ids);
+            }
+        }
+        candidates.sort_unstable();
+        candidates.dedup();
+        candidates
+    }
+
+    fn band_keys(&self, spamsum: &Spamsum) -> Vec<(u32, u64)> {
+        let LshOptions { bands, rows } = self.options;
+        let mut signature = vec![u64::MAX; (bands * rows) as usize];
+        for key in hashed_keys(spamsum) {
+            for (i, min_hash) in signature.iter_mut().enumerate() {
+                *min_hash = (*min_hash).min(mix(key ^ mix(i as u64 + 1)));
+            }
+        }
+        signature
+            .chunks(rows as usize)
+            .enumerate()
+            .map(|(band, rows)| {
+                let hash = rows.iter().fold(0, |hash, &row| mix(hash ^ row));
+                (band as u32, hash)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_for_recall() {
+        for &(similarity, recall) in &[(0.5, 0.95), (0.3, 0.9), (0.8, 0.99), (0.9, 0.5)] {
+            let options = LshOptions::for_recall(similarity, recall);
+            assert!(options.recall(similarity) >= recall, "{:?}", options);
+            assert!(options.bands * options.rows <= 256, "{:?}", options);
+        }
+        assert!(LshOptions::default().recall(0.5) >= 0.99);
+    }
+
+    #[test]
+    fn test_invalid_options() {
+        for &(bands, rows) in &[(0, 3), (36, 0), (257, 1), (16, 17), (u32::MAX, 2)] {
+            let options = LshOptions { bands, rows };
+            assert!(matches!(
+                LshIndex::new(options),
+                Err(SpamsumError::InvalidOptions(_))
+            ));
+        }
+        assert!(LshIndex::new(LshOptions {
+            bands: 16,
+            rows: 16
+        })
+        .is_ok());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_deserialize_invalid_options() {
+        let options: LshOptions = serde_json::from_str(r#"{"bands":36,"rows":3}"#).unwrap();
+        assert_eq!(options, LshOptions::default());
+        for json in &[r#"{"bands":0,"rows":3}"#, r#"{"bands":65536,"rows":65536}"#] {
+            assert!(
+                serde_json::from_str::<LshOptions>(json).is_err(),
+                "{}",
+                json
+            );
+        }
+    }
+
+    #[test]
+    fn test_candidates() {
+        let spamsums: Vec<Spamsum> = [
+            "48:ABCDEFGHIJKLMNOPQRSTUVWXYZ:ABCD",
+            "48:ABCDEFGHIJKLMNOPQRSTUVWXYz:ABCD",
+            "48:abcdefghijklmnopqrstuvwxyz:ABCD",
+            "3:Hn:Hn",
+            "3:Hn:Hn",
+        ]
+        .iter()
+        .map(|s| s.parse().unwrap())
+        .collect();
+        let mut index = LshIndex::new(LshOptions::default()).unwrap();
+        for (id, spamsum) in spamsums.iter().enumerate() {
+            index.insert(id, spamsum);
+        }
+        assert_eq!(index.candidates(&spamsums[0]), vec![0, 1]);
+        assert_eq!(index.candidates(&spamsums[2]), vec![2]);
+        assert_eq!(index.candidates(&spamsums[3]), vec![3, 4]);
+        index.remove(1, &spamsums[1]);
+        index.remove(3, &spamsums[3]);
+        assert_eq!(index.candidates(&spamsums[0]), vec![0]);
+        assert_eq!(index.candidates(&spamsums[4]), vec![4]);
+    }
+}
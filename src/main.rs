@@ -1,18 +1,49 @@
 #[macro_use]
 extern crate clap;
+use std::cmp;
+use std::collections::BTreeMap;
 use std::fs;
+use std::io::{self, BufReader, Read};
+use std::path::{Path, PathBuf};
 use std::result::Result;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
 
-use clap::{App, Arg};
+use clap::{App, Arg, ErrorKind};
+use memmap2::Mmap;
 
-use spamsum::{get_configured_spamsum, SpamsumOptions};
+use spamsum::{
+    chunk_boundaries, chunk_hash, compare, compare_with_cutoff, explain, get_configured_spamsum,
+    get_segmented_spamsum_from_reader, get_spamsum_from_reader, parse_hash_list, Algorithm,
+    NamedSpamsum, Spamsum, SpamsumError, SpamsumHasher, SpamsumOptions, WindowHasher,
+};
 
-fn main() -> Result<(), &'static str> {
-    let matches = App::new("spamsum")
+// the input file name used for reading from stdin and how it is displayed
+const STDIN_PATH: &str = "-";
+const STDIN_NAME: &str = "stdin";
+// files of at least this size are memory-mapped instead of read into memory
+const MMAP_THRESHOLD: u64 = 64 * 1024 * 1024;
+// the size of the chunks that are read while the previous one is hashed
+const READ_AHEAD_SIZE: usize = 1024 * 1024;
+// the maximum number of files hashed in parallel per CPU, as hashing waits
+// for reading the files now and then
+const MAX_JOBS_PER_CPU: usize = 4;
+// the first line of hash lists written by ssdeep
+const SSDEEP_HEADER: &str = "ssdeep,1.1--blocksize:hash:hash,filename";
+
+fn main() -> Result<(), SpamsumError> {
+    let algorithm_names: Vec<&str> = Algorithm::all().into_iter().map(Algorithm::name).collect();
+    let app = App::new("spamsum")
         .version("0.1.0")
         .author("Hauke Lübbers dubbel14@googlemail.com")
         .about("Calculates the spamsum of files")
-        .arg(Arg::with_name("input_files").multiple(true).required(true))
+        .arg(
+            Arg::with_name("input_files")
+                .multiple(true)
+                .required(true)
+                .help("Files to hash, - reads from stdin"),
+        )
         .arg(
             Arg::with_name("blocksize")
                 .short("B")
@@ -21,6 +52,14 @@ fn main() -> Result<(), &'static str> {
                 .takes_value(true)
                 .help("Set a static blocksize (default is dynamic)"),
         )
+        .arg(
+            Arg::with_name("max_size")
+                .long("max-size")
+                .required(false)
+                .takes_value(true)
+                .value_name("BYTES")
+                .help("Fail on inputs larger than BYTES"),
+        )
         .arg(
             Arg::with_name("ignore_whitespace")
                 .short("W")
@@ -29,6 +68,21 @@ fn main() -> Result<(), &'static str> {
                 .takes_value(false)
                 .help("Ignore whitespace"),
         )
+        .arg(
+            Arg::with_name("strip_digits")
+                .long("strip-digits")
+                .required(false)
+                .takes_value(false)
+                .help("Ignore digits"),
+        )
+        .arg(
+            Arg::with_name("max_run_len")
+                .long("max-run-length")
+                .required(false)
+                .takes_value(true)
+                .value_name("N")
+                .help("Shorten runs of the same byte to N bytes"),
+        )
         .arg(
             Arg::with_name("ignore_headers")
                 .short("H")
@@ -37,23 +91,1377 @@ fn main() -> Result<(), &'static str> {
                 .takes_value(false)
                 .help("Ignore (e-mail) headers"),
         )
-        .get_matches();
-    let input_files = matches.values_of("input_files");
-    let options = SpamsumOptions {
-        blocksize: value_t!(matches.value_of("blocksize"), u32).unwrap_or_default(),
-        ignore_whitespace: matches.is_present("ignore_whitespace"),
-        ignore_headers: matches.is_present("ignore_headers"),
-    };
-    for input_file in input_files.unwrap() {
-        let input = match fs::read(input_file) {
-            Ok(file) => file,
-            Err(error) => panic!("Could not open the file: {:?}", error),
+        .arg(
+            Arg::with_name("ignore_mime_headers")
+                .long("ignore-mime-headers")
+                .required(false)
+                .takes_value(false)
+                .help("Ignore e-mail headers, MIME part headers and boundaries"),
+        )
+        .arg(
+            Arg::with_name("decode_transfer_encoding")
+                .long("decode-transfer-encoding")
+                .required(false)
+                .takes_value(false)
+                .help("Decode quoted-printable and base64 e-mail bodies"),
+        )
+        .arg(
+            Arg::with_name("ignore_html")
+                .long("ignore-html")
+                .required(false)
+                .takes_value(false)
+                .help("Ignore HTML tags and comments"),
+        )
+        .arg(
+            Arg::with_name("decode_html_entities")
+                .long("decode-html-entities")
+                .required(false)
+                .takes_value(false)
+                .requires("ignore_html")
+                .help("Decode HTML entities (with --ignore-html)"),
+        )
+        .arg(
+            Arg::with_name("strip_quoted_lines")
+                .long("strip-quoted-lines")
+                .required(false)
+                .takes_value(false)
+                .help("Ignore lines starting with >, i.e. quoted replies"),
+        )
+        .arg(
+            Arg::with_name("normalize_urls")
+                .long("normalize-urls")
+                .required(false)
+                .takes_value(false)
+                .help("Mask the query and the parts with digits of URLs"),
+        )
+        .arg(
+            Arg::with_name("tokenize")
+                .long("tokenize")
+                .required(false)
+                .takes_value(false)
+                .help("Hash words instead of bytes, ignoring case and punctuation"),
+        )
+        .arg(
+            Arg::with_name("key")
+                .long("key")
+                .required(false)
+                .takes_value(true)
+                .value_name("KEY")
+                .help("Calculate keyed spamsums, only comparable with the same KEY"),
+        )
+        .arg(
+            Arg::with_name("compare")
+                .short("d")
+                .long("compare")
+                .required(false)
+                .takes_value(false)
+                .help("Compare two files and print their similarity score"),
+        )
+        .arg(
+            Arg::with_name("explain")
+                .long("explain")
+                .required(false)
+                .takes_value(false)
+                .requires("compare")
+                .help("With --compare, also print which chunks of the (unfiltered) files match, were removed or were added"),
+        )
+        .arg(
+            Arg::with_name("match")
+                .short("m")
+                .long("match")
+                .required(false)
+                .takes_value(true)
+                .value_name("FILE")
+                .conflicts_with("compare")
+                .help("Match files against a list of known hashes"),
+        )
+        .arg(
+            Arg::with_name("compare_lists")
+                .short("x")
+                .long("compare-lists")
+                .required(false)
+                .takes_value(false)
+                .conflicts_with_all(&["compare", "match"])
+                .help("Compare the hashes of two hash list files"),
+        )
+        .arg(
+            Arg::with_name("recursive")
+                .short("r")
+                .long("recursive")
+                .required(false)
+                .takes_value(false)
+                .help("Hash all files in the given directories and their subdirectories"),
+        )
+        .arg(
+            Arg::with_name("ssdeep_output")
+                .short("s")
+                .long("ssdeep")
+                .required(false)
+                .takes_value(false)
+                .conflicts_with_all(&["compare", "match", "compare_lists"])
+                .help("Print hashes with file names in the ssdeep hash list format"),
+        )
+        .arg(
+            Arg::with_name("jobs")
+                .short("j")
+                .long("jobs")
+                .required(false)
+                .takes_value(true)
+                .value_name("N")
+                .help("Hash up to N files in parallel (default is 1, at most 4 per CPU)"),
+        )
+        .arg(
+            Arg::with_name("threshold")
+                .short("t")
+                .long("threshold")
+                .required(false)
+                .takes_value(true)
+                .help("Only show matches with a score above the threshold (default is 0)"),
+        )
+        .arg(
+            Arg::with_name("chunks")
+                .long("chunks")
+                .required(false)
+                .takes_value(false)
+                .conflicts_with_all(&["compare", "match", "compare_lists", "ssdeep_output"])
+                .help("Print the offset, length and hash of every chunk of the (unfiltered) input, for the blocksize of its spamsum or the one set with --blocksize"),
+        )
+        .arg(
+            Arg::with_name("segment_size")
+                .long("segment-size")
+                .required(false)
+                .takes_value(true)
+                .value_name("BYTES")
+                .conflicts_with_all(&["compare", "match", "compare_lists", "chunks"])
+                .help("Also print the spamsum of every segment of BYTES bytes of each file, with its byte range"),
+        )
+        .arg(
+            Arg::with_name("sha256")
+                .long("sha256")
+                .required(false)
+                .takes_value(false)
+                .conflicts_with_all(&["compare", "match", "compare_lists", "chunks", "segment_size", "window"])
+                .help("Also print the SHA-256 of each file, in CSV (or with --json, JSON) output"),
+        )
+        .arg(
+            Arg::with_name("md5")
+                .long("md5")
+                .required(false)
+                .takes_value(false)
+                .conflicts_with_all(&["compare", "match", "compare_lists", "chunks", "segment_size", "window"])
+                .help("Also print the MD5 of each file, in CSV (or with --json, JSON) output"),
+        )
+        .arg(
+            Arg::with_name("json")
+                .long("json")
+                .required(false)
+                .takes_value(false)
+                .conflicts_with_all(&["compare", "match", "compare_lists", "chunks", "ssdeep_output", "segment_size", "window"])
+                .help("Print the file name and hashes of each file as a line of JSON"),
+        )
+        .arg(
+            Arg::with_name("window")
+                .long("window")
+                .required(false)
+                .takes_value(true)
+                .value_name("BYTES")
+                .conflicts_with_all(&["compare", "compare_lists", "chunks", "segment_size"])
+                .help("Print (or match with --match) the spamsum of every window of BYTES bytes of each file or stream as soon as it was read, instead of the spamsum of the whole file"),
+        )
+        .arg(
+            Arg::with_name("stride")
+                .long("stride")
+                .required(false)
+                .takes_value(true)
+                .value_name("BYTES")
+                .requires("window")
+                .help("Start a new window every BYTES bytes (default is the window size)"),
+        )
+        .arg(
+            Arg::with_name("algorithm")
+                .long("algorithm")
+                .required(false)
+                .takes_value(true)
+                .value_name("NAME")
+                .possible_values(&algorithm_names)
+                .default_value("spamsum")
+                .help("Hash with another of the algorithms enabled at build time, which only supports hashing and --compare"),
+        );
+    #[cfg(feature = "sqlite")]
+    let app = app
+        .arg(
+            Arg::with_name("db")
+                .long("db")
+                .required(false)
+                .takes_value(true)
+                .value_name("FILE")
+                .conflicts_with_all(&["compare", "match", "compare_lists", "ssdeep_output", "segment_size", "window", "sha256", "md5", "json"])
+                .help("Store hashes in the table hashes(path, size, mtime, blocksize, hash) of a SQLite database"),
+        )
+        .arg(
+            Arg::with_name("match_db")
+                .long("match-db")
+                .required(false)
+                .takes_value(true)
+                .value_name("FILE")
+                .conflicts_with_all(&["compare", "match", "compare_lists", "db", "segment_size"])
+                .help("Match files against the hashes stored in a SQLite database"),
+        );
+    let matches = app.get_matches();
+    let input_files: Vec<&str> = matches.values_of("input_files").unwrap().collect();
+    let blocksize = if matches.is_present("blocksize") {
+        value_t_or_exit!(matches.value_of("blocksize"), u32)
+    } else {
+        0
+    };
+    let max_size = if matches.is_present("max_size") {
+        Some(value_t_or_exit!(matches.value_of("max_size"), u64))
+    } else {
+        None
+    };
+    let segment_size = if matches.is_present("segment_size") {
+        let segment_size = value_t_or_exit!(matches.value_of("segment_size"), u64);
+        if segment_size == 0 {
+            clap::Error::with_description(
+                "The segment size must not be 0",
+                ErrorKind::ValueValidation,
+            )
+            .exit();
+        }
+        Some(segment_size)
+    } else {
+        None
+    };
+    let window = if matches.is_present("window") {
+        let window = value_t_or_exit!(matches.value_of("window"), usize);
+        let stride = if matches.is_present("stride") {
+            value_t_or_exit!(matches.value_of("stride"), usize)
+        } else {
+            window
+        };
+        if window == 0 || stride == 0 {
+            clap::Error::with_description(
+                "The window and the stride must not be 0",
+                ErrorKind::ValueValidation,
+            )
+            .exit();
+        }
+        Some((window, stride))
+    } else {
+        None
+    };
+    let max_run_len = if matches.is_present("max_run_len") {
+        Some(value_t_or_exit!(matches.value_of("max_run_len"), u32))
+    } else {
+        None
+    };
+    let key = if matches.is_present("key") {
+        Some(value_t_or_exit!(matches.value_of("key"), u64))
+    } else {
+        None
+    };
+    let options = SpamsumOptions::builder()
+        .blocksize(blocksize)
+        .max_input_len(max_size)
+        .ignore_whitespace(matches.is_present("ignore_whitespace"))
+        .strip_digits(matches.is_present("strip_digits"))
+        .max_run_len(max_run_len)
+        .ignore_headers(matches.is_present("ignore_headers"))
+        .ignore_mime_headers(matches.is_present("ignore_mime_headers"))
+        .decode_transfer_encoding(matches.is_present("decode_transfer_encoding"))
+        .ignore_html(matches.is_present("ignore_html"))
+        .decode_html_entities(matches.is_present("decode_html_entities"))
+        .strip_quoted_lines(matches.is_present("strip_quoted_lines"))
+        .normalize_urls(matches.is_present("normalize_urls"))
+        .tokenize(matches.is_present("tokenize"))
+        .key(key)
+        .build()?;
+    // the name was checked by clap
+    let algorithm = Algorithm::from_name(matches.value_of("algorithm").unwrap()).unwrap();
+    let spamsum_only = [
+        "match",
+        "compare_lists",
+        "ssdeep_output",
+        "chunks",
+        "explain",
+        "segment_size",
+        "window",
+        "sha256",
+        "md5",
+        "json",
+        "db",
+        "match_db",
+    ];
+    if algorithm != Algorithm::Spamsum && spamsum_only.iter().any(|&arg| matches.is_present(arg)) {
+        clap::Error::with_description(
+            &format!(
+                "Only hashing and --compare support the {} algorithm",
+                algorithm
+            ),
+            ErrorKind::ArgumentConflict,
+        )
+        .exit();
+    }
+    if matches.is_present("compare") {
+        if input_files.len() != 2 {
+            clap::Error::with_description(
+                "Comparing requires exactly two input files",
+                ErrorKind::WrongNumberOfValues,
+            )
+            .exit();
+        }
+        if algorithm != Algorithm::Spamsum {
+            let first = algorithm.hash(&read_file(Path::new(input_files[0]))?, options)?;
+            let second = algorithm.hash(&read_file(Path::new(input_files[1]))?, options)?;
+            println!(
+                "{} matches {} ({})",
+                display_name(Path::new(input_files[0])),
+                display_name(Path::new(input_files[1])),
+                // both have the same algorithm
+                first.similarity(&second).unwrap()
+            );
+            return Ok(());
+        }
+        let first = hash_file(Path::new(input_files[0]), options)?;
+        let second = hash_file(Path::new(input_files[1]), options)?;
+        println!(
+            "{} matches {} ({})",
+            display_name(Path::new(input_files[0])),
+            display_name(Path::new(input_files[1])),
+            compare(&first, &second)
+        );
+        if matches.is_present("explain") {
+            print!(
+                "{}",
+                explain(
+                    &read_file(Path::new(input_files[0]))?,
+                    &read_file(Path::new(input_files[1]))?
+                )?
+            );
+        }
+        return Ok(());
+    }
+    if matches.is_present("chunks") {
+        for input_file in input_files {
+            print_chunks(Path::new(input_file), options)?;
+        }
+        return Ok(());
+    }
+    let threshold = if matches.is_present("threshold") {
+        value_t_or_exit!(matches.value_of("threshold"), u32)
+    } else {
+        0
+    };
+    if matches.is_present("compare_lists") {
+        if input_files.len() != 2 {
+            clap::Error::with_description(
+                "Comparing hash lists requires exactly two hash list files",
+                ErrorKind::WrongNumberOfValues,
+            )
+            .exit();
+        }
+        let known_hashes = load_hashes(input_files[0])?;
+        for candidate in load_hashes(input_files[1])? {
+            let name = format!("{}:{}", input_files[1], candidate.name());
+            print_matches(
+                &name,
+                candidate.spamsum(),
+                input_files[0],
+                &known_hashes,
+                threshold,
+            );
+        }
+        return Ok(());
+    }
+    let input_files = if matches.is_present("recursive") {
+        find_files(&input_files)
+    } else {
+        input_files.iter().map(PathBuf::from).collect()
+    };
+    if algorithm != Algorithm::Spamsum {
+        for input_file in &input_files {
+            println!("{}", algorithm.hash(&read_file(input_file)?, options)?);
+        }
+        return Ok(());
+    }
+    let jobs = if matches.is_present("jobs") {
+        let jobs = value_t_or_exit!(matches.value_of("jobs"), usize);
+        if jobs == 0 {
+            clap::Error::with_description(
+                "The number of jobs must not be 0",
+                ErrorKind::ValueValidation,
+            )
+            .exit();
+        }
+        let max_jobs = thread::available_parallelism().map_or(1, |n| n.get()) * MAX_JOBS_PER_CPU;
+        cmp::min(jobs, max_jobs)
+    } else {
+        1
+    };
+    let known_hashes = match matches.value_of("match") {
+        Some(known_hashes_file) => Some((known_hashes_file, load_hashes(known_hashes_file)?)),
+        None => None,
+    };
+    #[cfg(feature = "sqlite")]
+    let known_hashes = match matches.value_of("match_db") {
+        Some(database_file) => Some((database_file, sqlite::load_hashes(database_file)?)),
+        None => known_hashes,
+    };
+    if let Some((window, stride)) = window {
+        let ssdeep_output = matches.is_present("ssdeep_output");
+        if ssdeep_output {
+            println!("{}", SSDEEP_HEADER);
+        }
+        let known_hashes = known_hashes
+            .as_ref()
+            .map(|(known_hashes_file, known_hashes)| (*known_hashes_file, &known_hashes[..]));
+        for input_file in &input_files {
+            hash_windows(
+                input_file,
+                window,
+                stride,
+                options,
+                |name, spamsum| match known_hashes {
+                    Some((known_hashes_file, known_hashes)) => {
+                        print_matches(&name, &spamsum, known_hashes_file, known_hashes, threshold)
+                    }
+                    None if ssdeep_output => println!("{}", NamedSpamsum::new(spamsum, name)),
+                    None => println!("{}", spamsum),
+                },
+            )?;
+        }
+        return Ok(());
+    }
+    if let Some((known_hashes_file, known_hashes)) = known_hashes {
+        return hash_files(&input_files, options, jobs, |input_file, spamsum| {
+            print_matches(
+                &display_name(input_file),
+                &spamsum,
+                known_hashes_file,
+                &known_hashes,
+                threshold,
+            );
+        });
+    }
+    #[cfg(feature = "sqlite")]
+    {
+        if let Some(database_file) = matches.value_of("db") {
+            let store = sqlite::HashStore::open(database_file)?;
+            let mut result = Ok(());
+            hash_files(&input_files, options, jobs, |input_file, spamsum| {
+                if result.is_ok() {
+                    result = store.insert(input_file, &display_name(input_file), &spamsum);
+                }
+            })?;
+            result?;
+            return store.commit();
+        }
+    }
+    let (sha256, md5) = (matches.is_present("sha256"), matches.is_present("md5"));
+    let json = matches.is_present("json");
+    if sha256 || md5 || json {
+        if !json {
+            let mut header = String::from("spamsum");
+            for (enabled, name) in [(sha256, "sha256"), (md5, "md5")] {
+                if enabled {
+                    header = format!("{},{}", header, name);
+                }
+            }
+            println!("{},filename", header);
+        }
+        for input_file in &input_files {
+            let (spamsum, checksums) = hash_file_with_checksums(input_file, options, sha256, md5)?;
+            let name = display_name(input_file);
+            if json {
+                let mut line = format!("{{\"filename\":{}", json_string(&name));
+                line += &format!(",\"spamsum\":\"{}\"", spamsum);
+                for (algorithm, checksum) in checksums {
+                    line += &format!(",\"{}\":\"{}\"", algorithm, checksum);
+                }
+                println!("{}}}", line);
+            } else {
+                let mut line = spamsum.to_string();
+                for (_, checksum) in checksums {
+                    line = format!("{},{}", line, checksum);
+                }
+                // the file name is quoted like in ssdeep hash lists
+                println!("{},\"{}\"", line, name.replace('"', "\\\""));
+            }
+        }
+        return Ok(());
+    }
+    let ssdeep_output = matches.is_present("ssdeep_output");
+    if ssdeep_output {
+        println!("{}", SSDEEP_HEADER);
+    }
+    if let Some(segment_size) = segment_size {
+        for input_file in &input_files {
+            print_segments(input_file, segment_size, options, ssdeep_output)?;
+        }
+        return Ok(());
+    }
+    hash_files(&input_files, options, jobs, |input_file, spamsum| {
+        if ssdeep_output {
+            println!("{}", NamedSpamsum::new(spamsum, display_name(input_file)));
+        } else {
+            println!("{}", spamsum);
+        }
+    })
+}
+
+// Hashes the files with the given number of threads and hands the results to
+// `output` in the order of the files.
+fn hash_files<F>(
+    input_files: &[PathBuf],
+    options: SpamsumOptions,
+    jobs: usize,
+    mut output: F,
+) -> Result<(), SpamsumError>
+where
+    F: FnMut(&Path, Spamsum),
+{
+    if jobs <= 1 {
+        return hash_files_reading_ahead(input_files, options, output);
+    }
+    let next_file = AtomicUsize::new(0);
+    let failed = AtomicBool::new(false);
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|scope| {
+        for _ in 0..jobs {
+            let sender = sender.clone();
+            let (next_file, failed) = (&next_file, &failed);
+            scope.spawn(move || {
+                while !failed.load(Ordering::Relaxed) {
+                    let index = next_file.fetch_add(1, Ordering::Relaxed);
+                    if index >= input_files.len() {
+                        break;
+                    }
+                    let result = hash_file(&input_files[index], options);
+                    if result.is_err() {
+                        failed.store(true, Ordering::Relaxed);
+                    }
+                    if sender.send((index, result)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+        // results arrive in any order, so hold them back until all previous
+        // files have been output
+        let mut finished = BTreeMap::new();
+        let mut next_output = 0;
+        for (index, result) in receiver {
+            finished.insert(index, result);
+            while let Some(result) = finished.remove(&next_output) {
+                output(&input_files[next_output], result?);
+                next_output += 1;
+            }
+        }
+        Ok(())
+    })
+}
+
+// What the reading thread of `hash_files_reading_ahead()` hands over.
+enum ReadAhead {
+    Chunk(Vec<u8>),
+    // the end of a file, or the error that stopped reading it
+    End(io::Result<()>),
+}
+
+// Hashes the files one after another, while another thread already reads
+// the next chunk or file, so that reading and hashing overlap on spinning
+// disks and network file systems.
+fn hash_files_reading_ahead<F>(
+    input_files: &[PathBuf],
+    options: SpamsumOptions,
+    mut output: F,
+) -> Result<(), SpamsumError>
+where
+    F: FnMut(&Path, Spamsum),
+{
+    thread::scope(|scope| {
+        // two buffers take turns: one is hashed while the other one is read
+        // into, so only one chunk is read ahead
+        let (chunk_sender, chunks) = mpsc::sync_channel(1);
+        let (buffer_sender, buffers) = mpsc::channel();
+        for _ in 0..2 {
+            buffer_sender
+                .send(Vec::with_capacity(READ_AHEAD_SIZE))
+                .unwrap();
+        }
+        scope.spawn(move || {
+            for input_file in input_files {
+                let result = read_chunks(input_file, &chunk_sender, &buffers);
+                // the hashing stopped early after an error
+                if chunk_sender.send(ReadAhead::End(result)).is_err() {
+                    break;
+                }
+            }
+        });
+        // returning drops the receiving ends, which stops the reading thread
+        for input_file in input_files {
+            let mut hasher = SpamsumHasher::with_options(options)?;
+            loop {
+                match chunks.recv().unwrap() {
+                    ReadAhead::Chunk(chunk) => {
+                        hasher.update(&chunk);
+                        // the reading thread is gone after the last file
+                        let _ = buffer_sender.send(chunk);
+                        if hasher.exceeds_max_input_len() {
+                            // the input may be endless, so stop reading early
+                            return hasher.finalize().map(|_| ());
+                        }
+                    }
+                    ReadAhead::End(result) => {
+                        result?;
+                        break;
+                    }
+                }
+            }
+            output(input_file, hasher.finalize()?);
+        }
+        Ok(())
+    })
+}
+
+// Reads a file in chunks of READ_AHEAD_SIZE bytes into the buffers and
+// sends them to the hashing thread, including a last (maybe empty) shorter
+// chunk that returns the buffer to the rotation.
+fn read_chunks(
+    path: &Path,
+    chunks: &mpsc::SyncSender<ReadAhead>,
+    buffers: &mpsc::Receiver<Vec<u8>>,
+) -> io::Result<()> {
+    let mut reader: Box<dyn Read> = if path == Path::new(STDIN_PATH) {
+        Box::new(io::stdin().lock())
+    } else {
+        Box::new(fs::File::open(path)?)
+    };
+    loop {
+        let mut buffer = match buffers.recv() {
+            Ok(buffer) => buffer,
+            Err(_) => return Ok(()),
         };
-        let spamsum = match get_configured_spamsum(&input, options) {
-            Ok(spamsum) => spamsum,
-            Err(e) => return Err(e),
+        buffer.clear();
+        (&mut reader)
+            .take(READ_AHEAD_SIZE as u64)
+            .read_to_end(&mut buffer)?;
+        let last = buffer.len() < READ_AHEAD_SIZE;
+        if chunks.send(ReadAhead::Chunk(buffer)).is_err() || last {
+            return Ok(());
+        }
+    }
+}
+
+fn print_matches(
+    name: &str,
+    spamsum: &Spamsum,
+    known_hashes_file: &str,
+    known_hashes: &[NamedSpamsum],
+    threshold: u32,
+) {
+    for known in known_hashes {
+        if let Some(score) =
+            compare_with_cutoff(spamsum, known.spamsum(), threshold.saturating_add(1))
+        {
+            println!(
+                "{} matches {}:{} ({})",
+                name,
+                known_hashes_file,
+                known.name(),
+                score
+            );
+        }
+    }
+}
+
+// Reads a hash list file. Hashes without a file name are named by the hash.
+fn load_hashes(path: &str) -> Result<Vec<NamedSpamsum>, SpamsumError> {
+    let hashes = parse_hash_list(BufReader::new(fs::File::open(path)?))?;
+    Ok(hashes
+        .into_iter()
+        .map(|(spamsum, name)| {
+            let name = match name {
+                Some(name) => name.display().to_string(),
+                None => spamsum.to_string(),
+            };
+            NamedSpamsum::new(spamsum, name)
+        })
+        .collect())
+}
+
+// Expands directories to the regular files within them, skipping (and
+// reporting) everything that cannot be read. Symlinked directories are only
+// followed when given as an argument to avoid cycles.
+fn find_files(paths: &[&str]) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut pending: Vec<(PathBuf, bool)> = paths
+        .iter()
+        .rev()
+        .map(|path| (PathBuf::from(path), true))
+        .collect();
+    while let Some((path, is_argument)) = pending.pop() {
+        if is_argument && path == Path::new(STDIN_PATH) {
+            files.push(path);
+            continue;
+        }
+        let metadata = match fs::metadata(&path) {
+            Ok(metadata) => metadata,
+            Err(error) => {
+                eprintln!("Skipping {}: {}", path.display(), error);
+                continue;
+            }
         };
-        println!("{}", spamsum);
+        if metadata.is_file() {
+            files.push(path);
+            continue;
+        }
+        if !metadata.is_dir() || (!is_argument && is_symlink(&path)) {
+            continue;
+        }
+        let entries = match fs::read_dir(&path) {
+            Ok(entries) => entries,
+            Err(error) => {
+                eprintln!("Skipping {}: {}", path.display(), error);
+                continue;
+            }
+        };
+        let mut children: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .collect();
+        children.sort();
+        pending.extend(children.into_iter().rev().map(|child| (child, false)));
+    }
+    files
+}
+
+fn is_symlink(path: &Path) -> bool {
+    fs::symlink_metadata(path)
+        .map(|metadata| metadata.file_type().is_symlink())
+        .unwrap_or(false)
+}
+
+fn display_name(path: &Path) -> String {
+    if path == Path::new(STDIN_PATH) {
+        String::from(STDIN_NAME)
+    } else {
+        path.display().to_string()
+    }
+}
+
+fn print_chunks(path: &Path, options: SpamsumOptions) -> Result<(), SpamsumError> {
+    let input = read_file(path)?;
+    let blocksize = match options.blocksize {
+        0 => get_configured_spamsum(&input, options)?.blocksize(),
+        blocksize => blocksize,
+    };
+    println!("{} (blocksize {}):", display_name(path), blocksize);
+    let mut start = 0;
+    // what is left after the last boundary is hashed as well
+    let end = if input.is_empty() {
+        None
+    } else {
+        Some(input.len())
+    };
+    for end in chunk_boundaries(&input, blocksize).chain(end) {
+        if end > start {
+            let hash = chunk_hash(&input[start..end]);
+            println!("{}\t{}\t{:08x}", start, end - start, hash);
+        }
+        start = end;
     }
     Ok(())
 }
+
+// Prints the spamsum of the whole file followed by those of its segments,
+// which are named after their byte range in the ssdeep format.
+fn print_segments(
+    path: &Path,
+    segment_size: u64,
+    options: SpamsumOptions,
+    ssdeep_output: bool,
+) -> Result<(), SpamsumError> {
+    let segmented = if path == Path::new(STDIN_PATH) {
+        get_segmented_spamsum_from_reader(io::stdin().lock(), segment_size, options)?
+    } else {
+        get_segmented_spamsum_from_reader(fs::File::open(path)?, segment_size, options)?
+    };
+    let name = display_name(path);
+    if ssdeep_output {
+        println!(
+            "{}",
+            NamedSpamsum::new(segmented.spamsum().clone(), name.clone())
+        );
+    } else {
+        println!("{}", segmented.spamsum());
+    }
+    for (i, segment) in segmented.segments().iter().enumerate() {
+        let range = segmented.segment_range(i);
+        if ssdeep_output {
+            let name = format!("{}@{}-{}", name, range.start, range.end);
+            println!("{}", NamedSpamsum::new(segment.clone(), name));
+        } else {
+            println!("{}-{}\t{}", range.start, range.end, segment);
+        }
+    }
+    Ok(())
+}
+
+// Hashes the windows of the file, or of stdin as it arrives, and hands them
+// to `output` as soon as they are complete, named after their byte range.
+fn hash_windows<F>(
+    path: &Path,
+    window: usize,
+    stride: usize,
+    options: SpamsumOptions,
+    mut output: F,
+) -> Result<(), SpamsumError>
+where
+    F: FnMut(String, Spamsum),
+{
+    let mut reader: Box<dyn Read> = if path == Path::new(STDIN_PATH) {
+        Box::new(io::stdin().lock())
+    } else {
+        Box::new(fs::File::open(path)?)
+    };
+    let name = display_name(path);
+    let mut hasher = WindowHasher::new(window, stride, options)?;
+    let mut buffer = vec![0; READ_AHEAD_SIZE];
+    loop {
+        let length = match reader.read(&mut buffer) {
+            Ok(0) => return Ok(()),
+            Ok(length) => length,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(SpamsumError::Io(e)),
+        };
+        for (range, spamsum) in hasher.update(&buffer[..length])? {
+            output(format!("{}@{}-{}", name, range.start, range.end), spamsum);
+        }
+    }
+}
+
+// the names and hex digits of the checksums of a file
+type Checksums = Vec<(&'static str, String)>;
+
+// Reads the file once for its spamsum and the checksums that are enabled,
+// which are returned in the order SHA-256, MD5.
+fn hash_file_with_checksums(
+    path: &Path,
+    options: SpamsumOptions,
+    sha256: bool,
+    md5: bool,
+) -> Result<(Spamsum, Checksums), SpamsumError> {
+    let mut reader: Box<dyn Read> = if path == Path::new(STDIN_PATH) {
+        Box::new(io::stdin().lock())
+    } else {
+        Box::new(fs::File::open(path)?)
+    };
+    let mut hasher = SpamsumHasher::with_options(options)?;
+    let mut sha256 = if sha256 {
+        Some(checksum::Sha256::new())
+    } else {
+        None
+    };
+    let mut md5 = if md5 {
+        Some(checksum::Md5::new())
+    } else {
+        None
+    };
+    let mut buffer = vec![0; READ_AHEAD_SIZE];
+    loop {
+        let length = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(length) => length,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(SpamsumError::Io(e)),
+        };
+        hasher.update(&buffer[..length]);
+        if hasher.exceeds_max_input_len() {
+            // finalizing fails anyway
+            break;
+        }
+        if let Some(sha256) = sha256.as_mut() {
+            sha256.update(&buffer[..length]);
+        }
+        if let Some(md5) = md5.as_mut() {
+            md5.update(&buffer[..length]);
+        }
+    }
+    let mut checksums = Vec::new();
+    if let Some(sha256) = sha256 {
+        checksums.push(("sha256", sha256.finalize()));
+    }
+    if let Some(md5) = md5 {
+        checksums.push(("md5", md5.finalize()));
+    }
+    Ok((hasher.finalize()?, checksums))
+}
+
+// Quotes the string for JSON output.
+fn json_string(s: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn read_file(path: &Path) -> io::Result<Vec<u8>> {
+    if path == Path::new(STDIN_PATH) {
+        let mut input = Vec::new();
+        io::stdin().lock().read_to_end(&mut input)?;
+        Ok(input)
+    } else {
+        fs::read(path)
+    }
+}
+
+fn hash_file(path: &Path, options: SpamsumOptions) -> Result<Spamsum, SpamsumError> {
+    if path == Path::new(STDIN_PATH) {
+        return get_spamsum_from_reader(io::stdin().lock(), options);
+    }
+    let mut file = fs::File::open(path)?;
+    let length = file.metadata()?.len();
+    if length >= MMAP_THRESHOLD {
+        // Safety: the mapping is only read, but modifying the file while it
+        // is being hashed may lead to a wrong hash (or a crash if truncated),
+        // just like with any other tool using mmap.
+        return match unsafe { Mmap::map(&file) } {
+            Ok(mmap) => get_configured_spamsum(&mmap, options),
+            // not every platform or file system supports mmap
+            Err(_) => get_spamsum_from_reader(file, options),
+        };
+    }
+    let mut input = Vec::with_capacity(length as usize);
+    file.read_to_end(&mut input)?;
+    get_configured_spamsum(&input, options)
+}
+
+// The exact hashes printed with --sha256 and --md5, which are calculated in
+// the same pass over the input as the spamsum.
+mod checksum {
+    const SHA256_K: [u32; 64] = [
+        0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4,
+        0xab1c5ed5, 0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe,
+        0x9bdc06a7, 0xc19bf174, 0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f,
+        0x4a7484aa, 0x5cb0a9dc, 0x76f988da, 0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7,
+        0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967, 0x27b70a85, 0x2e1b2138, 0x4d2c6dfc,
+        0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85, 0xa2bfe8a1, 0xa81a664b,
+        0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070, 0x19a4c116,
+        0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+        0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7,
+        0xc67178f2,
+    ];
+
+    // the per-round left rotations and the integer parts of the sines
+    const MD5_S: [u32; 64] = [
+        7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 5, 9, 14, 20, 5, 9, 14, 20, 5,
+        9, 14, 20, 5, 9, 14, 20, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 6, 10,
+        15, 21, 6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21,
+    ];
+    const MD5_K: [u32; 64] = [
+        0xd76aa478, 0xe8c7b756, 0x242070db, 0xc1bdceee, 0xf57c0faf, 0x4787c62a, 0xa8304613,
+        0xfd469501, 0x698098d8, 0x8b44f7af, 0xffff5bb1, 0x895cd7be, 0x6b901122, 0xfd987193,
+        0xa679438e, 0x49b40821, 0xf61e2562, 0xc040b340, 0x265e5a51, 0xe9b6c7aa, 0xd62f105d,
+        0x02441453, 0xd8a1e681, 0xe7d3fbc8, 0x21e1cde6, 0xc33707d6, 0xf4d50d87, 0x455a14ed,
+        0xa9e3e905, 0xfcefa3f8, 0x676f02d9, 0x8d2a4c8a, 0xfffa3942, 0x8771f681, 0x6d9d6122,
+        0xfde5380c, 0xa4beea44, 0x4bdecfa9, 0xf6bb4b60, 0xbebfbc70, 0x289b7ec6, 0xeaa127fa,
+        0xd4ef3085, 0x04881d05, 0xd9d4d039, 0xe6db99e5, 0x1fa27cf8, 0xc4ac5665, 0xf4292244,
+        0x432aff97, 0xab9423a7, 0xfc93a039, 0x655b59c3, 0x8f0ccc92, 0xffeff47d, 0x85845dd1,
+        0x6fa87e4f, 0xfe2ce6e0, 0xa3014314, 0x4e0811a1, 0xf7537e82, 0xbd3af235, 0x2ad7d2bb,
+        0xeb86d391,
+    ];
+
+    // Both hashes process 64 byte blocks and pad the input the same way,
+    // except for the byte order of the length.
+    struct Blocks {
+        block: [u8; 64],
+        len: u64,
+    }
+
+    impl Blocks {
+        fn new() -> Blocks {
+            Blocks {
+                block: [0; 64],
+                len: 0,
+            }
+        }
+
+        fn update(&mut self, mut input: &[u8], mut compress: impl FnMut(&[u8; 64])) {
+            while !input.is_empty() {
+                let offset = (self.len % 64) as usize;
+                let length = std::cmp::min(64 - offset, input.len());
+                self.block[offset..offset + length].copy_from_slice(&input[..length]);
+                self.len += length as u64;
+                input = &input[length..];
+                if self.len.is_multiple_of(64) {
+                    compress(&self.block);
+                }
+            }
+        }
+
+        fn finish(mut self, len_bytes: [u8; 8], mut compress: impl FnMut(&[u8; 64])) {
+            let offset = (self.len % 64) as usize;
+            self.block[offset] = 0x80;
+            self.block[offset + 1..].iter_mut().for_each(|c| *c = 0);
+            if offset >= 56 {
+                compress(&self.block);
+                self.block = [0; 64];
+            }
+            self.block[56..].copy_from_slice(&len_bytes);
+            compress(&self.block);
+        }
+    }
+
+    pub struct Sha256 {
+        state: [u32; 8],
+        blocks: Blocks,
+    }
+
+    impl Sha256 {
+        pub fn new() -> Sha256 {
+            Sha256 {
+                state: [
+                    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c,
+                    0x1f83d9ab, 0x5be0cd19,
+                ],
+                blocks: Blocks::new(),
+            }
+        }
+
+        pub fn update(&mut self, input: &[u8]) {
+            let state = &mut self.state;
+            self.blocks
+                .update(input, |block| sha256_compress(state, block));
+        }
+
+        pub fn finalize(mut self) -> String {
+            let len_bytes = self.blocks.len.wrapping_mul(8).to_be_bytes();
+            let state = &mut self.state;
+            self.blocks
+                .finish(len_bytes, |block| sha256_compress(state, block));
+            self.state.iter().map(|x| format!("{:08x}", x)).collect()
+        }
+    }
+
+    fn sha256_compress(state: &mut [u32; 8], block: &[u8; 64]) {
+        let mut w = [0u32; 64];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = *state;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = h
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(SHA256_K[i])
+                .wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+        for (x, y) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *x = x.wrapping_add(y);
+        }
+    }
+
+    pub struct Md5 {
+        state: [u32; 4],
+        blocks: Blocks,
+    }
+
+    impl Md5 {
+        pub fn new() -> Md5 {
+            Md5 {
+                state: [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476],
+                blocks: Blocks::new(),
+            }
+        }
+
+        pub fn update(&mut self, input: &[u8]) {
+            let state = &mut self.state;
+            self.blocks
+                .update(input, |block| md5_compress(state, block));
+        }
+
+        pub fn finalize(mut self) -> String {
+            let len_bytes = self.blocks.len.wrapping_mul(8).to_le_bytes();
+            let state = &mut self.state;
+            self.blocks
+                .finish(len_bytes, |block| md5_compress(state, block));
+            self.state
+                .iter()
+                .flat_map(|x| x.to_le_bytes())
+                .map(|c| format!("{:02x}", c))
+                .collect()
+        }
+    }
+
+    fn md5_compress(state: &mut [u32; 4], block: &[u8; 64]) {
+        let mut m = [0u32; 16];
+        for (i, word) in block.chunks(4).enumerate() {
+            m[i] = u32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        let [mut a, mut b, mut c, mut d] = *state;
+        for i in 0..64 {
+            let (f, g) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+            let f = f.wrapping_add(a).wrapping_add(MD5_K[i]).wrapping_add(m[g]);
+            a = d;
+            d = c;
+            c = b;
+            b = b.wrapping_add(f.rotate_left(MD5_S[i]));
+        }
+        for (x, y) in state.iter_mut().zip([a, b, c, d]) {
+            *x = x.wrapping_add(y);
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn sha256(input: &[u8]) -> String {
+            let mut sha256 = Sha256::new();
+            sha256.update(input);
+            sha256.finalize()
+        }
+
+        fn md5(input: &[u8]) -> String {
+            let mut md5 = Md5::new();
+            md5.update(input);
+            md5.finalize()
+        }
+
+        #[test]
+        fn test_sha256() {
+            // FIPS 180-4 examples
+            for &(input, expected) in &[
+                (
+                    &b""[..],
+                    "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+                ),
+                (
+                    b"abc",
+                    "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+                ),
+                (
+                    b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq",
+                    "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1",
+                ),
+                (
+                    b"abcdefghbcdefghicdefghijdefghijkefghijklfghijklmghijklmnhijklmnoijklmnopjklmnopqklmnopqrlmnopqrsmnopqrstnopqrstu",
+                    "cf5b16a778af8380036ce59e7b0492370b249b11e8f07a51afac45037afee9d1",
+                ),
+            ] {
+                assert_eq!(sha256(input), expected);
+            }
+        }
+
+        #[test]
+        fn test_md5() {
+            // RFC 1321 test suite
+            for &(input, expected) in &[
+                ("", "d41d8cd98f00b204e9800998ecf8427e"),
+                ("a", "0cc175b9c0f1b6a831c399e269772661"),
+                ("abc", "900150983cd24fb0d6963f7d28e17f72"),
+                ("message digest", "f96b697d7cb7938d525a2f31aaf161d0"),
+                (
+                    "abcdefghijklmnopqrstuvwxyz",
+                    "c3fcd3d76192e4007dfb496cca67e13b",
+                ),
+                (
+                    "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789",
+                    "d174ab98d277d9f5a5611c2c9f419d9f",
+                ),
+                (
+                    "12345678901234567890123456789012345678901234567890123456789012345678901234567890",
+                    "57edf4a22be3c955ac49da2e2107b67a",
+                ),
+            ] {
+                assert_eq!(md5(input.as_bytes()), expected);
+            }
+        }
+
+        #[test]
+        fn test_padding() {
+            // the length just fits into the last block, or needs another one
+            for &(length, expected_sha256, expected_md5) in &[
+                (
+                    55,
+                    "9f4390f8d30c2dd92ec9f095b65e2b9ae9b0a925a5258e241c9f1e910f734318",
+                    "ef1772b6dff9a122358552954ad0df65",
+                ),
+                (
+                    56,
+                    "b35439a4ac6f0948b6d6f9e3c6af0f5f590ce20f1bde7090ef7970686ec6738a",
+                    "3b0c8ac703f828b04c6c197006d17218",
+                ),
+                (
+                    63,
+                    "7d3e74a05d7db15bce4ad9ec0658ea98e3f06eeecf16b4c6fff2da457ddc2f34",
+                    "b06521f39153d618550606be297466d5",
+                ),
+                (
+                    64,
+                    "ffe054fe7ae0cb6dc65c3af9b61d5209f439851db43d0ba5997337df154668eb",
+                    "014842d480b571495a4a0363793f7367",
+                ),
+                (
+                    65,
+                    "635361c48bb9eab14198e76ea8ab7f1a41685d6ad62aa9146d301d4f17eb0ae0",
+                    "c743a45e0d2e6a95cb859adae0248435",
+                ),
+            ] {
+                let input = vec![b'a'; length];
+                assert_eq!(sha256(&input), expected_sha256);
+                assert_eq!(md5(&input), expected_md5);
+            }
+        }
+
+        #[test]
+        fn test_chunked_update() {
+            let input = vec![b'a'; 1_000_000];
+            let mut sha256 = Sha256::new();
+            let mut md5 = Md5::new();
+            for chunk in input.chunks(997) {
+                sha256.update(chunk);
+                md5.update(chunk);
+            }
+            assert_eq!(
+                sha256.finalize(),
+                "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0"
+            );
+            assert_eq!(md5.finalize(), "7707d6ae4e027c70eea2a935c2296f21");
+        }
+    }
+}
+
+// Stores hashes in and loads them from SQLite databases.
+#[cfg(feature = "sqlite")]
+mod sqlite {
+    use std::fs;
+    use std::io;
+    use std::path::Path;
+    use std::time::UNIX_EPOCH;
+
+    use rusqlite::{params, Connection, OpenFlags};
+    use spamsum::{NamedSpamsum, Spamsum, SpamsumError};
+
+    use crate::STDIN_PATH;
+
+    const CREATE_TABLE: &str = "CREATE TABLE IF NOT EXISTS hashes (
+        path TEXT NOT NULL,
+        size INTEGER,
+        mtime INTEGER,
+        blocksize INTEGER NOT NULL,
+        hash TEXT NOT NULL
+    )";
+
+    // Inserts hashes in a single transaction, which is only committed by
+    // `commit()`.
+    pub struct HashStore {
+        connection: Connection,
+    }
+
+    impl HashStore {
+        pub fn open(path: &str) -> Result<HashStore, SpamsumError> {
+            let connection = Connection::open(path).map_err(to_error)?;
+            connection.execute(CREATE_TABLE, []).map_err(to_error)?;
+            connection.execute_batch("BEGIN").map_err(to_error)?;
+            Ok(HashStore { connection })
+        }
+
+        pub fn insert(
+            &self,
+            path: &Path,
+            name: &str,
+            spamsum: &Spamsum,
+        ) -> Result<(), SpamsumError> {
+            // stdin has neither a size nor a modification time
+            let metadata = if path == Path::new(STDIN_PATH) {
+                None
+            } else {
+                Some(fs::metadata(path)?)
+            };
+            let size = metadata.as_ref().map(|metadata| metadata.len() as i64);
+            let mtime = metadata
+                .and_then(|metadata| metadata.modified().ok())
+                .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                .map(|duration| duration.as_secs() as i64);
+            self.connection
+                .prepare_cached(
+                    "INSERT INTO hashes (path, size, mtime, blocksize, hash) VALUES (?, ?, ?, ?, ?)",
+                )
+                .and_then(|mut statement| {
+                    statement.execute(params![
+                        name,
+                        size,
+                        mtime,
+                        spamsum.blocksize(),
+                        spamsum.to_string()
+                    ])
+                })
+                .map_err(to_error)?;
+            Ok(())
+        }
+
+        pub fn commit(self) -> Result<(), SpamsumError> {
+            self.connection.execute_batch("COMMIT").map_err(to_error)
+        }
+    }
+
+    // Loads the hashes of a database written by `HashStore`, named by path.
+    pub fn load_hashes(path: &str) -> Result<Vec<NamedSpamsum>, SpamsumError> {
+        let connection = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .map_err(to_error)?;
+        let mut statement = connection
+            .prepare("SELECT path, hash FROM hashes ORDER BY rowid")
+            .map_err(to_error)?;
+        let rows = statement
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })
+            .map_err(to_error)?;
+        let mut hashes = Vec::new();
+        for row in rows {
+            let (path, hash) = row.map_err(to_error)?;
+            hashes.push(NamedSpamsum::new(hash.parse()?, path));
+        }
+        Ok(hashes)
+    }
+
+    fn to_error(error: rusqlite::Error) -> SpamsumError {
+        SpamsumError::Io(io::Error::other(error))
+    }
+}
@@ -0,0 +1,103 @@
+use std::cmp;
+
+use crate::compare::compare_normalized;
+use crate::ngram::NgramIndex;
+use crate::Spamsum;
+
+/// Finds near-duplicates online: every inserted spamsum is matched against
+/// all spamsums inserted before it.
+///
+/// Spamsums are identified by the order they were inserted in, starting at 0.
+/// Like `similarity_join()`, only spamsums that share a substring are
+/// compared, so inserting stays fast for large numbers of mostly unrelated
+/// spamsums.
+///
+/// ```
+/// use spamsum::{get_spamsum, StreamingMatcher};
+///
+/// let mut matcher = StreamingMatcher::new(50);
+/// assert_eq!(matcher.insert(&get_spamsum(b"Hello").unwrap()), vec![]);
+/// assert_eq!(matcher.insert(&get_spamsum(b"Goodbye").unwrap()), vec![]);
+/// assert_eq!(matcher.insert(&get_spamsum(b"Hello").unwrap()), vec![(0, 100)]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct StreamingMatcher {
+    min_score: u32,
+    spamsums: Vec<Spamsum>,
+    index: NgramIndex,
+}
+
+impl StreamingMatcher {
+    /// Creates a matcher that reports matches with a score of at least
+    /// `min_score`.
+    pub fn new(min_score: u32) -> StreamingMatcher {
+        StreamingMatcher {
+            min_score: cmp::max(min_score, 1),
+            spamsums: Vec::new(),
+            index: NgramIndex::default(),
+        }
+    }
+
+    /// Inserts the spamsum and returns the previously inserted spamsums that
+    /// match it as `(id, score)`, sorted by id.
+    pub fn insert(&mut self, spamsum: &Spamsum) -> Vec<(usize, u32)> {
+        let spamsum = spamsum.normalized();
+        let mut matches = Vec::new();
+        for id in self.index.candidates(&spamsum) {
+            let score = compare_normalized(
+                &spamsum,
+                &self.spamsums[id],
+                Default::default(),
+                self.min_score,
+            );
+            if score >= self.min_score {
+                matches.push((id, score));
+            }
+        }
+        self.index.insert(self.spamsums.len(), &spamsum);
+        self.spamsums.push(spamsum);
+        matches
+    }
+
+    /// Returns the number of inserted spamsums.
+    pub fn len(&self) -> usize {
+        self.spamsums.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.spamsums.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compare;
+
+    #[test]
+    fn test_streaming_matcher() {
+        let spamsums: Vec<Spamsum> = [
+            "48:ABCDEFGHIJKLMNOP:ABCD",
+            "48:ABCDEFGHIJKLMNQR:WXYZ",
+            "12:ABCDEFGHIJKLMNOP:ABCD",
+            "24:QRSTUVWX:ABCDEFGHIJKLMNOP",
+            "3:Hn:Hn",
+            "48:ABCDEFGHIJKLMNOOOOOOOP:ABCD",
+        ]
+        .iter()
+        .map(|s| s.parse().unwrap())
+        .collect();
+        let mut matcher = StreamingMatcher::new(80);
+        assert!(matcher.is_empty());
+        let matches: Vec<Vec<(usize, u32)>> = spamsums
+            .iter()
+            .map(|spamsum| matcher.insert(spamsum))
+            .collect();
+        assert_eq!(matcher.len(), 6);
+        assert_eq!(matches[1], vec![(0, compare(&spamsums[0], &spamsums[1]))]);
+        assert_eq!(matches[2], vec![]);
+        assert_eq!(matches[3], vec![(0, 100), (1, 88)]);
+        assert_eq!(matches[4], vec![]);
+        assert_eq!(matches[5], vec![(0, 96), (1, 83), (3, 96)]);
+    }
+}
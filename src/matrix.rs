@@ -0,0 +1,217 @@
+use alloc::vec::Vec;
+use core::cmp;
+
+use crate::compare::compare_normalized;
+use crate::{Spamsum, SpamsumError};
+
+/// The similarity scores of all pairs in a list of spamsums, as calculated by
+/// `compare_all()`.
+///
+/// Only the upper triangle is stored, and only for pairs with a score above 0
+/// (or at least the threshold), so the matrix stays small for large lists of
+/// mostly unrelated spamsums.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SimilarityMatrix {
+    len: usize,
+    // (i, j, score) with i < j, sorted by (i, j)
+    pairs: Vec<(usize, usize, u32)>,
+}
+
+impl SimilarityMatrix {
+    // The pairs must be sorted by (i, j).
+    pub(crate) fn from_pairs(len: usize, pairs: Vec<(usize, usize, u32)>) -> SimilarityMatrix {
+        SimilarityMatrix { len, pairs }
+    }
+
+    /// Returns the number of compared spamsums.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the score of the `i`-th and `j`-th spamsum, or `None` if the
+    /// pair scored below the threshold. A spamsum always scores 100 against
+    /// itself.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `i` or `j` is not smaller than `len()`.
+    pub fn score(&self, i: usize, j: usize) -> Option<u32> {
+        assert!(
+            i < self.len && j < self.len,
+            "index out of bounds for {} spamsums",
+            self.len
+        );
+        if i == j {
+            return Some(100);
+        }
+        let key = (cmp::min(i, j), cmp::max(i, j));
+        self.pairs
+            .binary_search_by_key(&key, |&(i, j, _)| (i, j))
+            .ok()
+            .map(|index| self.pairs[index].2)
+    }
+
+    /// Returns all stored pairs as `(i, j, score)` with `i < j`, sorted by
+    /// `i` and then `j`.
+    pub fn pairs(&self) -> &[(usize, usize, u32)] {
+        &self.pairs
+    }
+}
+
+/// Compares every pair of the given spamsums.
+///
+/// ```
+/// use spamsum::{compare_all, get_spamsum};
+///
+/// let spamsums = vec![
+///     get_spamsum(b"Hello").unwrap(),
+///     get_spamsum(b"Hello").unwrap(),
+///     get_spamsum(b"Goodbye").unwrap(),
+/// ];
+/// let matrix = compare_all(&spamsums);
+/// assert_eq!(matrix.score(0, 1), Some(100));
+/// assert_eq!(matrix.score(1, 2), None);
+/// ```
+pub fn compare_all(spamsums: &[Spamsum]) -> SimilarityMatrix {
+    compare_all_with_threshold(spamsums, 1)
+}
+
+/// Compares every pair of the given spamsums like `compare_all()`, but only
+/// keeps scores of at least `min_score`.
+pub fn compare_all_with_threshold(spamsums: &[Spamsum], min_score: u32) -> SimilarityMatrix {
+    let normalized: Vec<Spamsum> = spamsums.iter().map(Spamsum::normalized).collect();
+    let pairs = (0..normalized.len())
+        .flat_map(|i| row_pairs(&normalized, i, min_score))
+        .collect();
+    SimilarityMatrix::from_pairs(spamsums.len(), pairs)
+}
+
+/// Compares every pair of the given spamsums like
+/// `compare_all_with_threshold()`, but calls `should_cancel` before every
+/// spamsum is compared with the later ones and fails with
+/// `SpamsumError::Cancelled` as soon as it returns true, so that comparing
+/// large lists can be aborted.
+///
+/// ```
+/// use spamsum::{compare_all, compare_all_with_cancel, get_spamsum, SpamsumError};
+///
+/// let spamsums = vec![get_spamsum(b"Hello").unwrap(), get_spamsum(b"Hello").unwrap()];
+/// let matrix = compare_all_with_cancel(&spamsums, 1, &|| false).unwrap();
+/// assert_eq!(matrix, compare_all(&spamsums));
+/// assert!(matches!(
+///     compare_all_with_cancel(&spamsums, 1, &|| true),
+///     Err(SpamsumError::Cancelled)
+/// ));
+/// ```
+pub fn compare_all_with_cancel(
+    spamsums: &[Spamsum],
+    min_score: u32,
+    should_cancel: &dyn Fn() -> bool,
+) -> Result<SimilarityMatrix, SpamsumError> {
+    let normalized: Vec<Spamsum> = spamsums.iter().map(Spamsum::normalized).collect();
+    let mut pairs = Vec::new();
+    for i in 0..normalized.len() {
+        if should_cancel() {
+            return Err(SpamsumError::Cancelled);
+        }
+        pairs.extend(row_pairs(&normalized, i, min_score));
+    }
+    Ok(SimilarityMatrix::from_pairs(spamsums.len(), pairs))
+}
+
+// The pairs of the i-th normalized spamsum with all later ones that score at
+// least `min_score`.
+pub(crate) fn row_pairs(
+    normalized: &[Spamsum],
+    i: usize,
+    min_score: u32,
+) -> impl Iterator<Item = (usize, usize, u32)> + '_ {
+    let min_score = cmp::max(min_score, 1);
+    let a = &normalized[i];
+    normalized
+        .iter()
+        .enumerate()
+        .skip(i + 1)
+        .filter(move |(_, b)| a.is_comparable_with(b))
+        .map(move |(j, b)| {
+            (
+                i,
+                j,
+                compare_normalized(a, b, Default::default(), min_score),
+            )
+        })
+        .filter(move |&(_, _, score)| score >= min_score)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compare;
+
+    fn spamsums() -> Vec<Spamsum> {
+        [
+            "48:ABCDEFGHIJKLMNOP:ABCD",
+            "48:ABCDEFGHIJKLMNOQ:WXYZ",
+            "12:ABCDEFGHIJKLMNOP:ABCD",
+            "24:QRSTUVWX:ABCDEFGHIJKLMNOP",
+        ]
+        .iter()
+        .map(|s| s.parse().unwrap())
+        .collect()
+    }
+
+    #[test]
+    fn test_compare_all() {
+        let spamsums = spamsums();
+        let matrix = compare_all(&spamsums);
+        assert_eq!(matrix.len(), 4);
+        for i in 0..spamsums.len() {
+            for j in 0..spamsums.len() {
+                let score = compare(&spamsums[i], &spamsums[j]);
+                let expected = if score > 0 { Some(score) } else { None };
+                assert_eq!(matrix.score(i, j), expected);
+            }
+        }
+        assert_eq!(matrix.pairs(), &[(0, 1, 94), (0, 3, 100), (1, 3, 94)]);
+    }
+
+    #[test]
+    fn test_compare_all_with_threshold() {
+        let matrix = compare_all_with_threshold(&spamsums(), 95);
+        assert_eq!(matrix.pairs(), &[(0, 3, 100)]);
+        assert_eq!(matrix.score(1, 0), None);
+        assert!(compare_all(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_compare_all_with_cancel() {
+        let spamsums = spamsums();
+        let matrix = compare_all_with_cancel(&spamsums, 95, &|| false).unwrap();
+        assert_eq!(matrix, compare_all_with_threshold(&spamsums, 95));
+        // cancelled after the second row
+        let rows = core::cell::Cell::new(0);
+        let should_cancel = || {
+            rows.set(rows.get() + 1);
+            rows.get() > 2
+        };
+        assert!(matches!(
+            compare_all_with_cancel(&spamsums, 1, &should_cancel),
+            Err(SpamsumError::Cancelled)
+        ));
+        assert_eq!(rows.get(), 3);
+        assert!(compare_all_with_cancel(&[], 1, &|| true)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_score_out_of_bounds() {
+        compare_all(&spamsums()).score(0, 4);
+    }
+}
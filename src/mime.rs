@@ -0,0 +1,339 @@
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use crate::{HeaderEnd, SpamsumOptions, BASE64_CHARSET};
+
+// nested multipart messages beyond this depth are hashed as they are
+const MAX_DEPTH: usize = 8;
+
+// Applies the MIME options to a (multipart) MIME message:
+// `ignore_mime_headers` drops the headers of the message and its parts, the
+// boundaries and the text before the first and after the last part, while
+// `decode_transfer_encoding` decodes quoted-printable and base64 bodies.
+pub(crate) fn filter_mime(message: &[u8], options: SpamsumOptions) -> Vec<u8> {
+    let mut output = Vec::with_capacity(message.len());
+    append_entity(message, options, 0, &mut output);
+    output
+}
+
+fn append_entity(entity: &[u8], options: SpamsumOptions, depth: usize, output: &mut Vec<u8>) {
+    let (headers, body) = split_headers(entity);
+    if !options.ignore_mime_headers {
+        output.extend_from_slice(headers);
+    }
+    let boundary = if depth < MAX_DEPTH {
+        header_value(headers, b"content-type")
+            .and_then(|content_type| multipart_boundary(&content_type))
+    } else {
+        None
+    };
+    if let Some(parts) = boundary.and_then(|boundary| split_parts(body, &boundary)) {
+        let mut end = 0;
+        for part in parts {
+            if !options.ignore_mime_headers {
+                output.extend_from_slice(&body[end..part.start]);
+            }
+            end = part.end;
+            append_entity(&body[part], options, depth + 1, output);
+        }
+        if !options.ignore_mime_headers {
+            output.extend_from_slice(&body[end..]);
+        }
+        return;
+    }
+    let encoding = if options.decode_transfer_encoding {
+        header_value(headers, b"content-transfer-encoding")
+    } else {
+        None
+    };
+    match encoding.as_ref().map(|encoding| encoding.trim_ascii()) {
+        Some(encoding) if encoding.eq_ignore_ascii_case(b"quoted-printable") => {
+            decode_quoted_printable(body, output)
+        }
+        Some(encoding) if encoding.eq_ignore_ascii_case(b"base64") => decode_base64(body, output),
+        _ => output.extend_from_slice(body),
+    }
+}
+
+// Splits at the first empty line. Without one, there are no headers.
+fn split_headers(entity: &[u8]) -> (&[u8], &[u8]) {
+    // parts without headers start with the empty line
+    let mut header_end = HeaderEnd {
+        last_bytes: u16::from(b'\n'),
+    };
+    match entity.iter().position(|&c| header_end.update(c)) {
+        Some(position) => entity.split_at(position + 1),
+        None => (&[], entity),
+    }
+}
+
+// Returns the unfolded value of the first header with the given (lowercase)
+// name.
+fn header_value(headers: &[u8], name: &[u8]) -> Option<Vec<u8>> {
+    let mut value: Option<Vec<u8>> = None;
+    for line in lines(headers) {
+        if line.starts_with(b" ") || line.starts_with(b"\t") {
+            // a folded line continues the previous header
+            if let Some(value) = value.as_mut() {
+                value.extend_from_slice(line);
+            }
+            continue;
+        }
+        if value.is_some() {
+            break;
+        }
+        if let Some(colon) = line.iter().position(|&c| c == b':') {
+            if line[..colon].trim_ascii_end().eq_ignore_ascii_case(name) {
+                value = Some(line[colon + 1..].to_vec());
+            }
+        }
+    }
+    value
+}
+
+// Returns the boundary parameter of a multipart content type.
+fn multipart_boundary(content_type: &[u8]) -> Option<Vec<u8>> {
+    let content_type = content_type.trim_ascii_start();
+    if !content_type.get(..10)?.eq_ignore_ascii_case(b"multipart/") {
+        return None;
+    }
+    let lowercase = content_type.to_ascii_lowercase();
+    let start = lowercase
+        .windows(9)
+        .position(|window| window == b"boundary=")?
+        + 9;
+    let value = &content_type[start..];
+    let boundary = if let Some(quoted) = value.strip_prefix(b"\"") {
+        &quoted[..quoted.iter().position(|&c| c == b'"')?]
+    } else {
+        let end = value
+            .iter()
+            .position(|&c| c == b';' || c.is_ascii_whitespace())
+            .unwrap_or(value.len());
+        &value[..end]
+    };
+    if boundary.is_empty() {
+        None
+    } else {
+        Some(boundary.to_vec())
+    }
+}
+
+// Returns the ranges of the parts between the boundary lines, or `None` if
+// there are none. The line break before a boundary line belongs to the
+// boundary.
+fn split_parts(body: &[u8], boundary: &[u8]) -> Option<Vec<Range<usize>>> {
+    let mut parts = Vec::new();
+    let mut part_start = None;
+    let mut line_start = 0;
+    while line_start < body.len() {
+        let line_end = body[line_start..]
+            .iter()
+            .position(|&c| c == b'\n')
+            .map_or(body.len(), |position| line_start + position + 1);
+        let line = &body[line_start..line_end];
+        if let Some(rest) = line
+            .strip_prefix(b"--")
+            .and_then(|line| line.strip_prefix(boundary))
+        {
+            let is_last = rest.starts_with(b"--");
+            let rest = if is_last { &rest[2..] } else { rest };
+            if rest.trim_ascii().is_empty() {
+                if let Some(part_start) = part_start {
+                    let mut part_end = line_start;
+                    if part_end > part_start && body[part_end - 1] == b'\n' {
+                        part_end -= 1;
+                        if part_end > part_start && body[part_end - 1] == b'\r' {
+                            part_end -= 1;
+                        }
+                    }
+                    parts.push(part_start..part_end);
+                }
+                if is_last {
+                    return Some(parts);
+                }
+                part_start = Some(line_end);
+            }
+        }
+        line_start = line_end;
+    }
+    // be lenient with messages that lack the closing boundary
+    parts.push(part_start?..body.len());
+    Some(parts)
+}
+
+// Soft line breaks are removed and "=XX" is decoded, anything invalid is kept
+// as it is.
+fn decode_quoted_printable(input: &[u8], output: &mut Vec<u8>) {
+    let mut i = 0;
+    while i < input.len() {
+        if input[i] != b'=' {
+            output.push(input[i]);
+            i += 1;
+        } else if input[i + 1..].starts_with(b"\r\n") {
+            i += 3;
+        } else if input[i + 1..].starts_with(b"\n") {
+            i += 2;
+        } else if let Some(c) = input
+            .get(i + 1..i + 3)
+            .and_then(|hex| Some(hex_digit(hex[0])? << 4 | hex_digit(hex[1])?))
+        {
+            output.push(c);
+            i += 3;
+        } else {
+            output.push(b'=');
+            i += 1;
+        }
+    }
+}
+
+fn hex_digit(c: u8) -> Option<u8> {
+    (c as char).to_digit(16).map(|digit| digit as u8)
+}
+
+// Skips everything outside the alphabet (i.e. line breaks) and stops at the
+// padding.
+fn decode_base64(input: &[u8], output: &mut Vec<u8>) {
+    let mut bits: u32 = 0;
+    let mut bit_count = 0;
+    for &c in input {
+        if c == b'=' {
+            break;
+        }
+        let value = match BASE64_CHARSET.bytes().position(|b| b == c) {
+            Some(value) => value as u32,
+            None => continue,
+        };
+        bits = bits << 6 | value;
+        bit_count += 6;
+        if bit_count >= 8 {
+            bit_count -= 8;
+            output.push((bits >> bit_count) as u8);
+            bits &= (1 << bit_count) - 1;
+        }
+    }
+}
+
+fn lines(input: &[u8]) -> impl Iterator<Item = &[u8]> {
+    input
+        .split(|&c| c == b'\n')
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strip_mime_headers(message: &[u8]) -> Vec<u8> {
+        let options = SpamsumOptions::builder()
+            .ignore_mime_headers(true)
+            .build()
+            .unwrap();
+        filter_mime(message, options)
+    }
+
+    #[test]
+    fn test_strip_mime_headers() {
+        let message = b"From: someone\r\n\
+            Content-Type: multipart/mixed;\r\n\
+            \tboundary=\"outer\"\r\n\
+            \r\n\
+            This is a multi-part message in MIME format.\r\n\
+            --outer\r\n\
+            Content-Type: text/plain\r\n\
+            \r\n\
+            Dear Sir\r\n\
+            --outer\r\n\
+            Content-Type: multipart/alternative; boundary=inner\r\n\
+            \r\n\
+            --inner\r\n\
+            Content-Type: text/plain\r\n\
+            \r\n\
+            Please buy\r\n\
+            --inner\r\n\
+            \r\n\
+            my stuff\r\n\
+            --inner--\r\n\
+            --outer--\r\n\
+            epilogue\r\n";
+        assert_eq!(
+            strip_mime_headers(message),
+            b"Dear SirPlease buymy stuff".to_vec()
+        );
+    }
+
+    #[test]
+    fn test_strip_mime_headers_without_multipart() {
+        let message = b"Content-Type: text/plain\n\nDear Sir\n--outer\n";
+        assert_eq!(strip_mime_headers(message), b"Dear Sir\n--outer\n".to_vec());
+        // the boundary never shows up
+        let message = b"Content-Type: multipart/mixed; boundary=x\n\nDear Sir\n";
+        assert_eq!(strip_mime_headers(message), b"Dear Sir\n".to_vec());
+        assert_eq!(strip_mime_headers(b"Dear Sir"), b"Dear Sir".to_vec());
+    }
+
+    #[test]
+    fn test_strip_mime_headers_without_closing_boundary() {
+        let message =
+            b"Content-Type: Multipart/Mixed; Boundary=x\n\n--x\n\nDear Sir\n--x\n\nPlease buy\n";
+        assert_eq!(
+            strip_mime_headers(message),
+            b"Dear SirPlease buy\n".to_vec()
+        );
+    }
+
+    #[test]
+    fn test_header_value() {
+        let headers = b"Subject: a\r\nContent-TYPE : text/plain;\r\n charset=utf-8\r\nX: b\r\n";
+        assert_eq!(
+            header_value(headers, b"content-type").unwrap(),
+            b" text/plain; charset=utf-8".to_vec()
+        );
+        assert_eq!(header_value(headers, b"to"), None);
+    }
+
+    #[test]
+    fn test_decode_transfer_encoding() {
+        let message = b"Content-Type: multipart/mixed; boundary=x\n\
+            \n\
+            preamble\n\
+            --x\n\
+            Content-Transfer-Encoding: quoted-printable\n\
+            \n\
+            Dear Sir=2C please=\n buy=XX\n\
+            --x\n\
+            Content-Transfer-Encoding: BASE64\n\
+            \n\
+            bXkgc3R1\n\
+            ZmY=\n\
+            --x--\n";
+        let options = SpamsumOptions::builder()
+            .decode_transfer_encoding(true)
+            .build()
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(filter_mime(message, options)).unwrap(),
+            "Content-Type: multipart/mixed; boundary=x\n\
+            \n\
+            preamble\n\
+            --x\n\
+            Content-Transfer-Encoding: quoted-printable\n\
+            \n\
+            Dear Sir, please buy=XX\n\
+            --x\n\
+            Content-Transfer-Encoding: BASE64\n\
+            \n\
+            my stuff\n\
+            --x--\n"
+        );
+        let options = SpamsumOptions::builder()
+            .decode_transfer_encoding(true)
+            .ignore_mime_headers(true)
+            .build()
+            .unwrap();
+        assert_eq!(
+            filter_mime(message, options),
+            b"Dear Sir, please buy=XXmy stuff".to_vec()
+        );
+    }
+}
@@ -0,0 +1,455 @@
+use std::collections::VecDeque;
+#[cfg(feature = "serde")]
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::lsh::MAX_SIGNATURE_LENGTH;
+use crate::word::{words_doc, WordHasher};
+use crate::{
+    check_options, filter_input, mix, Algorithm, FuzzyDigest, FuzzyHash, ParseSpamsumError,
+    SpamsumError, SpamsumOptions,
+};
+
+/// What the sets of `MinHash` signatures consist of.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Shingles {
+    /// Overlapping sequences of this many bytes, for any kind of input.
+    Bytes(u32),
+    /// Overlapping sequences of this many words, for texts.
+    #[doc = words_doc!()]
+    Tokens(u32),
+}
+
+/// Options for `MinHash` signatures.
+///
+/// Signatures are only comparable if they were calculated with the same
+/// shingles. More hash values estimate the Jaccard similarity more
+/// precisely: the standard error is at most `0.5 / sqrt(num_hashes)`.
+/// There are at most 256 hash values.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "MinHashOptionsFields")
+)]
+pub struct MinHashOptions {
+    pub num_hashes: u32,
+    pub shingles: Shingles,
+}
+
+// The serialized fields of `MinHashOptions`, which are checked before they
+// are used.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+#[serde(rename = "MinHashOptions")]
+struct MinHashOptionsFields {
+    num_hashes: u32,
+    shingles: Shingles,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<MinHashOptionsFields> for MinHashOptions {
+    type Error = SpamsumError;
+
+    fn try_from(fields: MinHashOptionsFields) -> Result<MinHashOptions, SpamsumError> {
+        let options = MinHashOptions {
+            num_hashes: fields.num_hashes,
+            shingles: fields.shingles,
+        };
+        options.check()?;
+        Ok(options)
+    }
+}
+
+impl MinHashOptions {
+    fn check(&self) -> Result<(), SpamsumError> {
+        if self.num_hashes > MAX_SIGNATURE_LENGTH {
+            return Err(SpamsumError::InvalidOptions("too many MinHash values"));
+        }
+        Ok(())
+    }
+}
+
+impl Default for MinHashOptions {
+    /// 128 hash values of shingles of 5 bytes.
+    fn default() -> MinHashOptions {
+        MinHashOptions {
+            num_hashes: 128,
+            shingles: Shingles::Bytes(5),
+        }
+    }
+}
+
+/// A MinHash signature, which estimates the Jaccard similarity of the sets
+/// of shingles of two inputs, i.e. the fraction of their shingles they have
+/// in common.
+///
+/// Unlike spamsums, signatures of inputs of very different sizes can still
+/// be compared, and their similarity can be found for a large corpus with
+/// locality-sensitive hashing, see `minhash_connected_components()`.
+///
+/// ```
+/// use spamsum::{get_minhash, MinHashOptions, Shingles};
+///
+/// let options = MinHashOptions {
+///     num_hashes: 256,
+///     shingles: Shingles::Tokens(1),
+/// };
+/// let a = get_minhash(b"Please buy my stuff", Default::default(), options).unwrap();
+/// let b = get_minhash(b"Please buy our stuff", Default::default(), options).unwrap();
+/// // 3 of 5 different words are shared
+/// assert!((a.jaccard(&b) - 0.6).abs() < 0.15);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MinHash {
+    signature: Vec<u64>,
+}
+
+impl MinHash {
+    /// Creates a signature from its hash values, e.g. ones that were stored.
+    pub fn from_signature(signature: Vec<u64>) -> MinHash {
+        MinHash { signature }
+    }
+
+    /// Returns the hash values of the signature.
+    pub fn signature(&self) -> &[u64] {
+        &self.signature
+    }
+
+    /// Returns the estimated Jaccard similarity from 0.0 to 1.0, the
+    /// fraction of equal hash values.
+    ///
+    /// The first `n` hash values of a signature are the same for any
+    /// `num_hashes` of at least `n`, so signatures of different lengths are
+    /// compared by the hash values they both have. Empty inputs are equal to
+    /// each other, and so are empty signatures.
+    pub fn jaccard(&self, other: &MinHash) -> f64 {
+        let len = self.signature.len().min(other.signature.len());
+        if len == 0 {
+            return if self.signature.len() == other.signature.len() {
+                1.0
+            } else {
+                0.0
+            };
+        }
+        let equal = self
+            .signature
+            .iter()
+            .zip(other.signature.iter())
+            .filter(|(a, b)| a == b)
+            .count();
+        equal as f64 / len as f64
+    }
+}
+
+/// Writes the hash values as 16 hex digits each.
+impl fmt::Display for MinHash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for min_hash in &self.signature {
+            write!(f, "{:016x}", min_hash)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for MinHash {
+    type Err = ParseSpamsumError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(c) = s.chars().find(|c| !c.is_ascii_hexdigit()) {
+            return Err(ParseSpamsumError::InvalidCharacter(c));
+        }
+        if s.is_empty() || !s.len().is_multiple_of(16) {
+            return Err(ParseSpamsumError::InvalidFormat);
+        }
+        // only ASCII hex digits are left
+        let signature = (0..s.len())
+            .step_by(16)
+            .map(|i| u64::from_str_radix(&s[i..i + 16], 16).unwrap())
+            .collect();
+        Ok(MinHash::from_signature(signature))
+    }
+}
+
+/// Incrementally calculates the `MinHash` signature of data that arrives in
+/// chunks.
+#[derive(Clone)]
+pub struct MinHasher {
+    shingle_len: usize,
+    tokens: bool,
+    seeds: Vec<u64>,
+    signature: Vec<u64>,
+    // the last bytes or the hashes of the last words
+    window: VecDeque<u64>,
+    word: WordHasher,
+    shingles: u64,
+}
+
+impl MinHasher {
+    /// Creates a hasher for signatures with the given options; 0 hash values
+    /// or shingle lengths are treated like 1. Fails for more than 256 hash
+    /// values.
+    pub fn new(options: MinHashOptions) -> Result<MinHasher, SpamsumError> {
+        options.check()?;
+        let (shingle_len, tokens) = match options.shingles {
+            Shingles::Bytes(len) => (len, false),
+            Shingles::Tokens(len) => (len, true),
+        };
+        let num_hashes = options.num_hashes.max(1) as usize;
+        Ok(MinHasher {
+            shingle_len: shingle_len.max(1) as usize,
+            tokens,
+            seeds: (0..num_hashes).map(|i| mix(i as u64 + 1)).collect(),
+            signature: vec![u64::MAX; num_hashes],
+            window: VecDeque::new(),
+            word: WordHasher::default(),
+            shingles: 0,
+        })
+    }
+
+    /// Hashes the next chunk of input.
+    pub fn update(&mut self, input: &[u8]) {
+        for &c in input {
+            if !self.tokens {
+                self.push(u64::from(c));
+            } else if let Some(hash) = self.word.push(c) {
+                self.push(hash);
+            }
+        }
+    }
+
+    // Adds a byte or word to the window and hashes the window if it is a
+    // complete shingle.
+    fn push(&mut self, element: u64) {
+        if self.window.len() == self.shingle_len {
+            self.window.pop_front();
+        }
+        self.window.push_back(element);
+        if self.window.len() == self.shingle_len {
+            self.insert_window();
+        }
+    }
+
+    fn insert_window(&mut self) {
+        let shingle = self.window.iter().fold(0, |hash, &x| mix(hash ^ x));
+        for (min_hash, &seed) in self.signature.iter_mut().zip(self.seeds.iter()) {
+            *min_hash = (*min_hash).min(mix(shingle ^ seed));
+        }
+        self.shingles += 1;
+    }
+
+    /// Returns the signature of the input so far. An input shorter than a
+    /// shingle is hashed as a single shorter shingle.
+    pub fn finalize(mut self) -> MinHash {
+        if let Some(hash) = self.word.finish() {
+            self.push(hash);
+        }
+        if self.shingles == 0 && !self.window.is_empty() {
+            self.insert_window();
+        }
+        MinHash::from_signature(self.signature)
+    }
+
+    /// Starts over with a new input.
+    pub fn reset(&mut self) {
+        self.signature
+            .iter_mut()
+            .for_each(|min_hash| *min_hash = u64::MAX);
+        self.window.clear();
+        self.word = WordHasher::default();
+        self.shingles = 0;
+    }
+}
+
+impl Default for MinHasher {
+    fn default() -> MinHasher {
+        MinHasher::new(MinHashOptions::default()).unwrap()
+    }
+}
+
+impl FuzzyHash for MinHash {
+    const ALGORITHM: Algorithm = Algorithm::MinHash;
+
+    /// Hashes with the default `MinHashOptions`.
+    fn hash(input: &[u8], options: SpamsumOptions) -> Result<MinHash, SpamsumError> {
+        get_minhash(input, options, Default::default())
+    }
+
+    fn similarity(&self, other: &MinHash) -> u32 {
+        (100.0 * self.jaccard(other)).round() as u32
+    }
+}
+
+impl FuzzyDigest for MinHasher {
+    type Output = MinHash;
+
+    fn update(&mut self, data: impl AsRef<[u8]>) {
+        MinHasher::update(self, data.as_ref());
+    }
+
+    fn finalize(self) -> Result<MinHash, SpamsumError> {
+        Ok(MinHasher::finalize(self))
+    }
+
+    fn finalize_reset(&mut self) -> Result<MinHash, SpamsumError> {
+        let minhash = self.clone().finalize();
+        self.reset();
+        Ok(minhash)
+    }
+
+    fn reset(&mut self) {
+        MinHasher::reset(self);
+    }
+}
+
+/// Calculates the `MinHash` signature of `input`, after the same
+/// preprocessing as `get_configured_spamsum()` with `options`.
+pub fn get_minhash(
+    input: &[u8],
+    options: SpamsumOptions,
+    minhash_options: MinHashOptions,
+) -> Result<MinHash, SpamsumError> {
+    check_options(&options)?;
+    if let Some(max_input_len) = options.max_input_len {
+        if input.len() as u64 > max_input_len {
+            return Err(SpamsumError::InputLimitExceeded(max_input_len));
+        }
+    }
+    let mut hasher = MinHasher::new(minhash_options)?;
+    hasher.update(&filter_input(input, options));
+    Ok(hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pseudo_random_bytes;
+
+    #[test]
+    fn test_jaccard() {
+        let options = MinHashOptions {
+            num_hashes: 256,
+            shingles: Shingles::Bytes(4),
+        };
+        let minhash = |input: &[u8]| get_minhash(input, Default::default(), options).unwrap();
+        let whole = pseudo_random_bytes(20_000, 1);
+        // the halves share almost no shingles
+        let a = minhash(&whole[..15_000]);
+        let b = minhash(&whole[5_000..]);
+        assert_eq!(a.signature().len(), 256);
+        assert_eq!(a.jaccard(&a), 1.0);
+        assert!((a.jaccard(&b) - 0.5).abs() < 0.1, "{}", a.jaccard(&b));
+        assert!(a.jaccard(&minhash(&pseudo_random_bytes(15_000, 2))) < 0.05);
+        // signatures with fewer hash values are prefixes
+        let options = MinHashOptions {
+            num_hashes: 128,
+            ..options
+        };
+        let short = get_minhash(&whole[..15_000], Default::default(), options);
+        assert_eq!(short.unwrap().signature(), &a.signature()[..128]);
+    }
+
+    #[test]
+    fn test_empty_signatures() {
+        let empty = MinHash::from_signature(Vec::new());
+        let minhash = get_minhash(b"", Default::default(), Default::default()).unwrap();
+        assert_eq!(empty.jaccard(&empty), 1.0);
+        assert_eq!(empty.jaccard(&minhash), 0.0);
+        assert_eq!(minhash.jaccard(&empty), 0.0);
+        assert_eq!(minhash.jaccard(&minhash), 1.0);
+    }
+
+    #[test]
+    fn test_invalid_options() {
+        for &num_hashes in &[257, u32::MAX] {
+            let options = MinHashOptions {
+                num_hashes,
+                ..Default::default()
+            };
+            assert!(matches!(
+                MinHasher::new(options),
+                Err(SpamsumError::InvalidOptions(_))
+            ));
+            assert!(get_minhash(b"Hello", Default::default(), options).is_err());
+        }
+        let options = MinHashOptions {
+            num_hashes: 256,
+            ..Default::default()
+        };
+        assert!(MinHasher::new(options).is_ok());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_deserialize_options() {
+        let options = MinHashOptions {
+            num_hashes: 64,
+            shingles: Shingles::Tokens(2),
+        };
+        let json = serde_json::to_string(&options).unwrap();
+        assert_eq!(
+            serde_json::from_str::<MinHashOptions>(&json).unwrap(),
+            options
+        );
+        let json = r#"{"num_hashes":4294967295,"shingles":{"Bytes":5}}"#;
+        assert!(serde_json::from_str::<MinHashOptions>(json).is_err());
+    }
+
+    #[test]
+    fn test_format() {
+        let minhash = MinHash::from_signature(vec![1, u64::MAX]);
+        assert_eq!(minhash.to_string(), "0000000000000001ffffffffffffffff");
+        assert_eq!(minhash.to_string().parse(), Ok(minhash));
+        assert_eq!("".parse::<MinHash>(), Err(ParseSpamsumError::InvalidFormat));
+        assert_eq!(
+            "000000000000001".parse::<MinHash>(),
+            Err(ParseSpamsumError::InvalidFormat)
+        );
+        assert_eq!(
+            "000000000000000g".parse::<MinHash>(),
+            Err(ParseSpamsumError::InvalidCharacter('g'))
+        );
+    }
+
+    #[test]
+    fn test_tokens() {
+        let options = MinHashOptions {
+            num_hashes: 64,
+            shingles: Shingles::Tokens(2),
+        };
+        let minhash = |input: &[u8]| get_minhash(input, Default::default(), options).unwrap();
+        assert_eq!(
+            minhash(b"Please buy my stuff"),
+            minhash(b"  Please, buy my\nstuff!")
+        );
+        assert_eq!(minhash(b"Please").jaccard(&minhash(b"Please")), 1.0);
+        assert_eq!(minhash(b"").jaccard(&minhash(b"")), 1.0);
+        assert!(minhash(b"Please buy").jaccard(&minhash(b"buy Please")) < 0.2);
+    }
+
+    #[test]
+    fn test_streaming() {
+        let input = pseudo_random_bytes(10_000, 3);
+        for &shingles in &[Shingles::Bytes(7), Shingles::Tokens(3)] {
+            let options = MinHashOptions {
+                num_hashes: 32,
+                shingles,
+            };
+            let mut hasher = MinHasher::new(options).unwrap();
+            for chunk in input.chunks(99) {
+                hasher.update(chunk);
+            }
+            assert_eq!(
+                FuzzyDigest::finalize_reset(&mut hasher).unwrap(),
+                get_minhash(&input, Default::default(), options).unwrap()
+            );
+            assert_eq!(
+                hasher.finalize(),
+                get_minhash(b"", Default::default(), options).unwrap()
+            );
+        }
+    }
+}
@@ -0,0 +1,177 @@
+use alloc::string::String;
+#[cfg(feature = "std")]
+use alloc::vec::Vec;
+use core::fmt;
+use core::str::FromStr;
+#[cfg(feature = "std")]
+use std::io::BufRead;
+#[cfg(feature = "std")]
+use std::path::PathBuf;
+
+#[cfg(feature = "std")]
+use crate::SpamsumError;
+use crate::{ParseSpamsumError, Spamsum};
+
+/// A spamsum together with the name of the file it was calculated from.
+///
+/// It is displayed and parsed in the `hash,"filename"` format used by ssdeep
+/// hash lists, with quotes in the file name escaped as `\"`.
+///
+/// ```
+/// use spamsum::NamedSpamsum;
+///
+/// let named: NamedSpamsum = r#"3:Hn:Hn,"test.txt""#.parse().unwrap();
+/// assert_eq!(named.name(), "test.txt");
+/// assert_eq!(named.spamsum().to_string(), "3:Hn:Hn");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NamedSpamsum {
+    spamsum: Spamsum,
+    name: String,
+}
+
+impl NamedSpamsum {
+    pub fn new(spamsum: Spamsum, name: String) -> NamedSpamsum {
+        NamedSpamsum { spamsum, name }
+    }
+
+    pub fn spamsum(&self) -> &Spamsum {
+        &self.spamsum
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn into_parts(self) -> (Spamsum, String) {
+        (self.spamsum, self.name)
+    }
+}
+
+impl fmt::Display for NamedSpamsum {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{},\"{}\"", self.spamsum, self.name.replace('"', "\\\""))
+    }
+}
+
+impl FromStr for NamedSpamsum {
+    type Err = ParseSpamsumError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // hashes never contain a comma, file names might
+        let position = s.find(',').ok_or(ParseSpamsumError::InvalidFormat)?;
+        let name = &s[position + 1..];
+        let name = if name.len() >= 2 && name.starts_with('"') && name.ends_with('"') {
+            &name[1..name.len() - 1]
+        } else {
+            name
+        };
+        Ok(NamedSpamsum {
+            spamsum: s[..position].parse()?,
+            name: name.replace("\\\"", "\""),
+        })
+    }
+}
+
+/// Reads a hash list as written by spamsum or ssdeep, i.e. one `hash` or
+/// `hash,"filename"` per line, and returns the spamsums with their file names.
+///
+/// The ssdeep header line (`ssdeep,1.1--blocksize:hash:hash,filename`), blank
+/// lines and comments starting with `#` are skipped.
+///
+/// ```
+/// use std::path::PathBuf;
+///
+/// let list = "ssdeep,1.1--blocksize:hash:hash,filename\n\
+///             3:Hn:Hn,\"a.txt\"\n\
+///             3:Hn:Hn\n";
+/// let hashes = spamsum::parse_hash_list(list.as_bytes()).unwrap();
+/// assert_eq!(hashes[0].1, Some(PathBuf::from("a.txt")));
+/// assert_eq!(hashes[1].1, None);
+/// ```
+#[cfg(feature = "std")]
+pub fn parse_hash_list<R: BufRead>(
+    reader: R,
+) -> Result<Vec<(Spamsum, Option<PathBuf>)>, SpamsumError> {
+    let mut hashes = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with("ssdeep,") {
+            continue;
+        }
+        if line.contains(',') {
+            let (spamsum, name) = line.parse::<NamedSpamsum>()?.into_parts();
+            hashes.push((spamsum, Some(PathBuf::from(name))));
+        } else {
+            hashes.push((line.parse()?, None));
+        }
+    }
+    Ok(hashes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_named_spamsum() {
+        let named = NamedSpamsum::new("3:Hn:Hn".parse().unwrap(), String::from("a \"b\".txt"));
+        assert_eq!(named.to_string(), r#"3:Hn:Hn,"a \"b\".txt""#);
+        assert_eq!(named.to_string().parse::<NamedSpamsum>().unwrap(), named);
+    }
+
+    #[test]
+    fn test_parse_named_spamsum() {
+        let named: NamedSpamsum = "3:Hn:Hn,/tmp/a,b.txt".parse().unwrap();
+        assert_eq!(named.name(), "/tmp/a,b.txt");
+        assert_eq!(
+            "3:Hn:Hn".parse::<NamedSpamsum>().unwrap_err(),
+            ParseSpamsumError::InvalidFormat
+        );
+        assert_eq!(
+            "3:H-n:Hn,\"a.txt\"".parse::<NamedSpamsum>().unwrap_err(),
+            ParseSpamsumError::InvalidCharacter('-')
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_parse_hash_list() {
+        let list = concat!(
+            "ssdeep,1.1--blocksize:hash:hash,filename\r\n",
+            "3:Hn:Hn,\"/tmp/a \\\"b\\\".txt\"\r\n",
+            "\r\n",
+            "  # a comment\n",
+            "6:clclDDvWIMF/hv:cGZ/EJv\n",
+            "12:ccsv:Iv,plain,name.txt",
+        );
+        let hashes = parse_hash_list(list.as_bytes()).unwrap();
+        let hashes: Vec<(String, Option<String>)> = hashes
+            .into_iter()
+            .map(|(spamsum, path)| {
+                let path = path.map(|path| path.to_str().unwrap().to_owned());
+                (spamsum.to_string(), path)
+            })
+            .collect();
+        assert_eq!(
+            hashes,
+            vec![
+                (
+                    String::from("3:Hn:Hn"),
+                    Some(String::from("/tmp/a \"b\".txt"))
+                ),
+                (String::from("6:clclDDvWIMF/hv:cGZ/EJv"), None),
+                (
+                    String::from("12:ccsv:Iv"),
+                    Some(String::from("plain,name.txt"))
+                ),
+            ]
+        );
+        match parse_hash_list("3:Hn:Hn\n3:Hn".as_bytes()) {
+            Err(SpamsumError::Parse(ParseSpamsumError::InvalidFormat)) => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+}
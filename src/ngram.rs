@@ -0,0 +1,152 @@
+use std::collections::HashMap;
+
+use crate::{mix, Spamsum, ROLLING_WINDOW};
+
+// An inverted index from the 7-character substrings of normalized spamsums
+// to the spamsums containing them.
+//
+// `compare()` only scores hashes that share such a substring at the same
+// blocksize (or are identical), so the index finds every spamsum that can
+// score above 0 without comparing all pairs. The candidates still need to be
+// compared, as sharing a substring does not guarantee a high score.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct NgramIndex {
+    // (blocksize, packed substring) -> ids
+    postings: HashMap<(u64, u64), Vec<usize>>,
+    // spamsums too short to contain any substring can only match themselves
+    short: HashMap<Spamsum, Vec<usize>>,
+}
+
+impl NgramIndex {
+    // Adds a normalized spamsum under the given id.
+    pub(crate) fn insert(&mut self, id: usize, spamsum: &Spamsum) {
+        let mut has_ngrams = false;
+        for key in ngram_keys(spamsum) {
+            has_ngrams = true;
+            let ids = self.postings.entry(key).or_default();
+            // a hash may contain the same substring more than once
+            if ids.last() != Some(&id) {
+                ids.push(id);
+            }
+        }
+        if !has_ngrams {
+            self.short.entry(spamsum.clone()).or_default().push(id);
+        }
+    }
+
+    // Removes a spamsum that was inserted under the given id. Ids must be
+    // inserted in ascending order for this to work.
+    pub(crate) fn remove(&mut self, id: usize, spamsum: &Spamsum) {
+        let mut has_ngrams = false;
+        for key in ngram_keys(spamsum) {
+            has_ngrams = true;
+            if let Some(ids) = self.postings.get_mut(&key) {
+                if let Ok(position) = ids.binary_search(&id) {
+                    ids.remove(position);
+                }
+                if ids.is_empty() {
+                    self.postings.remove(&key);
+                }
+            }
+        }
+        if !has_ngrams {
+            if let Some(ids) = self.short.get_mut(spamsum) {
+                ids.retain(|&other| other != id);
+                if ids.is_empty() {
+                    self.short.remove(spamsum);
+                }
+            }
+        }
+    }
+
+    // Returns the sorted ids of all indexed spamsums that may score above 0
+    // against the normalized `spamsum`.
+    pub(crate) fn candidates(&self, spamsum: &Spamsum) -> Vec<usize> {
+        let mut candidates = Vec::new();
+        for key in ngram_keys(spamsum) {
+            if let Some(ids) = self.postings.get(&key) {
+                candidates.extend_from_slice(ids);
+            }
+        }
+        if let Some(ids) = self.short.get(spamsum) {
+            candidates.extend_from_slice(ids);
+        }
+        candidates.sort_unstable();
+        candidates.dedup();
+        candidates
+    }
+}
+
+// The substrings of both hashes with the blocksize they were calculated with,
+// so the left hash of one spamsum meets the right hash of a spamsum with half
+// its blocksize.
+pub(crate) fn ngram_keys(spamsum: &Spamsum) -> impl Iterator<Item = (u64, u64)> + '_ {
+    let blocksize = u64::from(spamsum.blocksize());
+    let right_blocksize = blocksize * 2;
+    ngrams(spamsum.left_hash())
+        .map(move |ngram| (blocksize, ngram))
+        .chain(ngrams(spamsum.right_hash()).map(move |ngram| (right_blocksize, ngram)))
+}
+
+fn ngrams(hash: &str) -> impl Iterator<Item = u64> + '_ {
+    hash.as_bytes()
+        .windows(ROLLING_WINDOW as usize)
+        .map(|ngram| {
+            ngram
+                .iter()
+                .fold(0, |packed, &c| packed << 8 | u64::from(c))
+        })
+}
+
+// The keys of the normalized spamsum hashed to single numbers, or the whole
+// spamsum if it is too short to contain any, as it can then only match itself.
+pub(crate) fn hashed_keys(spamsum: &Spamsum) -> impl Iterator<Item = u64> + '_ {
+    let mut keys = ngram_keys(spamsum)
+        .map(|(blocksize, ngram)| mix(blocksize) ^ ngram)
+        .peekable();
+    let whole = if keys.peek().is_none() {
+        let hash = spamsum
+            .to_string()
+            .bytes()
+            .fold(0, |hash, c| mix(hash ^ u64::from(c)));
+        Some(hash)
+    } else {
+        None
+    };
+    keys.chain(whole)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_candidates() {
+        let spamsums: Vec<Spamsum> = [
+            "48:ABCDEFGHIJKLMNOP:ABCD",
+            "48:XYZABCDEFGXYZ:WXYZ",
+            "12:ABCDEFGHIJKLMNOP:ABCD",
+            "24:QRSTUVWX:ABCDEFGHIJKLMNOP",
+            "3:Hn:Hn",
+            "3:Hn:Hn",
+        ]
+        .iter()
+        .map(|s| s.parse().unwrap())
+        .collect();
+        let mut index = NgramIndex::default();
+        for (id, spamsum) in spamsums.iter().enumerate() {
+            index.insert(id, spamsum);
+        }
+        assert_eq!(index.candidates(&spamsums[0]), vec![0, 1, 3]);
+        assert_eq!(index.candidates(&spamsums[2]), vec![2]);
+        assert_eq!(index.candidates(&spamsums[4]), vec![4, 5]);
+        assert!(index.candidates(&"6:Hn:Hn".parse().unwrap()).is_empty());
+
+        index.remove(3, &spamsums[3]);
+        index.remove(4, &spamsums[4]);
+        assert_eq!(index.candidates(&spamsums[0]), vec![0, 1]);
+        assert_eq!(index.candidates(&spamsums[4]), vec![5]);
+        index.remove(5, &spamsums[5]);
+        assert!(index.short.is_empty());
+    }
+}
@@ -0,0 +1,339 @@
+use core::fmt;
+use core::str::FromStr;
+
+use crate::{
+    check_options, filter_input, Algorithm, FuzzyDigest, FuzzyHash, ParseSpamsumError,
+    SpamsumError, SpamsumOptions,
+};
+
+const DIGEST_LENGTH: usize = 32;
+
+// The byte permutation of the reference implementation, built the same way
+// (including its quirk of not checking the first entry again after a
+// collision).
+const TRAN: [u8; 256] = tran();
+
+const fn tran() -> [u8; 256] {
+    let mut tran = [0u8; 256];
+    let mut j: u32 = 0;
+    let mut i = 0;
+    while i < 256 {
+        j = (j * 53 + 1) & 255;
+        j += j;
+        if j > 255 {
+            j -= 255;
+        }
+        let mut k = 0;
+        while k < i {
+            if j == tran[k] as u32 {
+                j = (j + 1) & 255;
+                k = 0;
+            }
+            k += 1;
+        }
+        tran[i] = j as u8;
+        i += 1;
+    }
+    tran
+}
+
+// Hashes a trigram into one of the 256 accumulators, with a different hash
+// for every kind of trigram `n`.
+#[inline]
+fn tran3(a: u8, b: u8, c: u8, n: u8) -> usize {
+    let x = TRAN[usize::from(a.wrapping_add(n))] ^ TRAN[usize::from(b)].wrapping_mul(2 * n + 1);
+    usize::from(x.wrapping_add(TRAN[usize::from(c ^ TRAN[usize::from(n)])]))
+}
+
+/// A Nilsimsa digest, the 256 bit locality-sensitive hash used by spam
+/// filters like DCC and Razor.
+///
+/// Two digests are compared with `score()`, which counts the bits they have
+/// in common: from -128 for complementary digests over 0 for unrelated ones
+/// to 128 for identical ones. Digests are written as 64 lowercase hex
+/// digits, like the reference implementation.
+///
+/// ```
+/// use spamsum::{get_nilsimsa, Nilsimsa};
+///
+/// let nilsimsa = get_nilsimsa(b"Please buy my stuff\nDear Sir or Madam\n", Default::default()).unwrap();
+/// assert_eq!(nilsimsa.to_string().parse::<Nilsimsa>(), Ok(nilsimsa));
+/// assert_eq!(nilsimsa.score(&nilsimsa), 128);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Nilsimsa {
+    digest: [u8; DIGEST_LENGTH],
+}
+
+impl Nilsimsa {
+    /// Returns the number of equal bits minus 128, i.e. 128 for identical
+    /// digests and around 0 for unrelated ones.
+    pub fn score(&self, other: &Nilsimsa) -> i32 {
+        let different_bits: u32 = self
+            .digest
+            .iter()
+            .zip(other.digest.iter())
+            .map(|(a, b)| (a ^ b).count_ones())
+            .sum();
+        128 - different_bits as i32
+    }
+
+    /// Returns the 32 bytes of the digest.
+    pub fn as_bytes(&self) -> &[u8; DIGEST_LENGTH] {
+        &self.digest
+    }
+}
+
+impl fmt::Display for Nilsimsa {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for c in self.digest.iter() {
+            write!(f, "{:02x}", c)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Nilsimsa {
+    type Err = ParseSpamsumError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() != 2 * DIGEST_LENGTH {
+            return Err(ParseSpamsumError::InvalidFormat);
+        }
+        if let Some(c) = s.chars().find(|c| !c.is_ascii_hexdigit()) {
+            return Err(ParseSpamsumError::InvalidCharacter(c));
+        }
+        let mut digest = [0; DIGEST_LENGTH];
+        for (i, c) in digest.iter_mut().enumerate() {
+            // only ASCII hex digits are left
+            *c = u8::from_str_radix(&s[2 * i..2 * i + 2], 16).unwrap();
+        }
+        Ok(Nilsimsa { digest })
+    }
+}
+
+/// Incrementally calculates the Nilsimsa digest of data that arrives in
+/// chunks.
+///
+/// ```
+/// use spamsum::{get_nilsimsa, NilsimsaHasher};
+///
+/// let mut hasher = NilsimsaHasher::new();
+/// hasher.update(b"Please buy my stuff\n");
+/// hasher.update(b"Dear Sir or Madam\n");
+/// let expected = get_nilsimsa(b"Please buy my stuff\nDear Sir or Madam\n", Default::default());
+/// assert_eq!(hasher.finalize(), expected.unwrap());
+/// ```
+#[derive(Clone)]
+pub struct NilsimsaHasher {
+    accumulators: [u32; 256],
+    // the last 4 bytes, the most recent one first
+    window: [u8; 4],
+    length: u64,
+}
+
+impl NilsimsaHasher {
+    pub fn new() -> NilsimsaHasher {
+        NilsimsaHasher {
+            accumulators: [0; 256],
+            window: [0; 4],
+            length: 0,
+        }
+    }
+
+    /// Hashes the next chunk of input.
+    pub fn update(&mut self, input: &[u8]) {
+        for &c in input {
+            let [b0, b1, b2, b3] = self.window;
+            if self.length >= 2 {
+                self.accumulators[tran3(c, b0, b1, 0)] += 1;
+            }
+            if self.length >= 3 {
+                self.accumulators[tran3(c, b0, b2, 1)] += 1;
+                self.accumulators[tran3(c, b1, b2, 2)] += 1;
+            }
+            if self.length >= 4 {
+                self.accumulators[tran3(c, b0, b3, 3)] += 1;
+                self.accumulators[tran3(c, b1, b3, 4)] += 1;
+                self.accumulators[tran3(c, b2, b3, 5)] += 1;
+                self.accumulators[tran3(b3, b0, c, 6)] += 1;
+                self.accumulators[tran3(b3, b2, c, 7)] += 1;
+            }
+            self.window = [c, b0, b1, b2];
+            self.length += 1;
+        }
+    }
+
+    /// Returns the digest of the input so far, in which every bit tells
+    /// whether its accumulator is above the average.
+    pub fn finalize(self) -> Nilsimsa {
+        // the number of trigrams hashed
+        let total = match self.length {
+            0..=2 => 0,
+            3 => 1,
+            4 => 4,
+            length => length.saturating_mul(8) - 28,
+        };
+        let threshold = total / 256;
+        let mut digest = [0; DIGEST_LENGTH];
+        for (i, &count) in self.accumulators.iter().enumerate() {
+            if u64::from(count) > threshold {
+                // the reference implementation writes the bytes in reverse
+                digest[DIGEST_LENGTH - 1 - i / 8] |= 1 << (i % 8);
+            }
+        }
+        Nilsimsa { digest }
+    }
+
+    /// Starts over with a new input.
+    pub fn reset(&mut self) {
+        *self = NilsimsaHasher::new();
+    }
+}
+
+impl Default for NilsimsaHasher {
+    fn default() -> NilsimsaHasher {
+        NilsimsaHasher::new()
+    }
+}
+
+impl FuzzyHash for Nilsimsa {
+    const ALGORITHM: Algorithm = Algorithm::Nilsimsa;
+
+    fn hash(input: &[u8], options: SpamsumOptions) -> Result<Nilsimsa, SpamsumError> {
+        get_nilsimsa(input, options)
+    }
+
+    // unrelated inputs score around 0
+    fn similarity(&self, other: &Nilsimsa) -> u32 {
+        (self.score(other).max(0) as u32 * 100 + 64) / 128
+    }
+}
+
+impl FuzzyDigest for NilsimsaHasher {
+    type Output = Nilsimsa;
+
+    fn update(&mut self, data: impl AsRef<[u8]>) {
+        NilsimsaHasher::update(self, data.as_ref());
+    }
+
+    fn finalize(self) -> Result<Nilsimsa, SpamsumError> {
+        Ok(NilsimsaHasher::finalize(self))
+    }
+
+    fn finalize_reset(&mut self) -> Result<Nilsimsa, SpamsumError> {
+        let nilsimsa = self.clone().finalize();
+        self.reset();
+        Ok(nilsimsa)
+    }
+
+    fn reset(&mut self) {
+        NilsimsaHasher::reset(self);
+    }
+}
+
+/// Calculates the Nilsimsa digest of `input`, after the same preprocessing
+/// as `get_configured_spamsum()` with `options`, e.g. without whitespace or
+/// e-mail headers.
+pub fn get_nilsimsa(input: &[u8], options: SpamsumOptions) -> Result<Nilsimsa, SpamsumError> {
+    check_options(&options)?;
+    if let Some(max_input_len) = options.max_input_len {
+        if input.len() as u64 > max_input_len {
+            return Err(SpamsumError::InputLimitExceeded(max_input_len));
+        }
+    }
+    let mut hasher = NilsimsaHasher::new();
+    hasher.update(&filter_input(input, options));
+    Ok(hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tran() {
+        assert_eq!(
+            &TRAN[..8],
+            &[0x02, 0xD6, 0x9E, 0x6F, 0xF9, 0x1D, 0x04, 0xAB]
+        );
+    }
+
+    #[test]
+    fn test_nilsimsa_known_answers() {
+        // the examples of the Python nilsimsa package
+        for &(input, digest) in &[
+            (
+                "abcdefgh",
+                "14c8118000000000030800000004042004189020001308014088003280000078",
+            ),
+            (
+                "abcdefghijk",
+                "14c811840010000c0328200108040630041890200217582d4098103280000078",
+            ),
+        ] {
+            let nilsimsa = get_nilsimsa(input.as_bytes(), Default::default()).unwrap();
+            assert_eq!(nilsimsa.to_string(), digest, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_nilsimsa_format() {
+        let nilsimsa = get_nilsimsa(b"Please buy my stuff", Default::default()).unwrap();
+        let s = nilsimsa.to_string();
+        assert_eq!(s.len(), 64);
+        assert_eq!(s, s.to_lowercase());
+        assert_eq!(s.parse(), Ok(nilsimsa));
+        assert_eq!(
+            s[1..].parse::<Nilsimsa>(),
+            Err(ParseSpamsumError::InvalidFormat)
+        );
+        assert_eq!(
+            format!("x{}", &s[1..]).parse::<Nilsimsa>(),
+            Err(ParseSpamsumError::InvalidCharacter('x'))
+        );
+    }
+
+    #[test]
+    fn test_nilsimsa_score() {
+        let text = "Dear Sir or Madam, we are pleased to offer you the opportunity \
+                    to buy our finest stuff at a very special price. Reply today!";
+        let a = get_nilsimsa(text.as_bytes(), Default::default()).unwrap();
+        let b = get_nilsimsa(text.replace("today", "now").as_bytes(), Default::default()).unwrap();
+        let c = get_nilsimsa(
+            b"Minutes of the meeting of the board of directors, held on Monday in the main office.",
+            Default::default(),
+        )
+        .unwrap();
+        assert_eq!(a.score(&a), 128);
+        assert_eq!(a.score(&b), b.score(&a));
+        assert!(a.score(&b) > a.score(&c));
+        let complement = Nilsimsa {
+            digest: a.digest.map(|c| !c),
+        };
+        assert_eq!(a.score(&complement), -128);
+    }
+
+    #[test]
+    fn test_nilsimsa_short_input() {
+        let empty = NilsimsaHasher::new().finalize();
+        assert_eq!(empty.as_bytes(), &[0; DIGEST_LENGTH]);
+        // a single trigram sets a single bit
+        let trigram = get_nilsimsa(b"abc", Default::default()).unwrap();
+        let bits: u32 = trigram.as_bytes().iter().map(|c| c.count_ones()).sum();
+        assert_eq!(bits, 1);
+    }
+
+    #[test]
+    fn test_nilsimsa_with_options() {
+        let options = SpamsumOptions::builder()
+            .ignore_whitespace(true)
+            .build()
+            .unwrap();
+        assert_eq!(
+            get_nilsimsa(b"Please buy my stuff", options).unwrap(),
+            get_nilsimsa(b"Pleasebuymystuff", Default::default()).unwrap()
+        );
+    }
+}
@@ -0,0 +1,202 @@
+use rayon::iter::{
+    IndexedParallelIterator, IntoParallelIterator, IntoParallelRefIterator, ParallelIterator,
+};
+
+use crate::compare::compare_normalized;
+use crate::matrix::row_pairs;
+use crate::{get_configured_spamsum, SimilarityMatrix, Spamsum, SpamsumError, SpamsumOptions};
+
+/// Calculates the spamsums of many inputs on all cores, using rayon's global
+/// thread pool. The results are in the order of the inputs.
+///
+/// ```
+/// use spamsum::hash_many;
+///
+/// let inputs = vec![b"Please buy my stuff\nDear Sir or Madam\n".to_vec(), Vec::new()];
+/// let spamsums = hash_many(&inputs, Default::default());
+/// assert_eq!(spamsums[0].as_ref().unwrap().to_string(), "3:clclDDvWIMF/hv:cGZ/EJv");
+/// assert_eq!(spamsums[1].as_ref().unwrap().to_string(), "3::");
+/// ```
+///
+/// Files can be hashed in parallel by mapping their paths to their contents,
+/// e.g. with `paths.par_iter().map(|path| std::fs::read(path).unwrap())`.
+pub fn hash_many<I>(inputs: I, options: SpamsumOptions) -> Vec<Result<Spamsum, SpamsumError>>
+where
+    I: IntoParallelIterator,
+    I::Item: AsRef<[u8]>,
+{
+    inputs
+        .into_par_iter()
+        .map(|input| get_configured_spamsum(input.as_ref(), options))
+        .collect()
+}
+
+/// Compares `query` with every spamsum of `corpus` on all cores and returns
+/// the matches as `(index, score)`, in the order of the corpus.
+///
+/// Spamsums with a score of 0 are left out.
+///
+/// ```
+/// use spamsum::{compare_many, get_spamsum};
+///
+/// let corpus = vec![
+///     get_spamsum(b"Goodbye").unwrap(),
+///     get_spamsum(b"Hello").unwrap(),
+/// ];
+/// let query = get_spamsum(b"Hello").unwrap();
+/// assert_eq!(compare_many(&query, &corpus), vec![(1, 100)]);
+/// ```
+pub fn compare_many(query: &Spamsum, corpus: &[Spamsum]) -> Vec<(usize, u32)> {
+    let query = query.normalized();
+    corpus
+        .par_iter()
+        .enumerate()
+        .filter(|(_, candidate)| query.is_comparable_with(candidate))
+        .map(|(index, candidate)| {
+            let score = compare_normalized(&query, &candidate.normalized(), Default::default(), 1);
+            (index, score)
+        })
+        .filter(|&(_, score)| score > 0)
+        .collect()
+}
+
+/// Compares every pair of the given spamsums like
+/// `compare_all_with_threshold()`, but on all cores.
+///
+/// ```
+/// use spamsum::{compare_all, compare_all_parallel, get_spamsum};
+///
+/// let spamsums = vec![
+///     get_spamsum(b"Hello").unwrap(),
+///     get_spamsum(b"Hello").unwrap(),
+///     get_spamsum(b"Goodbye").unwrap(),
+/// ];
+/// assert_eq!(compare_all_parallel(&spamsums, 1), compare_all(&spamsums));
+/// ```
+pub fn compare_all_parallel(spamsums: &[Spamsum], min_score: u32) -> SimilarityMatrix {
+    let normalized: Vec<Spamsum> = spamsums.par_iter().map(Spamsum::normalized).collect();
+    // the rows get shorter towards the end, which rayon balances by
+    // splitting the remaining work whenever a thread runs out of it
+    let pairs = (0..normalized.len())
+        .into_par_iter()
+        .flat_map_iter(|i| row_pairs(&normalized, i, min_score))
+        .collect();
+    SimilarityMatrix::from_pairs(spamsums.len(), pairs)
+}
+
+/// Compares every pair of the given spamsums like `compare_all_parallel()`,
+/// but fails with `SpamsumError::Cancelled` as soon as `should_cancel`
+/// returns true, which every thread checks before it compares a spamsum with
+/// the later ones.
+pub fn compare_all_parallel_with_cancel(
+    spamsums: &[Spamsum],
+    min_score: u32,
+    should_cancel: &(dyn Fn() -> bool + Sync),
+) -> Result<SimilarityMatrix, SpamsumError> {
+    let normalized: Vec<Spamsum> = spamsums.par_iter().map(Spamsum::normalized).collect();
+    let rows: Vec<Vec<(usize, usize, u32)>> = (0..normalized.len())
+        .into_par_iter()
+        .map(|i| {
+            if should_cancel() {
+                return Err(SpamsumError::Cancelled);
+            }
+            Ok(row_pairs(&normalized, i, min_score).collect())
+        })
+        .collect::<Result<_, _>>()?;
+    let pairs = rows.into_iter().flatten().collect();
+    Ok(SimilarityMatrix::from_pairs(spamsums.len(), pairs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compare, compare_all_with_threshold};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn corpus() -> Vec<Spamsum> {
+        (0..50u32)
+            .map(|i| {
+                let input: Vec<u8> = (0..2000 + i * 10)
+                    .map(|j| (j * (j % 7 + i % 3) % 251) as u8)
+                    .collect();
+                get_configured_spamsum(&input, Default::default()).unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_hash_many() {
+        let inputs: Vec<Vec<u8>> = (0..100u32)
+            .map(|i| (0..i * 100).map(|j| (j * j % 251) as u8).collect())
+            .collect();
+        let options = SpamsumOptions::builder().blocksize(6).build().unwrap();
+        let spamsums = hash_many(inputs.par_iter(), options);
+        assert_eq!(spamsums.len(), inputs.len());
+        for (input, spamsum) in inputs.iter().zip(spamsums) {
+            assert_eq!(
+                spamsum.unwrap(),
+                get_configured_spamsum(input, options).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_hash_many_with_error() {
+        let options = SpamsumOptions::builder()
+            .max_input_len(Some(3))
+            .build()
+            .unwrap();
+        let spamsums = hash_many(vec![&b"abc"[..], &b"abcd"[..]], options);
+        assert!(spamsums[0].is_ok());
+        assert!(matches!(
+            spamsums[1],
+            Err(SpamsumError::InputLimitExceeded(3))
+        ));
+    }
+
+    #[test]
+    fn test_compare_many() {
+        let corpus = corpus();
+        let matches = compare_many(&corpus[10], &corpus);
+        let expected: Vec<(usize, u32)> = corpus
+            .iter()
+            .map(|candidate| compare(&corpus[10], candidate))
+            .enumerate()
+            .filter(|&(_, score)| score > 0)
+            .collect();
+        assert_eq!(matches, expected);
+        assert!(matches.contains(&(10, 100)));
+    }
+
+    #[test]
+    fn test_compare_all_parallel() {
+        let corpus = corpus();
+        for &min_score in [0, 1, 50, 100].iter() {
+            assert_eq!(
+                compare_all_parallel(&corpus, min_score),
+                compare_all_with_threshold(&corpus, min_score)
+            );
+        }
+        let pairs = compare_all_parallel(&corpus, 1).pairs().len();
+        assert!(
+            pairs > 0 && pairs < corpus.len() * (corpus.len() - 1) / 2,
+            "{}",
+            pairs
+        );
+        assert!(compare_all_parallel(&[], 1).is_empty());
+    }
+
+    #[test]
+    fn test_compare_all_parallel_with_cancel() {
+        let corpus = corpus();
+        let matrix = compare_all_parallel_with_cancel(&corpus, 1, &|| false);
+        assert_eq!(matrix.unwrap(), compare_all_parallel(&corpus, 1));
+        // cancelled by another thread while comparing
+        let rows = AtomicUsize::new(0);
+        let should_cancel = || rows.fetch_add(1, Ordering::Relaxed) >= 10;
+        assert!(matches!(
+            compare_all_parallel_with_cancel(&corpus, 1, &should_cancel),
+            Err(SpamsumError::Cancelled)
+        ));
+    }
+}
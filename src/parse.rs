@@ -0,0 +1,197 @@
+#[cfg(feature = "serde")]
+use alloc::string::String;
+use alloc::vec::Vec;
+#[cfg(feature = "serde")]
+use core::convert::TryFrom;
+use core::error::Error;
+use core::fmt;
+use core::str::FromStr;
+
+use crate::{Alphabet, HashBuffer, Spamsum, MAX_HASH_LENGTH};
+
+/// Error returned when parsing a `Spamsum` from a string fails.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseSpamsumError {
+    /// The string is not of the form `blocksize:left_hash:right_hash`.
+    InvalidFormat,
+    /// The blocksize is not a positive number.
+    InvalidBlocksize,
+    /// A hash contains a character outside of the alphabet.
+    InvalidCharacter(char),
+    /// A hash contains a symbol larger than 63.
+    InvalidSymbol(u8),
+    /// A hash is longer than the maximum hash length.
+    HashTooLong,
+}
+
+impl fmt::Display for ParseSpamsumError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseSpamsumError::InvalidFormat => {
+                write!(f, "expected a spamsum of the form blocksize:hash:hash")
+            }
+            ParseSpamsumError::InvalidBlocksize => write!(f, "invalid blocksize"),
+            ParseSpamsumError::InvalidCharacter(c) => write!(f, "invalid hash character {:?}", c),
+            ParseSpamsumError::InvalidSymbol(symbol) => write!(f, "invalid hash symbol {}", symbol),
+            ParseSpamsumError::HashTooLong => write!(f, "hash exceeds the maximum length"),
+        }
+    }
+}
+
+impl Error for ParseSpamsumError {}
+
+impl FromStr for Spamsum {
+    type Err = ParseSpamsumError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Spamsum::parse_with_alphabet(s, &Alphabet::STANDARD)
+    }
+}
+
+impl Spamsum {
+    /// Parses a spamsum like `from_str()`, but with the hashes written in
+    /// `alphabet` instead of standard base64.
+    pub fn parse_with_alphabet(s: &str, alphabet: &Alphabet) -> Result<Spamsum, ParseSpamsumError> {
+        let parts: Vec<&str> = s.split(':').collect();
+        if parts.len() != 3 {
+            return Err(ParseSpamsumError::InvalidFormat);
+        }
+        let blocksize = match parts[0].parse::<u32>() {
+            Ok(blocksize) if blocksize > 0 => blocksize,
+            _ => return Err(ParseSpamsumError::InvalidBlocksize),
+        };
+        Ok(Spamsum {
+            left_hash_blocksize: blocksize,
+            left_hash: decode_hash(parts[1], MAX_HASH_LENGTH, alphabet)?,
+            right_hash: decode_hash(parts[2], MAX_HASH_LENGTH / 2, alphabet)?,
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+pub(crate) fn parse_hash(hash: &str, hash_length: u32) -> Result<HashBuffer, ParseSpamsumError> {
+    decode_hash(hash, hash_length, &Alphabet::STANDARD)
+}
+
+// The serialized fields of a `Spamsum`, which are checked like a parsed
+// spamsum before they are used.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+#[serde(rename = "Spamsum")]
+pub(crate) struct SpamsumFields {
+    left_hash_blocksize: u32,
+    left_hash: String,
+    right_hash: String,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<SpamsumFields> for Spamsum {
+    type Error = ParseSpamsumError;
+
+    fn try_from(fields: SpamsumFields) -> Result<Spamsum, ParseSpamsumError> {
+        if fields.left_hash_blocksize == 0 {
+            return Err(ParseSpamsumError::InvalidBlocksize);
+        }
+        Ok(Spamsum {
+            left_hash_blocksize: fields.left_hash_blocksize,
+            left_hash: decode_hash(&fields.left_hash, MAX_HASH_LENGTH, &Alphabet::STANDARD)?,
+            right_hash: decode_hash(&fields.right_hash, MAX_HASH_LENGTH / 2, &Alphabet::STANDARD)?,
+        })
+    }
+}
+
+// Checks a hash written in `alphabet` and returns it in standard base64.
+fn decode_hash(
+    hash: &str,
+    hash_length: u32,
+    alphabet: &Alphabet,
+) -> Result<HashBuffer, ParseSpamsumError> {
+    if let Some(c) = hash.chars().find(|&c| alphabet.decode(c).is_none()) {
+        return Err(ParseSpamsumError::InvalidCharacter(c));
+    }
+    if hash.len() > hash_length as usize {
+        return Err(ParseSpamsumError::HashTooLong);
+    }
+    Ok(alphabet.translate(hash, &Alphabet::STANDARD).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_spamsum() {
+        let spamsum: Spamsum = "3:clclDDvWIMF/hv:cGZ/EJv".parse().unwrap();
+        assert_eq!(spamsum.left_hash_blocksize, 3);
+        assert_eq!(spamsum.left_hash, "clclDDvWIMF/hv");
+        assert_eq!(spamsum.right_hash, "cGZ/EJv");
+        assert_eq!(spamsum.to_string(), "3:clclDDvWIMF/hv:cGZ/EJv");
+    }
+
+    #[test]
+    fn test_parse_empty_hashes() {
+        let spamsum: Spamsum = "3::".parse().unwrap();
+        assert_eq!(spamsum.to_string(), "3::");
+    }
+
+    #[test]
+    fn test_parse_invalid_format() {
+        assert_eq!(
+            "3:clclDDvWIMF/hv".parse::<Spamsum>().unwrap_err(),
+            ParseSpamsumError::InvalidFormat
+        );
+        assert_eq!(
+            "3:a:b:c".parse::<Spamsum>().unwrap_err(),
+            ParseSpamsumError::InvalidFormat
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid_blocksize() {
+        assert_eq!(
+            "x:Hn:Hn".parse::<Spamsum>().unwrap_err(),
+            ParseSpamsumError::InvalidBlocksize
+        );
+        assert_eq!(
+            "0:Hn:Hn".parse::<Spamsum>().unwrap_err(),
+            ParseSpamsumError::InvalidBlocksize
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid_hash() {
+        assert_eq!(
+            "3:H-n:Hn".parse::<Spamsum>().unwrap_err(),
+            ParseSpamsumError::InvalidCharacter('-')
+        );
+        let too_long = format!("3:Hn:{}", "A".repeat(129));
+        assert_eq!(
+            too_long.parse::<Spamsum>().unwrap_err(),
+            ParseSpamsumError::HashTooLong
+        );
+    }
+
+    #[test]
+    fn test_parse_hash_length() {
+        let input = crate::pseudo_random_bytes(50_000, 1);
+        for &hash_length in &[16, 128, 256] {
+            let options = crate::SpamsumOptions::builder()
+                .hash_length(hash_length)
+                .build()
+                .unwrap();
+            let spamsum = crate::get_configured_spamsum(&input, options).unwrap();
+            assert!(spamsum.left_hash.len() > hash_length as usize / 2);
+            assert_eq!(spamsum.to_string().parse(), Ok(spamsum));
+        }
+    }
+
+    #[test]
+    fn test_parse_with_alphabet() {
+        let spamsum = Spamsum::parse_with_alphabet("3:c-lD_hv:cGZ_", &Alphabet::URL_SAFE).unwrap();
+        assert_eq!(spamsum.to_string(), "3:c+lD/hv:cGZ/");
+        assert_eq!(
+            Spamsum::parse_with_alphabet("3:c+lD/hv:cGZ/", &Alphabet::URL_SAFE).unwrap_err(),
+            ParseSpamsumError::InvalidCharacter('+')
+        );
+    }
+}
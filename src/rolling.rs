@@ -0,0 +1,273 @@
+use core::hash::Hasher;
+use core::num::Wrapping;
+
+use crate::ROLLING_WINDOW;
+
+const WINDOW: usize = ROLLING_WINDOW as usize;
+// the number of hashes `push_slice()` calculates at once
+const BLOCK_LENGTH: usize = 64;
+
+/// The rolling hash spamsum uses to find the chunk boundaries.
+///
+/// The hash only depends on the last 7 bytes pushed, so the same content
+/// produces the same hashes wherever it shows up in the input. This makes it
+/// useful on its own, e.g. for content-defined chunking.
+///
+/// ```
+/// use spamsum::RollingHash;
+///
+/// let mut first = RollingHash::new();
+/// let mut second = RollingHash::new();
+/// for &c in b"Dear Sir, please buy" {
+///     first.push(c);
+/// }
+/// for &c in b"Madam, please buy" {
+///     second.push(c);
+/// }
+/// assert_eq!(first.value(), second.value());
+/// ```
+#[derive(Debug, Clone)]
+pub struct RollingHash {
+    window: [u8; ROLLING_WINDOW as usize],
+    window_sum: Wrapping<u32>,  // h1
+    window_sum2: Wrapping<u32>, // h2
+    shift_hash: Wrapping<u32>,  // h3
+    position: u64,
+}
+
+impl RollingHash {
+    pub fn new() -> RollingHash {
+        RollingHash {
+            window: [0; ROLLING_WINDOW as usize],
+            window_sum: Wrapping(0),
+            window_sum2: Wrapping(0),
+            shift_hash: Wrapping(0),
+            position: 0,
+        }
+    }
+
+    /// Adds a byte to the window and returns the new hash.
+    #[inline]
+    pub fn push(&mut self, element: u8) -> u32 {
+        let c = element as u32;
+        let rolling_pos = (self.position % u64::from(ROLLING_WINDOW)) as usize;
+
+        self.window_sum2 -= self.window_sum;
+        self.window_sum2 += Wrapping(ROLLING_WINDOW * c);
+
+        self.window_sum -= Wrapping(self.window[rolling_pos] as u32);
+        self.window_sum += Wrapping(c);
+
+        self.shift_hash <<= 5;
+        self.shift_hash ^= Wrapping(c);
+
+        self.window[rolling_pos] = element;
+        self.position += 1;
+
+        (self.window_sum + self.window_sum2 + self.shift_hash).0
+    }
+
+    /// Adds all bytes of `input` to the window and writes the hash after
+    /// each of them to `hashes`, like calling `push()` for every byte.
+    ///
+    /// As every hash only depends on the last 7 bytes, the hashes of a block
+    /// are calculated independently of each other, which lets the compiler
+    /// vectorize the loop.
+    ///
+    /// ```
+    /// use spamsum::RollingHash;
+    ///
+    /// let mut rolling = RollingHash::new();
+    /// let mut hashes = [0; 11];
+    /// rolling.push_slice(b"Dear Sir or", &mut hashes);
+    /// let mut expected = RollingHash::new();
+    /// for (&c, &hash) in b"Dear Sir or".iter().zip(hashes.iter()) {
+    ///     assert_eq!(expected.push(c), hash);
+    /// }
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `input` and `hashes` differ in length.
+    pub fn push_slice(&mut self, input: &[u8], hashes: &mut [u32]) {
+        assert_eq!(input.len(), hashes.len());
+        for (input, hashes) in input
+            .chunks(BLOCK_LENGTH)
+            .zip(hashes.chunks_mut(BLOCK_LENGTH))
+        {
+            // the last 6 bytes of the window, oldest first, then the block
+            let mut bytes = [0; WINDOW - 1 + BLOCK_LENGTH];
+            for (i, byte) in bytes[..WINDOW - 1].iter_mut().enumerate() {
+                *byte = self.window[((self.position + 1 + i as u64) % WINDOW as u64) as usize];
+            }
+            bytes[WINDOW - 1..WINDOW - 1 + input.len()].copy_from_slice(input);
+            let block_hashes = block_hashes(&bytes);
+            hashes.copy_from_slice(&block_hashes[..hashes.len()]);
+            if input.len() >= WINDOW {
+                // the window only consists of bytes of the block now
+                *self = RollingHash::new();
+                input[input.len() - WINDOW..].iter().for_each(|&c| {
+                    self.push(c);
+                });
+            } else {
+                input.iter().for_each(|&c| {
+                    self.push(c);
+                });
+            }
+        }
+    }
+
+    /// Returns the current hash, i.e. the one the last `push()` returned.
+    pub fn value(&self) -> u32 {
+        (self.window_sum + self.window_sum2 + self.shift_hash).0
+    }
+
+    /// Empties the window, as if no bytes had been pushed.
+    pub fn reset(&mut self) {
+        *self = RollingHash::new();
+    }
+}
+
+// The hashes of every window in a block, where the first 6 bytes are the
+// end of the previous window: the window sum h1 plus the sum h2 that weighs
+// the bytes from 1 (oldest) to 7 (newest), and the shift hash h3, in which
+// bytes older than 7 have been shifted out. The hashes are summed up byte
+// by byte of the window, for all windows at once, which vectorizes.
+#[inline]
+fn block_hashes(bytes: &[u8; WINDOW - 1 + BLOCK_LENGTH]) -> [u32; BLOCK_LENGTH] {
+    let mut sums = [0u32; BLOCK_LENGTH];
+    let mut shift_hashes = [0u32; BLOCK_LENGTH];
+    for age in 0..WINDOW {
+        let bytes = &bytes[WINDOW - 1 - age..WINDOW - 1 - age + BLOCK_LENGTH];
+        let weight = (WINDOW - age) as u32 + 1;
+        let shift = 5 * age as u32;
+        for ((sum, shift_hash), &c) in sums.iter_mut().zip(shift_hashes.iter_mut()).zip(bytes) {
+            *sum += weight * u32::from(c);
+            *shift_hash ^= u32::from(c) << shift;
+        }
+    }
+    for (sum, &shift_hash) in sums.iter_mut().zip(shift_hashes.iter()) {
+        *sum = sum.wrapping_add(shift_hash);
+    }
+    sums
+}
+
+impl Default for RollingHash {
+    fn default() -> RollingHash {
+        RollingHash::new()
+    }
+}
+
+/// Pushes the written bytes, so that the rolling hash can be used wherever a
+/// `Hasher` is expected, e.g. with `BuildHasherDefault<RollingHash>`.
+/// `finish()` returns the current value.
+///
+/// Keep in mind that only the last 7 bytes written count, which makes it a
+/// poor hasher for hash maps, but a useful one for sampling content by its
+/// hash value.
+///
+/// ```
+/// use core::hash::Hasher;
+/// use spamsum::RollingHash;
+///
+/// let mut hasher = RollingHash::new();
+/// hasher.write(b"Dear Sir, please buy");
+/// let mut rolling = RollingHash::new();
+/// for &c in b"please buy" {
+///     rolling.push(c);
+/// }
+/// assert_eq!(hasher.finish(), u64::from(rolling.value()));
+/// ```
+impl Hasher for RollingHash {
+    fn write(&mut self, bytes: &[u8]) {
+        for &c in bytes {
+            self.push(c);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        u64::from(self.value())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rolling_hash_depends_on_window_only() {
+        let mut first = RollingHash::new();
+        let mut second = RollingHash::new();
+        for &c in b"Dear Sir or Madam" {
+            first.push(c);
+        }
+        for &c in b"Please buy my stuff" {
+            second.push(c);
+        }
+        let hashes: Vec<(u32, u32)> = b"and some more"
+            .iter()
+            .map(|&c| (first.push(c), second.push(c)))
+            .collect();
+        // the windows are equal once 7 bytes have been pushed
+        for (i, (first, second)) in hashes.iter().enumerate() {
+            assert_eq!(first == second, i >= 6, "{}", i);
+        }
+    }
+
+    #[test]
+    fn test_hasher() {
+        use core::hash::{BuildHasher, BuildHasherDefault};
+
+        let build = BuildHasherDefault::<RollingHash>::default();
+        let mut hasher = build.build_hasher();
+        hasher.write(b"Please buy");
+        hasher.write_u8(b' ');
+        hasher.write(b"my stuff");
+        let mut rolling = RollingHash::new();
+        for &c in b"Please buy my stuff" {
+            rolling.push(c);
+        }
+        assert_eq!(hasher.finish(), u64::from(rolling.value()));
+        // finishing does not reset the window
+        assert_eq!(hasher.finish(), u64::from(rolling.value()));
+    }
+
+    #[test]
+    fn test_rolling_hash_beyond_4_gib() {
+        let mut rolling = RollingHash::new();
+        let mut large_rolling = RollingHash::new();
+        large_rolling.position = u64::from(u32::MAX) - 3;
+        for &c in b"Please buy my stuff\nDear Sir or Madam\n" {
+            assert_eq!(rolling.push(c), large_rolling.push(c));
+        }
+    }
+
+    #[test]
+    fn test_push_slice() {
+        let input: Vec<u8> = (0..300u32).map(|i| (i * i * 31 % 251) as u8).collect();
+        for &split in [0, 3, 64, 100].iter() {
+            let mut rolling = RollingHash::new();
+            let mut expected = RollingHash::new();
+            let mut hashes = vec![0; input.len()];
+            rolling.push_slice(&input[..split], &mut hashes[..split]);
+            rolling.push_slice(&input[split..], &mut hashes[split..]);
+            for (&c, &hash) in input.iter().zip(hashes.iter()) {
+                assert_eq!(expected.push(c), hash);
+            }
+            assert_eq!(rolling.value(), expected.value());
+            assert_eq!(rolling.push(0xff), expected.push(0xff));
+        }
+    }
+
+    #[test]
+    fn test_rolling_hash_reset() {
+        let mut hash = RollingHash::new();
+        assert_eq!(hash.value(), 0);
+        let expected = hash.push(b'x');
+        assert_eq!(hash.value(), expected);
+        hash.push(b'y');
+        hash.reset();
+        assert_eq!(hash.value(), 0);
+        assert_eq!(hash.push(b'x'), expected);
+    }
+}
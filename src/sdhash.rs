@@ -0,0 +1,528 @@
+use std::collections::VecDeque;
+use std::convert::TryInto;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{
+    check_options, filter_input, mix, Algorithm, Alphabet, FuzzyDigest, FuzzyHash,
+    ParseSpamsumError, SpamsumError, SpamsumOptions, FNV_OFFSET_BASIS, FNV_PRIME,
+};
+
+// the length of the features and of the windows their entropy is taken of
+const FEATURE_LENGTH: usize = 64;
+// features are selected among this many neighbours
+const POP_WINDOW: u64 = 64;
+// a feature has to be the best of this many windows to be selected
+const POP_THRESHOLD: u32 = 16;
+// features with a lower or higher entropy (in permille of the maximum) are
+// too common, e.g. padding, text in a single case or compressed data
+const MIN_ENTROPY: u32 = 100;
+const MAX_ENTROPY: u32 = 990;
+// every feature sets 5 bits in a filter of 2048 bits
+const FILTER_WORDS: usize = 32;
+const FILTER_BITS: u32 = 64 * FILTER_WORDS as u32;
+const BITS_PER_FEATURE: u32 = 5;
+const FEATURES_PER_FILTER: u32 = 160;
+// filters with fewer features are too small to be compared
+const MIN_FEATURES: u32 = 16;
+// the fixed point scale of the entropy terms
+const ENTROPY_SCALE: f64 = (1 << 20) as f64;
+const PREFIX: &str = "sdbf-lite";
+// the bytes of a feature and of the window before it
+const RING_LENGTH: usize = 2 * FEATURE_LENGTH;
+
+// A Bloom filter of up to FEATURES_PER_FILTER features.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct FeatureFilter {
+    bits: [u64; FILTER_WORDS],
+    len: u32,
+}
+
+impl FeatureFilter {
+    fn new() -> FeatureFilter {
+        FeatureFilter {
+            bits: [0; FILTER_WORDS],
+            len: 0,
+        }
+    }
+
+    // Ignores features (or rather their bits) that are already in the filter.
+    fn insert(&mut self, feature_hash: u64) {
+        let mut inserted = false;
+        for i in 0..BITS_PER_FEATURE {
+            let bit = (feature_hash >> (11 * i)) as usize % FILTER_BITS as usize;
+            let mask = 1 << (bit % 64);
+            if self.bits[bit / 64] & mask == 0 {
+                self.bits[bit / 64] |= mask;
+                inserted = true;
+            }
+        }
+        if inserted {
+            self.len += 1;
+        }
+    }
+
+    fn count_ones(&self) -> u32 {
+        self.bits.iter().map(|word| word.count_ones()).sum()
+    }
+
+    // Rates the common bits between the number expected by chance (0) and the
+    // most possible (100), like sdhash, where anything below 30% of the way
+    // counts as chance.
+    fn score(&self, other: &FeatureFilter) -> u32 {
+        let common: u32 = self
+            .bits
+            .iter()
+            .zip(other.bits.iter())
+            .map(|(a, b)| (a & b).count_ones())
+            .sum();
+        let (ones, other_ones) = (f64::from(self.count_ones()), f64::from(other.count_ones()));
+        let max = ones.min(other_ones);
+        let expected = ones * other_ones / f64::from(FILTER_BITS);
+        let cutoff = expected + 0.3 * (max - expected);
+        let common = f64::from(common);
+        if common <= cutoff {
+            0
+        } else {
+            (100.0 * (common - cutoff) / (max - cutoff)).round() as u32
+        }
+    }
+}
+
+/// A similarity digest in the style of sdhash: the statistically improbable
+/// features of the input, stored in a sequence of Bloom filters.
+///
+/// Features are 64 byte sequences that stand out among their neighbours by
+/// the entropy of their bytes. Since every part of the input contributes its
+/// own features, a fragment, e.g. a part of a binary or a file carved from a
+/// disk image, is still found in the whole, where a spamsum of the fragment
+/// would have nothing in common with the spamsum of the whole.
+///
+/// This is a simplified variant: features are ranked by their entropy alone,
+/// without the empirical precedence table of sdhash, and they are hashed with
+/// FNV-1a instead of SHA-1, so the digests, written as
+/// `sdbf-lite:length:features:filters` with the filters in base64, are not
+/// compatible with those of sdhash.
+///
+/// ```
+/// use spamsum::get_sdbf;
+///
+/// let input: Vec<u8> = (0..20_000u64)
+///     .map(|i| (i.wrapping_mul(i).wrapping_mul(0x9e37_79b9_7f4a_7c15) >> 56) as u8)
+///     .collect();
+/// let whole = get_sdbf(&input, Default::default()).unwrap();
+/// let fragment = get_sdbf(&input[5_000..9_000], Default::default()).unwrap();
+/// assert!(whole.score(&fragment) > 50);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Sdbf {
+    input_len: u64,
+    filters: Vec<FeatureFilter>,
+}
+
+impl Sdbf {
+    /// Returns the similarity of the best matching filters, averaged over the
+    /// digest with fewer filters, from 0 to 100. Digests of which one has no
+    /// filter with at least 16 features cannot be compared and score 0.
+    pub fn score(&self, other: &Sdbf) -> u32 {
+        let (fewer, more) = if self.filters.len() <= other.filters.len() {
+            (self, other)
+        } else {
+            (other, self)
+        };
+        let mut sum = 0;
+        let mut count = 0;
+        for filter in fewer.filters.iter().filter(|f| f.len >= MIN_FEATURES) {
+            let best = more
+                .filters
+                .iter()
+                .filter(|f| f.len >= MIN_FEATURES)
+                .map(|other| filter.score(other))
+                .max();
+            match best {
+                Some(best) => sum += best,
+                None => return 0,
+            }
+            count += 1;
+        }
+        // rounded
+        (sum + count / 2).checked_div(count).unwrap_or(0)
+    }
+
+    /// Returns the length of the (preprocessed) input.
+    pub fn input_len(&self) -> u64 {
+        self.input_len
+    }
+
+    /// Returns the number of features in the digest.
+    pub fn features(&self) -> u32 {
+        self.filters.iter().map(|filter| filter.len).sum()
+    }
+}
+
+impl fmt::Display for Sdbf {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // all filters but the last are full
+        let last_len = self.filters.last().map_or(0, |filter| filter.len);
+        write!(f, "{}:{}:{}:", PREFIX, self.input_len, last_len)?;
+        let bytes: Vec<u8> = self
+            .filters
+            .iter()
+            .flat_map(|filter| filter.bits.iter().flat_map(|word| word.to_le_bytes()))
+            .collect();
+        for group in bytes.chunks(3) {
+            let value = group
+                .iter()
+                .enumerate()
+                .fold(0u32, |value, (i, &c)| value | u32::from(c) << (16 - 8 * i));
+            for i in 0..4 {
+                if i <= group.len() {
+                    let index = (value >> (18 - 6 * i)) & 0x3f;
+                    write!(f, "{}", Alphabet::STANDARD.encode(index as u8))?;
+                } else {
+                    write!(f, "=")?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Sdbf {
+    type Err = ParseSpamsumError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(4, ':');
+        let (prefix, input_len, last_len, filters) =
+            match (parts.next(), parts.next(), parts.next(), parts.next()) {
+                (Some(prefix), Some(input_len), Some(last_len), Some(filters)) => {
+                    (prefix, input_len, last_len, filters)
+                }
+                _ => return Err(ParseSpamsumError::InvalidFormat),
+            };
+        if prefix != PREFIX {
+            return Err(ParseSpamsumError::InvalidFormat);
+        }
+        let input_len = input_len
+            .parse()
+            .map_err(|_| ParseSpamsumError::InvalidFormat)?;
+        let last_len: u32 = last_len
+            .parse()
+            .map_err(|_| ParseSpamsumError::InvalidFormat)?;
+        let mut bytes = Vec::new();
+        let filters = filters.trim_end_matches('=');
+        let mut value = 0u32;
+        for (i, c) in filters.chars().enumerate() {
+            let index = Alphabet::STANDARD
+                .decode(c)
+                .ok_or(ParseSpamsumError::InvalidCharacter(c))?;
+            value = value << 6 | u32::from(index);
+            if i % 4 == 3 {
+                bytes.extend_from_slice(&value.to_be_bytes()[1..]);
+                value = 0;
+            }
+        }
+        match filters.len() % 4 {
+            0 => {}
+            2 => bytes.push((value >> 4) as u8),
+            3 => bytes.extend_from_slice(&(value >> 2).to_be_bytes()[2..]),
+            _ => return Err(ParseSpamsumError::InvalidFormat),
+        }
+        let filter_len = 8 * FILTER_WORDS;
+        if bytes.is_empty()
+            || bytes.len() % filter_len != 0
+            || !(1..=FEATURES_PER_FILTER).contains(&last_len)
+        {
+            return Err(ParseSpamsumError::InvalidFormat);
+        }
+        let count = bytes.len() / filter_len;
+        let filters = bytes
+            .chunks(filter_len)
+            .enumerate()
+            .map(|(i, bytes)| {
+                let mut filter = FeatureFilter::new();
+                for (word, bytes) in filter.bits.iter_mut().zip(bytes.chunks(8)) {
+                    // chunks of 8 bytes
+                    *word = u64::from_le_bytes(bytes.try_into().unwrap());
+                }
+                filter.len = if i + 1 == count {
+                    last_len
+                } else {
+                    FEATURES_PER_FILTER
+                };
+                filter
+            })
+            .collect();
+        Ok(Sdbf { input_len, filters })
+    }
+}
+
+/// Incrementally calculates the sdhash-style digest of data that arrives in
+/// chunks, see `Sdbf`.
+///
+/// Memory usage does not depend on the size of the input, apart from the
+/// digest itself, which grows by 256 bytes for every 160 features.
+#[derive(Clone)]
+pub struct SdbfHasher {
+    // the fixed point entropy term of every count of a byte in a window
+    terms: [u64; FEATURE_LENGTH + 1],
+    // the counts of the bytes in the current window and its entropy
+    counts: [u8; 256],
+    entropy: u64,
+    // the current window and the one before it
+    ring: [u8; RING_LENGTH],
+    length: u64,
+    // the candidates for the best feature of the current window: positions
+    // with decreasing entropies
+    candidates: VecDeque<(u64, u32)>,
+    // the best feature of the previous windows and for how many of them
+    popular: Option<(u64, u32)>,
+    filters: Vec<FeatureFilter>,
+}
+
+impl SdbfHasher {
+    pub fn new() -> SdbfHasher {
+        let mut terms = [0; FEATURE_LENGTH + 1];
+        for (count, term) in terms.iter_mut().enumerate().skip(1) {
+            let p = count as f64 / FEATURE_LENGTH as f64;
+            *term = (-p * p.log2() * ENTROPY_SCALE).round() as u64;
+        }
+        SdbfHasher {
+            terms,
+            counts: [0; 256],
+            entropy: 0,
+            ring: [0; RING_LENGTH],
+            length: 0,
+            candidates: VecDeque::new(),
+            popular: None,
+            filters: Vec::new(),
+        }
+    }
+
+    /// Hashes the next chunk of input.
+    pub fn update(&mut self, input: &[u8]) {
+        for &c in input {
+            if self.length >= FEATURE_LENGTH as u64 {
+                let old = self.ring[(self.length as usize - FEATURE_LENGTH) % RING_LENGTH];
+                self.count(old, false);
+            }
+            self.ring[self.length as usize % RING_LENGTH] = c;
+            self.count(c, true);
+            self.length += 1;
+            if self.length >= FEATURE_LENGTH as u64 {
+                self.select(self.length - FEATURE_LENGTH as u64);
+            }
+        }
+    }
+
+    fn count(&mut self, c: u8, add: bool) {
+        let count = &mut self.counts[usize::from(c)];
+        self.entropy -= self.terms[usize::from(*count)];
+        if add {
+            *count += 1;
+        } else {
+            *count -= 1;
+        }
+        self.entropy += self.terms[usize::from(*count)];
+    }
+
+    // Rates the feature at `position`, which has just been completed, and
+    // inserts the best feature of the window of features ending with it if
+    // that has been the best often enough.
+    fn select(&mut self, position: u64) {
+        // the entropy in permille of the maximum of 6 bits
+        let max_entropy = 6.0 * ENTROPY_SCALE;
+        let mut rank = (self.entropy as f64 * 1000.0 / max_entropy).round() as u32;
+        if !(MIN_ENTROPY..=MAX_ENTROPY).contains(&rank) {
+            rank = 0;
+        }
+        while self.candidates.back().is_some_and(|&(_, r)| r < rank) {
+            self.candidates.pop_back();
+        }
+        self.candidates.push_back((position, rank));
+        if position + 1 < POP_WINDOW {
+            return;
+        }
+        if self.candidates[0].0 + POP_WINDOW <= position {
+            self.candidates.pop_front();
+        }
+        let (best, rank) = self.candidates[0];
+        if rank == 0 {
+            return;
+        }
+        let popularity = match self.popular {
+            Some((popular, popularity)) if popular == best => popularity + 1,
+            _ => 1,
+        };
+        self.popular = Some((best, popularity));
+        if popularity == POP_THRESHOLD {
+            self.insert(best);
+        }
+    }
+
+    fn insert(&mut self, position: u64) {
+        // FNV-1a, mixed to spread the bits
+        let mut hash = FNV_OFFSET_BASIS;
+        for i in 0..FEATURE_LENGTH {
+            let c = self.ring[(position as usize + i) % RING_LENGTH];
+            hash = (hash ^ u64::from(c)).wrapping_mul(FNV_PRIME);
+        }
+        let hash = mix(hash);
+        if self
+            .filters
+            .last()
+            .is_none_or(|filter| filter.len == FEATURES_PER_FILTER)
+        {
+            self.filters.push(FeatureFilter::new());
+        }
+        // duplicates within a filter are only counted once
+        self.filters.last_mut().unwrap().insert(hash);
+    }
+
+    /// Returns the digest of the input so far.
+    ///
+    /// Fails with `SpamsumError::InsufficientComplexity` if the input has
+    /// no features, e.g. because it is shorter than a few hundred bytes or
+    /// consists of padding.
+    pub fn finalize(self) -> Result<Sdbf, SpamsumError> {
+        if self.filters.is_empty() {
+            return Err(SpamsumError::InsufficientComplexity);
+        }
+        Ok(Sdbf {
+            input_len: self.length,
+            filters: self.filters,
+        })
+    }
+
+    /// Starts over with a new input.
+    pub fn reset(&mut self) {
+        *self = SdbfHasher::new();
+    }
+}
+
+impl Default for SdbfHasher {
+    fn default() -> SdbfHasher {
+        SdbfHasher::new()
+    }
+}
+
+impl FuzzyHash for Sdbf {
+    const ALGORITHM: Algorithm = Algorithm::Sdhash;
+
+    fn hash(input: &[u8], options: SpamsumOptions) -> Result<Sdbf, SpamsumError> {
+        get_sdbf(input, options)
+    }
+
+    fn similarity(&self, other: &Sdbf) -> u32 {
+        self.score(other)
+    }
+}
+
+impl FuzzyDigest for SdbfHasher {
+    type Output = Sdbf;
+
+    fn update(&mut self, data: impl AsRef<[u8]>) {
+        SdbfHasher::update(self, data.as_ref());
+    }
+
+    fn finalize(self) -> Result<Sdbf, SpamsumError> {
+        SdbfHasher::finalize(self)
+    }
+
+    fn finalize_reset(&mut self) -> Result<Sdbf, SpamsumError> {
+        let sdbf = self.clone().finalize();
+        self.reset();
+        sdbf
+    }
+
+    fn reset(&mut self) {
+        SdbfHasher::reset(self);
+    }
+}
+
+/// Calculates the sdhash-style digest of `input`, after the same
+/// preprocessing as `get_configured_spamsum()` with `options`.
+pub fn get_sdbf(input: &[u8], options: SpamsumOptions) -> Result<Sdbf, SpamsumError> {
+    check_options(&options)?;
+    if let Some(max_input_len) = options.max_input_len {
+        if input.len() as u64 > max_input_len {
+            return Err(SpamsumError::InputLimitExceeded(max_input_len));
+        }
+    }
+    let mut hasher = SdbfHasher::new();
+    hasher.update(&filter_input(input, options));
+    hasher.finalize()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compare, get_spamsum, pseudo_random_bytes};
+
+    #[test]
+    fn test_sdbf_format() {
+        for &length in &[1_000, 50_000] {
+            let sdbf = get_sdbf(&pseudo_random_bytes(length, 1), Default::default()).unwrap();
+            let s = sdbf.to_string();
+            assert!(s.starts_with(&format!("sdbf-lite:{}:", length)));
+            assert_eq!(s.parse(), Ok(sdbf));
+        }
+        assert_eq!(
+            "sdbf:1000:5:AAAA".parse::<Sdbf>(),
+            Err(ParseSpamsumError::InvalidFormat)
+        );
+        assert_eq!(
+            "sdbf-lite:1000:5:AAAA".parse::<Sdbf>(),
+            Err(ParseSpamsumError::InvalidFormat)
+        );
+        assert_eq!(
+            "sdbf-lite:1000:5:AA.A".parse::<Sdbf>(),
+            Err(ParseSpamsumError::InvalidCharacter('.'))
+        );
+    }
+
+    #[test]
+    fn test_sdbf_streaming() {
+        let input = pseudo_random_bytes(100_000, 2);
+        let mut hasher = SdbfHasher::new();
+        for chunk in input.chunks(777) {
+            hasher.update(chunk);
+        }
+        assert_eq!(
+            hasher.finalize().unwrap(),
+            get_sdbf(&input, Default::default()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_sdbf_fragment() {
+        let whole = pseudo_random_bytes(200_000, 3);
+        let fragment = &whole[120_000..130_000];
+        let unrelated = pseudo_random_bytes(200_000, 4);
+        let a = get_sdbf(&whole, Default::default()).unwrap();
+        let b = get_sdbf(fragment, Default::default()).unwrap();
+        let c = get_sdbf(&unrelated, Default::default()).unwrap();
+        assert!(a.filters.len() > 1);
+        assert_eq!(a.score(&a), 100);
+        assert_eq!(a.score(&b), b.score(&a));
+        assert!(a.score(&b) > 50);
+        assert!(a.score(&c) < 10);
+        // spamsum loses the fragment
+        let spamsum = get_spamsum(&whole).unwrap();
+        assert_eq!(compare(&spamsum, &get_spamsum(fragment).unwrap()), 0);
+    }
+
+    #[test]
+    fn test_sdbf_insufficient_complexity() {
+        assert!(matches!(
+            get_sdbf(&pseudo_random_bytes(100, 5), Default::default()),
+            Err(SpamsumError::InsufficientComplexity)
+        ));
+        assert!(matches!(
+            get_sdbf(&[0; 10_000], Default::default()),
+            Err(SpamsumError::InsufficientComplexity)
+        ));
+    }
+}
@@ -0,0 +1,158 @@
+use std::cmp::{self, Reverse};
+use std::collections::BinaryHeap;
+
+use crate::compare::compare_normalized;
+use crate::ngram::NgramIndex;
+use crate::Spamsum;
+
+/// Returns the `k` spamsums of `corpus` that are most similar to `query` as
+/// `(index, score)`, best first.
+///
+/// Spamsums with a score of 0 are never returned, so the result may contain
+/// fewer than `k` entries. Ties are broken by preferring the lower index.
+///
+/// ```
+/// use spamsum::{find_top_k, get_spamsum};
+///
+/// let corpus = vec![
+///     get_spamsum(b"Goodbye").unwrap(),
+///     get_spamsum(b"Hello").unwrap(),
+/// ];
+/// let query = get_spamsum(b"Hello").unwrap();
+/// assert_eq!(find_top_k(&query, &corpus, 1), vec![(1, 100)]);
+/// ```
+pub fn find_top_k(query: &Spamsum, corpus: &[Spamsum], k: usize) -> Vec<(usize, u32)> {
+    if k == 0 {
+        return Vec::new();
+    }
+    let query = query.normalized();
+    // a min-heap of the best matches so far, with the worst (and among equal
+    // scores the latest) match on top
+    let mut best = BinaryHeap::with_capacity(k + 1);
+    for (index, candidate) in corpus.iter().enumerate() {
+        if !query.is_comparable_with(candidate) {
+            continue;
+        }
+        // once there are k matches, only better ones are of interest
+        let min_score = match best.peek() {
+            Some(&Reverse((score, _))) if best.len() == k => score + 1,
+            _ => 1,
+        };
+        if min_score > 100 {
+            break;
+        }
+        let score = compare_normalized(
+            &query,
+            &candidate.normalized(),
+            Default::default(),
+            min_score,
+        );
+        if score >= min_score {
+            best.push(Reverse((score, Reverse(index))));
+            if best.len() > k {
+                best.pop();
+            }
+        }
+    }
+    let mut result: Vec<(usize, u32)> = best
+        .into_iter()
+        .map(|Reverse((score, Reverse(index)))| (index, score))
+        .collect();
+    result.sort_by_key(|&(index, score)| (Reverse(score), index));
+    result
+}
+
+/// Returns all pairs of a spamsum from `left` and one from `right` with a
+/// score of at least `min_score` as `(left index, right index, score)`,
+/// sorted by the left and then the right index.
+///
+/// Only spamsums that share a substring (and can therefore score above 0) are
+/// compared, so this is much faster than comparing every pair for large sets
+/// of mostly unrelated spamsums.
+///
+/// ```
+/// use spamsum::{get_spamsum, similarity_join};
+///
+/// let yesterday = vec![get_spamsum(b"Hello").unwrap()];
+/// let today = vec![
+///     get_spamsum(b"Goodbye").unwrap(),
+///     get_spamsum(b"Hello").unwrap(),
+/// ];
+/// assert_eq!(similarity_join(&yesterday, &today, 50), vec![(0, 1, 100)]);
+/// ```
+pub fn similarity_join(
+    left: &[Spamsum],
+    right: &[Spamsum],
+    min_score: u32,
+) -> Vec<(usize, usize, u32)> {
+    let min_score = cmp::max(min_score, 1);
+    let right: Vec<Spamsum> = right.iter().map(Spamsum::normalized).collect();
+    let mut index = NgramIndex::default();
+    for (j, spamsum) in right.iter().enumerate() {
+        index.insert(j, spamsum);
+    }
+    let mut pairs = Vec::new();
+    for (i, spamsum) in left.iter().enumerate() {
+        let spamsum = spamsum.normalized();
+        for j in index.candidates(&spamsum) {
+            let score = compare_normalized(&spamsum, &right[j], Default::default(), min_score);
+            if score >= min_score {
+                pairs.push((i, j, score));
+            }
+        }
+    }
+    pairs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compare;
+
+    fn corpus() -> Vec<Spamsum> {
+        [
+            "48:ABCDEFGHIJKLMNOQ:WXYZ",
+            "12:ABCDEFGHIJKLMNOP:ABCD",
+            "48:ABCDEFGHIJKLMNOP:ABCD",
+            "48:ABCDEFGHIJKLMNOQ:WXYZ",
+            "48:ABCDEFGHIJKLMNQR:WXYZ",
+            "24:QRSTUVWX:ABCDEFGHIJKLMNOP",
+        ]
+        .iter()
+        .map(|s| s.parse().unwrap())
+        .collect()
+    }
+
+    #[test]
+    fn test_find_top_k() {
+        let corpus = corpus();
+        let query = &corpus[2];
+        assert_eq!(find_top_k(query, &corpus, 0), vec![]);
+        assert_eq!(find_top_k(query, &corpus, 2), vec![(2, 100), (5, 100)]);
+        assert_eq!(
+            find_top_k(query, &corpus, 4),
+            vec![(2, 100), (5, 100), (0, 94), (3, 94)]
+        );
+        let all = find_top_k(query, &corpus, 10);
+        assert_eq!(all.len(), 5);
+        assert_eq!(all[4], (4, compare(query, &corpus[4])));
+    }
+
+    #[test]
+    fn test_similarity_join() {
+        let corpus = corpus();
+        let (left, right) = corpus.split_at(3);
+        let mut expected = Vec::new();
+        for (i, a) in left.iter().enumerate() {
+            for (j, b) in right.iter().enumerate() {
+                let score = compare(a, b);
+                if score >= 90 {
+                    expected.push((i, j, score));
+                }
+            }
+        }
+        assert_eq!(expected.len(), 5);
+        assert_eq!(similarity_join(left, right, 90), expected);
+        assert!(similarity_join(left, &[], 1).is_empty());
+    }
+}
@@ -0,0 +1,272 @@
+use alloc::vec::Vec;
+use core::cmp;
+use core::ops::Range;
+#[cfg(feature = "std")]
+use std::io::{self, Read};
+
+#[cfg(feature = "std")]
+use crate::READ_BUFFER_SIZE;
+use crate::{compare, Spamsum, SpamsumError, SpamsumHasher, SpamsumOptions};
+
+/// The spamsum of a whole input together with one spamsum for every segment
+/// of a fixed size, so that corrupted or copied parts of e.g. disk images
+/// can be told apart.
+///
+/// ```
+/// use spamsum::get_segmented_spamsum;
+///
+/// let input: Vec<u8> = (0..250_000u64).map(|i| (i * i % 251) as u8).collect();
+/// let mut corrupted = input.clone();
+/// corrupted[120_000..130_000].iter_mut().for_each(|c| *c = 0);
+///
+/// let options = Default::default();
+/// let segmented = get_segmented_spamsum(&input, 100_000, options).unwrap();
+/// assert_eq!(segmented.segments().len(), 3);
+/// assert_eq!(segmented.segment_range(2), 200_000..250_000);
+/// let scores = segmented.compare_segments(&get_segmented_spamsum(&corrupted, 100_000, options).unwrap());
+/// assert_eq!(scores[0], 100);
+/// assert!(scores[1] < 100);
+/// assert_eq!(scores[2], 100);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SegmentedSpamsum {
+    segment_size: u64,
+    input_len: u64,
+    spamsum: Spamsum,
+    segments: Vec<Spamsum>,
+}
+
+impl SegmentedSpamsum {
+    pub fn segment_size(&self) -> u64 {
+        self.segment_size
+    }
+
+    /// Returns the spamsum of the whole input.
+    pub fn spamsum(&self) -> &Spamsum {
+        &self.spamsum
+    }
+
+    /// Returns the spamsums of the segments, of which only the last one may
+    /// be shorter than the segment size. An empty input has no segments.
+    pub fn segments(&self) -> &[Spamsum] {
+        &self.segments
+    }
+
+    /// Returns the byte range of the input that the segment with the given
+    /// index covers.
+    pub fn segment_range(&self, index: usize) -> Range<u64> {
+        let start = index as u64 * self.segment_size;
+        start..cmp::min(start + self.segment_size, self.input_len)
+    }
+
+    /// Compares the segments at the same offsets of both inputs, e.g. of an
+    /// image and a partially corrupted copy of it. Segments that only one of
+    /// the inputs has are left out.
+    pub fn compare_segments(&self, other: &SegmentedSpamsum) -> Vec<u32> {
+        self.segments
+            .iter()
+            .zip(other.segments.iter())
+            .map(|(a, b)| compare(a, b))
+            .collect()
+    }
+}
+
+/// Incrementally calculates a `SegmentedSpamsum` of data that arrives in
+/// chunks, which do not have to line up with the segments.
+///
+/// The options apply to every segment as if it was an input of its own, so
+/// options like `ignore_headers` are only useful for the whole input.
+pub struct SegmentedHasher {
+    segment_size: u64,
+    input_len: u64,
+    hasher: SpamsumHasher,
+    segment_hasher: SpamsumHasher,
+    segments: Vec<Spamsum>,
+}
+
+impl SegmentedHasher {
+    /// Creates a hasher for segments of `segment_size` bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `segment_size` is 0.
+    pub fn new(
+        segment_size: u64,
+        options: SpamsumOptions,
+    ) -> Result<SegmentedHasher, SpamsumError> {
+        assert!(segment_size > 0, "segment size must not be 0");
+        Ok(SegmentedHasher {
+            segment_size,
+            input_len: 0,
+            hasher: SpamsumHasher::with_options(options)?,
+            segment_hasher: SpamsumHasher::with_options(options)?,
+            segments: Vec::new(),
+        })
+    }
+
+    /// Hashes the next chunk of input. Once the input is longer than
+    /// `max_input_len`, the segments are not hashed anymore either.
+    pub fn update(&mut self, mut input: &[u8]) {
+        self.hasher.update(input);
+        if self.exceeds_max_input_len() {
+            return;
+        }
+        while !input.is_empty() {
+            let segment_len = self.input_len % self.segment_size;
+            let length = cmp::min(input.len() as u64, self.segment_size - segment_len) as usize;
+            self.segment_hasher.update(&input[..length]);
+            self.input_len += length as u64;
+            input = &input[length..];
+            if self.input_len.is_multiple_of(self.segment_size) {
+                let segment = self.segment_hasher.finalize_reset();
+                // the error shows up again when finalizing the whole input
+                if let Ok(segment) = segment {
+                    self.segments.push(segment);
+                }
+            }
+        }
+    }
+
+    /// Returns whether the input so far is longer than `max_input_len`, see
+    /// `SpamsumHasher::exceeds_max_input_len()`.
+    pub fn exceeds_max_input_len(&self) -> bool {
+        self.hasher.exceeds_max_input_len()
+    }
+
+    /// Returns the spamsums of the input so far. Fails like
+    /// `SpamsumHasher::finalize()` for the whole input.
+    pub fn finalize(mut self) -> Result<SegmentedSpamsum, SpamsumError> {
+        let spamsum = self.hasher.finalize()?;
+        if !self.input_len.is_multiple_of(self.segment_size) {
+            self.segments.push(self.segment_hasher.finalize()?);
+        }
+        Ok(SegmentedSpamsum {
+            segment_size: self.segment_size,
+            input_len: self.input_len,
+            spamsum,
+            segments: self.segments,
+        })
+    }
+}
+
+/// Calculates the spamsum of `input` and of every segment of `segment_size`
+/// bytes of it, see `SegmentedSpamsum`.
+///
+/// # Panics
+///
+/// Panics if `segment_size` is 0.
+pub fn get_segmented_spamsum(
+    input: &[u8],
+    segment_size: u64,
+    options: SpamsumOptions,
+) -> Result<SegmentedSpamsum, SpamsumError> {
+    let mut hasher = SegmentedHasher::new(segment_size, options)?;
+    hasher.update(input);
+    hasher.finalize()
+}
+
+/// Like `get_segmented_spamsum()`, but reads the input from `reader` in
+/// fixed-size chunks, so that images larger than memory can be hashed.
+#[cfg(feature = "std")]
+pub fn get_segmented_spamsum_from_reader<R: Read>(
+    mut reader: R,
+    segment_size: u64,
+    options: SpamsumOptions,
+) -> Result<SegmentedSpamsum, SpamsumError> {
+    let mut hasher = SegmentedHasher::new(segment_size, options)?;
+    let mut buffer = vec![0; READ_BUFFER_SIZE];
+    loop {
+        match reader.read(&mut buffer) {
+            Ok(0) => return hasher.finalize(),
+            Ok(length) => {
+                hasher.update(&buffer[..length]);
+                if hasher.exceeds_max_input_len() {
+                    // stop reading early
+                    return hasher.finalize();
+                }
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(SpamsumError::Io(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{get_configured_spamsum, pseudo_random_bytes};
+
+    #[test]
+    fn test_segmented_spamsum() {
+        let input = pseudo_random_bytes(25_000, 1);
+        let options = Default::default();
+        let segmented = get_segmented_spamsum(&input, 10_000, options).unwrap();
+        assert_eq!(
+            segmented.spamsum(),
+            &get_configured_spamsum(&input, options).unwrap()
+        );
+        assert_eq!(segmented.segments().len(), 3);
+        for (i, segment) in segmented.segments().iter().enumerate() {
+            let range = segmented.segment_range(i);
+            let expected = &input[range.start as usize..range.end as usize];
+            assert_eq!(segment, &get_configured_spamsum(expected, options).unwrap());
+        }
+        assert_eq!(segmented.segment_range(2), 20_000..25_000);
+
+        // the chunks do not have to line up with the segments
+        let mut hasher = SegmentedHasher::new(10_000, options).unwrap();
+        for chunk in input.chunks(3_333) {
+            hasher.update(chunk);
+        }
+        assert_eq!(hasher.finalize().unwrap(), segmented);
+        #[cfg(feature = "std")]
+        {
+            let from_reader = get_segmented_spamsum_from_reader(&input[..], 10_000, options);
+            assert_eq!(from_reader.unwrap(), segmented);
+        }
+    }
+
+    #[test]
+    fn test_segmented_spamsum_exact_segments() {
+        let input = pseudo_random_bytes(20_000, 1);
+        let segmented = get_segmented_spamsum(&input, 10_000, Default::default()).unwrap();
+        assert_eq!(segmented.segments().len(), 2);
+        let empty = get_segmented_spamsum(b"", 10_000, Default::default()).unwrap();
+        assert!(empty.segments().is_empty());
+        assert_eq!(empty.spamsum().to_string(), "3::");
+    }
+
+    #[test]
+    fn test_compare_segments() {
+        let first = pseudo_random_bytes(30_000, 1);
+        let mut second = first.clone();
+        second[12_000..13_000].copy_from_slice(&pseudo_random_bytes(1_000, 2));
+        second.truncate(20_000);
+        let options = Default::default();
+        let first = get_segmented_spamsum(&first, 10_000, options).unwrap();
+        let second = get_segmented_spamsum(&second, 10_000, options).unwrap();
+        let scores = first.compare_segments(&second);
+        assert_eq!(scores.len(), 2);
+        assert_eq!(scores[0], 100);
+        assert!(scores[1] > 0 && scores[1] < 100, "{}", scores[1]);
+    }
+
+    #[test]
+    fn test_segmented_spamsum_input_limit() {
+        let options = SpamsumOptions::builder()
+            .max_input_len(Some(15_000))
+            .build()
+            .unwrap();
+        assert!(matches!(
+            get_segmented_spamsum(&pseudo_random_bytes(20_000, 1), 10_000, options),
+            Err(SpamsumError::InputLimitExceeded(15_000))
+        ));
+        // an endless input is not read to the end
+        #[cfg(feature = "std")]
+        assert!(matches!(
+            get_segmented_spamsum_from_reader(io::repeat(1), 10_000, options),
+            Err(SpamsumError::InputLimitExceeded(15_000))
+        ));
+    }
+}
@@ -0,0 +1,105 @@
+//! Serializes a `Spamsum` as its compact `blocksize:left_hash:right_hash`
+//! string instead of a struct.
+//!
+//! ```
+//! use serde::{Deserialize, Serialize};
+//! use spamsum::Spamsum;
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct Message {
+//!     #[serde(with = "spamsum::serde_str")]
+//!     spamsum: Spamsum,
+//! }
+//! ```
+use alloc::string::String;
+
+use serde::de::{self, Deserialize, Deserializer};
+use serde::ser::Serializer;
+
+use crate::Spamsum;
+
+pub fn serialize<S: Serializer>(spamsum: &Spamsum, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(spamsum)
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Spamsum, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    s.parse().map_err(de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use crate::{Spamsum, SpamsumOptions};
+
+    #[derive(Serialize, Deserialize)]
+    struct Message {
+        #[serde(with = "crate::serde_str")]
+        spamsum: Spamsum,
+    }
+
+    #[test]
+    fn test_serialize_as_string() {
+        let message = Message {
+            spamsum: "3:clclDDvWIMF/hv:cGZ/EJv".parse().unwrap(),
+        };
+        let json = serde_json::to_string(&message).unwrap();
+        assert_eq!(json, r#"{"spamsum":"3:clclDDvWIMF/hv:cGZ/EJv"}"#);
+        let message: Message = serde_json::from_str(&json).unwrap();
+        assert_eq!(message.spamsum.to_string(), "3:clclDDvWIMF/hv:cGZ/EJv");
+    }
+
+    #[test]
+    fn test_deserialize_invalid_string() {
+        assert!(serde_json::from_str::<Message>(r#"{"spamsum":"3:clcl"}"#).is_err());
+    }
+
+    #[test]
+    fn test_serialize_structs() {
+        let spamsum: Spamsum = "3:Hn:Hn".parse().unwrap();
+        let json = serde_json::to_string(&spamsum).unwrap();
+        assert_eq!(
+            json,
+            r#"{"left_hash_blocksize":3,"left_hash":"Hn","right_hash":"Hn"}"#
+        );
+        let options: SpamsumOptions = serde_json::from_str(
+            r#"{"blocksize":0,"ignore_whitespace":true,"ignore_headers":false}"#,
+        )
+        .unwrap();
+        assert!(options.ignore_whitespace);
+    }
+
+    #[test]
+    fn test_deserialize_invalid_struct() {
+        for json in &[
+            r#"{"left_hash_blocksize":0,"left_hash":"!!-","right_hash":""}"#,
+            r#"{"left_hash_blocksize":0,"left_hash":"Hn","right_hash":"Hn"}"#,
+            r#"{"left_hash_blocksize":3,"left_hash":"!!-","right_hash":""}"#,
+            r#"{"left_hash_blocksize":3,"left_hash":"Hn","right_hash":"é"}"#,
+        ] {
+            assert!(serde_json::from_str::<Spamsum>(json).is_err(), "{}", json);
+        }
+        let long = format!(
+            r#"{{"left_hash_blocksize":3,"left_hash":"{}","right_hash":""}}"#,
+            "A".repeat(257)
+        );
+        assert!(serde_json::from_str::<Spamsum>(&long).is_err());
+        // hashes of a longer hash length are accepted
+        let spamsum: Spamsum = serde_json::from_str(&long.replace("A\"", "\"")).unwrap();
+        assert_eq!(spamsum.left_hash().len(), 256);
+    }
+
+    #[test]
+    fn test_serialize_hash_length() {
+        let input = crate::pseudo_random_bytes(50_000, 1);
+        let options = SpamsumOptions::builder().hash_length(256).build().unwrap();
+        let message = Message {
+            spamsum: crate::get_configured_spamsum(&input, options).unwrap(),
+        };
+        assert!(message.spamsum.left_hash().len() > 64);
+        let json = serde_json::to_string(&message).unwrap();
+        let copy: Message = serde_json::from_str(&json).unwrap();
+        assert_eq!(copy.spamsum, message.spamsum);
+    }
+}
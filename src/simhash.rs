@@ -0,0 +1,257 @@
+use core::fmt;
+use core::str::FromStr;
+
+use crate::word::{words_doc, WordHasher};
+use crate::{
+    check_options, filter_input, mix, Algorithm, FuzzyDigest, FuzzyHash, ParseSpamsumError,
+    SpamsumError, SpamsumOptions,
+};
+
+/// A 64 bit SimHash fingerprint of the words of a text, for finding near
+/// duplicates.
+///
+/// Every word votes for the bits of its hash, so texts that share most of
+/// their words get fingerprints that differ in only a few bits, and
+/// `distance()` counts those. Near duplicates are usually taken to be at a
+/// distance of at most 3 bits; unrelated texts are at around 32.
+///
+#[doc = words_doc!()]
+/// Case and the order of the words are ignored only as far as the
+/// preprocessing options make them.
+///
+/// ```
+/// use spamsum::{get_simhash, SimHash};
+///
+/// let a = get_simhash(b"Please buy my stuff, Dear Sir or Madam", Default::default()).unwrap();
+/// let b = get_simhash(b"Please buy my stuff! Dear Sir or Madam", Default::default()).unwrap();
+/// assert_eq!(a.distance(&b), 0);
+/// assert_eq!(a.to_string().parse::<SimHash>(), Ok(a));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SimHash {
+    fingerprint: u64,
+}
+
+impl SimHash {
+    /// Creates a SimHash from a fingerprint, e.g. one stored as an integer.
+    pub fn new(fingerprint: u64) -> SimHash {
+        SimHash { fingerprint }
+    }
+
+    /// Returns the fingerprint.
+    pub fn value(&self) -> u64 {
+        self.fingerprint
+    }
+
+    /// Returns the number of bits in which the fingerprints differ, from 0
+    /// for (near) duplicates to 64.
+    pub fn distance(&self, other: &SimHash) -> u32 {
+        (self.fingerprint ^ other.fingerprint).count_ones()
+    }
+}
+
+impl fmt::Display for SimHash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:016x}", self.fingerprint)
+    }
+}
+
+impl FromStr for SimHash {
+    type Err = ParseSpamsumError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(c) = s.chars().find(|c| !c.is_ascii_hexdigit()) {
+            return Err(ParseSpamsumError::InvalidCharacter(c));
+        }
+        if s.len() != 16 {
+            return Err(ParseSpamsumError::InvalidFormat);
+        }
+        // only ASCII hex digits are left
+        Ok(SimHash::new(u64::from_str_radix(s, 16).unwrap()))
+    }
+}
+
+/// Incrementally calculates the SimHash of data that arrives in chunks; a
+/// word may span chunks.
+///
+/// ```
+/// use spamsum::{get_simhash, SimHashHasher};
+///
+/// let mut hasher = SimHashHasher::new();
+/// hasher.update(b"Please buy my st");
+/// hasher.update(b"uff\nDear Sir or Madam\n");
+/// let expected = get_simhash(b"Please buy my stuff\nDear Sir or Madam\n", Default::default());
+/// assert_eq!(hasher.finalize(), expected.unwrap());
+/// ```
+#[derive(Clone)]
+pub struct SimHashHasher {
+    // the sum of the votes of all words for every bit
+    votes: [i64; 64],
+    word: WordHasher,
+}
+
+impl SimHashHasher {
+    pub fn new() -> SimHashHasher {
+        SimHashHasher {
+            votes: [0; 64],
+            word: WordHasher::default(),
+        }
+    }
+
+    /// Hashes the next chunk of input.
+    pub fn update(&mut self, input: &[u8]) {
+        for &c in input {
+            if let Some(hash) = self.word.push(c) {
+                self.vote(hash);
+            }
+        }
+    }
+
+    fn vote(&mut self, hash: u64) {
+        // the high bits of FNV-1a hardly depend on the last bytes
+        let hash = mix(hash);
+        for (i, vote) in self.votes.iter_mut().enumerate() {
+            if hash & (1 << i) != 0 {
+                *vote += 1;
+            } else {
+                *vote -= 1;
+            }
+        }
+    }
+
+    /// Returns the SimHash of the input so far; bits on which the words do
+    /// not agree, e.g. for an empty input, are 0.
+    pub fn finalize(mut self) -> SimHash {
+        if let Some(hash) = self.word.finish() {
+            self.vote(hash);
+        }
+        let fingerprint = self
+            .votes
+            .iter()
+            .enumerate()
+            .filter(|&(_, &vote)| vote > 0)
+            .fold(0, |fingerprint, (i, _)| fingerprint | 1 << i);
+        SimHash::new(fingerprint)
+    }
+
+    /// Starts over with a new input.
+    pub fn reset(&mut self) {
+        *self = SimHashHasher::new();
+    }
+}
+
+impl Default for SimHashHasher {
+    fn default() -> SimHashHasher {
+        SimHashHasher::new()
+    }
+}
+
+impl FuzzyHash for SimHash {
+    const ALGORITHM: Algorithm = Algorithm::SimHash;
+
+    fn hash(input: &[u8], options: SpamsumOptions) -> Result<SimHash, SpamsumError> {
+        get_simhash(input, options)
+    }
+
+    // unrelated inputs differ in around 32 bits
+    fn similarity(&self, other: &SimHash) -> u32 {
+        100 * 32u32.saturating_sub(self.distance(other)) / 32
+    }
+}
+
+impl FuzzyDigest for SimHashHasher {
+    type Output = SimHash;
+
+    fn update(&mut self, data: impl AsRef<[u8]>) {
+        SimHashHasher::update(self, data.as_ref());
+    }
+
+    fn finalize(self) -> Result<SimHash, SpamsumError> {
+        Ok(SimHashHasher::finalize(self))
+    }
+
+    fn finalize_reset(&mut self) -> Result<SimHash, SpamsumError> {
+        let simhash = self.clone().finalize();
+        self.reset();
+        Ok(simhash)
+    }
+
+    fn reset(&mut self) {
+        SimHashHasher::reset(self);
+    }
+}
+
+/// Calculates the SimHash of `input`, after the same preprocessing as
+/// `get_configured_spamsum()` with `options`, e.g. with `strip_digits` or
+/// without quoted lines.
+pub fn get_simhash(input: &[u8], options: SpamsumOptions) -> Result<SimHash, SpamsumError> {
+    check_options(&options)?;
+    if let Some(max_input_len) = options.max_input_len {
+        if input.len() as u64 > max_input_len {
+            return Err(SpamsumError::InputLimitExceeded(max_input_len));
+        }
+    }
+    let mut hasher = SimHashHasher::new();
+    hasher.update(&filter_input(input, options));
+    Ok(hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "Dear Sir or Madam, we are pleased to offer you the opportunity \
+                        to buy our finest stuff at a very special price. This offer is \
+                        only valid for a limited time, so do not miss your chance and \
+                        order today. Our friendly staff is looking forward to hearing \
+                        from you. Kind regards, the sales team";
+
+    #[test]
+    fn test_simhash_format() {
+        let simhash = SimHash::new(0x0123_4567_89ab_cdef);
+        assert_eq!(simhash.to_string(), "0123456789abcdef");
+        assert_eq!("0123456789ABCDEF".parse(), Ok(simhash));
+        assert_eq!(
+            "123456789abcdef".parse::<SimHash>(),
+            Err(ParseSpamsumError::InvalidFormat)
+        );
+        assert_eq!(
+            "+123456789abcdef".parse::<SimHash>(),
+            Err(ParseSpamsumError::InvalidCharacter('+'))
+        );
+    }
+
+    #[test]
+    fn test_simhash_distance() {
+        let a = get_simhash(TEXT.as_bytes(), Default::default()).unwrap();
+        let b = get_simhash(TEXT.replace("today", "now").as_bytes(), Default::default()).unwrap();
+        let c = get_simhash(
+            b"Minutes of the meeting of the board of directors, held on Monday in \
+              the main office. The board discussed the budget of the next year \
+              and decided to postpone the vote until the figures are final.",
+            Default::default(),
+        )
+        .unwrap();
+        assert_eq!(a.distance(&a), 0);
+        assert_eq!(a.distance(&b), b.distance(&a));
+        assert!(a.distance(&b) <= 8);
+        assert!(a.distance(&c) > 16);
+    }
+
+    #[test]
+    fn test_simhash_words() {
+        let simhash = |input: &[u8]| get_simhash(input, Default::default()).unwrap();
+        assert_eq!(simhash(b""), SimHash::new(0));
+        assert_eq!(simhash(b"a b"), simhash(b"  a\n\nb."));
+        assert_ne!(simhash(b"ab"), simhash(b"a b"));
+        let options = SpamsumOptions::builder()
+            .strip_digits(true)
+            .build()
+            .unwrap();
+        assert_eq!(
+            get_simhash(b"order 123 today", options).unwrap(),
+            get_simhash(b"order 456 today", options).unwrap()
+        );
+    }
+}
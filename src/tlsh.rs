@@ -0,0 +1,494 @@
+use core::fmt;
+use core::str::FromStr;
+
+use crate::filter_input;
+use crate::hasher::DigestState;
+use crate::{
+    check_options, Algorithm, FnvHasher, FuzzyDigest, FuzzyHash, ParseSpamsumError, Spamsum,
+    SpamsumError, SpamsumOptions,
+};
+
+// the buckets that make up the digest; the triplets are hashed into 256
+// buckets, but only the first 128 are used
+const BUCKETS: usize = 128;
+// every byte of the code holds 2 bits for each of 4 buckets
+const CODE_SIZE: usize = BUCKETS / 4;
+const MIN_DATA_LENGTH: u64 = 50;
+// the hex digits of checksum, length, quartile ratios and code
+const HEX_LENGTH: usize = 2 * (3 + CODE_SIZE);
+const VERSION_PREFIX: &str = "T1";
+// chunk size used when hashing both digests in one pass
+const CHUNK_LENGTH: usize = 64 * 1024;
+
+// Pearson's permutation of 0 to 255, which TLSH hashes the byte triplets
+// with
+const PEARSON: [u8; 256] = [
+    1, 87, 49, 12, 176, 178, 102, 166, 121, 193, 6, 84, 249, 230, 44, 163, 14, 197, 213, 181, 161,
+    85, 218, 80, 64, 239, 24, 226, 236, 142, 38, 200, 110, 177, 104, 103, 141, 253, 255, 50, 77,
+    101, 81, 18, 45, 96, 31, 222, 25, 107, 190, 70, 86, 237, 240, 34, 72, 242, 20, 214, 244, 227,
+    149, 235, 97, 234, 57, 22, 60, 250, 82, 175, 208, 5, 127, 199, 111, 62, 135, 248, 174, 169,
+    211, 58, 66, 154, 106, 195, 245, 171, 17, 187, 182, 179, 0, 243, 132, 56, 148, 75, 128, 133,
+    158, 100, 130, 126, 91, 13, 153, 246, 216, 219, 119, 68, 223, 78, 83, 88, 201, 99, 122, 11, 92,
+    32, 136, 114, 52, 10, 138, 30, 48, 183, 156, 35, 61, 26, 143, 74, 251, 94, 129, 162, 63, 152,
+    170, 7, 115, 167, 241, 206, 3, 150, 55, 59, 151, 220, 90, 53, 23, 131, 125, 173, 15, 238, 79,
+    95, 89, 16, 105, 137, 225, 224, 217, 160, 37, 123, 118, 73, 2, 157, 46, 116, 9, 145, 134, 228,
+    207, 212, 202, 215, 69, 229, 27, 188, 67, 124, 168, 252, 42, 4, 29, 108, 21, 247, 19, 205, 39,
+    203, 233, 40, 186, 147, 198, 192, 155, 33, 164, 191, 98, 204, 165, 180, 117, 76, 140, 36, 210,
+    172, 41, 54, 159, 8, 185, 232, 113, 196, 231, 47, 146, 120, 51, 65, 28, 144, 254, 221, 93, 189,
+    194, 139, 112, 43, 71, 109, 184, 209,
+];
+
+/// A TLSH digest (Trend Micro Locality Sensitive Hash), in the standard
+/// variant with 128 buckets and a 1 byte checksum.
+///
+/// TLSH summarizes the distribution of byte triplets instead of chunks, so
+/// it often finds similar binaries that spamsum does not. Two digests are
+/// compared with `distance()`: 0 means (almost) identical, and the distance
+/// grows with the differences, without an upper bound.
+///
+/// Digests are written like the reference implementation, as `T1` and 70
+/// hex digits.
+///
+/// ```
+/// use spamsum::{get_tlsh, Tlsh};
+///
+/// let input: Vec<u8> = (0..2000u32).map(|i| (i * i % 251) as u8).collect();
+/// let tlsh = get_tlsh(&input, Default::default()).unwrap();
+/// assert_eq!(tlsh.to_string().len(), 72);
+/// assert_eq!(tlsh.to_string().parse::<Tlsh>(), Ok(tlsh));
+/// assert_eq!(tlsh.distance(&tlsh), 0);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Tlsh {
+    checksum: u8,
+    // the logarithm of the input length
+    lvalue: u8,
+    // the first and second quartile as a ratio of the third one, mod 16
+    q1_ratio: u8,
+    q2_ratio: u8,
+    code: [u8; CODE_SIZE],
+}
+
+impl Tlsh {
+    /// Returns the distance to `other`, which includes the difference of the
+    /// input lengths, like the reference implementation by default.
+    pub fn distance(&self, other: &Tlsh) -> u32 {
+        let distance = match mod_diff(self.lvalue, other.lvalue, 256) {
+            0 => 0,
+            1 => 1,
+            difference => difference * 12,
+        };
+        distance + self.distance_without_length(other)
+    }
+
+    /// Returns the distance to `other` without the difference of the input
+    /// lengths, e.g. to find content that has been appended to or cut out.
+    pub fn distance_without_length(&self, other: &Tlsh) -> u32 {
+        let mut distance = 0;
+        for &(a, b) in [
+            (self.q1_ratio, other.q1_ratio),
+            (self.q2_ratio, other.q2_ratio),
+        ]
+        .iter()
+        {
+            distance += match mod_diff(a, b, 16) {
+                difference @ 0..=1 => difference,
+                difference => (difference - 1) * 12,
+            };
+        }
+        if self.checksum != other.checksum {
+            distance += 1;
+        }
+        for (&a, &b) in self.code.iter().zip(other.code.iter()) {
+            for shift in (0..8).step_by(2) {
+                distance += match ((a >> shift) & 3).abs_diff((b >> shift) & 3) {
+                    3 => 6,
+                    difference => u32::from(difference),
+                };
+            }
+        }
+        distance
+    }
+}
+
+// The distance of x and y on a circle of `range` values.
+fn mod_diff(x: u8, y: u8, range: u32) -> u32 {
+    let difference = u32::from(x.abs_diff(y));
+    difference.min(range - difference)
+}
+
+fn swap_nibbles(c: u8) -> u8 {
+    c.rotate_left(4)
+}
+
+impl fmt::Display for Tlsh {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(VERSION_PREFIX)?;
+        write!(
+            f,
+            "{:02X}{:02X}{:02X}",
+            swap_nibbles(self.checksum),
+            swap_nibbles(self.lvalue),
+            self.q1_ratio << 4 | self.q2_ratio
+        )?;
+        for c in self.code.iter().rev() {
+            write!(f, "{:02X}", c)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses digests with or without the `T1` version prefix.
+impl FromStr for Tlsh {
+    type Err = ParseSpamsumError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.strip_prefix(VERSION_PREFIX).unwrap_or(s);
+        if s.len() != HEX_LENGTH {
+            return Err(ParseSpamsumError::InvalidFormat);
+        }
+        if let Some(c) = s.chars().find(|c| !c.is_ascii_hexdigit()) {
+            return Err(ParseSpamsumError::InvalidCharacter(c));
+        }
+        // only ASCII hex digits are left
+        let byte = |i: usize| u8::from_str_radix(&s[2 * i..2 * i + 2], 16).unwrap();
+        let mut code = [0; CODE_SIZE];
+        for (i, c) in code.iter_mut().enumerate() {
+            *c = byte(3 + CODE_SIZE - 1 - i);
+        }
+        Ok(Tlsh {
+            checksum: swap_nibbles(byte(0)),
+            lvalue: swap_nibbles(byte(1)),
+            q1_ratio: byte(2) >> 4,
+            q2_ratio: byte(2) & 0xF,
+            code,
+        })
+    }
+}
+
+/// Incrementally calculates the TLSH digest of data that arrives in chunks.
+///
+/// ```
+/// use spamsum::{get_tlsh, TlshHasher};
+///
+/// let input: Vec<u8> = (0..2000u32).map(|i| (i * i % 251) as u8).collect();
+/// let mut hasher = TlshHasher::new();
+/// for chunk in input.chunks(100) {
+///     hasher.update(chunk);
+/// }
+/// assert_eq!(hasher.finalize().unwrap(), get_tlsh(&input, Default::default()).unwrap());
+/// ```
+#[derive(Clone)]
+pub struct TlshHasher {
+    buckets: [u32; 256],
+    checksum: u8,
+    // the last 4 bytes, the most recent one first
+    window: [u8; 4],
+    length: u64,
+}
+
+impl TlshHasher {
+    pub fn new() -> TlshHasher {
+        TlshHasher {
+            buckets: [0; 256],
+            checksum: 0,
+            window: [0; 4],
+            length: 0,
+        }
+    }
+
+    /// Hashes the next chunk of input.
+    pub fn update(&mut self, input: &[u8]) {
+        for &c in input {
+            let [b1, b2, b3, b4] = self.window;
+            if self.length >= 4 {
+                self.checksum = pearson(0, c, b1, self.checksum);
+                for &(salt, x, y) in [
+                    (2, b1, b2),
+                    (3, b1, b3),
+                    (5, b2, b3),
+                    (7, b2, b4),
+                    (11, b1, b4),
+                    (13, b3, b4),
+                ]
+                .iter()
+                {
+                    self.buckets[usize::from(pearson(salt, c, x, y))] += 1;
+                }
+            }
+            self.window = [c, b1, b2, b3];
+            self.length += 1;
+        }
+    }
+
+    /// Returns the digest of the input so far.
+    ///
+    /// Fails with `InsufficientComplexity` if the input is shorter than 50
+    /// bytes or too uniform, and with `InputTooLarge` beyond 4 GiB, which
+    /// the length of a digest cannot represent.
+    pub fn finalize(self) -> Result<Tlsh, SpamsumError> {
+        if self.length < MIN_DATA_LENGTH {
+            return Err(SpamsumError::InsufficientComplexity);
+        }
+        if self.length > u64::from(u32::MAX) {
+            return Err(SpamsumError::InputTooLarge(self.length));
+        }
+        let buckets = &self.buckets[..BUCKETS];
+        // at least half of the buckets have to be used
+        if buckets.iter().filter(|&&count| count > 0).count() <= BUCKETS / 2 {
+            return Err(SpamsumError::InsufficientComplexity);
+        }
+        let mut sorted = [0; BUCKETS];
+        sorted.copy_from_slice(buckets);
+        sorted.sort_unstable();
+        let q1 = sorted[BUCKETS / 4 - 1];
+        let q2 = sorted[BUCKETS / 2 - 1];
+        let q3 = sorted[BUCKETS - BUCKETS / 4 - 1];
+        if q3 == 0 {
+            return Err(SpamsumError::InsufficientComplexity);
+        }
+        let mut code = [0; CODE_SIZE];
+        for (c, counts) in code.iter_mut().zip(buckets.chunks(4)) {
+            for (j, &count) in counts.iter().enumerate() {
+                let quartile = if count > q3 {
+                    3
+                } else if count > q2 {
+                    2
+                } else if count > q1 {
+                    1
+                } else {
+                    0
+                };
+                *c |= quartile << (2 * j);
+            }
+        }
+        Ok(Tlsh {
+            checksum: self.checksum,
+            lvalue: length_value(self.length),
+            q1_ratio: (u64::from(q1) * 100 / u64::from(q3) % 16) as u8,
+            q2_ratio: (u64::from(q2) * 100 / u64::from(q3) % 16) as u8,
+            code,
+        })
+    }
+
+    /// Starts over with a new input.
+    pub fn reset(&mut self) {
+        *self = TlshHasher::new();
+    }
+}
+
+impl Default for TlshHasher {
+    fn default() -> TlshHasher {
+        TlshHasher::new()
+    }
+}
+
+impl FuzzyHash for Tlsh {
+    const ALGORITHM: Algorithm = Algorithm::Tlsh;
+
+    fn hash(input: &[u8], options: SpamsumOptions) -> Result<Tlsh, SpamsumError> {
+        get_tlsh(input, options)
+    }
+
+    // distances of 100 and more are mostly between unrelated inputs
+    fn similarity(&self, other: &Tlsh) -> u32 {
+        100 - self.distance(other).min(100)
+    }
+}
+
+impl FuzzyDigest for TlshHasher {
+    type Output = Tlsh;
+
+    fn update(&mut self, data: impl AsRef<[u8]>) {
+        TlshHasher::update(self, data.as_ref());
+    }
+
+    fn finalize(self) -> Result<Tlsh, SpamsumError> {
+        TlshHasher::finalize(self)
+    }
+
+    fn finalize_reset(&mut self) -> Result<Tlsh, SpamsumError> {
+        let tlsh = self.clone().finalize();
+        self.reset();
+        tlsh
+    }
+
+    fn reset(&mut self) {
+        TlshHasher::reset(self);
+    }
+}
+
+#[inline]
+fn pearson(salt: u8, a: u8, b: u8, c: u8) -> u8 {
+    let mut h = PEARSON[usize::from(salt)];
+    h = PEARSON[usize::from(h ^ a)];
+    h = PEARSON[usize::from(h ^ b)];
+    PEARSON[usize::from(h ^ c)]
+}
+
+// The length on a logarithmic scale, growing by a factor of 1.5 per step for
+// small inputs, 1.3 for medium-sized ones and 1.1 for large ones.
+fn length_value(length: u64) -> u8 {
+    let log = (length as f64).ln();
+    let value = if length <= 656 {
+        log / 1.5f64.ln()
+    } else if length <= 3199 {
+        log / 1.3f64.ln() - 8.72777
+    } else {
+        log / 1.1f64.ln() - 62.5472
+    };
+    value.floor() as u32 as u8
+}
+
+/// Calculates the TLSH digest of `input`, after the same preprocessing as
+/// `get_configured_spamsum()` with `options`, e.g. without whitespace or
+/// e-mail headers.
+pub fn get_tlsh(input: &[u8], options: SpamsumOptions) -> Result<Tlsh, SpamsumError> {
+    check_input(input, options)?;
+    let mut hasher = TlshHasher::new();
+    hasher.update(&filter_input(input, options));
+    hasher.finalize()
+}
+
+/// Calculates both the spamsum and the TLSH digest of `input` in one pass
+/// over the (preprocessed) input, chunk by chunk, so that large inputs only
+/// have to be preprocessed and read from memory once.
+///
+/// ```
+/// use spamsum::{get_spamsum, get_spamsum_and_tlsh, get_tlsh};
+///
+/// let input: Vec<u8> = (0..2000u32).map(|i| (i * i % 251) as u8).collect();
+/// let (spamsum, tlsh) = get_spamsum_and_tlsh(&input, Default::default()).unwrap();
+/// assert_eq!(spamsum, get_spamsum(&input).unwrap());
+/// assert_eq!(tlsh, get_tlsh(&input, Default::default()).unwrap());
+/// ```
+pub fn get_spamsum_and_tlsh(
+    input: &[u8],
+    options: SpamsumOptions,
+) -> Result<(Spamsum, Tlsh), SpamsumError> {
+    check_input(input, options)?;
+    let input = filter_input(input, options);
+    let mut state = DigestState::<FnvHasher>::new(options);
+    let mut hasher = TlshHasher::new();
+    for chunk in input.chunks(CHUNK_LENGTH) {
+        state.update_slice(chunk);
+        hasher.update(chunk);
+    }
+    Ok((state.digest()?, hasher.finalize()?))
+}
+
+fn check_input(input: &[u8], options: SpamsumOptions) -> Result<(), SpamsumError> {
+    check_options(&options)?;
+    if let Some(max_input_len) = options.max_input_len {
+        if input.len() as u64 > max_input_len {
+            return Err(SpamsumError::InputLimitExceeded(max_input_len));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{get_configured_spamsum, pseudo_random_bytes};
+
+    #[test]
+    fn test_pearson_is_permutation() {
+        let mut seen = [false; 256];
+        for &c in PEARSON.iter() {
+            assert!(!seen[usize::from(c)]);
+            seen[usize::from(c)] = true;
+        }
+    }
+
+    #[test]
+    fn test_tlsh_format() {
+        let tlsh = get_tlsh(&pseudo_random_bytes(5000, 1), Default::default()).unwrap();
+        let s = tlsh.to_string();
+        assert!(s.starts_with("T1"));
+        assert_eq!(s.len(), 2 + HEX_LENGTH);
+        assert!(s[2..]
+            .chars()
+            .all(|c| c.is_ascii_digit() || ('A'..='F').contains(&c)));
+        assert_eq!(s.parse(), Ok(tlsh));
+        assert_eq!(s[2..].parse(), Ok(tlsh));
+        assert_eq!(
+            s[3..].parse::<Tlsh>(),
+            Err(ParseSpamsumError::InvalidFormat)
+        );
+        assert_eq!(
+            format!("x{}", &s[3..]).parse::<Tlsh>(),
+            Err(ParseSpamsumError::InvalidCharacter('x'))
+        );
+    }
+
+    #[test]
+    fn test_tlsh_distance() {
+        let original = pseudo_random_bytes(10_000, 1);
+        let mut modified = original.clone();
+        for i in (0..modified.len()).step_by(500) {
+            modified[i] ^= 0xFF;
+        }
+        let unrelated = pseudo_random_bytes(10_000, 7);
+        let a = get_tlsh(&original, Default::default()).unwrap();
+        let b = get_tlsh(&modified, Default::default()).unwrap();
+        let c = get_tlsh(&unrelated, Default::default()).unwrap();
+        assert_eq!(a.distance(&a), 0);
+        assert_eq!(a.distance(&b), b.distance(&a));
+        assert!(a.distance(&b) < a.distance(&c));
+        // the lengths are equal
+        assert_eq!(a.distance(&b), a.distance_without_length(&b));
+    }
+
+    #[test]
+    fn test_tlsh_length_value() {
+        assert_eq!(length_value(50), 9);
+        let mut previous = 0;
+        for length in (50..100_000).step_by(13) {
+            let value = length_value(length);
+            assert!(value >= previous, "{}", length);
+            previous = value;
+        }
+        assert!(length_value(1 << 20) > length_value(1 << 19));
+    }
+
+    #[test]
+    fn test_tlsh_insufficient_complexity() {
+        assert!(matches!(
+            get_tlsh(&pseudo_random_bytes(49, 1), Default::default()),
+            Err(SpamsumError::InsufficientComplexity)
+        ));
+        assert!(matches!(
+            get_tlsh(&[0; 10_000], Default::default()),
+            Err(SpamsumError::InsufficientComplexity)
+        ));
+    }
+
+    #[test]
+    fn test_tlsh_hasher() {
+        let input = pseudo_random_bytes(3000, 3);
+        let mut hasher = TlshHasher::new();
+        for chunk in input.chunks(7) {
+            hasher.update(chunk);
+        }
+        let tlsh = FuzzyDigest::finalize_reset(&mut hasher).unwrap();
+        assert_eq!(tlsh, get_tlsh(&input, Default::default()).unwrap());
+        hasher.update(&input);
+        assert_eq!(hasher.finalize().unwrap(), tlsh);
+    }
+
+    #[test]
+    fn test_spamsum_and_tlsh_with_options() {
+        let mut input = pseudo_random_bytes(5000, 1);
+        input.extend_from_slice(b"\n \t text with whitespace \n");
+        let options = SpamsumOptions::builder()
+            .ignore_whitespace(true)
+            .build()
+            .unwrap();
+        let (spamsum, tlsh) = get_spamsum_and_tlsh(&input, options).unwrap();
+        assert_eq!(spamsum, get_configured_spamsum(&input, options).unwrap());
+        assert_eq!(tlsh, get_tlsh(&input, options).unwrap());
+        assert_ne!(tlsh, get_tlsh(&input, Default::default()).unwrap());
+    }
+}
@@ -0,0 +1,154 @@
+use alloc::vec::Vec;
+
+use crate::filter::Filter;
+
+// longer URLs are cut off, since only their beginning is hashed anyway
+const MAX_URL_LENGTH: usize = 2048;
+
+const SCHEMES: [&[u8]; 2] = [b"http://", b"https://"];
+
+// Normalizes http(s) URLs in input fed byte by byte, so that per-recipient
+// tracking URLs of the same campaign look the same: URLs are lowercased, the
+// query and fragment are dropped, and host labels and path segments
+// containing digits are replaced by "*".
+#[derive(Clone, Debug, Default)]
+pub(crate) struct UrlFilter {
+    // a possible start of a URL, or the URL read so far
+    buffer: Vec<u8>,
+    in_url: bool,
+}
+
+impl UrlFilter {
+    fn flush(&mut self, emit: &mut dyn FnMut(u8)) {
+        if self.in_url {
+            normalize_url(&self.buffer, emit);
+        } else {
+            self.buffer.iter().for_each(|&c| emit(c));
+        }
+        self.buffer.clear();
+        self.in_url = false;
+    }
+}
+
+impl Filter for UrlFilter {
+    fn update(&mut self, c: u8, emit: &mut dyn FnMut(u8)) {
+        if self.in_url {
+            if !ends_url(c) {
+                if self.buffer.len() < MAX_URL_LENGTH {
+                    self.buffer.push(c);
+                }
+                return;
+            }
+            self.flush(emit);
+            emit(c);
+            return;
+        }
+        self.buffer.push(c);
+        if SCHEMES
+            .iter()
+            .any(|scheme| scheme.eq_ignore_ascii_case(&self.buffer))
+        {
+            self.in_url = true;
+        } else if !SCHEMES.iter().any(|scheme| {
+            scheme.len() > self.buffer.len()
+                && scheme[..self.buffer.len()].eq_ignore_ascii_case(&self.buffer)
+        }) {
+            // only the last byte can start a scheme again, as "h" does not
+            // show up in the schemes after their first byte
+            let last = self.buffer.pop().unwrap();
+            self.buffer.iter().for_each(|&c| emit(c));
+            self.buffer.clear();
+            if last.eq_ignore_ascii_case(&b'h') {
+                self.buffer.push(last);
+            } else {
+                emit(last);
+            }
+        }
+    }
+
+    // Emits whatever is still pending at the end of the input.
+    fn finish(&mut self, emit: &mut dyn FnMut(u8)) {
+        self.flush(emit);
+    }
+}
+
+fn ends_url(c: u8) -> bool {
+    c.is_ascii_whitespace()
+        || c.is_ascii_control()
+        || matches!(
+            c,
+            b'"' | b'\'' | b'<' | b'>' | b'(' | b')' | b'[' | b']' | b'{' | b'}' | b'`'
+        )
+}
+
+fn normalize_url(url: &[u8], emit: &mut dyn FnMut(u8)) {
+    // punctuation at the end most likely belongs to the surrounding text
+    let end = url
+        .iter()
+        .rposition(|c| !matches!(c, b'.' | b',' | b';' | b':' | b'!' | b'?'))
+        .map_or(0, |position| position + 1);
+    let (url, trailing) = url.split_at(end);
+    let scheme_end = url.windows(3).position(|window| window == b"://").unwrap() + 3;
+    url[..scheme_end]
+        .iter()
+        .for_each(|c| emit(c.to_ascii_lowercase()));
+    let rest = &url[scheme_end..];
+    let rest = &rest[..rest
+        .iter()
+        .position(|&c| c == b'?' || c == b'#')
+        .unwrap_or(rest.len())];
+    let (host, path) = rest.split_at(rest.iter().position(|&c| c == b'/').unwrap_or(rest.len()));
+    for (i, label) in host.split(|&c| c == b'.').enumerate() {
+        if i > 0 {
+            emit(b'.');
+        }
+        emit_masked(label, emit);
+    }
+    for segment in path.split(|&c| c == b'/').skip(1) {
+        emit(b'/');
+        emit_masked(segment, emit);
+    }
+    trailing.iter().for_each(|&c| emit(c));
+}
+
+fn emit_masked(part: &[u8], emit: &mut dyn FnMut(u8)) {
+    if part.iter().any(u8::is_ascii_digit) {
+        emit(b'*');
+    } else {
+        part.iter().for_each(|c| emit(c.to_ascii_lowercase()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn normalize_urls(input: &[u8]) -> String {
+        let mut output = Vec::new();
+        let mut filter = UrlFilter::default();
+        let mut emit = |c| output.push(c);
+        for &c in input {
+            filter.update(c, &mut emit);
+        }
+        filter.finish(&mut emit);
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_normalize_urls() {
+        assert_eq!(
+            normalize_urls(b"Click HTTPS://U123.Example.com/Offer/a8f3e1/?id=42#top now!"),
+            "Click https://*.example.com/offer/*/ now!"
+        );
+        assert_eq!(
+            normalize_urls(b"(see http://example.com/x1.)"),
+            "(see http://example.com/*.)"
+        );
+        assert_eq!(normalize_urls(b"hhttp://a.b"), "hhttp://a.b");
+        assert_eq!(
+            normalize_urls(b"http:/ https http://"),
+            "http:/ https http://"
+        );
+        assert_eq!(normalize_urls(b"hat htt"), "hat htt");
+    }
+}
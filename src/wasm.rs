@@ -0,0 +1,60 @@
+//! JavaScript functions for hashing and comparing in browsers and other
+//! WebAssembly runtimes like Cloudflare Workers.
+//!
+//! The module is built with
+//!
+//! ```text
+//! cargo rustc --release --target wasm32-unknown-unknown --features wasm --crate-type cdylib
+//! wasm-bindgen --target web --out-dir pkg target/wasm32-unknown-unknown/release/spamsum.wasm
+//! ```
+//!
+//! (or `--target bundler` for bundlers and Workers), after which it is used
+//! like
+//!
+//! ```text
+//! import init, { hash, compare } from "./pkg/spamsum.js";
+//!
+//! await init();
+//! const spamsum = hash(new TextEncoder().encode("Hello"));
+//! compare(spamsum, "3:iKn:iKn"); // 0 to 100
+//! ```
+use wasm_bindgen::prelude::*;
+
+use crate::{get_spamsum, Spamsum};
+
+/// Returns the spamsum of a `Uint8Array` as a string.
+///
+/// Throws if the input is too large to be hashed.
+#[wasm_bindgen]
+pub fn hash(input: &[u8]) -> Result<String, JsError> {
+    Ok(get_spamsum(input)?.to_string())
+}
+
+/// Compares two spamsum strings and returns their similarity from 0 to 100.
+///
+/// Throws if one of them is not a valid spamsum.
+#[wasm_bindgen]
+pub fn compare(a: &str, b: &str) -> Result<u32, JsError> {
+    let a: Spamsum = a.parse()?;
+    let b: Spamsum = b.parse()?;
+    Ok(crate::compare(&a, &b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash() {
+        let input = b"Please buy my stuff\nDear Sir or Madam\n";
+        assert_eq!(hash(input).unwrap(), "3:clclDDvWIMF/hv:cGZ/EJv");
+        assert_eq!(hash(b"").unwrap(), "3::");
+    }
+
+    #[test]
+    fn test_compare() {
+        let a = "3:clclDDvWIMF/hv:cGZ/EJv";
+        assert_eq!(compare(a, a).unwrap(), 100);
+        assert_eq!(compare(a, "3:Hn:Hn").unwrap(), 0);
+    }
+}
@@ -0,0 +1,183 @@
+use alloc::vec::Vec;
+use core::cmp;
+use core::ops::Range;
+
+use crate::{check_options, get_configured_spamsum, Spamsum, SpamsumError, SpamsumOptions};
+
+/// Calculates the spamsum of every window of a fixed size of a stream, with
+/// a new window starting every `stride` bytes, as soon as the window has
+/// arrived. This allows matching e.g. network captures or growing logs
+/// against known content while they are being written.
+///
+/// Every window is hashed as an input of its own, so hashing takes about
+/// `window / stride` times as long as hashing the stream once, and the
+/// last `window` bytes are buffered.
+///
+/// ```
+/// use spamsum::WindowHasher;
+///
+/// let mut hasher = WindowHasher::new(1_000, 500, Default::default()).unwrap();
+/// assert_eq!(hasher.update(&[b'a'; 900]).unwrap().len(), 0);
+/// let windows = hasher.update(&[b'b'; 1_100]).unwrap();
+/// let ranges: Vec<_> = windows.iter().map(|(range, _)| range.clone()).collect();
+/// assert_eq!(ranges, vec![0..1_000, 500..1_500, 1_000..2_000]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct WindowHasher {
+    window: usize,
+    stride: usize,
+    options: SpamsumOptions,
+    // the input from `start` on that is still needed for the next windows
+    buffer: Vec<u8>,
+    start: u64,
+    // the number of bytes to skip before the next window if the stride is
+    // larger than the window
+    skip: usize,
+}
+
+impl WindowHasher {
+    /// Creates a hasher for windows of `window` bytes that start every
+    /// `stride` bytes; the stride may be larger than the window to sample
+    /// the stream.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `window` or `stride` is 0.
+    pub fn new(
+        window: usize,
+        stride: usize,
+        options: SpamsumOptions,
+    ) -> Result<WindowHasher, SpamsumError> {
+        assert!(window > 0, "window must not be 0");
+        assert!(stride > 0, "stride must not be 0");
+        check_options(&options)?;
+        // otherwise every single window would fail
+        if let Some(max_input_len) = options.max_input_len {
+            if window as u64 > max_input_len {
+                return Err(SpamsumError::InputLimitExceeded(max_input_len));
+            }
+        }
+        Ok(WindowHasher {
+            window,
+            stride,
+            options,
+            buffer: Vec::with_capacity(window),
+            start: 0,
+            skip: 0,
+        })
+    }
+
+    /// Hashes the next chunk of the stream and returns the byte ranges and
+    /// spamsums of the windows that it completed, in order.
+    pub fn update(&mut self, mut input: &[u8]) -> Result<Vec<(Range<u64>, Spamsum)>, SpamsumError> {
+        let mut windows = Vec::new();
+        while !input.is_empty() {
+            if self.skip > 0 {
+                let length = cmp::min(self.skip, input.len());
+                self.skip -= length;
+                self.start += length as u64;
+                input = &input[length..];
+                continue;
+            }
+            let length = cmp::min(self.window - self.buffer.len(), input.len());
+            self.buffer.extend_from_slice(&input[..length]);
+            input = &input[length..];
+            if self.buffer.len() == self.window {
+                let spamsum = get_configured_spamsum(&self.buffer, self.options)?;
+                windows.push((self.start..self.start + self.window as u64, spamsum));
+                let drained = cmp::min(self.stride, self.window);
+                self.buffer.drain(..drained);
+                self.start += drained as u64;
+                self.skip = self.stride - drained;
+            }
+        }
+        Ok(windows)
+    }
+
+    /// Starts over with a new stream.
+    pub fn reset(&mut self) {
+        self.buffer.clear();
+        self.start = 0;
+        self.skip = 0;
+    }
+}
+
+/// Calculates the spamsum of every window of `window` bytes of `input` that
+/// starts at a multiple of `stride`, see `WindowHasher`. A shorter input has
+/// no windows.
+///
+/// # Panics
+///
+/// Panics if `window` or `stride` is 0.
+pub fn get_window_spamsums(
+    input: &[u8],
+    window: usize,
+    stride: usize,
+    options: SpamsumOptions,
+) -> Result<Vec<(Range<u64>, Spamsum)>, SpamsumError> {
+    WindowHasher::new(window, stride, options)?.update(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pseudo_random_bytes;
+
+    #[test]
+    fn test_window_spamsums() {
+        let input = pseudo_random_bytes(10_000, 1);
+        let options = Default::default();
+        for &(window, stride) in &[(4_000, 1_000), (3_000, 3_000), (2_000, 3_500)] {
+            let windows = get_window_spamsums(&input, window, stride, options).unwrap();
+            let expected_len = (input.len() - window) / stride + 1;
+            assert_eq!(windows.len(), expected_len);
+            for (i, (range, spamsum)) in windows.iter().enumerate() {
+                assert_eq!(range.start, (i * stride) as u64);
+                let expected = &input[range.start as usize..range.end as usize];
+                assert_eq!(expected.len(), window);
+                assert_eq!(spamsum, &get_configured_spamsum(expected, options).unwrap());
+            }
+            // the chunks of the stream do not have to line up with the windows
+            let mut hasher = WindowHasher::new(window, stride, options).unwrap();
+            let mut streamed = Vec::new();
+            for chunk in input.chunks(777) {
+                streamed.extend(hasher.update(chunk).unwrap());
+            }
+            assert_eq!(streamed, windows);
+        }
+        assert!(get_window_spamsums(&input[..999], 1_000, 10, options)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_window_hasher_reset() {
+        let input = pseudo_random_bytes(5_000, 1);
+        let mut hasher = WindowHasher::new(2_000, 1_000, Default::default()).unwrap();
+        let windows = hasher.update(&input).unwrap();
+        hasher.update(b"more").unwrap();
+        hasher.reset();
+        assert_eq!(hasher.update(&input).unwrap(), windows);
+    }
+
+    #[test]
+    fn test_window_hasher_options() {
+        let options = SpamsumOptions::builder()
+            .max_input_len(Some(1_000))
+            .build()
+            .unwrap();
+        assert!(matches!(
+            WindowHasher::new(2_000, 1_000, options),
+            Err(SpamsumError::InputLimitExceeded(1_000))
+        ));
+        let options = SpamsumOptions::builder()
+            .ignore_whitespace(true)
+            .build()
+            .unwrap();
+        let windows = get_window_spamsums(b"a b c d", 4, 2, options).unwrap();
+        assert_eq!(
+            windows[0].1,
+            get_configured_spamsum(b"ab", options).unwrap()
+        );
+    }
+}
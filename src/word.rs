@@ -0,0 +1,36 @@
+use crate::{FNV_OFFSET_BASIS, FNV_PRIME};
+
+// What a word is, for the documentation of everything hashing words.
+macro_rules! words_doc {
+    () => {
+        "Words are runs of ASCII letters and digits and non-ASCII bytes, so any \
+         other character separates them."
+    };
+}
+pub(crate) use words_doc;
+
+// Splits text into words and hashes each with FNV-1a; a word may span
+// chunks of input.
+#[derive(Clone, Default)]
+pub(crate) struct WordHasher {
+    // the hash of the current word, if inside of one
+    hash: Option<u64>,
+}
+
+impl WordHasher {
+    // Hashes the next byte and returns the hash of the word it ends, if any.
+    pub(crate) fn push(&mut self, c: u8) -> Option<u64> {
+        if c.is_ascii_alphanumeric() || !c.is_ascii() {
+            let hash = self.hash.unwrap_or(FNV_OFFSET_BASIS);
+            self.hash = Some((hash ^ u64::from(c)).wrapping_mul(FNV_PRIME));
+            None
+        } else {
+            self.finish()
+        }
+    }
+
+    // Returns the hash of the word at the end of the input, if any.
+    pub(crate) fn finish(&mut self) -> Option<u64> {
+        self.hash.take()
+    }
+}
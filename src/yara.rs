@@ -0,0 +1,103 @@
+use alloc::string::String;
+use core::fmt::Write;
+
+use crate::Spamsum;
+
+/// Writes a YARA rule skeleton for a set of spamsums, e.g. a cluster from
+/// `connected_components()`, so that detections can be shipped to existing
+/// scanning infrastructure.
+///
+/// YARA cannot calculate spamsums by itself, so the spamsums are carried in
+/// the rule's metadata, following the convention of ssdeep-aware scanners
+/// and post-processing:
+///
+/// - one `ssdeep` entry per spamsum, and
+/// - `ssdeep_min_score`, the score a file's spamsum must reach with any of
+///   them to match.
+///
+/// The condition is `false`, so the rule never matches on its own until it
+/// is replaced, e.g. with a call to a fuzzy hashing module or with patterns
+/// and other conditions found by an analyst.
+///
+/// Characters of `name` that YARA does not allow in identifiers are
+/// replaced with `_`.
+///
+/// ```
+/// use spamsum::{yara_rule, Spamsum};
+///
+/// let spamsums: Vec<Spamsum> = vec!["3:clclDDvWIMF/hv:cGZ/EJv".parse().unwrap()];
+/// let rule = yara_rule("cluster-1", &spamsums, 80);
+/// assert!(rule.starts_with("rule cluster_1\n"));
+/// assert!(rule.contains("ssdeep = \"3:clclDDvWIMF/hv:cGZ/EJv\""));
+/// ```
+///
+/// writes
+///
+/// ```text
+/// rule cluster_1
+/// {
+///     meta:
+///         ssdeep_min_score = 80
+///         ssdeep = "3:clclDDvWIMF/hv:cGZ/EJv"
+///     condition:
+///         false
+/// }
+/// ```
+pub fn yara_rule(name: &str, spamsums: &[Spamsum], min_score: u32) -> String {
+    let mut rule = String::new();
+    // writing to a String cannot fail
+    writeln!(rule, "rule {}", identifier(name)).unwrap();
+    rule.push_str("{\n    meta:\n");
+    writeln!(rule, "        ssdeep_min_score = {}", min_score).unwrap();
+    for spamsum in spamsums {
+        // hashes only contain base64 characters, which need no escaping
+        writeln!(rule, "        ssdeep = \"{}\"", spamsum).unwrap();
+    }
+    rule.push_str("    condition:\n        false\n}\n");
+    rule
+}
+
+// YARA identifiers consist of up to 128 letters, digits and underscores and
+// do not start with a digit.
+fn identifier(name: &str) -> String {
+    let mut identifier = String::new();
+    if !name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        identifier.push('_');
+    }
+    for c in name.chars() {
+        if identifier.len() == 128 {
+            break;
+        }
+        identifier.push(if c.is_ascii_alphanumeric() { c } else { '_' });
+    }
+    identifier
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_yara_rule() {
+        let spamsums: Vec<Spamsum> = vec![
+            "3:clclDDvWIMF/hv:cGZ/EJv".parse().unwrap(),
+            "6:Hn:Hn".parse().unwrap(),
+        ];
+        let rule = yara_rule("spam", &spamsums, 60);
+        assert!(rule.starts_with("rule spam\n{\n"));
+        assert!(rule.contains("        ssdeep_min_score = 60\n"));
+        assert!(rule.contains("        ssdeep = \"3:clclDDvWIMF/hv:cGZ/EJv\"\n"));
+        assert!(rule.contains("        ssdeep = \"6:Hn:Hn\"\n"));
+        assert!(rule.ends_with("    condition:\n        false\n}\n"));
+    }
+
+    #[test]
+    fn test_identifier() {
+        assert_eq!(identifier("cluster_1"), "cluster_1");
+        assert_eq!(identifier("mail.eml"), "mail_eml");
+        assert_eq!(identifier("1st"), "_1st");
+        assert_eq!(identifier(""), "_");
+        assert_eq!(identifier("é"), "__");
+        assert_eq!(identifier(&"a".repeat(200)).len(), 128);
+    }
+}
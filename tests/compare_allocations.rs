@@ -0,0 +1,59 @@
+// Checks that comparing spamsums does not allocate. This needs its own
+// global allocator, so it is not a unit test: the allocator would count the
+// allocations of every other test as well.
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+use spamsum::{compare, get_configured_spamsum, Spamsum, SpamsumOptions};
+
+// counts the allocations of every thread
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|allocations| allocations.set(allocations.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+// Hashes longer than the default are kept on the heap.
+fn long_spamsum(input: &[u8]) -> Spamsum {
+    let options = SpamsumOptions::builder().hash_length(192).build().unwrap();
+    get_configured_spamsum(input, options).unwrap()
+}
+
+#[test]
+fn test_compare_does_not_allocate() {
+    let input: Vec<u8> = (0..50_000u64)
+        .map(|i| (i.wrapping_mul(0x9e37_79b9_7f4a_7c15) >> 56) as u8)
+        .collect();
+    let mut modified = input.clone();
+    modified[1_000..1_100].iter_mut().for_each(|c| *c = 0);
+    let pairs = [
+        (
+            "48:ABCDEFGHIJKLMNOP:ABCD".parse::<Spamsum>().unwrap(),
+            "48:ABCDEFGHIJKLMNOQ:WXYZ".parse::<Spamsum>().unwrap(),
+        ),
+        (long_spamsum(&input), long_spamsum(&modified)),
+    ];
+    assert!(pairs[1].0.left_hash().len() > 64);
+    let allocations = ALLOCATIONS.with(Cell::get);
+    let scores: [u32; 2] = [
+        compare(&pairs[0].0, &pairs[0].1),
+        compare(&pairs[1].0, &pairs[1].1),
+    ];
+    assert_eq!(ALLOCATIONS.with(Cell::get), allocations);
+    assert_eq!(scores[0], 94);
+    assert!(scores[1] > 50 && scores[1] < 100, "{}", scores[1]);
+}
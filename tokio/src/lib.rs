@@ -0,0 +1,205 @@
+//! Hashing with tokio, for services that read their input asynchronously.
+use std::io;
+use std::panic;
+use std::path::PathBuf;
+
+use futures::stream::{Stream, StreamExt};
+use spamsum::{get_configured_spamsum, Spamsum, SpamsumError, SpamsumHasher, SpamsumOptions};
+use tokio::fs::File;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::task;
+
+// chunk size used when reading input from a reader
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Calculates the spamsum of everything read from `reader`, like
+/// `spamsum::get_spamsum_from_reader()`, but without blocking the runtime
+/// while waiting for the input.
+///
+/// The input is hashed chunk by chunk as it arrives, and hashing a chunk
+/// takes well under a millisecond. Options that need the whole input at
+/// once, like `ignore_mime_headers`, `decode_transfer_encoding` or a
+/// `filter`, make the hasher buffer it instead, and all of it is hashed when
+/// finalizing, so that is done with `spawn_blocking()`.
+///
+/// ```
+/// use spamsum_tokio::get_spamsum_from_async_reader;
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// let input = &b"Please buy my stuff\nDear Sir or Madam\n"[..];
+/// let spamsum = get_spamsum_from_async_reader(input, Default::default()).await;
+/// assert_eq!(spamsum.unwrap().to_string(), "3:clclDDvWIMF/hv:cGZ/EJv");
+/// # }
+/// ```
+pub async fn get_spamsum_from_async_reader<R: AsyncRead + Unpin>(
+    mut reader: R,
+    options: SpamsumOptions,
+) -> Result<Spamsum, SpamsumError> {
+    let mut hasher = SpamsumHasher::with_options(options)?;
+    let mut buffer = vec![0; READ_BUFFER_SIZE];
+    loop {
+        match reader.read(&mut buffer).await {
+            Ok(0) => return spawn_hashing(move || hasher.finalize()).await,
+            Ok(length) => {
+                hasher.update(&buffer[..length]);
+                if hasher.exceeds_max_input_len() {
+                    // stop reading early, finalizing fails right away
+                    return hasher.finalize();
+                }
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(SpamsumError::Io(e)),
+        }
+    }
+}
+
+/// An input of `hash_stream()`: a file to read or a buffer that is already
+/// in memory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Input {
+    Path(PathBuf),
+    Buffer(Vec<u8>),
+}
+
+impl From<PathBuf> for Input {
+    fn from(path: PathBuf) -> Input {
+        Input::Path(path)
+    }
+}
+
+impl From<Vec<u8>> for Input {
+    fn from(buffer: Vec<u8>) -> Input {
+        Input::Buffer(buffer)
+    }
+}
+
+/// Hashes a stream of files or buffers, at most `concurrency` of them at
+/// once, and yields the results in the order of the inputs.
+///
+/// Files are read with `get_spamsum_from_async_reader()` and buffers are
+/// hashed with `spawn_blocking()`, so neither blocks the runtime. The next
+/// input is only taken from `inputs` while fewer than `concurrency` are in
+/// progress, and only as long as the results are consumed, so a slow
+/// consumer slows down the producer instead of letting results pile up.
+///
+/// ```
+/// use futures::stream::{self, StreamExt};
+/// use spamsum_tokio::{hash_stream, Input};
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// let inputs = stream::iter(vec![b"Hello".to_vec(), Vec::new()]);
+/// let spamsums: Vec<_> = hash_stream(inputs, Default::default(), 8).collect().await;
+/// assert_eq!(spamsums[1].as_ref().unwrap().to_string(), "3::");
+/// # }
+/// ```
+///
+/// # Panics
+///
+/// Panics if `concurrency` is 0.
+pub fn hash_stream<S>(
+    inputs: S,
+    options: SpamsumOptions,
+    concurrency: usize,
+) -> impl Stream<Item = Result<Spamsum, SpamsumError>>
+where
+    S: Stream,
+    S::Item: Into<Input>,
+{
+    assert!(concurrency > 0, "concurrency must be at least 1");
+    inputs
+        .map(move |input| hash_input(input.into(), options))
+        .buffered(concurrency)
+}
+
+async fn hash_input(input: Input, options: SpamsumOptions) -> Result<Spamsum, SpamsumError> {
+    match input {
+        Input::Path(path) => {
+            let file = File::open(path).await.map_err(SpamsumError::Io)?;
+            get_spamsum_from_async_reader(file, options).await
+        }
+        Input::Buffer(buffer) => {
+            spawn_hashing(move || get_configured_spamsum(&buffer, options)).await
+        }
+    }
+}
+
+// Hashes on a thread of the blocking pool instead of the runtime.
+async fn spawn_hashing<F>(hash: F) -> Result<Spamsum, SpamsumError>
+where
+    F: FnOnce() -> Result<Spamsum, SpamsumError> + Send + 'static,
+{
+    match task::spawn_blocking(hash).await {
+        Ok(result) => result,
+        Err(e) if e.is_panic() => panic::resume_unwind(e.into_panic()),
+        // the runtime is shutting down
+        Err(e) => Err(SpamsumError::Io(io::Error::other(e))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::stream;
+    use spamsum::get_spamsum;
+
+    #[tokio::test]
+    async fn test_get_spamsum_from_async_reader() {
+        let input: Vec<u8> = (0..200_000u32).map(|i| (i * i % 251) as u8).collect();
+        let spamsum = get_spamsum_from_async_reader(&input[..], Default::default()).await;
+        assert_eq!(spamsum.unwrap(), get_spamsum(&input).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_async_reader_with_max_input_len() {
+        let options = SpamsumOptions::builder()
+            .max_input_len(Some(3))
+            .build()
+            .unwrap();
+        let spamsum = get_spamsum_from_async_reader(&b"Hello"[..], options).await;
+        assert!(matches!(spamsum, Err(SpamsumError::InputLimitExceeded(3))));
+    }
+
+    #[tokio::test]
+    async fn test_async_reader_with_buffering_options() {
+        let input = b"Subject: Hello\n\nPlease buy my stuff\nDear Sir or Madam\n";
+        let options = SpamsumOptions::builder()
+            .ignore_mime_headers(true)
+            .build()
+            .unwrap();
+        let spamsum = get_spamsum_from_async_reader(&input[..], options).await;
+        assert_eq!(
+            spamsum.unwrap(),
+            get_configured_spamsum(input, options).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_hash_stream() {
+        let input = b"Please buy my stuff\nDear Sir or Madam\n".to_vec();
+        let path = std::env::temp_dir().join(format!("spamsum-tokio-{}", std::process::id()));
+        std::fs::write(&path, &input).unwrap();
+        let inputs = vec![
+            Input::Buffer(b"Hello".to_vec()),
+            Input::Path(path.clone()),
+            Input::Path(path.with_extension("missing")),
+            Input::Buffer(input.clone()),
+        ];
+        let spamsums: Vec<_> = hash_stream(stream::iter(inputs), Default::default(), 2)
+            .collect()
+            .await;
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(spamsums.len(), 4);
+        assert_eq!(
+            spamsums[0].as_ref().unwrap(),
+            &get_spamsum(b"Hello").unwrap()
+        );
+        assert_eq!(
+            spamsums[1].as_ref().unwrap().to_string(),
+            "3:clclDDvWIMF/hv:cGZ/EJv"
+        );
+        assert!(matches!(spamsums[2], Err(SpamsumError::Io(_))));
+        assert_eq!(spamsums[3].as_ref().unwrap(), spamsums[1].as_ref().unwrap());
+    }
+}